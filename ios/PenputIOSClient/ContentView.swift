@@ -3,6 +3,7 @@ import SwiftUI
 // Root UI: connect/disconnect + touchpad + metrics.
 struct ContentView: View {
     @StateObject private var client = UdpTouchClient()
+    @StateObject private var browser = ServerBrowser()
 
     @State private var host: String = ""
     @State private var portText: String = "9002"
@@ -27,6 +28,17 @@ struct ContentView: View {
                         .textFieldStyle(.roundedBorder)
                         .frame(width: 90)
 
+                    if !browser.servers.isEmpty {
+                        Menu("Nearby") {
+                            ForEach(browser.servers) { server in
+                                Button(server.name) {
+                                    host = server.host
+                                    portText = String(server.port)
+                                }
+                            }
+                        }
+                    }
+
                     if client.state == .connected || client.state == .awaitingApproval || client.state == .connecting {
                         Button("Disconnect") {
                             client.disconnect()
@@ -76,6 +88,10 @@ struct ContentView: View {
         }
         .onAppear {
             // Pre-fill host with last used value if needed.
+            browser.start()
+        }
+        .onDisappear {
+            browser.stop()
         }
     }
 }
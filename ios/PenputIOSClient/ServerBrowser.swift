@@ -0,0 +1,62 @@
+import Combine
+import Foundation
+
+// Finds Penput servers on the local network: they advertise `_penput._udp` over Bonjour
+// with their UDP port, so the user can pick one instead of typing its IP.
+final class ServerBrowser: NSObject, ObservableObject {
+    struct Server: Identifiable, Hashable {
+        let name: String
+        let host: String
+        let port: UInt16
+
+        var id: String { name }
+    }
+
+    @Published private(set) var servers: [Server] = []
+
+    private let browser = NetServiceBrowser()
+    // Services found but not yet resolved to a host and port; kept alive while resolving.
+    private var resolving: [NetService] = []
+
+    override init() {
+        super.init()
+        browser.delegate = self
+    }
+
+    func start() {
+        browser.searchForServices(ofType: "_penput._udp.", inDomain: "local.")
+    }
+
+    func stop() {
+        browser.stop()
+        resolving.removeAll()
+        servers.removeAll()
+    }
+}
+
+extension ServerBrowser: NetServiceBrowserDelegate {
+    func netServiceBrowser(_ browser: NetServiceBrowser, didFind service: NetService, moreComing: Bool) {
+        service.delegate = self
+        resolving.append(service)
+        service.resolve(withTimeout: 5)
+    }
+
+    func netServiceBrowser(_ browser: NetServiceBrowser, didRemove service: NetService, moreComing: Bool) {
+        resolving.removeAll { $0 == service }
+        servers.removeAll { $0.name == service.name }
+    }
+}
+
+extension ServerBrowser: NetServiceDelegate {
+    func netServiceDidResolveAddress(_ service: NetService) {
+        resolving.removeAll { $0 == service }
+        guard let host = service.hostName, service.port > 0 else { return }
+        let server = Server(name: service.name, host: host, port: UInt16(service.port))
+        servers.removeAll { $0.name == server.name }
+        servers.append(server)
+    }
+
+    func netService(_ service: NetService, didNotResolve errorDict: [String: NSNumber]) {
+        resolving.removeAll { $0 == service }
+    }
+}
@@ -7,7 +7,7 @@ import UIKit
 //
 // Packet format (big-endian):
 // - HELLO: [0x01][w:u16][h:u16]
-// - MOVE:  [0x02][x:u16][y:u16]
+// - MOVE:  [0x02][x:u16][y:u16][seq:u32]
 // - PING:  [0x03][t:u64]
 // - ACCEPT: [0x10][remote_w:u16][remote_h:u16] (optional)
 // - REJECT: [0x11]
@@ -99,7 +99,10 @@ final class UdpTouchClient: NSObject, ObservableObject {
 
     // Reusable packet buffers to reduce allocations.
     private var helloPacket = [UInt8](repeating: 0, count: 5)
-    private var movePacket = [UInt8](repeating: 0, count: 5)
+    private var movePacket = [UInt8](repeating: 0, count: 9)
+
+    // Numbers each MOVE so the server can drop late or duplicated ones.
+    private var moveSeq: UInt32 = 0
     private var pingPacket = [UInt8](repeating: 0, count: 9)
 
     private func cancelConnection() {
@@ -333,6 +336,11 @@ final class UdpTouchClient: NSObject, ObservableObject {
         movePacket[2] = UInt8(latestX & 0xff)
         movePacket[3] = UInt8((latestY >> 8) & 0xff)
         movePacket[4] = UInt8(latestY & 0xff)
+        moveSeq &+= 1
+        movePacket[5] = UInt8((moveSeq >> 24) & 0xff)
+        movePacket[6] = UInt8((moveSeq >> 16) & 0xff)
+        movePacket[7] = UInt8((moveSeq >> 8) & 0xff)
+        movePacket[8] = UInt8(moveSeq & 0xff)
 
         sendBytes(movePacket)
         updateSendRateOnSend()
@@ -0,0 +1,323 @@
+use crate::corners::{Corner, DEFAULT_CORNER_SIZE, HotCorners};
+use crate::keyboard::{KeyCode, Modifier};
+use crate::mouse::MouseController;
+use crate::power::SystemAction;
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use std::sync::Arc;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+/// Longest pause a macro step may request.
+const MAX_DELAY: Duration = Duration::from_secs(10);
+
+/// Actions one session may have waiting behind the one running; more are refused.
+const MAX_QUEUED: usize = 4;
+
+/// One step of a macro.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum Step {
+    /// `"ctrl+shift+t"`, `"enter"`, `"ctrl++"`: optional modifiers, then one key.
+    Chord(#[serde(deserialize_with = "chord")] (Vec<Modifier>, KeyCode)),
+    /// `{ text = "..." }` types the string.
+    Text { text: String },
+    /// `{ delay_ms = 200 }` waits, e.g. for a dialog to open.
+    Delay { delay_ms: u64 },
+    /// `{ command = "lock" }` starts an entry of the `[commands]` allowlist.
+    Command { command: String },
+    /// `{ system = "display_off" }` performs a power action enabled under `[system]`.
+    System { system: SystemAction },
+}
+
+fn chord<'de, D>(de: D) -> Result<(Vec<Modifier>, KeyCode), D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let spec = String::deserialize(de)?;
+    parse_chord(&spec).ok_or_else(|| serde::de::Error::custom(format!("invalid chord: {spec}")))
+}
+
+/// Split `"ctrl+shift+t"` into modifiers and key. A trailing `++` means the `+` key.
+pub fn parse_chord(spec: &str) -> Option<(Vec<Modifier>, KeyCode)> {
+    let (mods, key) = match spec.strip_suffix("++") {
+        Some(mods) => (mods, "+"),
+        None if spec == "+" => ("", "+"),
+        None => spec.rsplit_once('+').unwrap_or(("", spec)),
+    };
+    let modifiers = mods
+        .split('+')
+        .filter(|m| !m.is_empty())
+        .map(Modifier::parse)
+        .collect::<Option<Vec<_>>>()?;
+    Some((modifiers, KeyCode::parse(key)?))
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ActionsFile {
+    #[serde(default)]
+    actions: BTreeMap<String, Vec<Step>>,
+    #[serde(default)]
+    commands: BTreeMap<String, String>,
+    #[serde(default)]
+    system: SystemSection,
+    #[serde(default)]
+    hot_corners: HotCornersSection,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SystemSection {
+    #[serde(default)]
+    allow: BTreeSet<SystemAction>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct HotCornersSection {
+    size: Option<u16>,
+    top_left: Option<String>,
+    top_right: Option<String>,
+    bottom_left: Option<String>,
+    bottom_right: Option<String>,
+}
+
+/// Named macros loaded from the `--actions` file, triggered by `action` messages.
+///
+/// ```toml
+/// [actions]
+/// screenshot = ["meta+shift+s"]
+/// copy_all = ["ctrl+a", "ctrl+c"]
+/// sign_off = [{ text = "Best regards" }, "enter"]
+/// save_and_lock = ["ctrl+s", { command = "lock" }]
+///
+/// # The only shell commands that can ever run; each is also an action of the same name.
+/// [commands]
+/// lock = "loginctl lock-session"
+///
+/// # Power actions (`lock_screen`, `suspend`, `display_off`); none are allowed by default.
+/// # Each is also an action of the same name.
+/// [system]
+/// allow = ["display_off"]
+///
+/// # Actions fired when a client moves the cursor into a screen corner.
+/// [hot_corners]
+/// size = 10
+/// top_left = "screenshot"
+/// ```
+#[derive(Debug, Default)]
+pub struct ActionRegistry {
+    actions: BTreeMap<String, Vec<Step>>,
+    commands: BTreeMap<String, String>,
+    hot_corners: BTreeMap<Corner, String>,
+    corner_size: u16,
+}
+
+impl ActionRegistry {
+    pub fn load(path: &Path) -> Result<Self> {
+        let raw = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let mut file: ActionsFile =
+            toml::from_str(&raw).with_context(|| format!("Failed to parse {}", path.display()))?;
+
+        for (name, steps) in &file.actions {
+            for step in steps {
+                if let Step::Command { command } = step
+                    && !file.commands.contains_key(command)
+                {
+                    bail!("Action {name} runs {command}, which is not listed under [commands]");
+                }
+                if let Step::System { system } = step
+                    && !file.system.allow.contains(system)
+                {
+                    bail!(
+                        "Action {name} uses {}, which is not allowed under [system]",
+                        system.name()
+                    );
+                }
+            }
+        }
+        for name in file.commands.keys() {
+            if file.actions.contains_key(name) {
+                bail!("{name} is defined both as an action and as a command");
+            }
+            let step = Step::Command {
+                command: name.clone(),
+            };
+            file.actions.insert(name.clone(), vec![step]);
+        }
+        for &system in &file.system.allow {
+            let name = system.name();
+            if file.actions.contains_key(name) {
+                bail!("{name} is defined both as an action and as a system action");
+            }
+            file.actions
+                .insert(name.to_owned(), vec![Step::System { system }]);
+        }
+
+        let section = file.hot_corners;
+        let mut hot_corners = BTreeMap::new();
+        for (corner, name) in Corner::ALL.into_iter().zip([
+            section.top_left,
+            section.top_right,
+            section.bottom_left,
+            section.bottom_right,
+        ]) {
+            let Some(name) = name else {
+                continue;
+            };
+            if !file.actions.contains_key(&name) {
+                bail!("Hot corner action {name} is not defined");
+            }
+            hot_corners.insert(corner, name);
+        }
+
+        Ok(Self {
+            actions: file.actions,
+            commands: file.commands,
+            hot_corners,
+            corner_size: section.size.unwrap_or(DEFAULT_CORNER_SIZE),
+        })
+    }
+
+    /// Action names in sorted order, for clients building a button grid.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.actions.keys().map(String::as_str)
+    }
+
+    /// Corners with a bound action, for the input worker to watch.
+    pub fn hot_corners(&self) -> HotCorners {
+        HotCorners {
+            size: self.corner_size,
+            corners: self.hot_corners.keys().copied().collect(),
+        }
+    }
+
+    /// Run the action bound to each hot corner the cursor enters, for the life of the server.
+    pub async fn run_hot_corners(self: Arc<Self>, mouse: Arc<MouseController>) {
+        let mut hits = mouse.subscribe_hot_corners();
+        loop {
+            let corner = match hits.recv().await {
+                Ok(corner) => corner,
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return,
+            };
+            if let Some(name) = self.hot_corners.get(&corner) {
+                info!("📐 Hot corner {corner:?}: {name}");
+                self.run(name, &mouse).await;
+            }
+        }
+    }
+
+    /// Run the macro bound to `name`. Returns false if there is no such action.
+    ///
+    /// Steps are queued on the input worker in order; delays pause this task only.
+    pub async fn run(&self, name: &str, mouse: &MouseController) -> bool {
+        let Some(steps) = self.actions.get(name) else {
+            warn!("Unknown action requested: {name}");
+            return false;
+        };
+        for step in steps {
+            let _ = match step {
+                Step::Chord((modifiers, key)) if modifiers.is_empty() => mouse.tap_key(*key),
+                Step::Chord((modifiers, key)) => mouse.shortcut(modifiers, *key),
+                Step::Text { text } => mouse.type_text(text),
+                Step::Delay { delay_ms } => {
+                    tokio::time::sleep(Duration::from_millis(*delay_ms).min(MAX_DELAY)).await;
+                    Ok(())
+                }
+                Step::Command { command } => {
+                    self.spawn_command(command);
+                    Ok(())
+                }
+                Step::System { system } => {
+                    perform_system(*system).await;
+                    Ok(())
+                }
+            };
+        }
+        true
+    }
+
+    /// Start an allowlisted command through the platform shell without waiting for it,
+    /// so launching a long-running program doesn't hold up the macro.
+    fn spawn_command(&self, name: &str) {
+        // Validated at load time, so only `[commands]` entries can get here.
+        let Some(line) = self.commands.get(name) else {
+            return;
+        };
+        #[cfg(target_os = "windows")]
+        let mut cmd = {
+            let mut cmd = tokio::process::Command::new("cmd");
+            cmd.arg("/C").arg(line);
+            cmd
+        };
+        #[cfg(not(target_os = "windows"))]
+        let mut cmd = {
+            let mut cmd = tokio::process::Command::new("sh");
+            cmd.arg("-c").arg(line);
+            cmd
+        };
+        cmd.stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+
+        match cmd.spawn() {
+            Ok(mut child) => {
+                info!("▶️  Running command {name}");
+                let name = name.to_owned();
+                // Reap the child so it doesn't linger as a zombie.
+                tokio::spawn(async move {
+                    match child.wait().await {
+                        Ok(status) if !status.success() => {
+                            warn!("Command {name} exited with {status}")
+                        }
+                        Ok(_) => {}
+                        Err(err) => warn!("Command {name} failed: {err}"),
+                    }
+                });
+            }
+            Err(err) => warn!("Failed to start command {name}: {err}"),
+        }
+    }
+}
+
+/// One session's actions, run one at a time in the order asked for by a task of their
+/// own, so a macro that pauses between steps holds up neither input nor the next macro.
+pub struct ActionQueue {
+    names: mpsc::Sender<String>,
+}
+
+impl ActionQueue {
+    pub fn new(actions: Arc<ActionRegistry>, mouse: Arc<MouseController>) -> Self {
+        let (names, mut queued) = mpsc::channel::<String>(MAX_QUEUED);
+        // Ends once the session drops the queue and what it asked for has run.
+        tokio::spawn(async move {
+            while let Some(name) = queued.recv().await {
+                actions.run(&name, &mouse).await;
+            }
+        });
+        Self { names }
+    }
+
+    /// Queue the action `name`. Returns false if too many are waiting already.
+    pub fn push(&self, name: String) -> bool {
+        self.names.try_send(name).is_ok()
+    }
+}
+
+/// Perform an allowlisted power action off the async runtime, since some block briefly.
+async fn perform_system(action: SystemAction) {
+    info!("⏻ Performing system action {}", action.name());
+    match tokio::task::spawn_blocking(move || action.perform()).await {
+        Ok(Ok(())) => {}
+        Ok(Err(err)) => warn!("System action {} failed: {err}", action.name()),
+        Err(err) => warn!("System action {} panicked: {err}", action.name()),
+    }
+}
@@ -0,0 +1,127 @@
+use serde::Deserialize;
+
+/// Client pixels of cursor travel per radian of rotation, before `--sensitivity`.
+const PIXELS_PER_RADIAN: f64 = 1000.0;
+
+/// Angular rates below this (rad/s) are sensor noise; ignoring them stops the cursor drifting.
+const NOISE_FLOOR: f64 = 0.02;
+
+/// Weight of each accelerometer reading when correcting the gyro-tracked gravity direction.
+const ACCEL_BLEND: f64 = 0.02;
+
+/// Accelerometer readings further than this from 1 g (m/s²) are dominated by hand motion.
+const ACCEL_TOLERANCE: f64 = 2.0;
+
+const STANDARD_GRAVITY: f64 = 9.81;
+
+/// Longest gap between samples that is still integrated; longer gaps restart the filter.
+const MAX_SAMPLE_GAP: f64 = 0.1;
+
+/// One IMU reading, in the phone's own axes (x right, y toward the top edge, z out of the screen).
+#[derive(Debug, Copy, Clone, Default, Deserialize)]
+pub struct MotionSample {
+    /// Client timestamp in milliseconds, used for integration so network jitter doesn't matter.
+    pub t: u64,
+    /// Angular velocity in rad/s.
+    pub gx: f64,
+    pub gy: f64,
+    pub gz: f64,
+    /// Acceleration in m/s² including gravity, pointing up at rest (`az` ≈ +9.81 lying face up).
+    /// All zeros means the client has no accelerometer.
+    #[serde(default)]
+    pub ax: f64,
+    #[serde(default)]
+    pub ay: f64,
+    #[serde(default)]
+    pub az: f64,
+}
+
+type Vec3 = [f64; 3];
+
+fn dot(a: Vec3, b: Vec3) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: Vec3, b: Vec3) -> Vec3 {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn normalized(v: Vec3) -> Option<Vec3> {
+    let len = dot(v, v).sqrt();
+    (len > 1e-9).then(|| [v[0] / len, v[1] / len, v[2] / len])
+}
+
+/// Turns phone rotation into relative cursor motion, like a Wii-style pointer.
+///
+/// A complementary filter tracks which way is up (gyro for short-term, accelerometer to
+/// cancel drift), so turning left/right always moves the cursor horizontally and tilting
+/// the top edge up/down moves it vertically, however the phone is rolled in the hand.
+#[derive(Debug)]
+pub struct AirMouse {
+    /// World "up" in device coordinates.
+    up: Vec3,
+    last_t: Option<u64>,
+}
+
+impl Default for AirMouse {
+    fn default() -> Self {
+        Self {
+            // Lying face up until the accelerometer says otherwise.
+            up: [0.0, 0.0, 1.0],
+            last_t: None,
+        }
+    }
+}
+
+impl AirMouse {
+    /// Feed one sample; returns the cursor delta in client pixels, if any.
+    pub fn sample(&mut self, s: MotionSample) -> Option<(f64, f64)> {
+        let omega = [s.gx, s.gy, s.gz];
+        let accel = [s.ax, s.ay, s.az];
+        if !omega.iter().chain(&accel).all(|v| v.is_finite()) {
+            return None;
+        }
+
+        let prev = self.last_t.replace(s.t);
+        let dt = s.t.checked_sub(prev?)? as f64 / 1000.0;
+        if dt <= 0.0 || dt > MAX_SAMPLE_GAP {
+            return None;
+        }
+
+        // A world-fixed vector seen from the rotating device turns the other way: du/dt = -ω × u.
+        let turn = cross(omega, self.up);
+        let mut up = [
+            self.up[0] - turn[0] * dt,
+            self.up[1] - turn[1] * dt,
+            self.up[2] - turn[2] * dt,
+        ];
+        if (dot(accel, accel).sqrt() - STANDARD_GRAVITY).abs() < ACCEL_TOLERANCE
+            && let Some(a) = normalized(accel)
+        {
+            for (u, a) in up.iter_mut().zip(a) {
+                *u += (a - *u) * ACCEL_BLEND;
+            }
+        }
+        self.up = normalized(up).unwrap_or(self.up);
+
+        // The phone points along its top edge; `right` is horizontal and square to it.
+        let right = normalized(cross([0.0, 1.0, 0.0], self.up))?;
+        let yaw = dot(omega, self.up);
+        let pitch = dot(omega, right);
+        let yaw = if yaw.abs() < NOISE_FLOOR { 0.0 } else { yaw };
+        let pitch = if pitch.abs() < NOISE_FLOOR {
+            0.0
+        } else {
+            pitch
+        };
+
+        // Positive yaw swings the top edge left, positive pitch raises it.
+        let dx = -yaw * dt * PIXELS_PER_RADIAN;
+        let dy = -pitch * dt * PIXELS_PER_RADIAN;
+        (dx != 0.0 || dy != 0.0).then_some((dx, dy))
+    }
+}
@@ -0,0 +1,228 @@
+use crate::connection::{Decision, Device, Role, Transport};
+use crate::trust;
+use crate::webhook::Webhooks;
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::fs::File;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Mutex;
+use tracing::warn;
+
+/// How much the audit log records (`--audit-level`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AuditLevel {
+    /// Requests, decisions, connections and disconnections.
+    #[default]
+    Sessions,
+    /// The same, plus how many input events of each kind every session injected.
+    Inputs,
+}
+
+impl AuditLevel {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "sessions" => Some(Self::Sessions),
+            "inputs" => Some(Self::Inputs),
+            _ => None,
+        }
+    }
+}
+
+/// What an input event did, for the per-session counts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputKind {
+    /// Moves, clicks, scrolling, touch, stylus and motion.
+    Pointer,
+    /// Keys, shortcuts, media keys and typed text.
+    Keyboard,
+    Clipboard,
+    OpenUrl,
+    Action,
+    Gamepad,
+    /// Mapping, calibration, display and pointer settings.
+    Settings,
+}
+
+/// Input events a session injected, by kind.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct InputCounts {
+    pointer: u64,
+    keyboard: u64,
+    clipboard: u64,
+    open_url: u64,
+    action: u64,
+    gamepad: u64,
+    settings: u64,
+}
+
+impl InputCounts {
+    pub fn add(&mut self, kind: InputKind) {
+        let count = match kind {
+            InputKind::Pointer => &mut self.pointer,
+            InputKind::Keyboard => &mut self.keyboard,
+            InputKind::Clipboard => &mut self.clipboard,
+            InputKind::OpenUrl => &mut self.open_url,
+            InputKind::Action => &mut self.action,
+            InputKind::Gamepad => &mut self.gamepad,
+            InputKind::Settings => &mut self.settings,
+        };
+        *count += 1;
+    }
+}
+
+/// A client as the log names it: the start of its ID, which is all the console shows
+/// too, and what it calls itself.
+#[derive(Debug, Serialize)]
+pub struct Who<'a> {
+    device_id: Option<&'a str>,
+    name: Option<&'a str>,
+    platform: Option<&'a str>,
+}
+
+impl<'a> From<&'a Device> for Who<'a> {
+    fn from(device: &'a Device) -> Self {
+        Self {
+            device_id: device.id.as_deref().map(trust::short_id),
+            name: device.name.as_deref(),
+            platform: device.platform.as_deref(),
+        }
+    }
+}
+
+/// One line of the audit log.
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum AuditEvent<'a> {
+    /// A client asked to connect and the operator was asked.
+    Requested {
+        addr: SocketAddr,
+        role: Role,
+        #[serde(flatten)]
+        device: Who<'a>,
+        /// The client whose place it asked for, for a takeover.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        replaces: Option<SocketAddr>,
+    },
+    /// A request was answered, by the operator or without asking.
+    Decided {
+        addr: SocketAddr,
+        role: Role,
+        #[serde(flatten)]
+        device: Who<'a>,
+        decision: Decision,
+        /// Who or what decided: `operator`, `timeout`, `auto_approve`, `trusted`, `qr`,
+        /// `pairing_code`, `lockout` or `signature`.
+        by: &'static str,
+    },
+    /// An approved client's session started.
+    Connected {
+        addr: SocketAddr,
+        transport: Transport,
+        role: Role,
+        #[serde(flatten)]
+        device: Who<'a>,
+    },
+    /// A session ended, for whatever reason.
+    Disconnected {
+        addr: SocketAddr,
+        transport: Transport,
+        role: Role,
+        #[serde(flatten)]
+        device: Who<'a>,
+        seconds: u64,
+        /// Only with `--audit-level inputs`.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        inputs: Option<InputCounts>,
+    },
+    /// The operator disconnected a client from the console.
+    Kicked { addr: SocketAddr },
+}
+
+#[derive(Serialize)]
+struct Line<'a> {
+    time: String,
+    #[serde(flatten)]
+    event: &'a AuditEvent<'a>,
+}
+
+/// An append-only JSON-lines record of who connected, who let them in and what they
+/// did (`--audit-log`), each event also posted to the webhooks (`--webhook`). Does
+/// nothing when there is neither.
+#[derive(Debug, Default)]
+pub struct AuditLog {
+    file: Option<Mutex<File>>,
+    webhooks: Option<Webhooks>,
+    level: AuditLevel,
+}
+
+impl AuditLog {
+    pub fn new(level: AuditLevel) -> Self {
+        Self {
+            level,
+            ..Self::default()
+        }
+    }
+
+    /// Append to the log at `path`, creating it readable only by the current user where
+    /// the platform allows.
+    pub fn with_file(self, path: &Path) -> Result<Self> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut options = std::fs::OpenOptions::new();
+        options.append(true).create(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let file = options
+            .open(path)
+            .with_context(|| format!("Failed to open the audit log {}", path.display()))?;
+        Ok(Self {
+            file: Some(Mutex::new(file)),
+            ..self
+        })
+    }
+
+    pub fn with_webhooks(self, webhooks: Webhooks) -> Self {
+        Self {
+            webhooks: Some(webhooks),
+            ..self
+        }
+    }
+
+    /// Whether sessions should count the input events they inject.
+    pub fn counts_inputs(&self) -> bool {
+        self.is_on() && self.level == AuditLevel::Inputs
+    }
+
+    fn is_on(&self) -> bool {
+        self.file.is_some() || self.webhooks.is_some()
+    }
+
+    pub fn record(&self, event: AuditEvent) {
+        if !self.is_on() {
+            return;
+        }
+        let line = Line {
+            time: chrono::Local::now().to_rfc3339(),
+            event: &event,
+        };
+        let mut raw = match serde_json::to_string(&line) {
+            Ok(raw) => raw,
+            Err(err) => {
+                warn!("Failed to write the audit log: {err}");
+                return;
+            }
+        };
+        if let Some(webhooks) = &self.webhooks {
+            webhooks.send(&raw);
+        }
+        if let Some(file) = &self.file {
+            raw.push('\n');
+            if let Err(err) = file.lock().unwrap().write_all(raw.as_bytes()) {
+                warn!("Failed to write the audit log: {err}");
+            }
+        }
+    }
+}
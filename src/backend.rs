@@ -0,0 +1,727 @@
+use crate::mouse::MouseButton;
+use serde::Serialize;
+use std::fmt;
+use std::io;
+
+/// Which pointer backend to use, chosen with `--backend`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    /// The best native backend for this platform and session, or enigo if there is none.
+    #[default]
+    Auto,
+    Enigo,
+    Uinput,
+    Portal,
+    SendInput,
+    CgEvent,
+}
+
+impl BackendKind {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "auto" => Some(Self::Auto),
+            "enigo" => Some(Self::Enigo),
+            "uinput" => Some(Self::Uinput),
+            "portal" => Some(Self::Portal),
+            "sendinput" => Some(Self::SendInput),
+            "cgevent" => Some(Self::CgEvent),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for BackendKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Auto => "auto",
+            Self::Enigo => "enigo",
+            Self::Uinput => "uinput",
+            Self::Portal => "portal",
+            Self::SendInput => "sendinput",
+            Self::CgEvent => "cgevent",
+        })
+    }
+}
+
+/// Native pointer device that takes over absolute moves, buttons and scrolling from enigo.
+///
+/// The cursor read-back and keys still go through enigo.
+pub(crate) trait PointerBackend {
+    fn kind(&self) -> BackendKind;
+
+    /// Put the cursor at a desktop position. Backends that can address fractions of a pixel
+    /// keep them; the rest round.
+    fn move_abs(&mut self, x: f64, y: f64) -> io::Result<()>;
+
+    /// Move the cursor by whole pixels. Backends without relative motion report
+    /// `Unsupported`, and enigo moves the cursor instead.
+    fn move_rel(&mut self, dx: i32, dy: i32) -> io::Result<()> {
+        let _ = (dx, dy);
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    fn button(&mut self, button: MouseButton, pressed: bool) -> io::Result<()>;
+
+    /// Scroll by whole notches; positive is right / down, as with enigo.
+    fn scroll(&mut self, notches_x: i32, notches_y: i32) -> io::Result<()>;
+
+    /// Follow a change in the desktop extent.
+    fn resize(&mut self, desktop_w: u16, desktop_h: u16) -> io::Result<()>;
+}
+
+/// Open the native pointer backend `kind`, sized to span the desktop.
+///
+/// `Auto` picks, under Wayland, the RemoteDesktop portal first, since it is the compositor's
+/// own way in; then uinput, which works under X11 and Wayland alike. On Windows it picks
+/// SendInput and on macOS CGEvent. `Unsupported` means the backend isn't in this build or on this platform.
+pub(crate) fn open_backend(
+    kind: BackendKind,
+    desktop_w: u16,
+    desktop_h: u16,
+) -> io::Result<Box<dyn PointerBackend>> {
+    match kind {
+        BackendKind::Enigo => Err(unsupported("enigo is not a native backend")),
+        BackendKind::Auto => open_auto(desktop_w, desktop_h),
+        BackendKind::Uinput => open_uinput(desktop_w, desktop_h),
+        BackendKind::Portal => open_portal(),
+        BackendKind::SendInput => open_send_input(),
+        BackendKind::CgEvent => open_cg_event(),
+    }
+}
+
+/// Whether the OS lets this process inject input, where that takes a permission the user
+/// grants; `None` where it doesn't.
+pub fn input_permission() -> Option<bool> {
+    #[cfg(target_os = "macos")]
+    {
+        Some(macos::is_trusted())
+    }
+    #[cfg(not(target_os = "macos"))]
+    {
+        None
+    }
+}
+
+fn unsupported(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, msg)
+}
+
+fn open_auto(desktop_w: u16, desktop_h: u16) -> io::Result<Box<dyn PointerBackend>> {
+    if cfg!(target_os = "windows") {
+        return open_send_input();
+    }
+    if cfg!(target_os = "macos") {
+        return open_cg_event();
+    }
+    if cfg!(all(target_os = "linux", feature = "backend-portal")) && is_wayland_session() {
+        match open_portal() {
+            Ok(dev) => return Ok(dev),
+            Err(err) if cfg!(feature = "backend-uinput") => {
+                tracing::warn!("Remote desktop portal unavailable ({err}); trying uinput");
+            }
+            Err(err) => return Err(err),
+        }
+    }
+    open_uinput(desktop_w, desktop_h)
+}
+
+pub fn is_wayland_session() -> bool {
+    std::env::var_os("XDG_SESSION_TYPE").is_some_and(|t| t == "wayland")
+        || std::env::var_os("WAYLAND_DISPLAY").is_some()
+}
+
+fn open_uinput(desktop_w: u16, desktop_h: u16) -> io::Result<Box<dyn PointerBackend>> {
+    #[cfg(all(target_os = "linux", feature = "backend-uinput"))]
+    {
+        Ok(Box::new(linux::UinputPointer::new(desktop_w, desktop_h)?))
+    }
+    #[cfg(not(all(target_os = "linux", feature = "backend-uinput")))]
+    {
+        let _ = (desktop_w, desktop_h);
+        Err(unsupported(
+            "uinput needs Linux and the backend-uinput feature",
+        ))
+    }
+}
+
+fn open_portal() -> io::Result<Box<dyn PointerBackend>> {
+    #[cfg(all(target_os = "linux", feature = "backend-portal"))]
+    {
+        Ok(Box::new(portal::PortalPointer::new()?))
+    }
+    #[cfg(not(all(target_os = "linux", feature = "backend-portal")))]
+    {
+        Err(unsupported(
+            "the portal needs Linux and the backend-portal feature",
+        ))
+    }
+}
+
+fn open_send_input() -> io::Result<Box<dyn PointerBackend>> {
+    #[cfg(target_os = "windows")]
+    {
+        Ok(Box::new(windows::SendInputPointer))
+    }
+    #[cfg(not(target_os = "windows"))]
+    {
+        Err(unsupported("SendInput needs Windows"))
+    }
+}
+
+fn open_cg_event() -> io::Result<Box<dyn PointerBackend>> {
+    #[cfg(target_os = "macos")]
+    {
+        Ok(Box::new(macos::CgEventPointer::new()?))
+    }
+    #[cfg(not(target_os = "macos"))]
+    {
+        Err(unsupported("CGEvent needs macOS"))
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "backend-uinput"))]
+mod linux {
+    use super::{BackendKind, PointerBackend};
+    use crate::mouse::MouseButton;
+    use crate::uinput::*;
+    use std::io;
+
+    /// Absolute pointer, like a VM's USB tablet: the kernel and compositor see a real device,
+    /// so it works under Wayland as well as X11 and positions are never rounded through XTest.
+    pub struct UinputPointer {
+        dev: UinputDevice,
+    }
+
+    impl UinputPointer {
+        pub fn new(desktop_w: u16, desktop_h: u16) -> io::Result<Self> {
+            let axes = [
+                AbsAxis {
+                    code: ABS_X,
+                    min: 0,
+                    max: i32::from(desktop_w.max(1)) - 1,
+                    resolution: 0,
+                },
+                AbsAxis {
+                    code: ABS_Y,
+                    min: 0,
+                    max: i32::from(desktop_h.max(1)) - 1,
+                    resolution: 0,
+                },
+            ];
+            let dev = UinputDevice::create(&DeviceSpec {
+                name: "Penput Virtual Pointer",
+                props: &[],
+                keys: &[BTN_LEFT, BTN_RIGHT, BTN_MIDDLE],
+                rels: &[REL_WHEEL, REL_HWHEEL],
+                axes: &axes,
+            })?;
+            Ok(Self { dev })
+        }
+    }
+
+    impl PointerBackend for UinputPointer {
+        fn kind(&self) -> BackendKind {
+            BackendKind::Uinput
+        }
+
+        fn move_abs(&mut self, x: f64, y: f64) -> io::Result<()> {
+            self.dev.emit(EV_ABS, ABS_X, x.round() as i32);
+            self.dev.emit(EV_ABS, ABS_Y, y.round() as i32);
+            self.dev.sync()
+        }
+
+        fn button(&mut self, button: MouseButton, pressed: bool) -> io::Result<()> {
+            let code = match button {
+                MouseButton::Left => BTN_LEFT,
+                MouseButton::Right => BTN_RIGHT,
+                MouseButton::Middle => BTN_MIDDLE,
+            };
+            self.dev.emit(EV_KEY, code, i32::from(pressed));
+            self.dev.sync()
+        }
+
+        fn scroll(&mut self, notches_x: i32, notches_y: i32) -> io::Result<()> {
+            if notches_x != 0 {
+                self.dev.emit(EV_REL, REL_HWHEEL, notches_x);
+            }
+            // evdev wheels count up as positive.
+            if notches_y != 0 {
+                self.dev.emit(EV_REL, REL_WHEEL, -notches_y);
+            }
+            self.dev.sync()
+        }
+
+        fn resize(&mut self, desktop_w: u16, desktop_h: u16) -> io::Result<()> {
+            // Axis ranges are fixed once the device exists, so it is replaced.
+            *self = Self::new(desktop_w, desktop_h)?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "backend-portal"))]
+mod portal {
+    use super::{BackendKind, PointerBackend};
+    use crate::mouse::MouseButton;
+    use ashpd::desktop::Session;
+    use ashpd::desktop::remote_desktop::{
+        Axis, DeviceType, KeyState, RemoteDesktop, SelectDevicesOptions,
+    };
+    use ashpd::desktop::screencast::{Screencast, SelectSourcesOptions, SourceType};
+    use std::io;
+    use tokio::runtime::Runtime;
+    use tracing::info;
+
+    // evdev button codes, which the portal uses.
+    const BTN_LEFT: i32 = 0x110;
+    const BTN_RIGHT: i32 = 0x111;
+    const BTN_MIDDLE: i32 = 0x112;
+
+    /// One shared monitor. Absolute positions are sent relative to the stream they fall in.
+    struct Monitor {
+        node: u32,
+        x: i32,
+        y: i32,
+        width: i32,
+        height: i32,
+    }
+
+    /// Pointer injected through the xdg-desktop-portal RemoteDesktop interface, which every
+    /// major Wayland compositor implements. Starting it asks the user for permission once.
+    pub struct PortalPointer {
+        /// The portal API is async; the worker thread drives it through its own runtime.
+        rt: Runtime,
+        proxy: RemoteDesktop,
+        session: Session<RemoteDesktop>,
+        monitors: Vec<Monitor>,
+    }
+
+    impl PortalPointer {
+        pub fn new() -> io::Result<Self> {
+            let rt = tokio::runtime::Builder::new_multi_thread()
+                .worker_threads(1)
+                .enable_all()
+                .build()?;
+            let (proxy, session, monitors) = rt.block_on(start()).map_err(io::Error::other)?;
+            if monitors.is_empty() {
+                return Err(io::Error::other("the portal shared no monitors"));
+            }
+            info!("🖥️  Remote desktop portal session started");
+            Ok(Self {
+                rt,
+                proxy,
+                session,
+                monitors,
+            })
+        }
+
+        /// The stream containing a desktop position, or the nearest one, with the position
+        /// made relative to it.
+        fn locate(&self, x: f64, y: f64) -> (u32, f64, f64) {
+            let local = |m: &Monitor| {
+                let lx = (x - f64::from(m.x)).clamp(0.0, f64::from(m.width - 1));
+                let ly = (y - f64::from(m.y)).clamp(0.0, f64::from(m.height - 1));
+                (lx, ly)
+            };
+            let distance = |m: &Monitor| {
+                let (lx, ly) = local(m);
+                (x - f64::from(m.x) - lx).hypot(y - f64::from(m.y) - ly)
+            };
+            let m = self
+                .monitors
+                .iter()
+                .min_by(|a, b| distance(a).total_cmp(&distance(b)))
+                .expect("at least one monitor");
+            let (lx, ly) = local(m);
+            (m.node, lx, ly)
+        }
+    }
+
+    async fn start() -> ashpd::Result<(RemoteDesktop, Session<RemoteDesktop>, Vec<Monitor>)> {
+        let proxy = RemoteDesktop::new().await?;
+        let screencast = Screencast::new().await?;
+        let session = proxy.create_session(Default::default()).await?;
+        proxy
+            .select_devices(
+                &session,
+                SelectDevicesOptions::default().set_devices(Some(DeviceType::Pointer.into())),
+            )
+            .await?;
+        // Absolute moves are addressed to screen-cast streams, so every monitor is shared.
+        screencast
+            .select_sources(
+                &session,
+                SelectSourcesOptions::default()
+                    .set_sources(Some(SourceType::Monitor.into()))
+                    .set_multiple(true),
+            )
+            .await?;
+        let selected = proxy
+            .start(&session, None, Default::default())
+            .await?
+            .response()?;
+        let monitors = selected
+            .streams()
+            .iter()
+            .map(|s| {
+                let (x, y) = s.position().unwrap_or((0, 0));
+                let (width, height) = s.size().unwrap_or((1, 1));
+                Monitor {
+                    node: s.pipe_wire_node_id(),
+                    x,
+                    y,
+                    width: width.max(1),
+                    height: height.max(1),
+                }
+            })
+            .collect();
+        Ok((proxy, session, monitors))
+    }
+
+    impl PointerBackend for PortalPointer {
+        fn kind(&self) -> BackendKind {
+            BackendKind::Portal
+        }
+
+        fn move_abs(&mut self, x: f64, y: f64) -> io::Result<()> {
+            let (node, x, y) = self.locate(x, y);
+            self.rt
+                .block_on(self.proxy.notify_pointer_motion_absolute(
+                    &self.session,
+                    node,
+                    x,
+                    y,
+                    Default::default(),
+                ))
+                .map_err(io::Error::other)
+        }
+
+        fn move_rel(&mut self, dx: i32, dy: i32) -> io::Result<()> {
+            self.rt
+                .block_on(self.proxy.notify_pointer_motion(
+                    &self.session,
+                    dx.into(),
+                    dy.into(),
+                    Default::default(),
+                ))
+                .map_err(io::Error::other)
+        }
+
+        fn button(&mut self, button: MouseButton, pressed: bool) -> io::Result<()> {
+            let code = match button {
+                MouseButton::Left => BTN_LEFT,
+                MouseButton::Right => BTN_RIGHT,
+                MouseButton::Middle => BTN_MIDDLE,
+            };
+            let state = if pressed {
+                KeyState::Pressed
+            } else {
+                KeyState::Released
+            };
+            self.rt
+                .block_on(self.proxy.notify_pointer_button(
+                    &self.session,
+                    code,
+                    state,
+                    Default::default(),
+                ))
+                .map_err(io::Error::other)
+        }
+
+        fn scroll(&mut self, notches_x: i32, notches_y: i32) -> io::Result<()> {
+            for (axis, steps) in [(Axis::Horizontal, notches_x), (Axis::Vertical, notches_y)] {
+                if steps != 0 {
+                    self.rt
+                        .block_on(self.proxy.notify_pointer_axis_discrete(
+                            &self.session,
+                            axis,
+                            steps,
+                            Default::default(),
+                        ))
+                        .map_err(io::Error::other)?;
+                }
+            }
+            Ok(())
+        }
+
+        fn resize(&mut self, _desktop_w: u16, _desktop_h: u16) -> io::Result<()> {
+            // Streams carry their own geometry; a new layout would need a new session.
+            Ok(())
+        }
+    }
+
+    impl Drop for PortalPointer {
+        fn drop(&mut self) {
+            let _ = self.rt.block_on(self.session.close());
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod windows {
+    use super::{BackendKind, PointerBackend};
+    use crate::mouse::MouseButton;
+    use std::io;
+    use windows_sys::Win32::UI::Input::KeyboardAndMouse::{
+        INPUT, INPUT_MOUSE, MOUSE_EVENT_FLAGS, MOUSEEVENTF_ABSOLUTE, MOUSEEVENTF_HWHEEL,
+        MOUSEEVENTF_LEFTDOWN, MOUSEEVENTF_LEFTUP, MOUSEEVENTF_MIDDLEDOWN, MOUSEEVENTF_MIDDLEUP,
+        MOUSEEVENTF_MOVE, MOUSEEVENTF_MOVE_NOCOALESCE, MOUSEEVENTF_RIGHTDOWN, MOUSEEVENTF_RIGHTUP,
+        MOUSEEVENTF_VIRTUALDESK, MOUSEEVENTF_WHEEL, SendInput,
+    };
+    use windows_sys::Win32::UI::WindowsAndMessaging::{
+        GetSystemMetrics, SM_CXVIRTUALSCREEN, SM_CYVIRTUALSCREEN, SM_XVIRTUALSCREEN,
+        SM_YVIRTUALSCREEN, WHEEL_DELTA,
+    };
+
+    /// Absolute moves are normalized to 0..=65535 across the whole virtual desktop.
+    const NORMALIZED_MAX: f64 = 65535.0;
+
+    /// Mouse input injected with `SendInput` directly. Absolute moves keep their fraction of a
+    /// pixel in the normalized coordinates, which raw-input and pen-aware apps see, and span
+    /// every monitor in physical pixels since the process is DPI aware.
+    pub struct SendInputPointer;
+
+    fn send(dx: i32, dy: i32, data: i32, flags: MOUSE_EVENT_FLAGS) -> io::Result<()> {
+        let mut input = INPUT {
+            r#type: INPUT_MOUSE,
+            ..Default::default()
+        };
+        input.Anonymous.mi.dx = dx;
+        input.Anonymous.mi.dy = dy;
+        // Wheel deltas are signed but travel in an unsigned field.
+        input.Anonymous.mi.mouseData = data as u32;
+        input.Anonymous.mi.dwFlags = flags;
+        // SAFETY: `input` is fully initialized and outlives the call.
+        if unsafe { SendInput(1, &input, size_of::<INPUT>() as i32) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Map a desktop position onto the normalized range over `[origin, origin + extent)`.
+    fn normalize(pos: f64, origin: i32, extent: i32) -> i32 {
+        let extent = f64::from(extent.max(2) - 1);
+        ((pos - f64::from(origin)) / extent * NORMALIZED_MAX)
+            .round()
+            .clamp(0.0, NORMALIZED_MAX) as i32
+    }
+
+    impl PointerBackend for SendInputPointer {
+        fn kind(&self) -> BackendKind {
+            BackendKind::SendInput
+        }
+
+        fn move_abs(&mut self, x: f64, y: f64) -> io::Result<()> {
+            // Read fresh each time, so monitor changes need no bookkeeping.
+            // SAFETY: plain FFI calls without pointers.
+            let (left, top, width, height) = unsafe {
+                (
+                    GetSystemMetrics(SM_XVIRTUALSCREEN),
+                    GetSystemMetrics(SM_YVIRTUALSCREEN),
+                    GetSystemMetrics(SM_CXVIRTUALSCREEN),
+                    GetSystemMetrics(SM_CYVIRTUALSCREEN),
+                )
+            };
+            send(
+                normalize(x, left, width),
+                normalize(y, top, height),
+                0,
+                MOUSEEVENTF_MOVE
+                    | MOUSEEVENTF_ABSOLUTE
+                    | MOUSEEVENTF_VIRTUALDESK
+                    | MOUSEEVENTF_MOVE_NOCOALESCE,
+            )
+        }
+
+        fn move_rel(&mut self, dx: i32, dy: i32) -> io::Result<()> {
+            send(dx, dy, 0, MOUSEEVENTF_MOVE | MOUSEEVENTF_MOVE_NOCOALESCE)
+        }
+
+        fn button(&mut self, button: MouseButton, pressed: bool) -> io::Result<()> {
+            let flags = match (button, pressed) {
+                (MouseButton::Left, true) => MOUSEEVENTF_LEFTDOWN,
+                (MouseButton::Left, false) => MOUSEEVENTF_LEFTUP,
+                (MouseButton::Right, true) => MOUSEEVENTF_RIGHTDOWN,
+                (MouseButton::Right, false) => MOUSEEVENTF_RIGHTUP,
+                (MouseButton::Middle, true) => MOUSEEVENTF_MIDDLEDOWN,
+                (MouseButton::Middle, false) => MOUSEEVENTF_MIDDLEUP,
+            };
+            send(0, 0, 0, flags)
+        }
+
+        fn scroll(&mut self, notches_x: i32, notches_y: i32) -> io::Result<()> {
+            let delta = WHEEL_DELTA as i32;
+            if notches_x != 0 {
+                send(0, 0, notches_x * delta, MOUSEEVENTF_HWHEEL)?;
+            }
+            // Positive wheel data scrolls up.
+            if notches_y != 0 {
+                send(0, 0, -notches_y * delta, MOUSEEVENTF_WHEEL)?;
+            }
+            Ok(())
+        }
+
+        fn resize(&mut self, _desktop_w: u16, _desktop_h: u16) -> io::Result<()> {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod macos {
+    use super::{BackendKind, PointerBackend};
+    use crate::mouse::{HeldButtons, MouseButton};
+    use core_graphics::event::{
+        CGEvent, CGEventTapLocation, CGEventType, CGMouseButton, EventField, ScrollEventUnit,
+    };
+    use core_graphics::event_source::{CGEventSource, CGEventSourceStateID};
+    use core_graphics::geometry::CGPoint;
+    use std::io;
+    use std::time::{Duration, Instant};
+
+    /// Presses of one button this close together make a double (or triple) click.
+    const MULTI_CLICK_TIME: Duration = Duration::from_millis(500);
+
+    #[link(name = "ApplicationServices", kind = "framework")]
+    unsafe extern "C" {
+        fn AXIsProcessTrusted() -> bool;
+    }
+
+    /// Whether the user has granted Accessibility access. Without it, posted events are
+    /// silently dropped.
+    pub fn is_trusted() -> bool {
+        // SAFETY: takes no arguments and only reads the permission state.
+        unsafe { AXIsProcessTrusted() }
+    }
+
+    /// Mouse input posted as Quartz events at the HID level, in points like every other
+    /// macOS coordinate.
+    pub struct CgEventPointer {
+        source: CGEventSource,
+        held: HeldButtons,
+        /// The last press, for the click count macOS needs to see double clicks.
+        last_press: Option<(MouseButton, Instant, i64)>,
+    }
+
+    fn failed() -> io::Error {
+        io::Error::other("failed to create a CGEvent")
+    }
+
+    fn cg_button(button: MouseButton) -> CGMouseButton {
+        match button {
+            MouseButton::Left => CGMouseButton::Left,
+            MouseButton::Right => CGMouseButton::Right,
+            MouseButton::Middle => CGMouseButton::Center,
+        }
+    }
+
+    impl CgEventPointer {
+        pub fn new() -> io::Result<Self> {
+            let source = CGEventSource::new(CGEventSourceStateID::HIDSystemState)
+                .map_err(|()| io::Error::other("failed to create a CGEvent source"))?;
+            Ok(Self {
+                source,
+                held: HeldButtons::default(),
+                last_press: None,
+            })
+        }
+
+        fn location(&self) -> io::Result<CGPoint> {
+            Ok(CGEvent::new(self.source.clone())
+                .map_err(|()| failed())?
+                .location())
+        }
+
+        /// A move while a button is held is a drag, or apps never see the selection grow.
+        fn motion(&self, pos: CGPoint) -> io::Result<CGEvent> {
+            let (kind, button) = if self.held.contains(MouseButton::Left) {
+                (CGEventType::LeftMouseDragged, MouseButton::Left)
+            } else if self.held.contains(MouseButton::Right) {
+                (CGEventType::RightMouseDragged, MouseButton::Right)
+            } else if self.held.contains(MouseButton::Middle) {
+                (CGEventType::OtherMouseDragged, MouseButton::Middle)
+            } else {
+                (CGEventType::MouseMoved, MouseButton::Left)
+            };
+            CGEvent::new_mouse_event(self.source.clone(), kind, pos, cg_button(button))
+                .map_err(|()| failed())
+        }
+    }
+
+    impl PointerBackend for CgEventPointer {
+        fn kind(&self) -> BackendKind {
+            BackendKind::CgEvent
+        }
+
+        fn move_abs(&mut self, x: f64, y: f64) -> io::Result<()> {
+            self.motion(CGPoint::new(x, y))?
+                .post(CGEventTapLocation::HID);
+            Ok(())
+        }
+
+        fn move_rel(&mut self, dx: i32, dy: i32) -> io::Result<()> {
+            let pos = self.location()?;
+            let event = self.motion(CGPoint::new(pos.x + f64::from(dx), pos.y + f64::from(dy)))?;
+            // Games and 3D apps read the deltas rather than the position.
+            event.set_integer_value_field(EventField::MOUSE_EVENT_DELTA_X, dx.into());
+            event.set_integer_value_field(EventField::MOUSE_EVENT_DELTA_Y, dy.into());
+            event.post(CGEventTapLocation::HID);
+            Ok(())
+        }
+
+        fn button(&mut self, button: MouseButton, pressed: bool) -> io::Result<()> {
+            let kind = match (button, pressed) {
+                (MouseButton::Left, true) => CGEventType::LeftMouseDown,
+                (MouseButton::Left, false) => CGEventType::LeftMouseUp,
+                (MouseButton::Right, true) => CGEventType::RightMouseDown,
+                (MouseButton::Right, false) => CGEventType::RightMouseUp,
+                (MouseButton::Middle, true) => CGEventType::OtherMouseDown,
+                (MouseButton::Middle, false) => CGEventType::OtherMouseUp,
+            };
+            let now = Instant::now();
+            let clicks = match self.last_press {
+                Some((b, _, n)) if !pressed && b == button => n,
+                Some((b, at, n)) if b == button && now.duration_since(at) <= MULTI_CLICK_TIME => {
+                    n + 1
+                }
+                _ => 1,
+            };
+            if pressed {
+                self.last_press = Some((button, now, clicks));
+                self.held.insert(button);
+            } else {
+                self.held.remove(button);
+            }
+            let event = CGEvent::new_mouse_event(
+                self.source.clone(),
+                kind,
+                self.location()?,
+                cg_button(button),
+            )
+            .map_err(|()| failed())?;
+            event.set_integer_value_field(EventField::MOUSE_EVENT_CLICK_STATE, clicks);
+            event.post(CGEventTapLocation::HID);
+            Ok(())
+        }
+
+        fn scroll(&mut self, notches_x: i32, notches_y: i32) -> io::Result<()> {
+            // Quartz wheels count up and left as positive.
+            let event = CGEvent::new_scroll_event(
+                self.source.clone(),
+                ScrollEventUnit::LINE,
+                2,
+                -notches_y,
+                -notches_x,
+                0,
+            )
+            .map_err(|()| failed())?;
+            event.post(CGEventTapLocation::HID);
+            Ok(())
+        }
+
+        fn resize(&mut self, _desktop_w: u16, _desktop_h: u16) -> io::Result<()> {
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,33 @@
+use tracing::{info, warn};
+
+/// Longest URL accepted; real links are far shorter and this bounds what reaches the shell.
+pub const MAX_URL_LEN: usize = 2048;
+
+/// Accept only `http`/`https` links with a host and no whitespace or control characters,
+/// so a client can't launch `file:`, custom-scheme handlers or anything else via the OS.
+pub fn validate_url(url: &str) -> Option<&str> {
+    if url.len() > MAX_URL_LEN || url.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        return None;
+    }
+    let (scheme, rest) = url.split_once("://")?;
+    if !scheme.eq_ignore_ascii_case("http") && !scheme.eq_ignore_ascii_case("https") {
+        return None;
+    }
+    let host = rest.split(['/', '?', '#']).next()?;
+    if host.is_empty() || host.starts_with(['@', ':']) {
+        return None;
+    }
+    Some(url)
+}
+
+/// Open a validated URL in the desktop's default browser without waiting for it.
+pub fn open_url(url: &str) {
+    let Some(url) = validate_url(url) else {
+        warn!("Refusing to open URL: {url:?}");
+        return;
+    };
+    match open::that_detached(url) {
+        Ok(()) => info!("🌐 Opened {url}"),
+        Err(err) => warn!("Failed to open {url}: {err}"),
+    }
+}
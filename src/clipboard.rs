@@ -0,0 +1,110 @@
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tracing::warn;
+
+/// Largest clipboard text accepted from a client, in bytes.
+pub const MAX_CLIPBOARD_BYTES: usize = 64 * 1024;
+
+/// How often the desktop clipboard is checked for changes when sync is enabled.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+enum ClipboardCmd {
+    SetText(String),
+}
+
+/// Desktop clipboard access on a dedicated thread.
+///
+/// The OS clipboard handle is kept alive for the whole run: on X11 the owning process
+/// serves the contents to other apps, so dropping it after each write would lose them.
+///
+/// With `watch` on, desktop clipboard text changes are also published to subscribers
+/// (see [`Clipboard::subscribe`]); this is opt-in because it exposes everything copied.
+#[derive(Clone)]
+pub struct Clipboard {
+    tx: Sender<ClipboardCmd>,
+    changes: broadcast::Sender<String>,
+}
+
+impl Clipboard {
+    pub fn new(watch: bool) -> Self {
+        let (tx, rx) = mpsc::channel();
+        let (changes, _) = broadcast::channel(4);
+        let publish = changes.clone();
+        thread::spawn(move || {
+            // Opened on first use and retried after failures (e.g. no display yet).
+            let mut board: Option<arboard::Clipboard> = None;
+            // Last text seen or written, so our own writes and unchanged polls aren't echoed.
+            let mut last: Option<String> = None;
+            // The first poll only takes a baseline: sync pushes changes, not history.
+            let mut primed = false;
+            loop {
+                let cmd = if watch {
+                    match rx.recv_timeout(POLL_INTERVAL) {
+                        Ok(cmd) => Some(cmd),
+                        Err(RecvTimeoutError::Timeout) => None,
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    }
+                } else {
+                    match rx.recv() {
+                        Ok(cmd) => Some(cmd),
+                        Err(_) => break,
+                    }
+                };
+
+                if board.is_none() {
+                    match arboard::Clipboard::new() {
+                        Ok(b) => board = Some(b),
+                        Err(err) => {
+                            warn!("Clipboard unavailable: {err}");
+                            continue;
+                        }
+                    }
+                }
+                let Some(b) = board.as_mut() else {
+                    continue;
+                };
+                match cmd {
+                    Some(ClipboardCmd::SetText(text)) => {
+                        if let Err(err) = b.set_text(text.as_str()) {
+                            warn!("Failed to set clipboard: {err}");
+                        }
+                        last = Some(text);
+                    }
+                    None => {
+                        // Non-text contents (images, files) read as errors and are skipped.
+                        let text = b.get_text().ok();
+                        if !primed {
+                            primed = true;
+                            last = text;
+                            continue;
+                        }
+                        let Some(text) = text else {
+                            continue;
+                        };
+                        if last.as_ref() == Some(&text) {
+                            continue;
+                        }
+                        last = Some(text.clone());
+                        if text.len() <= MAX_CLIPBOARD_BYTES {
+                            let _ = publish.send(text);
+                        }
+                    }
+                }
+            }
+        });
+        Self { tx, changes }
+    }
+
+    /// Receive desktop clipboard text as it changes. Never yields anything unless
+    /// the clipboard was created with `watch`.
+    pub fn subscribe(&self) -> broadcast::Receiver<String> {
+        self.changes.subscribe()
+    }
+
+    /// Replace the desktop clipboard with `text`, so it can be pasted on the desktop.
+    pub fn set_text(&self, text: String) {
+        let _ = self.tx.send(ClipboardCmd::SetText(text));
+    }
+}
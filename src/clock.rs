@@ -0,0 +1,100 @@
+use std::time::{Duration, Instant};
+
+/// Weight of a new offset sample against the running estimate.
+const OFFSET_WEIGHT: f64 = 0.1;
+
+/// Pings whose round trip is this many times the best one seen are too queued up to say
+/// much about the clock, and are skipped.
+const RTT_SLACK: u32 = 2;
+
+/// Weight of a new latency sample against the running estimate.
+const LATENCY_WEIGHT: f64 = 0.1;
+
+/// A client's millisecond clock, lined up with this server's from its pings.
+///
+/// A PING carries the client's time `t` when sent and the round trip it last measured;
+/// taking half of that as the way here, the client's clock read `t + rtt/2` when the ping
+/// arrived. Events the client stamps with the same clock can then be placed in server
+/// time: when they happened rather than when they arrived.
+#[derive(Debug, Default)]
+pub struct ClockSync {
+    /// Server time the offset is counted from.
+    base: Option<Instant>,
+    /// Server milliseconds since `base`, less client milliseconds.
+    offset: Option<f64>,
+    best_rtt: Option<Duration>,
+    /// Newest stamp on a move, so an older move can't pull the cursor back.
+    newest_move: Option<u32>,
+    /// Running estimate of the time from an event to its arrival.
+    latency: Option<Duration>,
+}
+
+impl ClockSync {
+    /// Take a ping sent at client time `t` that arrived at `now`, with the round trip the
+    /// client last measured, if it has one yet.
+    pub fn ping(&mut self, t: u64, rtt: Option<Duration>, now: Instant) {
+        let Some(rtt) = rtt else {
+            return;
+        };
+        let best = *self.best_rtt.get_or_insert(rtt);
+        if rtt < best {
+            self.best_rtt = Some(rtt);
+        } else if rtt > best * RTT_SLACK {
+            return;
+        }
+        let base = *self.base.get_or_insert(now);
+        let sample = ms(now - base) - (t as f64 + ms(rtt) / 2.0);
+        self.offset = Some(match self.offset {
+            Some(offset) => offset + (sample - offset) * OFFSET_WEIGHT,
+            None => sample,
+        });
+    }
+
+    /// When the client's clock read `t` (its low 32 bits), in server time; `None` until a
+    /// ping has lined the clocks up.
+    pub fn local(&self, t: u32, now: Instant) -> Option<Instant> {
+        let (base, offset) = (self.base?, self.offset?);
+        // The full client time nearest to the client's clock now with these low bits.
+        let client_now = ms(now - base) - offset;
+        let full = client_now - f64::from((client_now as i64 as u32).wrapping_sub(t) as i32);
+        let since_base = full + offset;
+        if since_base >= 0.0 {
+            base.checked_add(Duration::from_secs_f64(since_base / 1000.0))
+        } else {
+            base.checked_sub(Duration::from_secs_f64(-since_base / 1000.0))
+        }
+    }
+
+    /// Whether a move stamped `t` is newer than every move before it. Unstamped moves
+    /// always are.
+    pub fn check_move(&mut self, t: Option<u32>) -> bool {
+        let Some(t) = t else {
+            return true;
+        };
+        match self.newest_move {
+            Some(newest) if (t.wrapping_sub(newest) as i32) < 0 => false,
+            _ => {
+                self.newest_move = Some(t);
+                true
+            }
+        }
+    }
+
+    /// Note that an event from `at` arrived at `now`.
+    pub fn arrived(&mut self, at: Instant, now: Instant) {
+        let sample = now.saturating_duration_since(at);
+        self.latency = Some(match self.latency {
+            Some(est) => est.mul_f64(1.0 - LATENCY_WEIGHT) + sample.mul_f64(LATENCY_WEIGHT),
+            None => sample,
+        });
+    }
+
+    /// How long events take from the client's clock to here, once stamped ones came in.
+    pub fn latency(&self) -> Option<Duration> {
+        self.latency
+    }
+}
+
+fn ms(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
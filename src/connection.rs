@@ -1,106 +1,1851 @@
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::io::Write;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
-use tokio::sync::{mpsc, oneshot, Mutex};
-use tracing::warn;
+use crate::audit::{AuditEvent, AuditLog, InputCounts, InputKind};
+use crate::mouse::{MouseController, SessionMapping};
+use crate::qr::PairingLinks;
+use crate::share::FileShares;
+use crate::trust::{self, Proof, TrustStore};
+use crate::{dialog, notify};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast, mpsc, oneshot, watch};
+use tracing::{info, warn};
+
+/// Most controllers connected at once when control is shared.
+pub const MAX_CONTROLLERS: usize = 4;
+
+/// How long a controller can come back with its session token after disconnecting.
+pub const RESUME_WINDOW: Duration = Duration::from_secs(10 * 60);
+
+/// How input from several approved controllers is arbitrated.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Arbitration {
+    /// One controller at a time; others are turned away while it is connected.
+    #[default]
+    Exclusive,
+    /// Every controller drives; whoever sent input last is in control.
+    LastWriter,
+    /// Only the holder of the control token drives; the others ask for it and wait their turn.
+    Token,
+}
+
+impl Arbitration {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "exclusive" => Some(Self::Exclusive),
+            "last-writer" => Some(Self::LastWriter),
+            "token" => Some(Self::Token),
+            _ => None,
+        }
+    }
+}
+
+/// Something sessions must react to, broadcast to all of them.
+#[derive(Debug, Clone, Copy)]
+pub enum SessionEvent {
+    /// `by` asked to take over `target`'s place; the approval prompt is open.
+    TakeoverRequested { target: SocketAddr, by: SocketAddr },
+    /// `target`'s place was handed to another client; its session must end.
+    Evicted { target: SocketAddr },
+    /// The session at `from` was resumed from `to` with its token; `from` is no longer it.
+    Resumed { from: SocketAddr, to: SocketAddr },
+    /// The operator disconnected `target` from the console.
+    Kicked { target: SocketAddr },
+}
+
+/// A session picked back up by [`SessionManager::resume_session`].
+#[derive(Debug)]
+pub struct Resumed {
+    pub claim: Claim,
+    /// Where the session was still connected from, if it hadn't ended.
+    pub from: Option<SocketAddr>,
+}
+
+/// Where a session token's session is, and until when the token is good.
+#[derive(Debug)]
+struct Resumable {
+    addr: SocketAddr,
+    /// `None` while the session is connected.
+    expires: Option<Instant>,
+}
+
+/// One controller's place. A place handed to another client gets a fresh id, so the
+/// previous holder's [`Claim`] can't free it.
+#[derive(Debug)]
+struct Place {
+    id: u64,
+    addr: SocketAddr,
+}
+
+/// How a client is connected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    WebSocket,
+    Udp,
+    /// WebTransport, over HTTP/3.
+    #[serde(rename = "webtransport")]
+    #[cfg_attr(not(feature = "webtransport"), allow(dead_code))]
+    Http3,
+    /// QUIC, from a native client.
+    #[cfg_attr(not(feature = "quic"), allow(dead_code))]
+    Quic,
+}
+
+impl Transport {
+    pub fn name(self) -> &'static str {
+        match self {
+            Transport::WebSocket => "WebSocket",
+            Transport::Udp => "UDP",
+            Transport::Http3 => "WebTransport",
+            Transport::Quic => "QUIC",
+        }
+    }
+}
+
+/// Longest device name or platform kept, in characters.
+const MAX_DEVICE_LABEL: usize = 64;
+
+/// What a client says about itself when it connects.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Device {
+    /// ID it generated for itself, for the trusted-device store. Never shown.
+    #[serde(skip)]
+    pub id: Option<String>,
+    /// A signed handshake sent instead of an ID, until it is checked.
+    #[serde(skip)]
+    pub proof: Option<Proof>,
+    /// `id` is the public key the handshake was checked against.
+    #[serde(skip)]
+    pub signed: bool,
+    /// The name the user gave it, e.g. `Dana's phone`.
+    pub name: Option<String>,
+    /// What it is, e.g. `iPhone 15`.
+    pub platform: Option<String>,
+    /// The pairing code it was given, for `--pairing pin`. Never shown.
+    #[serde(skip)]
+    pub pin: Option<String>,
+    /// The token from a scanned pairing QR code. Never shown.
+    #[serde(skip)]
+    pub pair: Option<String>,
+}
+
+impl Device {
+    /// `id` is either an ID or a signed handshake. Drops an ID that can't name a device,
+    /// and control characters (which could rewrite the terminal) from the name and
+    /// platform.
+    pub fn new(id: Option<&str>, name: Option<&str>, platform: Option<&str>) -> Self {
+        Self {
+            id: id.filter(|id| trust::is_valid_id(id)).map(str::to_owned),
+            proof: id.and_then(Proof::parse),
+            signed: false,
+            name: name.and_then(clean_label),
+            platform: platform.and_then(clean_label),
+            pin: None,
+            pair: None,
+        }
+    }
+
+    pub fn with_pin(mut self, pin: Option<&str>) -> Self {
+        self.pin = pin.map(str::to_owned);
+        self
+    }
+
+    pub fn with_pair(mut self, pair: Option<&str>) -> Self {
+        self.pair = pair.map(str::to_owned);
+        self
+    }
+
+    /// `iPhone 15 'Dana's phone'`, or `None` if the client didn't say.
+    pub fn label(&self) -> Option<String> {
+        match (&self.platform, &self.name) {
+            (Some(platform), Some(name)) => Some(format!("{platform} '{name}'")),
+            (Some(platform), None) => Some(platform.clone()),
+            (None, Some(name)) => Some(format!("'{name}'")),
+            (None, None) => None,
+        }
+    }
+
+    /// `iPhone 15 'Dana's phone' (192.168.1.5:51234)`, or just the address.
+    pub fn describe(&self, addr: SocketAddr) -> String {
+        match self.label() {
+            Some(label) => format!("{label} ({addr})"),
+            None => addr.to_string(),
+        }
+    }
+}
+
+fn clean_label(s: &str) -> Option<String> {
+    let s: String = s
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_DEVICE_LABEL)
+        .collect();
+    let s = s.trim();
+    (!s.is_empty()).then(|| s.to_owned())
+}
+
+/// A connected client, as the console lists it.
+#[derive(Debug, Clone)]
+pub struct ClientInfo {
+    pub addr: SocketAddr,
+    pub transport: Transport,
+    pub role: Role,
+    pub device: Device,
+    pub since: Instant,
+    /// Round trip the client last reported with its ping.
+    pub rtt: Option<Duration>,
+    /// Time from the client stamping an event to its arrival, once it stamps them.
+    pub latency: Option<Duration>,
+}
+
+#[derive(Debug)]
+struct Listed {
+    id: u64,
+    info: ClientInfo,
+}
+
+/// Tracks the connected controllers and decides whose input reaches the desktop.
+#[derive(Debug)]
+pub struct SessionManager {
+    policy: Arbitration,
+    /// Controllers connected or waiting for approval.
+    claimed: Mutex<Vec<Place>>,
+    next_place: AtomicU64,
+    /// Emergency stop: while set, every session is dropped and new ones are refused.
+    halted: watch::Sender<bool>,
+    /// Set once the server is shutting down, so the servers can say goodbye to their clients.
+    shutdown: watch::Sender<bool>,
+    /// The approved controller in control, followed by viewers and the other controllers.
+    controller: watch::Sender<Option<SocketAddr>>,
+    /// Controllers that asked for the token, oldest first.
+    waiting: Mutex<VecDeque<SocketAddr>>,
+    /// The controller whose mapping settings the input worker has.
+    mapped: Mutex<Option<SocketAddr>>,
+    /// Session tokens handed out on approval. Locked before `claimed` when both are needed.
+    tokens: Mutex<HashMap<u128, Resumable>>,
+    /// Approved clients of every role, for the console.
+    clients: Mutex<Vec<Listed>>,
+    /// Bumped whenever a client leaves the list.
+    left: watch::Sender<()>,
+    next_listing: AtomicU64,
+    events: broadcast::Sender<SessionEvent>,
+    audit: Arc<AuditLog>,
+}
+
+/// A controller's place, held from the connection attempt until the session ends.
+/// Dropping it frees the place, so no return path can leak it.
+#[derive(Debug)]
+#[must_use]
+pub struct Claim {
+    sessions: Arc<SessionManager>,
+    id: u64,
+    released: bool,
+}
+
+impl Claim {
+    /// Free the place now. Returns true if it was the last controller's, so the mapping
+    /// settings it left behind can be reset.
+    pub fn release(mut self) -> bool {
+        self.released = true;
+        self.sessions.release(self.id)
+    }
+}
+
+impl Drop for Claim {
+    fn drop(&mut self) {
+        if !self.released {
+            self.sessions.release(self.id);
+        }
+    }
+}
+
+/// A client's entry in the console's list, held while its session lasts. Dropping it
+/// takes the entry out.
+#[derive(Debug)]
+#[must_use]
+pub struct Listing {
+    sessions: Arc<SessionManager>,
+    id: u64,
+    /// Input the client sent while in control, for the audit log (`--audit-level inputs`).
+    inputs: Option<InputCounts>,
+}
+
+impl Listing {
+    /// Count an input message the client sent while in control.
+    pub fn count(&mut self, kind: InputKind) {
+        if let Some(inputs) = &mut self.inputs {
+            inputs.add(kind);
+        }
+    }
+}
+
+impl Drop for Listing {
+    fn drop(&mut self) {
+        let mut clients = self.sessions.clients.lock().unwrap();
+        let Some(index) = clients.iter().position(|c| c.id == self.id) else {
+            return;
+        };
+        let info = clients.remove(index).info;
+        drop(clients);
+        self.sessions.left.send_replace(());
+        self.sessions.audit.record(AuditEvent::Disconnected {
+            addr: info.addr,
+            transport: info.transport,
+            role: info.role,
+            device: (&info.device).into(),
+            seconds: info.since.elapsed().as_secs(),
+            inputs: self.inputs,
+        });
+    }
+}
+
+/// What an approved client may do.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// Drives the pointer and keyboard, as the arbitration policy allows.
+    #[default]
+    Controller,
+    /// Watches the cursor and who is connected; its input is ignored.
+    Viewer,
+}
+
+impl SessionManager {
+    pub fn new(policy: Arbitration, audit: Arc<AuditLog>) -> Self {
+        Self {
+            policy,
+            claimed: Mutex::new(Vec::new()),
+            next_place: AtomicU64::new(0),
+            halted: watch::Sender::new(false),
+            shutdown: watch::Sender::new(false),
+            controller: watch::Sender::new(None),
+            waiting: Mutex::new(VecDeque::new()),
+            mapped: Mutex::new(None),
+            tokens: Mutex::new(HashMap::new()),
+            clients: Mutex::new(Vec::new()),
+            left: watch::Sender::new(()),
+            next_listing: AtomicU64::new(0),
+            events: broadcast::Sender::new(16),
+            audit,
+        }
+    }
+
+    /// Whether several controllers may be connected at once.
+    pub fn is_shared(&self) -> bool {
+        self.policy != Arbitration::Exclusive
+    }
+
+    /// Cut off remote input: every session ends, session tokens are revoked and new
+    /// connections are rejected until [`SessionManager::resume`]. Callable from any thread.
+    pub fn halt(&self) {
+        self.tokens.lock().unwrap().clear();
+        if !self.halted.send_replace(true) {
+            warn!(
+                "🛑 Emergency stop: remote input cut off; type `resume` to accept connections again"
+            );
+        }
+    }
+
+    pub fn resume(&self) {
+        if self.halted.send_replace(false) {
+            info!("Accepting connections again");
+        }
+    }
+
+    pub fn is_halted(&self) -> bool {
+        *self.halted.borrow()
+    }
+
+    /// Resolves once input is halted (right away if it already is).
+    pub async fn halted(&self) {
+        let mut rx = self.halted.subscribe();
+        let _ = rx.wait_for(|halted| *halted).await;
+    }
+
+    /// Tell the servers to end every session: the server is shutting down.
+    pub fn shut_down(&self) {
+        self.shutdown.send_replace(true);
+    }
+
+    /// Resolves once the server is shutting down.
+    pub async fn shutting_down(&self) {
+        let mut rx = self.shutdown.subscribe();
+        let _ = rx.wait_for(|shutdown| *shutdown).await;
+    }
+
+    /// Resolves once no client is listed any more: every session has ended and let go of
+    /// its input.
+    pub async fn all_gone(&self) {
+        let mut left = self.left.subscribe();
+        while !self.clients.lock().unwrap().is_empty() {
+            if left.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Take a place for a new controller, if there is room.
+    pub fn claim(self: &Arc<Self>, addr: SocketAddr) -> Option<Claim> {
+        let mut claimed = self.claimed.lock().unwrap();
+        let limit = if self.is_shared() { MAX_CONTROLLERS } else { 1 };
+        if claimed.len() >= limit || claimed.iter().any(|p| p.addr == addr) {
+            return None;
+        }
+        let claim = self.new_claim();
+        claimed.push(Place { id: claim.id, addr });
+        Some(claim)
+    }
+
+    fn new_claim(self: &Arc<Self>) -> Claim {
+        Claim {
+            sessions: self.clone(),
+            id: self.next_place.fetch_add(1, Ordering::Relaxed),
+            released: false,
+        }
+    }
+
+    /// Mark a claimed controller as approved. It takes control if nobody has it.
+    pub fn activate(&self, addr: SocketAddr) {
+        self.controller.send_if_modified(|current| {
+            if current.is_some() {
+                return false;
+            }
+            *current = Some(addr);
+            true
+        });
+    }
+
+    /// The controller in control now and whenever it changes.
+    pub fn subscribe_controller(&self) -> watch::Receiver<Option<SocketAddr>> {
+        self.controller.subscribe()
+    }
+
+    pub fn subscribe_events(&self) -> broadcast::Receiver<SessionEvent> {
+        self.events.subscribe()
+    }
+
+    pub fn in_control(&self, addr: SocketAddr) -> bool {
+        *self.controller.borrow() == Some(addr)
+    }
+
+    /// Whether input from `addr` may reach the desktop now. Under last-writer-wins this
+    /// hands it control. The first input after control passed to `addr` puts its
+    /// `mapping` back, replacing the previous controller's.
+    pub fn take_turn(
+        &self,
+        addr: SocketAddr,
+        mouse: &MouseController,
+        mapping: &SessionMapping,
+    ) -> bool {
+        let turn = match self.policy {
+            Arbitration::LastWriter if !self.in_control(addr) => {
+                // A session that was just taken over may still be draining its input.
+                if !self.claimed.lock().unwrap().iter().any(|p| p.addr == addr) {
+                    return false;
+                }
+                self.controller.send_replace(Some(addr));
+                true
+            }
+            _ => self.in_control(addr),
+        };
+        if turn && self.mapped.lock().unwrap().replace(addr) != Some(addr) {
+            mouse.apply_mapping(mapping);
+        }
+        turn
+    }
+
+    /// Whether the input worker has `addr`'s mapping settings, so a change to them can be
+    /// applied at once rather than when it next takes its turn.
+    pub fn has_mapping(&self, addr: SocketAddr) -> bool {
+        *self.mapped.lock().unwrap() == Some(addr)
+    }
+
+    /// The client a takeover would replace: the one in control.
+    pub fn takeover_target(&self) -> Option<SocketAddr> {
+        *self.controller.borrow()
+    }
+
+    /// Tell `target` that `by` wants its place.
+    pub fn announce_takeover(&self, target: SocketAddr, by: SocketAddr) {
+        let _ = self
+            .events
+            .send(SessionEvent::TakeoverRequested { target, by });
+    }
+
+    /// Hand `target`'s place to `by` and tell `target` to leave. `by` still has to be
+    /// activated. Returns `None` if `target` has already gone.
+    pub fn take_over(self: &Arc<Self>, target: SocketAddr, by: SocketAddr) -> Option<Claim> {
+        let mut claimed = self.claimed.lock().unwrap();
+        let place = claimed.iter_mut().find(|p| p.addr == target)?;
+        let claim = self.new_claim();
+        *place = Place {
+            id: claim.id,
+            addr: by,
+        };
+        self.waiting.lock().unwrap().retain(|a| *a != target);
+        self.controller
+            .send_if_modified(|current| *current == Some(target) && current.take().is_some());
+        drop(claimed);
+        // Replaced clients don't get to come back without asking.
+        self.tokens.lock().unwrap().retain(|_, r| r.addr != target);
+        let _ = self.events.send(SessionEvent::Evicted { target });
+        Some(claim)
+    }
+
+    /// Hand out a token the approved controller at `addr` can resume its session with.
+    pub fn issue_token(&self, addr: SocketAddr) -> u128 {
+        let token = rand::random();
+        let resumable = Resumable {
+            addr,
+            expires: None,
+        };
+        self.tokens.lock().unwrap().insert(token, resumable);
+        token
+    }
+
+    /// Pick a session back up from `addr` with its token, skipping approval: a phone whose
+    /// source port changed, or that reconnects within [`RESUME_WINDOW`] of dropping off.
+    /// `None` for an unknown or lapsed token, or when there is no room.
+    pub fn resume_session(self: &Arc<Self>, token: u128, addr: SocketAddr) -> Option<Resumed> {
+        if self.is_halted() {
+            return None;
+        }
+        let now = Instant::now();
+        let mut tokens = self.tokens.lock().unwrap();
+        tokens.retain(|_, r| r.expires.is_none_or(|t| t > now));
+        let resumable = tokens.get_mut(&token)?;
+
+        if resumable.expires.is_some() {
+            let claim = self.claim(addr)?;
+            *resumable = Resumable {
+                addr,
+                expires: None,
+            };
+            return Some(Resumed { claim, from: None });
+        }
+
+        let from = resumable.addr;
+        let mut claimed = self.claimed.lock().unwrap();
+        let place = claimed.iter_mut().find(|p| p.addr == from)?;
+        let claim = self.new_claim();
+        *place = Place { id: claim.id, addr };
+        if from != addr {
+            for waiting in self.waiting.lock().unwrap().iter_mut() {
+                if *waiting == from {
+                    *waiting = addr;
+                }
+            }
+            self.controller.send_if_modified(|current| {
+                if *current != Some(from) {
+                    return false;
+                }
+                *current = Some(addr);
+                true
+            });
+            resumable.addr = addr;
+            let _ = self.events.send(SessionEvent::Resumed { from, to: addr });
+        }
+        Some(Resumed {
+            claim,
+            from: Some(from),
+        })
+    }
+
+    /// List an approved client for the console until the returned entry is dropped.
+    pub fn list(
+        self: &Arc<Self>,
+        addr: SocketAddr,
+        transport: Transport,
+        role: Role,
+        device: &Device,
+    ) -> Listing {
+        let id = self.next_listing.fetch_add(1, Ordering::Relaxed);
+        self.audit.record(AuditEvent::Connected {
+            addr,
+            transport,
+            role,
+            device: device.into(),
+        });
+        let info = ClientInfo {
+            addr,
+            transport,
+            role,
+            device: device.clone(),
+            since: Instant::now(),
+            rtt: None,
+            latency: None,
+        };
+        self.clients.lock().unwrap().push(Listed { id, info });
+        Listing {
+            sessions: self.clone(),
+            id,
+            inputs: self.audit.counts_inputs().then(InputCounts::default),
+        }
+    }
+
+    /// Record the round trip time the client at `addr` measured.
+    pub fn report_rtt(&self, addr: SocketAddr, rtt: Duration) {
+        for client in self.clients.lock().unwrap().iter_mut() {
+            if client.info.addr == addr {
+                client.info.rtt = Some(rtt);
+            }
+        }
+    }
+
+    /// Record how long the events of the client at `addr` take to arrive.
+    pub fn report_latency(&self, addr: SocketAddr, latency: Duration) {
+        for client in self.clients.lock().unwrap().iter_mut() {
+            if client.info.addr == addr {
+                client.info.latency = Some(latency);
+            }
+        }
+    }
+
+    /// The connected clients, oldest first.
+    pub fn clients(&self) -> Vec<ClientInfo> {
+        let clients = self.clients.lock().unwrap();
+        clients.iter().map(|c| c.info.clone()).collect()
+    }
+
+    /// Disconnect the client at `addr` and revoke its session token, so it has to be
+    /// approved again. Returns false if no client is connected from there.
+    pub fn kick(&self, addr: SocketAddr) -> bool {
+        if !self
+            .clients
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|c| c.info.addr == addr)
+        {
+            return false;
+        }
+        self.revoke(addr);
+        self.audit.record(AuditEvent::Kicked { addr });
+        let _ = self.events.send(SessionEvent::Kicked { target: addr });
+        true
+    }
+
+    /// Revoke the session token of the client at `addr`, so it can't come back without
+    /// being approved again.
+    pub fn revoke(&self, addr: SocketAddr) {
+        self.tokens.lock().unwrap().retain(|_, r| r.addr != addr);
+    }
+
+    /// Ask for the control token: taken at once if it is free, otherwise queued until the
+    /// holder releases it.
+    pub fn request_control(&self, addr: SocketAddr) {
+        if self.policy != Arbitration::Token {
+            return;
+        }
+        let mut waiting = self.waiting.lock().unwrap();
+        let taken = self.controller.send_if_modified(|current| {
+            if current.is_some() {
+                return false;
+            }
+            *current = Some(addr);
+            true
+        });
+        if !taken && !self.in_control(addr) && !waiting.contains(&addr) {
+            waiting.push_back(addr);
+        }
+    }
+
+    /// Give up the control token (or a queued request for it); the next in line gets it.
+    pub fn release_control(&self, addr: SocketAddr) {
+        if self.policy != Arbitration::Token {
+            return;
+        }
+        let mut waiting = self.waiting.lock().unwrap();
+        waiting.retain(|a| *a != addr);
+        if self.in_control(addr) {
+            self.controller.send_replace(waiting.pop_front());
+        }
+    }
+
+    /// Free place `id`, unless it has been handed to someone else. Returns true if it was
+    /// the last controller's.
+    fn release(&self, id: u64) -> bool {
+        let mut claimed = self.claimed.lock().unwrap();
+        let Some(index) = claimed.iter().position(|p| p.id == id) else {
+            return false;
+        };
+        let addr = claimed.remove(index).addr;
+        self.mapped.lock().unwrap().take_if(|a| *a == addr);
+        if self.policy == Arbitration::Token {
+            self.release_control(addr);
+        } else {
+            self.controller
+                .send_if_modified(|current| *current == Some(addr) && current.take().is_some());
+        }
+        let last = claimed.is_empty();
+        drop(claimed);
+        if let Some(resumable) = self
+            .tokens
+            .lock()
+            .unwrap()
+            .values_mut()
+            .find(|r| r.addr == addr && r.expires.is_none())
+        {
+            resumable.expires = Some(Instant::now() + RESUME_WINDOW);
+        }
+        last
+    }
+}
+
+/// Where the operator answers approval requests.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ApprovalUi {
+    /// A `y/n` prompt in the server terminal.
+    #[default]
+    Terminal,
+    /// A native dialog per request, for a server started without a terminal.
+    Dialog,
+}
+
+impl ApprovalUi {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "terminal" => Some(Self::Terminal),
+            "dialog" => Some(Self::Dialog),
+            _ => None,
+        }
+    }
+}
+
+/// Who may ask to connect at all.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Pairing {
+    /// Anyone who can reach the server.
+    #[default]
+    Off,
+    /// Only clients that send the code shown in the server terminal.
+    Pin,
+}
+
+impl Pairing {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "off" => Some(Self::Off),
+            "pin" => Some(Self::Pin),
+            _ => None,
+        }
+    }
+}
+
+/// Wrong pairing codes, from all addresses together within [`PIN_GUESS_WINDOW`], before
+/// the code is replaced. Each address is locked out after [`MAX_REFUSALS`], so it takes
+/// dozens of addresses to get here, and that many guesses hit one code in ten thousand.
+const MAX_PIN_GUESSES: u32 = 100;
+const PIN_GUESS_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+/// The 6-digit code clients must present under `--pairing pin`.
+#[derive(Debug)]
+pub struct PairingCode {
+    state: Mutex<PinState>,
+}
 
-/// Shared slot to enforce a single active client.
 #[derive(Debug)]
-pub struct ConnectionSlot {
-    inner: Mutex<Option<SocketAddr>>,
+struct PinState {
+    code: String,
+    /// Wrong codes sent since `since`.
+    guesses: u32,
+    since: Instant,
 }
 
-impl ConnectionSlot {
+impl PairingCode {
     pub fn new() -> Self {
         Self {
-            inner: Mutex::new(None),
+            state: Mutex::new(PinState {
+                code: new_pin(),
+                guesses: 0,
+                since: Instant::now(),
+            }),
         }
     }
 
-    /// Try to claim the slot for a new client. Returns true if claimed.
-    pub async fn try_claim(&self, addr: SocketAddr) -> bool {
-        let mut guard = self.inner.lock().await;
-        if guard.is_some() {
+    pub fn code(&self) -> String {
+        self.state.lock().unwrap().code.clone()
+    }
+
+    /// Whether `pin` is the current code. A missing code isn't a guess; after
+    /// [`MAX_PIN_GUESSES`] wrong ones a new code is shown.
+    fn check(&self, pin: Option<&str>) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let Some(pin) = pin else {
             return false;
+        };
+        if same_secret(pin, &state.code) {
+            return true;
+        }
+        if state.since.elapsed() > PIN_GUESS_WINDOW {
+            state.guesses = 0;
+            state.since = Instant::now();
         }
-        *guard = Some(addr);
+        state.guesses += 1;
+        if state.guesses >= MAX_PIN_GUESSES {
+            state.code = new_pin();
+            state.guesses = 0;
+            state.since = Instant::now();
+            warn!(
+                "🔢 Too many wrong pairing codes; the code is now {}",
+                state.code
+            );
+        }
+        false
+    }
+}
+
+fn new_pin() -> String {
+    format!("{:06}", rand::random_range(0..1_000_000))
+}
+
+/// Compare secrets in time that doesn't depend on where they differ.
+pub fn same_secret(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Refusals from one address before it is turned away without asking.
+const MAX_REFUSALS: u32 = 3;
+
+/// How long an address refused too often is turned away, and how long a refusal counts.
+const LOCKOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Addresses whose requests were refused recently, so one client can't keep the operator
+/// busy with prompts.
+#[derive(Debug, Default)]
+pub struct Lockouts {
+    refusals: Mutex<HashMap<IpAddr, Refusals>>,
+    /// Addresses with a request open, which may not open another.
+    asking: Mutex<HashSet<IpAddr>>,
+}
+
+#[derive(Debug)]
+struct Refusals {
+    count: u32,
+    last: Instant,
+    /// Turned away without asking until then.
+    locked_until: Option<Instant>,
+}
+
+impl Lockouts {
+    /// How much longer `ip` is turned away, if it is.
+    fn locked(&self, ip: IpAddr) -> Option<Duration> {
+        let refusals = self.refusals.lock().unwrap();
+        let until = refusals.get(&ip)?.locked_until?;
+        let left = until.saturating_duration_since(Instant::now());
+        (!left.is_zero()).then_some(left)
+    }
+
+    /// Note that a request from `ip` was refused. Returns true if that locked it out.
+    fn refused(&self, ip: IpAddr) -> bool {
+        let mut refusals = self.refusals.lock().unwrap();
+        let now = Instant::now();
+        refusals.retain(|_, r| r.locked_until.unwrap_or(r.last + LOCKOUT) > now);
+        let entry = refusals.entry(ip).or_insert(Refusals {
+            count: 0,
+            last: now,
+            locked_until: None,
+        });
+        entry.count += 1;
+        entry.last = now;
+        if entry.count < MAX_REFUSALS {
+            return false;
+        }
+        entry.count = 0;
+        entry.locked_until = Some(now + LOCKOUT);
         true
     }
 
-    /// Release the slot (called on disconnect).
-    pub async fn release(&self) {
-        let mut guard = self.inner.lock().await;
-        *guard = None;
+    /// Forget the refusals of `ip`, e.g. once it was approved.
+    fn clear(&self, ip: IpAddr) {
+        self.refusals.lock().unwrap().remove(&ip);
+    }
+
+    /// Hold the one request `ip` may have open, until the slot is dropped. `None` if it
+    /// already has one.
+    fn begin(self: &Arc<Self>, ip: IpAddr) -> Option<AskingSlot> {
+        self.asking.lock().unwrap().insert(ip).then(|| AskingSlot {
+            lockouts: self.clone(),
+            ip,
+        })
+    }
+
+    /// The addresses turned away now, with how much longer.
+    pub fn list(&self) -> Vec<(IpAddr, Duration)> {
+        let refusals = self.refusals.lock().unwrap();
+        let now = Instant::now();
+        let mut list: Vec<(IpAddr, Duration)> = refusals
+            .iter()
+            .filter_map(|(ip, r)| Some((*ip, r.locked_until?.checked_duration_since(now)?)))
+            .collect();
+        list.sort();
+        list
+    }
+
+    /// Let `ip`, or every address, ask again. Returns how many were locked out.
+    pub fn unlock(&self, ip: Option<IpAddr>) -> usize {
+        let locked = self.list();
+        let mut refusals = self.refusals.lock().unwrap();
+        let mut count = 0;
+        for (locked_ip, _) in locked {
+            if ip.is_none_or(|ip| ip == locked_ip) {
+                refusals.remove(&locked_ip);
+                count += 1;
+            }
+        }
+        count
     }
 }
 
+/// The request an address has open; see [`Lockouts::begin`].
+struct AskingSlot {
+    lockouts: Arc<Lockouts>,
+    ip: IpAddr,
+}
+
+impl Drop for AskingSlot {
+    fn drop(&mut self) {
+        self.lockouts.asking.lock().unwrap().remove(&self.ip);
+    }
+}
+
+/// An answer given outside the terminal, from a notification, dialog or the approval
+/// page: the approval request's number and the decision.
+pub type Answer = (u32, Decision);
+
+/// A request waiting for approval, as the approval page shows it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Waiting {
+    pub number: u32,
+    pub addr: SocketAddr,
+    pub role: Role,
+    #[serde(flatten)]
+    pub device: Device,
+    /// The connected client an approval would disconnect, for takeovers.
+    pub replaces: Option<SocketAddr>,
+    /// When it was asked, in Unix milliseconds.
+    pub since: i64,
+    /// When it times out, in Unix milliseconds.
+    pub expires: Option<i64>,
+}
+
 #[derive(Debug)]
 pub struct PendingApproval {
     pub ip: SocketAddr,
-    pub respond: oneshot::Sender<bool>,
+    pub role: Role,
+    pub device: Device,
+    /// The connected client an approval would disconnect, for takeovers.
+    pub replaces: Option<SocketAddr>,
+    /// When the prompt gives up and answers [`Decision::TimedOut`].
+    pub deadline: Option<tokio::time::Instant>,
+    pub respond: oneshot::Sender<Decision>,
+}
+
+/// How an approval request ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Decision {
+    Approved,
+    Rejected,
+    /// Nobody answered before the approval timeout, and the default is to deny.
+    TimedOut,
+    /// The client didn't send the pairing code (`--pairing pin`), so nobody was asked.
+    Unpaired,
+    /// The address was refused too often lately, or already has a request open, so nobody
+    /// was asked.
+    Throttled,
 }
 
 #[derive(Clone)]
 pub struct ApprovalBroker {
     auto_approve: bool,
+    /// How long a request waits for an answer; `None` waits as long as it takes.
+    timeout: Option<Duration>,
+    /// Approve instead of deny when the timeout passes.
+    approve_on_timeout: bool,
     tx: mpsc::Sender<PendingApproval>,
+    answers: mpsc::UnboundedSender<Answer>,
+    waiting: Arc<watch::Sender<Vec<Waiting>>>,
+    /// Devices approved before, let in without asking.
+    trusted: Arc<TrustStore>,
+    /// The code clients must send before they are asked about (`--pairing pin`).
+    pairing: Option<Arc<PairingCode>>,
+    /// The pairing QR code on screen, which lets one client in without asking.
+    links: Arc<PairingLinks>,
+    /// Addresses turned away without asking after too many refusals.
+    lockouts: Arc<Lockouts>,
+    /// Trusted devices that went idle too long (`--reapprove-after`), asked about again
+    /// next time.
+    stale: Arc<Mutex<HashSet<String>>>,
+    audit: Arc<AuditLog>,
+}
+
+/// The receiving end of an [`ApprovalBroker`], worked by [`approval_worker`].
+pub struct ApprovalInbox {
+    requests: mpsc::Receiver<PendingApproval>,
+    answered: mpsc::UnboundedReceiver<Answer>,
+    answers: mpsc::UnboundedSender<Answer>,
+    waiting: Arc<watch::Sender<Vec<Waiting>>>,
+    trusted: Arc<TrustStore>,
+    pairing: Option<Arc<PairingCode>>,
+    links: Arc<PairingLinks>,
+    lockouts: Arc<Lockouts>,
 }
 
 impl ApprovalBroker {
-    pub fn new(auto_approve: bool) -> (Self, mpsc::Receiver<PendingApproval>) {
-        let (tx, rx) = mpsc::channel(16);
-        (Self { auto_approve, tx }, rx)
+    pub fn new(
+        auto_approve: bool,
+        timeout: Option<Duration>,
+        approve_on_timeout: bool,
+        trusted: Arc<TrustStore>,
+        pairing: Option<Arc<PairingCode>>,
+        links: Arc<PairingLinks>,
+        audit: Arc<AuditLog>,
+    ) -> (Self, ApprovalInbox) {
+        let (tx, requests) = mpsc::channel(16);
+        let (answers, answered) = mpsc::unbounded_channel();
+        let waiting = Arc::new(watch::Sender::new(Vec::new()));
+        let lockouts = Arc::new(Lockouts::default());
+        let broker = Self {
+            auto_approve,
+            timeout,
+            approve_on_timeout,
+            tx,
+            answers: answers.clone(),
+            waiting: waiting.clone(),
+            trusted: trusted.clone(),
+            pairing: pairing.clone(),
+            links: links.clone(),
+            lockouts: lockouts.clone(),
+            stale: Arc::default(),
+            audit,
+        };
+        let inbox = ApprovalInbox {
+            requests,
+            answered,
+            answers,
+            waiting,
+            trusted,
+            pairing,
+            links,
+            lockouts,
+        };
+        (broker, inbox)
+    }
+
+    /// The requests waiting for approval now and whenever that changes.
+    pub fn subscribe_waiting(&self) -> watch::Receiver<Vec<Waiting>> {
+        self.waiting.subscribe()
+    }
+
+    /// Answer waiting request `number` from outside the terminal. Ignored if it has
+    /// already been answered.
+    pub fn answer(&self, number: u32, decision: Decision) {
+        let _ = self.answers.send((number, decision));
+    }
+
+    /// Have `device` asked about the next time it connects, even if it is trusted.
+    pub fn require_approval(&self, device: &Device) {
+        let id = device
+            .id
+            .clone()
+            .or_else(|| device.proof.as_ref().map(Proof::id));
+        if let Some(id) = id {
+            self.stale.lock().unwrap().insert(id);
+        }
+    }
+
+    /// Enqueue approval and wait for CLI decision. A trusted `device`, or one that scanned
+    /// the pairing QR code, is let in without asking, and an approved one is trusted from
+    /// now on.
+    pub async fn request_approval(&self, ip: SocketAddr, role: Role, device: &Device) -> Decision {
+        let Some(device) = &self.authenticate(ip, device) else {
+            return self.decided(ip, role, device, Decision::Rejected, "signature");
+        };
+        if let Some(pair) = &device.pair
+            && self.links.redeem(pair)
+        {
+            info!("📷 Paired by QR code: {}", device.describe(ip));
+            if let Some(id) = &device.id {
+                self.stale.lock().unwrap().remove(id);
+                self.trusted.remember(id, role, ip.ip(), device);
+            }
+            return self.decided(ip, role, device, Decision::Approved, "qr");
+        }
+        if let Some(id) = &device.id
+            && !self.stale.lock().unwrap().contains(id)
+            && self.trusted.admit(id, role, ip.ip(), device)
+        {
+            let id = trust::short_id(id);
+            info!("✓ Trusted device {} connected: {}", id, device.describe(ip));
+            return self.decided(ip, role, device, Decision::Approved, "trusted");
+        }
+        self.ask(ip, role, None, device).await
+    }
+
+    /// Ask to hand the place of the client in control to `ip`, which found no room. The
+    /// current client is told and, once the prompt is approved, disconnected. Returns the
+    /// place if `ip` now holds it and should be activated, otherwise why not. Trusted
+    /// devices are asked about too, since someone else is disconnected.
+    pub async fn request_takeover(
+        &self,
+        sessions: &Arc<SessionManager>,
+        ip: SocketAddr,
+        device: &Device,
+    ) -> Result<Claim, Decision> {
+        let Some(device) = &self.authenticate(ip, device) else {
+            return Err(self.decided(
+                ip,
+                Role::Controller,
+                device,
+                Decision::Rejected,
+                "signature",
+            ));
+        };
+        let target = sessions.takeover_target().ok_or(Decision::Rejected)?;
+        info!(
+            "🔁 {} asks to take over from {}",
+            device.describe(ip),
+            target
+        );
+        sessions.announce_takeover(target, ip);
+        match self.ask(ip, Role::Controller, Some(target), device).await {
+            // The emergency stop may have been hit while the prompt was open.
+            Decision::Approved if !sessions.is_halted() => {
+                sessions.take_over(target, ip).ok_or(Decision::Rejected)
+            }
+            Decision::Approved => Err(Decision::Rejected),
+            decision => Err(decision),
+        }
     }
 
-    /// Enqueue approval and wait for CLI decision.
-    pub async fn request_approval(&self, ip: SocketAddr) -> bool {
+    /// Check a signed handshake, after which the device goes by its public key. `None` if
+    /// the signature doesn't hold up.
+    fn authenticate(&self, ip: SocketAddr, device: &Device) -> Option<Device> {
+        let mut device = device.clone();
+        if let Some(proof) = &device.proof {
+            if !self.trusted.verify(proof) {
+                warn!(
+                    "✗ Rejecting {}: bad, stale or reused handshake signature",
+                    device.describe(ip)
+                );
+                return None;
+            }
+            device.id = Some(proof.id());
+            device.signed = true;
+        }
+        Some(device)
+    }
+
+    async fn ask(
+        &self,
+        ip: SocketAddr,
+        role: Role,
+        replaces: Option<SocketAddr>,
+        device: &Device,
+    ) -> Decision {
+        if let Some(left) = self.lockouts.locked(ip.ip()) {
+            warn!(
+                "✗ Rejecting {}: refused too often, locked out for {}",
+                device.describe(ip),
+                elapsed(left)
+            );
+            return self.decided(ip, role, device, Decision::Throttled, "lockout");
+        }
+        let Some(_slot) = self.lockouts.begin(ip.ip()) else {
+            warn!(
+                "✗ Rejecting {}: a request from {} is already waiting",
+                device.describe(ip),
+                ip.ip()
+            );
+            return self.decided(ip, role, device, Decision::Throttled, "lockout");
+        };
+        // Checked first, so that not even --auto-approve lets in a client without the code.
+        if let Some(pairing) = &self.pairing
+            && !pairing.check(device.pin.as_deref())
+        {
+            warn!(
+                "✗ Rejecting {}: wrong or missing pairing code",
+                device.describe(ip)
+            );
+            self.note_refusal(ip);
+            return self.decided(ip, role, device, Decision::Unpaired, "pairing_code");
+        }
         if self.auto_approve {
-            return true;
+            return self.decided(ip, role, device, Decision::Approved, "auto_approve");
         }
+        self.audit.record(AuditEvent::Requested {
+            addr: ip,
+            role,
+            device: device.into(),
+            replaces,
+        });
 
         let (tx, rx) = oneshot::channel();
-        if let Err(err) = self.tx.send(PendingApproval { ip, respond: tx }).await {
+        let pending = PendingApproval {
+            ip,
+            role,
+            device: device.clone(),
+            replaces,
+            deadline: self.timeout.map(|t| tokio::time::Instant::now() + t),
+            respond: tx,
+        };
+        if let Err(err) = self.tx.send(pending).await {
             warn!("Failed to enqueue approval request: {err}");
-            return false;
+            return Decision::Rejected;
+        }
+        match rx.await.unwrap_or(Decision::Rejected) {
+            // Only a device someone actually approved is remembered.
+            Decision::Approved => {
+                self.lockouts.clear(ip.ip());
+                if let Some(id) = &device.id {
+                    self.stale.lock().unwrap().remove(id);
+                    self.trusted.remember(id, role, ip.ip(), device);
+                }
+                self.decided(ip, role, device, Decision::Approved, "operator")
+            }
+            Decision::TimedOut if self.approve_on_timeout => {
+                info!("Approving {}: no answer in time", ip);
+                self.decided(ip, role, device, Decision::Approved, "timeout")
+            }
+            Decision::TimedOut => {
+                self.note_refusal(ip);
+                self.decided(ip, role, device, Decision::TimedOut, "timeout")
+            }
+            decision => {
+                self.note_refusal(ip);
+                self.decided(ip, role, device, decision, "operator")
+            }
+        }
+    }
+
+    /// Record how a request from `ip` was decided and by whom, and pass the decision on.
+    fn decided(
+        &self,
+        ip: SocketAddr,
+        role: Role,
+        device: &Device,
+        decision: Decision,
+        by: &'static str,
+    ) -> Decision {
+        self.audit.record(AuditEvent::Decided {
+            addr: ip,
+            role,
+            device: device.into(),
+            decision,
+            by,
+        });
+        decision
+    }
+
+    /// Count a refused request from `ip` towards locking it out.
+    fn note_refusal(&self, ip: SocketAddr) {
+        if self.lockouts.refused(ip.ip()) {
+            warn!(
+                "🚫 {} was refused {} times; turning it away without asking for {}",
+                ip.ip(),
+                MAX_REFUSALS,
+                elapsed(LOCKOUT)
+            );
         }
-        rx.await.unwrap_or(false)
     }
 }
 
-/// CLI worker that handles approve/deny prompts.
-pub async fn approval_worker(mut rx: mpsc::Receiver<PendingApproval>) {
-    use tokio::io::{stdin, AsyncBufReadExt, BufReader};
+/// CLI worker that handles approve/deny prompts and console commands, and takes the
+/// answers given in notifications, dialogs and on the approval page.
+pub async fn approval_worker(
+    inbox: ApprovalInbox,
+    shares: Arc<FileShares>,
+    sessions: Arc<SessionManager>,
+    mouse: Arc<MouseController>,
+    ui: ApprovalUi,
+    notify: bool,
+) {
+    use tokio::io::{AsyncBufReadExt, BufReader, stdin};
 
     let reader = BufReader::new(stdin());
     let mut lines = reader.lines();
+    let mut stdin_open = true;
+    let mut prune = tokio::time::interval(PRUNE_INTERVAL);
+    let ApprovalInbox {
+        mut requests,
+        mut answered,
+        answers,
+        waiting,
+        trusted,
+        pairing,
+        links,
+        lockouts,
+    } = inbox;
+    let mut console = Console {
+        shares,
+        sessions,
+        mouse,
+        ui,
+        notify,
+        answers,
+        waiting,
+        trusted,
+        pairing,
+        links,
+        lockouts,
+        queue: VecDeque::new(),
+        next_number: 1,
+        prompting: false,
+    };
 
-    while let Some(pending) = rx.recv().await {
-        let ip = pending.ip;
-        let respond = pending.respond;
+    loop {
+        let deadline = console
+            .queue
+            .iter()
+            .filter_map(|q| q.pending.deadline)
+            .min();
+        tokio::select! {
+            pending = requests.recv() => match pending {
+                Some(pending) => {
+                    console.enqueue(pending);
+                    if !stdin_open {
+                        console.stdin_closed();
+                    }
+                }
+                None => break,
+            },
+            line = lines.next_line(), if stdin_open => match line {
+                Ok(Some(line)) => console.run(&line),
+                Ok(None) => {
+                    stdin_open = false;
+                    console.stdin_closed();
+                }
+                Err(err) => {
+                    warn!("Failed to read input: {}", err);
+                    stdin_open = false;
+                    console.stdin_closed();
+                }
+            },
+            _ = tokio::time::sleep_until(deadline.unwrap_or_else(tokio::time::Instant::now)),
+                if deadline.is_some() => console.expire(),
+            _ = prune.tick(), if !console.queue.is_empty() => console.prompt(),
+            Some((number, decision)) = answered.recv() => console.answer_elsewhere(number, decision),
+        }
+        console.publish();
+    }
+}
+
+/// How often queued approval requests are checked for clients that stopped waiting.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// An approval request waiting for the operator, numbered so `approve <n>` can pick it.
+struct Queued {
+    number: u32,
+    asked: Instant,
+    /// `asked` and the deadline in Unix milliseconds, for the approval page.
+    since: i64,
+    expires: Option<i64>,
+    pending: PendingApproval,
+}
+
+impl Queued {
+    fn waiting(&self) -> Waiting {
+        Waiting {
+            number: self.number,
+            addr: self.pending.ip,
+            role: self.pending.role,
+            device: self.pending.device.clone(),
+            replaces: self.pending.replaces,
+            since: self.since,
+            expires: self.expires,
+        }
+    }
+
+    /// `#2 📱 connection from iPhone 15 'Dana's phone' (192.168.1.5:51234)`
+    fn describe(&self) -> String {
+        let ip = self.pending.device.describe(self.pending.ip);
+        match (self.pending.role, self.pending.replaces) {
+            (_, Some(current)) => {
+                format!(
+                    "#{} 🔁 takeover from {} (disconnects {})",
+                    self.number, ip, current
+                )
+            }
+            (Role::Controller, None) => format!("#{} 📱 connection from {}", self.number, ip),
+            (Role::Viewer, None) => format!("#{} 👀 view-only from {}", self.number, ip),
+        }
+    }
+}
+
+/// The server terminal: approval prompts, oldest first, and operator commands.
+struct Console {
+    shares: Arc<FileShares>,
+    sessions: Arc<SessionManager>,
+    mouse: Arc<MouseController>,
+    ui: ApprovalUi,
+    /// Raise a desktop notification for every request too (`--notify`).
+    notify: bool,
+    /// Where notifications and dialogs send their answers.
+    answers: mpsc::UnboundedSender<Answer>,
+    /// The queue as the approval page shows it.
+    waiting: Arc<watch::Sender<Vec<Waiting>>>,
+    trusted: Arc<TrustStore>,
+    pairing: Option<Arc<PairingCode>>,
+    links: Arc<PairingLinks>,
+    lockouts: Arc<Lockouts>,
+    queue: VecDeque<Queued>,
+    next_number: u32,
+    /// `Approve? (y/n)` is on screen, waiting for the rest of its line.
+    prompting: bool,
+}
+
+impl Console {
+    fn enqueue(&mut self, pending: PendingApproval) {
+        let number = self.next_number;
+        self.next_number += 1;
+        self.begin_line();
+        let from = pending.device.describe(pending.ip);
+        match (pending.role, pending.replaces) {
+            (_, Some(current)) => println!(
+                "[{}] 🔁 Takeover request #{} from {} (disconnects {})",
+                timestamp(),
+                number,
+                from,
+                current
+            ),
+            (Role::Controller, None) => {
+                println!(
+                    "[{}] 📱 Connection request #{} from {}",
+                    timestamp(),
+                    number,
+                    from
+                )
+            }
+            (Role::Viewer, None) => {
+                println!(
+                    "[{}] 👀 View-only request #{} from {}",
+                    timestamp(),
+                    number,
+                    from
+                )
+            }
+        }
+        let since = chrono::Utc::now().timestamp_millis();
+        let expires = pending.deadline.map(|deadline| {
+            let left = deadline.saturating_duration_since(tokio::time::Instant::now());
+            since + left.as_millis() as i64
+        });
+        let queued = Queued {
+            number,
+            asked: Instant::now(),
+            since,
+            expires,
+            pending,
+        };
+        let title = format!("Penput request #{number}");
+        if self.notify {
+            notify::request(
+                number,
+                title.clone(),
+                queued.describe(),
+                self.answers.clone(),
+            );
+        }
+        if self.ui == ApprovalUi::Dialog {
+            let body = format!("{}\n\nApprove?", queued.describe());
+            dialog::request(number, title, body, self.answers.clone());
+        }
+        self.queue.push_back(queued);
+        self.prompt();
+    }
+
+    /// Tell the approval page what is waiting, if that changed.
+    fn publish(&self) {
+        let waiting: Vec<Waiting> = self.queue.iter().map(Queued::waiting).collect();
+        self.waiting.send_if_modified(|current| {
+            if *current == waiting {
+                return false;
+            }
+            *current = waiting;
+            true
+        });
+    }
+
+    /// Ask about the oldest request still waiting, unless that is already on screen.
+    fn prompt(&mut self) {
+        self.prune();
+        let Some(front) = self.queue.front() else {
+            return;
+        };
+        // Dialogs ask for themselves.
+        if !self.prompting && self.ui == ApprovalUi::Terminal {
+            print!("           Approve #{}? (y/n): ", front.number);
+            let _ = std::io::stdout().flush();
+            self.prompting = true;
+        }
+    }
+
+    /// Drop requests whose client no longer waits for an answer, e.g. because it hung up.
+    fn prune(&mut self) {
+        while let Some(index) = self
+            .queue
+            .iter()
+            .position(|q| q.pending.respond.is_closed())
+        {
+            let queued = self.queue.remove(index).unwrap();
+            self.begin_line();
+            println!(
+                "[{}] ✗ Request #{} from {} withdrawn",
+                timestamp(),
+                queued.number,
+                queued.pending.device.describe(queued.pending.ip)
+            );
+        }
+    }
+
+    /// Move past an open prompt before printing something else.
+    fn begin_line(&mut self) {
+        if self.prompting {
+            println!();
+            self.prompting = false;
+        }
+    }
+
+    fn answer(&mut self, index: usize, decision: Decision) {
+        let Some(queued) = self.queue.remove(index) else {
+            return;
+        };
+        let ip = queued.pending.device.describe(queued.pending.ip);
+        match queued.pending.respond.send(decision) {
+            Ok(_) => match decision {
+                Decision::Approved => println!("[{}] ✓ Client approved: {}", timestamp(), ip),
+                Decision::Rejected | Decision::Unpaired | Decision::Throttled => {
+                    println!("[{}] ✗ Client rejected: {}", timestamp(), ip)
+                }
+                Decision::TimedOut => println!("[{}] ⌛ No answer for {}", timestamp(), ip),
+            },
+            Err(_) => println!("[{}] {} is no longer waiting", timestamp(), ip),
+        }
+    }
+
+    /// Request `number` was answered in its notification or dialog.
+    fn answer_elsewhere(&mut self, number: u32, decision: Decision) {
+        self.begin_line();
+        match self.queue.iter().position(|q| q.number == number) {
+            Some(index) => self.answer(index, decision),
+            None => println!("[{}] Request #{} was already answered", timestamp(), number),
+        }
+        self.prompt();
+    }
+
+    /// Answer the requests whose approval timeout has passed.
+    fn expire(&mut self) {
+        let now = tokio::time::Instant::now();
+        while let Some(index) = self
+            .queue
+            .iter()
+            .position(|q| q.pending.deadline.is_some_and(|d| d <= now))
+        {
+            self.begin_line();
+            self.answer(index, Decision::TimedOut);
+        }
+        self.prompt();
+    }
+
+    /// Nobody is left to answer at the terminal, so its requests are rejected. Dialogs
+    /// don't need it.
+    fn stdin_closed(&mut self) {
+        if self.ui == ApprovalUi::Dialog {
+            return;
+        }
+        self.begin_line();
+        while !self.queue.is_empty() {
+            self.answer(0, Decision::Rejected);
+        }
+    }
+
+    fn run(&mut self, line: &str) {
+        // Enter was pressed, so whatever prompt was open has its line.
+        self.prompting = false;
+        let line = line.trim();
+        let (cmd, args) = line
+            .split_once(' ')
+            .map_or((line, ""), |(cmd, args)| (cmd, args.trim()));
+        match cmd {
+            "share" => self.shares.run_command(args),
+            "resume" if self.sessions.is_halted() => self.sessions.resume(),
+            "resume" => println!("Not stopped; nothing to resume"),
+            "status" => self.status(),
+            "pending" => self.list_pending(),
+            "kick" => self.kick(args),
+            "sens" => self.sensitivity(args),
+            "trusted" => self.list_trusted(),
+            "untrust" => self.untrust(args),
+            "qr" => self.links.show(),
+            "unlock" => self.unlock(args),
+            "approve" => self.answer_numbered(args, Decision::Approved),
+            "reject" => self.answer_numbered(args, Decision::Rejected),
+            // Anything else answers the open prompt, as `y`/`yes` or a refusal.
+            _ if !self.queue.is_empty() && self.ui == ApprovalUi::Terminal => {
+                let yes = matches!(line.to_lowercase().as_str(), "y" | "yes");
+                let decision = if yes {
+                    Decision::Approved
+                } else {
+                    Decision::Rejected
+                };
+                self.answer(0, decision);
+            }
+            "" => {}
+            _ => println!(
+                "Unknown command: {cmd} (available: status, pending, kick [client], approve [n|all], reject [n|all], trusted, untrust <device|all>, unlock <ip|all>, qr, sens [value], share <path>, resume)"
+            ),
+        }
+        self.prompt();
+    }
+
+    /// `approve [n|all]` / `reject [n|all]`: the numbered request, every request, or the
+    /// oldest one.
+    fn answer_numbered(&mut self, args: &str, decision: Decision) {
+        if args == "all" {
+            if self.queue.is_empty() {
+                println!("No request is waiting");
+            }
+            while !self.queue.is_empty() {
+                self.answer(0, decision);
+            }
+            return;
+        }
+        let index = if args.is_empty() {
+            (!self.queue.is_empty()).then_some(0)
+        } else {
+            let number = args.trim_start_matches('#').parse::<u32>().ok();
+            self.queue.iter().position(|q| Some(q.number) == number)
+        };
+        match index {
+            Some(index) => self.answer(index, decision),
+            None if args.is_empty() => println!("No request is waiting"),
+            None => println!("No request {args} is waiting"),
+        }
+    }
+
+    fn status(&self) {
+        if self.sessions.is_halted() {
+            println!("Remote input: stopped (type `resume` to accept connections)");
+        } else {
+            println!("Remote input: on");
+        }
+        let clients = self.sessions.clients();
+        if clients.is_empty() {
+            println!("No clients connected");
+        }
+        for client in clients {
+            let role = match client.role {
+                Role::Controller
+                    if self.sessions.is_shared() && self.sessions.in_control(client.addr) =>
+                {
+                    "controller, in control"
+                }
+                Role::Controller => "controller",
+                Role::Viewer => "viewer",
+            };
+            let mut rtt = client.rtt.map_or("RTT unknown".to_string(), |rtt| {
+                format!("RTT {} ms", rtt.as_millis())
+            });
+            if let Some(latency) = client.latency {
+                rtt += &format!(", latency {} ms", latency.as_millis());
+            }
+            let device = client
+                .device
+                .label()
+                .map_or(String::new(), |label| format!(" {label},"));
+            println!(
+                "  {} {} {} over {}, connected {}, {}",
+                client.addr,
+                device,
+                role,
+                client.transport.name(),
+                elapsed(client.since.elapsed()),
+                rtt
+            );
+        }
+        self.list_pending();
+        if let Some(pairing) = &self.pairing {
+            println!("Pairing code: {}", pairing.code());
+        }
+        for (ip, left) in self.lockouts.list() {
+            println!(
+                "Locked out: {} for {} more (`unlock {}` to let it ask again)",
+                ip,
+                elapsed(left),
+                ip
+            );
+        }
+        println!("Sensitivity: {}", self.mouse.sensitivity());
+    }
+
+    /// `pending`: the requests waiting for approval, oldest (the one being asked) first.
+    fn list_pending(&self) {
+        if self.queue.is_empty() {
+            println!("No requests waiting for approval");
+            return;
+        }
+        println!("Waiting for approval:");
+        let now = tokio::time::Instant::now();
+        for queued in &self.queue {
+            let left = match queued.pending.deadline {
+                Some(deadline) => format!(
+                    ", times out in {}",
+                    elapsed(deadline.saturating_duration_since(now))
+                ),
+                None => String::new(),
+            };
+            println!(
+                "  {}, waiting {}{}",
+                queued.describe(),
+                elapsed(queued.asked.elapsed()),
+                left
+            );
+        }
+    }
 
-        if respond.is_closed() {
-            continue;
+    /// `kick [client]`: disconnect a client by `ip:port` or IP, or the only one connected.
+    fn kick(&self, args: &str) {
+        let matching: Vec<SocketAddr> = self
+            .sessions
+            .clients()
+            .iter()
+            .map(|c| c.addr)
+            .filter(|a| args.is_empty() || a.to_string() == args || a.ip().to_string() == args)
+            .collect();
+        match matching.as_slice() {
+            [] if args.is_empty() => println!("No clients connected"),
+            [] => println!("No client at {args}"),
+            [addr] => {
+                if self.sessions.kick(*addr) {
+                    println!("Disconnecting {addr}");
+                }
+            }
+            several => {
+                let list: Vec<String> = several.iter().map(SocketAddr::to_string).collect();
+                println!("Which one? kick {}", list.join(" | "));
+            }
         }
+    }
 
-        print!("[{}] 📱 Connection request from {}\n", timestamp(), ip);
-        print!("           Approve? (y/n): ");
-        let _ = std::io::stdout().flush();
+    /// `trusted`: the devices let in without asking.
+    fn list_trusted(&self) {
+        let Some(path) = self.trusted.path() else {
+            println!("Devices aren't remembered (--trusted off)");
+            return;
+        };
+        let devices = self.trusted.devices();
+        if devices.is_empty() {
+            println!("No trusted devices ({})", path.display());
+            return;
+        }
+        println!("Trusted devices ({}):", path.display());
+        for (id, device) in devices {
+            let role = match (device.role, device.signed) {
+                (Role::Controller, false) => "controller",
+                (Role::Controller, true) => "controller, signed",
+                (Role::Viewer, false) => "viewer",
+                (Role::Viewer, true) => "viewer, signed",
+            };
+            let label = device
+                .label()
+                .map_or(String::new(), |label| format!(" {label},"));
+            println!(
+                "  {} {} {}, added {}, last seen {} from {}",
+                trust::short_id(&id),
+                label,
+                role,
+                device.added,
+                device.last_seen,
+                device.last_addr
+            );
+        }
+    }
+
+    /// `untrust <device|all>`: forget a device, given by the start of its ID as `trusted`
+    /// lists it, or every device. Connected clients stay connected.
+    fn untrust(&self, args: &str) {
+        if args.is_empty() {
+            println!("Usage: untrust <device|all>");
+            return;
+        }
+        if args == "all" {
+            println!("Forgot {} trusted device(s)", self.trusted.revoke_all());
+            return;
+        }
+        let matching: Vec<String> = self
+            .trusted
+            .devices()
+            .into_iter()
+            .map(|(id, _)| id)
+            .filter(|id| id.starts_with(args))
+            .collect();
+        match matching.as_slice() {
+            [] => println!("No trusted device {args}"),
+            [id] => {
+                if self.trusted.revoke(id) {
+                    println!("Forgot device {}", trust::short_id(id));
+                }
+            }
+            several => {
+                let list: Vec<&str> = several.iter().map(|id| trust::short_id(id)).collect();
+                println!("Which one? untrust {}", list.join(" | "));
+            }
+        }
+    }
 
-        let mut approved = false;
-        match lines.next_line().await {
-            Ok(Some(line)) => {
-                let trimmed = line.trim().to_lowercase();
-                approved = trimmed == "y" || trimmed == "yes";
+    /// `unlock <ip|all>`: let an address that was refused too often ask again.
+    fn unlock(&self, args: &str) {
+        let ip = match args {
+            "" => {
+                println!("Usage: unlock <ip|all>");
+                return;
             }
-            Ok(None) => {}
-            Err(err) => warn!("Failed to read input: {}", err),
+            "all" => None,
+            ip => match ip.parse::<IpAddr>() {
+                Ok(ip) => Some(ip),
+                Err(_) => {
+                    println!("Not an IP address: {ip}");
+                    return;
+                }
+            },
+        };
+        match (ip, self.lockouts.unlock(ip)) {
+            (Some(ip), 0) => println!("{ip} isn't locked out"),
+            (Some(ip), _) => println!("{ip} may ask again"),
+            (None, count) => println!("Unlocked {count} address(es)"),
         }
+    }
 
-        match respond.send(approved) {
-            Ok(_) if approved => println!("[{}] ✓ Client approved: {}", timestamp(), ip),
-            Ok(_) => println!("[{}] ✗ Client rejected: {}", timestamp(), ip),
-            Err(_) => warn!("Approval channel closed before sending decision"),
+    /// `sens [value]`: show or change the server's pointer sensitivity.
+    fn sensitivity(&self, args: &str) {
+        if args.is_empty() {
+            println!("Sensitivity: {}", self.mouse.sensitivity());
+            return;
         }
+        match args.parse::<f64>() {
+            Ok(value) if value.is_finite() && value > 0.0 => {
+                self.mouse.set_sensitivity(value);
+                println!("Sensitivity set to {value}");
+            }
+            _ => println!("Invalid sensitivity: {args} (a positive number, e.g. 1.5)"),
+        }
+    }
+}
+
+/// A connection age such as `45s`, `3m12s` or `1h05m`.
+fn elapsed(d: Duration) -> String {
+    let secs = d.as_secs();
+    match secs {
+        0..60 => format!("{secs}s"),
+        60..3600 => format!("{}m{:02}s", secs / 60, secs % 60),
+        _ => format!("{}h{:02}m", secs / 3600, secs / 60 % 60),
     }
 }
 
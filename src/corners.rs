@@ -0,0 +1,49 @@
+use serde::Deserialize;
+
+/// Default side of each corner region, in desktop pixels.
+pub const DEFAULT_CORNER_SIZE: u16 = 10;
+
+/// A corner of the desktop.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl Corner {
+    pub const ALL: [Corner; 4] = [
+        Corner::TopLeft,
+        Corner::TopRight,
+        Corner::BottomLeft,
+        Corner::BottomRight,
+    ];
+}
+
+/// Which corners are live and how big they are; evaluated by the input worker.
+#[derive(Debug, Clone, Default)]
+pub struct HotCorners {
+    pub size: u16,
+    pub corners: Vec<Corner>,
+}
+
+impl HotCorners {
+    /// The live corner containing desktop point `x`/`y` on a `w`x`h` screen, if any.
+    pub fn hit(&self, x: i32, y: i32, w: i32, h: i32) -> Option<Corner> {
+        let size = i32::from(self.size);
+        let left = x < size;
+        let right = x >= w - size;
+        let top = y < size;
+        let bottom = y >= h - size;
+        let corner = match (left, right, top, bottom) {
+            (true, _, true, _) => Corner::TopLeft,
+            (_, true, true, _) => Corner::TopRight,
+            (true, _, _, true) => Corner::BottomLeft,
+            (_, true, _, true) => Corner::BottomRight,
+            _ => return None,
+        };
+        self.corners.contains(&corner).then_some(corner)
+    }
+}
@@ -0,0 +1,46 @@
+use serde::Serialize;
+use tokio::time::{Duration, Instant};
+
+/// Fastest a client may ask for cursor reports, about one 60 Hz frame.
+pub const MIN_STREAM_INTERVAL: Duration = Duration::from_millis(16);
+
+/// Where the OS cursor is, in desktop pixels.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+pub struct CursorPos {
+    pub x: i32,
+    pub y: i32,
+    /// Index of the display it is on, if any.
+    pub display: Option<usize>,
+}
+
+/// Periodic cursor reports for one session. Only changes are reported, so a resting
+/// cursor costs nothing on the wire.
+#[derive(Debug, Clone)]
+pub struct CursorStream {
+    interval: Duration,
+    next: Instant,
+    last: Option<CursorPos>,
+}
+
+impl CursorStream {
+    /// Report every `interval` (at least [`MIN_STREAM_INTERVAL`]); zero means no stream.
+    pub fn new(interval: Duration) -> Option<Self> {
+        (!interval.is_zero()).then(|| CursorStream {
+            interval: interval.max(MIN_STREAM_INTERVAL),
+            next: Instant::now(),
+            last: None,
+        })
+    }
+
+    /// When the position should next be read.
+    pub fn deadline(&self) -> Instant {
+        self.next
+    }
+
+    /// Take a fresh reading and schedule the next one. Returns it if it is worth sending.
+    pub fn update(&mut self, pos: Option<CursorPos>) -> Option<CursorPos> {
+        self.next = Instant::now() + self.interval;
+        let pos = pos?;
+        (self.last.replace(pos) != Some(pos)).then_some(pos)
+    }
+}
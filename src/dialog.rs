@@ -0,0 +1,54 @@
+use crate::connection::Answer;
+use tokio::sync::mpsc;
+
+/// Whether this build can show native dialogs (the `dialog` feature).
+pub const AVAILABLE: bool = cfg!(feature = "dialog");
+
+/// Ask about approval request `number` in a native Yes/No dialog and send the answer to
+/// `answers`; closing the dialog denies. A dialog still open once the request has been
+/// answered elsewhere is ignored.
+pub fn request(number: u32, title: String, body: String, answers: mpsc::UnboundedSender<Answer>) {
+    platform::request(number, title, body, answers);
+}
+
+#[cfg(feature = "dialog")]
+mod platform {
+    use crate::connection::{Answer, Decision};
+    use rfd::{AsyncMessageDialog, MessageButtons, MessageDialogResult, MessageLevel};
+    use tokio::sync::mpsc;
+
+    pub fn request(
+        number: u32,
+        title: String,
+        body: String,
+        answers: mpsc::UnboundedSender<Answer>,
+    ) {
+        // Each dialog runs on its own thread (zenity on Linux), so several can be open.
+        let dialog = AsyncMessageDialog::new()
+            .set_level(MessageLevel::Warning)
+            .set_title(title)
+            .set_description(body)
+            .set_buttons(MessageButtons::YesNo);
+        tokio::spawn(async move {
+            let decision = match dialog.show().await {
+                MessageDialogResult::Yes => Decision::Approved,
+                _ => Decision::Rejected,
+            };
+            let _ = answers.send((number, decision));
+        });
+    }
+}
+
+#[cfg(not(feature = "dialog"))]
+mod platform {
+    use crate::connection::Answer;
+    use tokio::sync::mpsc;
+
+    pub fn request(
+        _number: u32,
+        _title: String,
+        _body: String,
+        _answers: mpsc::UnboundedSender<Answer>,
+    ) {
+    }
+}
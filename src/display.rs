@@ -0,0 +1,181 @@
+use crate::mapping::Rect;
+use crate::mouse::MouseController;
+use crate::region::Region;
+use anyhow::{Context, Result};
+use display_info::DisplayInfo;
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// How often the layout is re-read; there is no portable change notification.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// One monitor, placed in desktop pixels: the units the platform's input injection uses,
+/// which are physical pixels except on macOS, where they are points.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Display {
+    pub index: usize,
+    pub name: String,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub primary: bool,
+    /// Physical pixels per logical pixel (1.5 at 150%, 2 on Retina).
+    pub scale_factor: f64,
+}
+
+impl Display {
+    /// Every connected display, in the order the OS lists them.
+    pub fn all() -> Result<Vec<Display>> {
+        let displays: Vec<Display> = DisplayInfo::all()
+            .context("Failed to enumerate displays")?
+            .into_iter()
+            .enumerate()
+            .map(|(index, info)| {
+                let scale_factor = f64::from(info.scale_factor);
+                let scale_factor = if scale_factor.is_finite() && scale_factor > 0.0 {
+                    scale_factor
+                } else {
+                    1.0
+                };
+                let (x, y, width, height) = platform::desktop_geometry(&info, scale_factor);
+                Display {
+                    index,
+                    name: format!("Display {}", index + 1),
+                    x,
+                    y,
+                    width: width.max(1),
+                    height: height.max(1),
+                    primary: info.is_primary,
+                    scale_factor,
+                }
+            })
+            .collect();
+        if displays.is_empty() {
+            anyhow::bail!("No displays found");
+        }
+        Ok(displays)
+    }
+
+    /// Index of the primary display, or the first one if none is marked primary.
+    pub fn primary_index(displays: &[Display]) -> usize {
+        displays.iter().position(|d| d.primary).unwrap_or(0)
+    }
+
+    pub fn rect(&self) -> Rect {
+        Rect {
+            x: self.x.into(),
+            y: self.y.into(),
+            w: self.width.into(),
+            h: self.height.into(),
+        }
+    }
+
+    fn region(&self) -> Option<Region> {
+        Region::new(self.x, self.y, self.width, self.height)
+    }
+
+    /// The smallest rectangle covering every display.
+    pub fn bounds(displays: &[Display]) -> Rect {
+        let left = displays.iter().map(|d| d.x).min().unwrap_or(0);
+        let top = displays.iter().map(|d| d.y).min().unwrap_or(0);
+        let right = displays.iter().map(|d| d.right()).max().unwrap_or(1);
+        let bottom = displays.iter().map(|d| d.bottom()).max().unwrap_or(1);
+        Rect {
+            x: left.into(),
+            y: top.into(),
+            w: (right - i64::from(left)) as f64,
+            h: (bottom - i64::from(top)) as f64,
+        }
+    }
+
+    /// The nearest desktop point that is on some display, for points that fall into the
+    /// gaps of a layout whose monitors differ in size or don't line up.
+    pub fn snap(displays: &[Display], x: i32, y: i32) -> (i32, i32) {
+        displays
+            .iter()
+            .filter_map(Display::region)
+            .map(|r| r.clamp(x, y))
+            .min_by_key(|&(cx, cy)| {
+                let dx = i64::from(cx) - i64::from(x);
+                let dy = i64::from(cy) - i64::from(y);
+                dx * dx + dy * dy
+            })
+            .unwrap_or((x, y))
+    }
+
+    /// Index of the display the desktop point is on, if any.
+    pub fn containing(displays: &[Display], x: i32, y: i32) -> Option<usize> {
+        displays
+            .iter()
+            .find(|d| x >= d.x && y >= d.y && i64::from(x) < d.right() && i64::from(y) < d.bottom())
+            .map(|d| d.index)
+    }
+
+    /// Far edges in desktop pixels, widened so they can't overflow.
+    pub fn right(&self) -> i64 {
+        i64::from(self.x) + i64::from(self.width)
+    }
+
+    pub fn bottom(&self) -> i64 {
+        i64::from(self.y) + i64::from(self.height)
+    }
+
+    /// Size as reported to clients, which use 16-bit dimensions.
+    pub fn size(&self) -> (u16, u16) {
+        let clamp = |v: u32| v.clamp(1, u16::MAX.into()) as u16;
+        (clamp(self.width), clamp(self.height))
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use display_info::DisplayInfo;
+
+    /// display_info divides X11 geometry by the Xft.dpi scale, but XTest and uinput work in
+    /// the server's real pixels, so scale it back.
+    pub fn desktop_geometry(info: &DisplayInfo, scale_factor: f64) -> (i32, i32, u32, u32) {
+        let scale = |v: f64| (v * scale_factor).round();
+        (
+            scale(info.x.into()) as i32,
+            scale(info.y.into()) as i32,
+            scale(info.width.into()) as u32,
+            scale(info.height.into()) as u32,
+        )
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod platform {
+    use display_info::DisplayInfo;
+
+    /// Already in injection units: points on macOS, and physical pixels on Windows since
+    /// the process is made per-monitor DPI aware before anything is enumerated.
+    pub fn desktop_geometry(info: &DisplayInfo, _scale_factor: f64) -> (i32, i32, u32, u32) {
+        (info.x, info.y, info.width, info.height)
+    }
+}
+
+/// Re-read the display layout periodically so hotplugged monitors and resolution changes
+/// reach the controller and, through it, connected clients.
+pub async fn watch(mouse: Arc<MouseController>) {
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        let displays = match tokio::task::spawn_blocking(Display::all).await {
+            Ok(Ok(displays)) => displays,
+            Ok(Err(err)) => {
+                warn!("Failed to re-read displays: {err:#}");
+                continue;
+            }
+            Err(_) => continue,
+        };
+        let count = displays.len();
+        if mouse.update_displays(displays) {
+            info!("🖥️  Display layout changed ({count} display(s))");
+        }
+    }
+}
@@ -0,0 +1,204 @@
+use serde::Deserialize;
+use std::io;
+
+/// Full controller state. Every report replaces the previous one, so a lost packet only
+/// delays the next change instead of leaving a button stuck.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct GamepadState {
+    /// Held buttons as XInput bits: d-pad up/down/left/right 0x0001-0x0008, start 0x0010,
+    /// back 0x0020, stick clicks 0x0040/0x0080, shoulders 0x0100/0x0200, guide 0x0400,
+    /// A/B/X/Y 0x1000-0x8000.
+    pub buttons: u16,
+    /// Sticks, full `i16` range; positive is right and up, as in XInput.
+    pub lx: i16,
+    pub ly: i16,
+    pub rx: i16,
+    pub ry: i16,
+    /// Analog triggers, 0 (released) to 255.
+    pub lt: u8,
+    pub rt: u8,
+}
+
+impl GamepadState {
+    /// Centered sticks, released triggers, no buttons.
+    pub fn is_neutral(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Platform virtual game controller.
+pub(crate) trait GamepadBackend {
+    fn report(&mut self, state: &GamepadState) -> io::Result<()>;
+}
+
+/// Open the virtual controller for this platform, if there is one and it is usable.
+pub(crate) fn open_backend() -> io::Result<Box<dyn GamepadBackend>> {
+    #[cfg(target_os = "linux")]
+    {
+        Ok(Box::new(linux::UinputGamepad::new()?))
+    }
+    #[cfg(all(target_os = "windows", feature = "vigem"))]
+    {
+        Ok(Box::new(windows::VigemGamepad::new()?))
+    }
+    #[cfg(all(target_os = "windows", not(feature = "vigem")))]
+    {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "built without the vigem feature",
+        ))
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "windows")))]
+    {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "no virtual gamepad backend on this platform",
+        ))
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::{GamepadBackend, GamepadState};
+    use crate::uinput::*;
+    use std::io;
+
+    // XInput button bits.
+    const BUTTON_DPAD_UP: u16 = 0x0001;
+    const BUTTON_DPAD_DOWN: u16 = 0x0002;
+    const BUTTON_DPAD_LEFT: u16 = 0x0004;
+    const BUTTON_DPAD_RIGHT: u16 = 0x0008;
+    const BUTTON_START: u16 = 0x0010;
+    const BUTTON_BACK: u16 = 0x0020;
+    const BUTTON_LEFT_THUMB: u16 = 0x0040;
+    const BUTTON_RIGHT_THUMB: u16 = 0x0080;
+    const BUTTON_LEFT_SHOULDER: u16 = 0x0100;
+    const BUTTON_RIGHT_SHOULDER: u16 = 0x0200;
+    const BUTTON_GUIDE: u16 = 0x0400;
+    const BUTTON_A: u16 = 0x1000;
+    const BUTTON_B: u16 = 0x2000;
+    const BUTTON_X: u16 = 0x4000;
+    const BUTTON_Y: u16 = 0x8000;
+
+    /// Face, shoulder and system buttons, mapped the way the kernel's xpad driver does.
+    const BUTTONS: [(u16, u16); 11] = [
+        (BUTTON_A, BTN_A),
+        (BUTTON_B, BTN_B),
+        (BUTTON_X, BTN_X),
+        (BUTTON_Y, BTN_Y),
+        (BUTTON_LEFT_SHOULDER, BTN_TL),
+        (BUTTON_RIGHT_SHOULDER, BTN_TR),
+        (BUTTON_BACK, BTN_SELECT),
+        (BUTTON_START, BTN_START),
+        (BUTTON_GUIDE, BTN_MODE),
+        (BUTTON_LEFT_THUMB, BTN_THUMBL),
+        (BUTTON_RIGHT_THUMB, BTN_THUMBR),
+    ];
+
+    /// Xbox-style controller; the d-pad is a hat, as xpad reports it.
+    pub struct UinputGamepad {
+        dev: UinputDevice,
+    }
+
+    impl UinputGamepad {
+        pub fn new() -> io::Result<Self> {
+            let axis = |code, min, max| AbsAxis {
+                code,
+                min,
+                max,
+                resolution: 0,
+            };
+            let stick = |code| axis(code, i16::MIN.into(), i16::MAX.into());
+            let axes = [
+                stick(ABS_X),
+                stick(ABS_Y),
+                stick(ABS_RX),
+                stick(ABS_RY),
+                axis(ABS_Z, 0, u8::MAX.into()),
+                axis(ABS_RZ, 0, u8::MAX.into()),
+                axis(ABS_HAT0X, -1, 1),
+                axis(ABS_HAT0Y, -1, 1),
+            ];
+            let keys = BUTTONS.map(|(_, key)| key);
+            let dev = UinputDevice::create(&DeviceSpec {
+                name: "Penput Virtual Gamepad",
+                props: &[],
+                keys: &keys,
+                rels: &[],
+                axes: &axes,
+            })?;
+            Ok(Self { dev })
+        }
+    }
+
+    /// -1, 0 or 1 for a pair of opposing d-pad bits.
+    fn hat(buttons: u16, negative: u16, positive: u16) -> i32 {
+        i32::from(buttons & positive != 0) - i32::from(buttons & negative != 0)
+    }
+
+    impl GamepadBackend for UinputGamepad {
+        fn report(&mut self, state: &GamepadState) -> io::Result<()> {
+            for (bit, key) in BUTTONS {
+                self.dev
+                    .emit(EV_KEY, key, i32::from(state.buttons & bit != 0));
+            }
+            // evdev Y axes grow downward.
+            self.dev.emit(EV_ABS, ABS_X, state.lx.into());
+            self.dev
+                .emit(EV_ABS, ABS_Y, (-i32::from(state.ly)).min(i16::MAX.into()));
+            self.dev.emit(EV_ABS, ABS_RX, state.rx.into());
+            self.dev
+                .emit(EV_ABS, ABS_RY, (-i32::from(state.ry)).min(i16::MAX.into()));
+            self.dev.emit(EV_ABS, ABS_Z, state.lt.into());
+            self.dev.emit(EV_ABS, ABS_RZ, state.rt.into());
+            let b = state.buttons;
+            self.dev.emit(
+                EV_ABS,
+                ABS_HAT0X,
+                hat(b, BUTTON_DPAD_LEFT, BUTTON_DPAD_RIGHT),
+            );
+            self.dev
+                .emit(EV_ABS, ABS_HAT0Y, hat(b, BUTTON_DPAD_UP, BUTTON_DPAD_DOWN));
+            // The kernel drops values that didn't change, so resending the full state is cheap.
+            self.dev.sync()
+        }
+    }
+}
+
+#[cfg(all(target_os = "windows", feature = "vigem"))]
+mod windows {
+    use super::{GamepadBackend, GamepadState};
+    use std::io;
+    use vigem_client::{Client, TargetId, XButtons, XGamepad, Xbox360Wired};
+
+    /// Virtual Xbox 360 pad through the ViGEmBus driver, which must be installed separately.
+    pub struct VigemGamepad {
+        target: Xbox360Wired<Client>,
+    }
+
+    impl VigemGamepad {
+        pub fn new() -> io::Result<Self> {
+            let client = Client::connect().map_err(io::Error::other)?;
+            let mut target = Xbox360Wired::new(client, TargetId::XBOX360_WIRED);
+            target.plugin().map_err(io::Error::other)?;
+            target.wait_ready().map_err(io::Error::other)?;
+            Ok(Self { target })
+        }
+    }
+
+    impl GamepadBackend for VigemGamepad {
+        fn report(&mut self, state: &GamepadState) -> io::Result<()> {
+            let pad = XGamepad {
+                buttons: XButtons { raw: state.buttons },
+                left_trigger: state.lt,
+                right_trigger: state.rt,
+                thumb_lx: state.lx,
+                thumb_ly: state.ly,
+                thumb_rx: state.rx,
+                thumb_ry: state.ry,
+            };
+            self.target.update(&pad).map_err(io::Error::other)
+        }
+    }
+}
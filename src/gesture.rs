@@ -0,0 +1,180 @@
+use crate::mouse::{HeldButtons, MouseButton, MouseController};
+use crate::pointer::PointerProfile;
+use crate::touch::TouchContact;
+use serde::Serialize;
+use tokio::time::{Duration, Instant};
+
+/// Longest touch that still counts as a tap.
+const TAP_MAX_DURATION: Duration = Duration::from_millis(250);
+
+/// How long one finger must rest before it latches into a drag.
+const LONG_PRESS: Duration = Duration::from_millis(500);
+
+/// Client pixels a finger may wander before a tap or long press becomes a move.
+const TAP_SLOP: f64 = 10.0;
+
+/// What a touchpad gesture means for the pointer.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum GestureAction {
+    /// Relative cursor move in client pixels.
+    Move {
+        dx: f64,
+        dy: f64,
+    },
+    Click(MouseButton),
+    /// Long press recognized: the left button is now held until every finger lifts.
+    DragStart,
+    DragEnd,
+}
+
+/// Haptic cue for the client when a gesture changes mode, so it can vibrate.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Feedback {
+    DragStart,
+    DragEnd,
+}
+
+impl GestureAction {
+    /// Cue to send back to the client for this action, if any.
+    pub fn feedback(self) -> Option<Feedback> {
+        match self {
+            GestureAction::DragStart => Some(Feedback::DragStart),
+            GestureAction::DragEnd => Some(Feedback::DragEnd),
+            GestureAction::Move { .. } | GestureAction::Click(_) => None,
+        }
+    }
+
+    /// Forward the action to the controller on behalf of a session.
+    pub fn apply(
+        self,
+        mouse: &MouseController,
+        held: &mut HeldButtons,
+        pointer: &mut PointerProfile,
+    ) {
+        let _ = match self {
+            GestureAction::Move { dx, dy } => mouse.move_relative(pointer, dx, dy),
+            GestureAction::Click(button) => mouse.click(button, 1),
+            GestureAction::DragStart => mouse.press_held(held, MouseButton::Left),
+            GestureAction::DragEnd => mouse.release_held(held, MouseButton::Left),
+        };
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Finger {
+    id: u8,
+    start: (f64, f64),
+    last: (f64, f64),
+}
+
+/// Turns raw touchpad frames into clicks, drags and relative moves.
+///
+/// One per session. Frames list every finger currently down, as for native touch; the
+/// gesture ends when a frame arrives with no fingers.
+#[derive(Debug, Default)]
+pub struct GestureRecognizer {
+    fingers: Vec<Finger>,
+    started: Option<Instant>,
+    max_fingers: usize,
+    /// Some finger left the tap slop, so this gesture can no longer be a tap or long press.
+    moved: bool,
+    dragging: bool,
+}
+
+impl GestureRecognizer {
+    /// Feed one frame received at `now`.
+    pub fn frame(&mut self, contacts: &[TouchContact], now: Instant) -> Vec<GestureAction> {
+        let mut actions = Vec::new();
+        if contacts.is_empty() {
+            self.finish(now, &mut actions);
+            return actions;
+        }
+        if self.started.is_none() {
+            self.started = Some(now);
+        }
+
+        // The first finger steers the cursor; remember its previous position before updating.
+        let primary = self.fingers.first().map(|f| (f.id, f.last));
+        self.fingers
+            .retain(|f| contacts.iter().any(|c| c.id == f.id));
+        for c in contacts {
+            let pos = (f64::from(c.x), f64::from(c.y));
+            match self.fingers.iter_mut().find(|f| f.id == c.id) {
+                Some(f) => f.last = pos,
+                None => self.fingers.push(Finger {
+                    id: c.id,
+                    start: pos,
+                    last: pos,
+                }),
+            }
+        }
+        self.max_fingers = self.max_fingers.max(self.fingers.len());
+
+        if !self.moved
+            && self.fingers.iter().any(|f| {
+                let (dx, dy) = (f.last.0 - f.start.0, f.last.1 - f.start.1);
+                dx.hypot(dy) > TAP_SLOP
+            })
+        {
+            self.moved = true;
+            // Catch up on the motion held back while this might still have been a tap.
+            if let [f] = self.fingers.as_slice() {
+                actions.push(GestureAction::Move {
+                    dx: f.last.0 - f.start.0,
+                    dy: f.last.1 - f.start.1,
+                });
+            }
+        } else if self.moved
+            && let [f] = self.fingers.as_slice()
+            && let Some((id, prev)) = primary
+            && id == f.id
+        {
+            let (dx, dy) = (f.last.0 - prev.0, f.last.1 - prev.1);
+            if dx != 0.0 || dy != 0.0 {
+                actions.push(GestureAction::Move { dx, dy });
+            }
+        }
+        actions
+    }
+
+    /// Fire time-based gestures (long press) that are due at `now`.
+    pub fn poll(&mut self, now: Instant) -> Option<GestureAction> {
+        let due = self.long_press_deadline()?;
+        if now < due {
+            return None;
+        }
+        self.dragging = true;
+        Some(GestureAction::DragStart)
+    }
+
+    /// When [`GestureRecognizer::poll`] next needs to run, if a timed gesture is pending.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.long_press_deadline()
+    }
+
+    fn long_press_deadline(&self) -> Option<Instant> {
+        if self.dragging || self.moved || self.max_fingers != 1 || self.fingers.len() != 1 {
+            return None;
+        }
+        Some(self.started? + LONG_PRESS)
+    }
+
+    fn finish(&mut self, now: Instant, actions: &mut Vec<GestureAction>) {
+        let Some(started) = self.started else {
+            return;
+        };
+        if self.dragging {
+            actions.push(GestureAction::DragEnd);
+        } else if !self.moved && now.duration_since(started) <= TAP_MAX_DURATION {
+            let button = match self.max_fingers {
+                1 => Some(MouseButton::Left),
+                2 => Some(MouseButton::Right),
+                3 => Some(MouseButton::Middle),
+                _ => None,
+            };
+            actions.extend(button.map(GestureAction::Click));
+        }
+        *self = Self::default();
+    }
+}
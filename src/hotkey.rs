@@ -0,0 +1,447 @@
+use crate::actions::parse_chord;
+use crate::keyboard::Modifier;
+use enigo::Key;
+use std::fmt;
+use std::io;
+use std::sync::mpsc;
+use std::thread;
+
+/// Emergency stop chord unless `--panic-key` says otherwise.
+pub const DEFAULT_PANIC_KEY: &str = "ctrl+alt+escape";
+
+/// Function keys every platform can grab, in order.
+const FUNCTION_KEYS: [Key; 12] = [
+    Key::F1,
+    Key::F2,
+    Key::F3,
+    Key::F4,
+    Key::F5,
+    Key::F6,
+    Key::F7,
+    Key::F8,
+    Key::F9,
+    Key::F10,
+    Key::F11,
+    Key::F12,
+];
+
+/// Other non-character keys every platform can grab.
+const NAMED_KEYS: [Key; 14] = [
+    Key::Escape,
+    Key::Space,
+    Key::Return,
+    Key::Tab,
+    Key::Backspace,
+    Key::Delete,
+    Key::Home,
+    Key::End,
+    Key::PageUp,
+    Key::PageDown,
+    Key::LeftArrow,
+    Key::UpArrow,
+    Key::RightArrow,
+    Key::DownArrow,
+];
+
+/// A system-wide key chord, caught whichever application has focus.
+#[derive(Debug, Clone)]
+pub struct Hotkey {
+    spec: String,
+    modifiers: Vec<Modifier>,
+    key: Key,
+}
+
+impl Hotkey {
+    /// A chord as in the actions file (`"ctrl+alt+escape"`). At least one modifier is
+    /// required so typing can't trigger it, and the key must be a letter, a digit, `f1`–`f12`
+    /// or one of `escape`, `space`, `enter`, `tab`, `backspace`, `delete`, `home`, `end`,
+    /// `pageup`, `pagedown` and the arrows.
+    pub fn parse(spec: &str) -> Option<Self> {
+        let (modifiers, key) = parse_chord(spec)?;
+        let key = key.to_enigo();
+        let grabbable = match key {
+            Key::Unicode(c) => c.is_ascii_alphanumeric(),
+            key => FUNCTION_KEYS.contains(&key) || NAMED_KEYS.contains(&key),
+        };
+        (grabbable && !modifiers.is_empty()).then(|| Hotkey {
+            spec: spec.to_ascii_lowercase(),
+            modifiers,
+            key,
+        })
+    }
+
+    fn has(&self, modifier: Modifier) -> bool {
+        self.modifiers.contains(&modifier)
+    }
+
+    /// `1` for F1 and so on, if the key is a function key.
+    fn function_key(&self) -> Option<usize> {
+        FUNCTION_KEYS
+            .iter()
+            .position(|k| *k == self.key)
+            .map(|i| i + 1)
+    }
+}
+
+impl fmt::Display for Hotkey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.spec)
+    }
+}
+
+/// Catch `hotkey` system-wide on a thread of its own, calling `on_press` each time it is
+/// pressed. Fails if the platform won't register it, e.g. because another program has it.
+pub fn listen(hotkey: Hotkey, on_press: impl Fn() + Send + 'static) -> io::Result<()> {
+    let (ready_tx, ready_rx) = mpsc::sync_channel(1);
+    thread::Builder::new()
+        .name("hotkey".into())
+        .spawn(move || platform::listen(&hotkey, &ready_tx, on_press))?;
+    ready_rx
+        .recv()
+        .map_err(|_| io::Error::other("hotkey listener exited"))?
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use super::Hotkey;
+    use crate::backend;
+    use crate::keyboard::Modifier;
+    use enigo::Key;
+    use std::io;
+    use std::sync::mpsc::SyncSender;
+    use x11rb::connection::Connection;
+    use x11rb::protocol::Event;
+    use x11rb::protocol::xproto::{ConnectionExt, GrabMode, Keysym, ModMask};
+    use x11rb::rust_connection::RustConnection;
+
+    /// `XK_F1`; the following function keys follow it.
+    const XK_F1: Keysym = 0xffbe;
+
+    fn keysym(hotkey: &Hotkey) -> Option<Keysym> {
+        if let Some(n) = hotkey.function_key() {
+            return Some(XK_F1 + n as Keysym - 1);
+        }
+        Some(match hotkey.key {
+            // Lowercase letters and digits are their own keysyms.
+            Key::Unicode(c) => c.to_ascii_lowercase().into(),
+            Key::Escape => 0xff1b,
+            Key::Space => 0x20,
+            Key::Return => 0xff0d,
+            Key::Tab => 0xff09,
+            Key::Backspace => 0xff08,
+            Key::Delete => 0xffff,
+            Key::Home => 0xff50,
+            Key::End => 0xff57,
+            Key::PageUp => 0xff55,
+            Key::PageDown => 0xff56,
+            Key::LeftArrow => 0xff51,
+            Key::UpArrow => 0xff52,
+            Key::RightArrow => 0xff53,
+            Key::DownArrow => 0xff54,
+            _ => return None,
+        })
+    }
+
+    /// Grab the chord on the root window. X11 only: Wayland compositors don't let clients
+    /// grab keys, and XWayland only sees them while one of its windows has focus.
+    fn grab(hotkey: &Hotkey) -> io::Result<RustConnection> {
+        if backend::is_wayland_session() {
+            return Err(io::Error::other("Wayland doesn't let programs grab global keys"));
+        }
+        let (conn, screen) = x11rb::connect(None).map_err(io::Error::other)?;
+        let setup = conn.setup();
+        let root = setup.roots[screen].root;
+        let (min, max) = (setup.min_keycode, setup.max_keycode);
+        let sym = keysym(hotkey).ok_or(io::ErrorKind::Unsupported)?;
+        let map = conn
+            .get_keyboard_mapping(min, max - min + 1)
+            .map_err(io::Error::other)?
+            .reply()
+            .map_err(io::Error::other)?;
+        let per_keycode = usize::from(map.keysyms_per_keycode).max(1);
+        let index = map
+            .keysyms
+            .iter()
+            .position(|s| *s == sym)
+            .ok_or_else(|| io::Error::other("the key isn't on the keyboard layout"))?;
+        let keycode = min + (index / per_keycode) as u8;
+
+        let mut mods = ModMask::from(0u16);
+        for (modifier, mask) in [
+            (Modifier::Ctrl, ModMask::CONTROL),
+            (Modifier::Alt, ModMask::M1),
+            (Modifier::Shift, ModMask::SHIFT),
+            (Modifier::Meta, ModMask::M4),
+        ] {
+            if hotkey.has(modifier) {
+                mods |= mask;
+            }
+        }
+        // Caps Lock and Num Lock count as modifiers to X, so grab with each combination.
+        for locks in [
+            ModMask::from(0u16),
+            ModMask::LOCK,
+            ModMask::M2,
+            ModMask::LOCK | ModMask::M2,
+        ] {
+            conn.grab_key(
+                true,
+                root,
+                mods | locks,
+                keycode,
+                GrabMode::ASYNC,
+                GrabMode::ASYNC,
+            )
+            .map_err(io::Error::other)?
+            .check()
+            .map_err(|_| io::Error::other("another program has grabbed it"))?;
+        }
+        Ok(conn)
+    }
+
+    pub fn listen(hotkey: &Hotkey, ready: &SyncSender<io::Result<()>>, on_press: impl Fn()) {
+        let conn = match grab(hotkey) {
+            Ok(conn) => {
+                let _ = ready.send(Ok(()));
+                conn
+            }
+            Err(err) => {
+                let _ = ready.send(Err(err));
+                return;
+            }
+        };
+        while let Ok(event) = conn.wait_for_event() {
+            if let Event::KeyPress(_) = event {
+                on_press();
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use super::Hotkey;
+    use crate::keyboard::Modifier;
+    use enigo::Key;
+    use std::io;
+    use std::sync::mpsc::SyncSender;
+    use windows_sys::Win32::UI::Input::KeyboardAndMouse::{
+        MOD_ALT, MOD_CONTROL, MOD_NOREPEAT, MOD_SHIFT, MOD_WIN, RegisterHotKey, VK_BACK, VK_DELETE,
+        VK_DOWN, VK_END, VK_ESCAPE, VK_F1, VK_HOME, VK_LEFT, VK_NEXT, VK_PRIOR, VK_RETURN,
+        VK_RIGHT, VK_SPACE, VK_TAB, VK_UP,
+    };
+    use windows_sys::Win32::UI::WindowsAndMessaging::{GetMessageW, MSG, WM_HOTKEY};
+
+    /// Identifies the hotkey among this thread's registrations.
+    const HOTKEY_ID: i32 = 1;
+
+    fn virtual_key(hotkey: &Hotkey) -> Option<u16> {
+        if let Some(n) = hotkey.function_key() {
+            return Some(VK_F1 + n as u16 - 1);
+        }
+        Some(match hotkey.key {
+            // Letters are their uppercase ASCII code, digits their own.
+            Key::Unicode(c) => c.to_ascii_uppercase() as u16,
+            Key::Escape => VK_ESCAPE,
+            Key::Space => VK_SPACE,
+            Key::Return => VK_RETURN,
+            Key::Tab => VK_TAB,
+            Key::Backspace => VK_BACK,
+            Key::Delete => VK_DELETE,
+            Key::Home => VK_HOME,
+            Key::End => VK_END,
+            Key::PageUp => VK_PRIOR,
+            Key::PageDown => VK_NEXT,
+            Key::LeftArrow => VK_LEFT,
+            Key::UpArrow => VK_UP,
+            Key::RightArrow => VK_RIGHT,
+            Key::DownArrow => VK_DOWN,
+            _ => return None,
+        })
+    }
+
+    pub fn listen(hotkey: &Hotkey, ready: &SyncSender<io::Result<()>>, on_press: impl Fn()) {
+        let Some(vk) = virtual_key(hotkey) else {
+            let _ = ready.send(Err(io::ErrorKind::Unsupported.into()));
+            return;
+        };
+        let mut mods = MOD_NOREPEAT;
+        for (modifier, flag) in [
+            (Modifier::Ctrl, MOD_CONTROL),
+            (Modifier::Alt, MOD_ALT),
+            (Modifier::Shift, MOD_SHIFT),
+            (Modifier::Meta, MOD_WIN),
+        ] {
+            if hotkey.has(modifier) {
+                mods |= flag;
+            }
+        }
+        // SAFETY: without a window the hotkey belongs to this thread, whose queue is read below.
+        if unsafe { RegisterHotKey(std::ptr::null_mut(), HOTKEY_ID, mods, vk.into()) } == 0 {
+            let _ = ready.send(Err(io::Error::last_os_error()));
+            return;
+        }
+        let _ = ready.send(Ok(()));
+
+        // SAFETY: MSG is plain data, filled in by each GetMessageW call.
+        let mut msg: MSG = unsafe { std::mem::zeroed() };
+        while unsafe { GetMessageW(&mut msg, std::ptr::null_mut(), 0, 0) } > 0 {
+            if msg.message == WM_HOTKEY {
+                on_press();
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use super::Hotkey;
+    use crate::keyboard::Modifier;
+    use core_foundation::runloop::CFRunLoop;
+    use core_graphics::event::{
+        CGEventFlags, CGEventTap, CGEventTapLocation, CGEventTapOptions, CGEventTapPlacement,
+        CGEventType, CGKeyCode, CallbackResult, EventField, KeyCode,
+    };
+    use enigo::Key;
+    use std::io;
+    use std::sync::mpsc::SyncSender;
+
+    const LETTERS: [CGKeyCode; 26] = [
+        KeyCode::ANSI_A,
+        KeyCode::ANSI_B,
+        KeyCode::ANSI_C,
+        KeyCode::ANSI_D,
+        KeyCode::ANSI_E,
+        KeyCode::ANSI_F,
+        KeyCode::ANSI_G,
+        KeyCode::ANSI_H,
+        KeyCode::ANSI_I,
+        KeyCode::ANSI_J,
+        KeyCode::ANSI_K,
+        KeyCode::ANSI_L,
+        KeyCode::ANSI_M,
+        KeyCode::ANSI_N,
+        KeyCode::ANSI_O,
+        KeyCode::ANSI_P,
+        KeyCode::ANSI_Q,
+        KeyCode::ANSI_R,
+        KeyCode::ANSI_S,
+        KeyCode::ANSI_T,
+        KeyCode::ANSI_U,
+        KeyCode::ANSI_V,
+        KeyCode::ANSI_W,
+        KeyCode::ANSI_X,
+        KeyCode::ANSI_Y,
+        KeyCode::ANSI_Z,
+    ];
+
+    const DIGITS: [CGKeyCode; 10] = [
+        KeyCode::ANSI_0,
+        KeyCode::ANSI_1,
+        KeyCode::ANSI_2,
+        KeyCode::ANSI_3,
+        KeyCode::ANSI_4,
+        KeyCode::ANSI_5,
+        KeyCode::ANSI_6,
+        KeyCode::ANSI_7,
+        KeyCode::ANSI_8,
+        KeyCode::ANSI_9,
+    ];
+
+    const FUNCTION_KEYS: [CGKeyCode; 12] = [
+        KeyCode::F1,
+        KeyCode::F2,
+        KeyCode::F3,
+        KeyCode::F4,
+        KeyCode::F5,
+        KeyCode::F6,
+        KeyCode::F7,
+        KeyCode::F8,
+        KeyCode::F9,
+        KeyCode::F10,
+        KeyCode::F11,
+        KeyCode::F12,
+    ];
+
+    fn key_code(hotkey: &Hotkey) -> Option<CGKeyCode> {
+        if let Some(n) = hotkey.function_key() {
+            return FUNCTION_KEYS.get(n - 1).copied();
+        }
+        Some(match hotkey.key {
+            Key::Unicode(c @ 'a'..='z') => LETTERS[usize::from(c as u8 - b'a')],
+            Key::Unicode(c @ '0'..='9') => DIGITS[usize::from(c as u8 - b'0')],
+            Key::Escape => KeyCode::ESCAPE,
+            Key::Space => KeyCode::SPACE,
+            Key::Return => KeyCode::RETURN,
+            Key::Tab => KeyCode::TAB,
+            Key::Backspace => KeyCode::DELETE,
+            Key::Delete => KeyCode::FORWARD_DELETE,
+            Key::Home => KeyCode::HOME,
+            Key::End => KeyCode::END,
+            Key::PageUp => KeyCode::PAGE_UP,
+            Key::PageDown => KeyCode::PAGE_DOWN,
+            Key::LeftArrow => KeyCode::LEFT_ARROW,
+            Key::UpArrow => KeyCode::UP_ARROW,
+            Key::RightArrow => KeyCode::RIGHT_ARROW,
+            Key::DownArrow => KeyCode::DOWN_ARROW,
+            _ => return None,
+        })
+    }
+
+    /// Watch key presses with a listen-only event tap, which needs the same Accessibility
+    /// access as injection.
+    pub fn listen(hotkey: &Hotkey, ready: &SyncSender<io::Result<()>>, on_press: impl Fn()) {
+        let Some(code) = key_code(hotkey) else {
+            let _ = ready.send(Err(io::ErrorKind::Unsupported.into()));
+            return;
+        };
+        let modifiers = [
+            (Modifier::Ctrl, CGEventFlags::CGEventFlagControl),
+            (Modifier::Alt, CGEventFlags::CGEventFlagAlternate),
+            (Modifier::Shift, CGEventFlags::CGEventFlagShift),
+            (Modifier::Meta, CGEventFlags::CGEventFlagCommand),
+        ];
+        let relevant = modifiers
+            .iter()
+            .fold(CGEventFlags::empty(), |acc, (_, flag)| acc | *flag);
+        let wanted = modifiers
+            .iter()
+            .filter(|(m, _)| hotkey.has(*m))
+            .fold(CGEventFlags::empty(), |acc, (_, flag)| acc | *flag);
+
+        let tap = CGEventTap::with_enabled(
+            CGEventTapLocation::Session,
+            CGEventTapPlacement::HeadInsertEventTap,
+            CGEventTapOptions::ListenOnly,
+            vec![CGEventType::KeyDown],
+            |_proxy, _type, event| {
+                let key = event.get_integer_value_field(EventField::KEYBOARD_EVENT_KEYCODE);
+                let repeat = event.get_integer_value_field(EventField::KEYBOARD_EVENT_AUTOREPEAT);
+                if key == i64::from(code) && repeat == 0 && event.get_flags() & relevant == wanted {
+                    on_press();
+                }
+                CallbackResult::Keep
+            },
+            || {
+                let _ = ready.send(Ok(()));
+                CFRunLoop::run_current();
+            },
+        );
+        if tap.is_err() {
+            let _ = ready.send(Err(io::Error::other(
+                "can't watch the keyboard; grant Accessibility access",
+            )));
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "windows", target_os = "macos")))]
+mod platform {
+    use super::Hotkey;
+    use std::io;
+    use std::sync::mpsc::SyncSender;
+
+    pub fn listen(_hotkey: &Hotkey, ready: &SyncSender<io::Result<()>>, _on_press: impl Fn()) {
+        let _ = ready.send(Err(io::ErrorKind::Unsupported.into()));
+    }
+}
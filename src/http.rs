@@ -1,16 +1,73 @@
+use crate::backend::{self, BackendKind};
+use crate::mouse::MouseController;
+use crate::share::{self, FileShares};
+use crate::tls::Tls;
 use axum::{
+    extract::State,
     http::{header::CACHE_CONTROL, HeaderValue, StatusCode},
-    routing::get_service,
-    Router,
+    routing::{get, get_service},
+    Json, Router,
 };
+use serde::Serialize;
+use std::net::TcpListener;
+use std::sync::Arc;
 use tower_http::{
     services::ServeDir,
     set_header::SetResponseHeaderLayer,
     trace::TraceLayer,
 };
 
-/// Build the HTTP router serving embedded static assets.
-pub fn build_http_router() -> anyhow::Result<Router> {
+/// Server health, as served at `/status`.
+#[derive(Serialize)]
+struct Status {
+    version: &'static str,
+    /// Pointer backend in use.
+    backend: BackendKind,
+    /// Why pointer injection is failing, if it is.
+    backend_error: Option<String>,
+    /// Whether the OS allows input injection, where that takes a permission (macOS).
+    input_permission: Option<bool>,
+}
+
+/// The ports the servers actually took, as served at `/discovery`, for clients that
+/// loaded the page without them in the URL.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Discovery {
+    pub http: u16,
+    pub ws: u16,
+    pub udp: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub webtransport: Option<WebTransportEndpoint>,
+}
+
+/// Where the WebTransport endpoint listens (`--webtransport-port`), and the SHA-256 of its
+/// certificate for the browser's `serverCertificateHashes`.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct WebTransportEndpoint {
+    pub port: u16,
+    pub cert_hash: [u8; 32],
+}
+
+async fn discovery(State(ports): State<Discovery>) -> Json<Discovery> {
+    Json(ports)
+}
+
+async fn status(State(mouse): State<Arc<MouseController>>) -> Json<Status> {
+    Json(Status {
+        version: env!("CARGO_PKG_VERSION"),
+        backend: mouse.backend(),
+        backend_error: mouse.backend_error(),
+        input_permission: backend::input_permission(),
+    })
+}
+
+/// Build the HTTP router serving embedded static assets, one-time file shares, status and
+/// discovery.
+pub fn build_http_router(
+    shares: Arc<FileShares>,
+    mouse: Arc<MouseController>,
+    ports: Discovery,
+) -> anyhow::Result<Router> {
     let static_service = get_service(ServeDir::new("static").append_index_html_on_directories(true))
         .handle_error(|err| async move {
             (
@@ -20,6 +77,10 @@ pub fn build_http_router() -> anyhow::Result<Router> {
         });
 
     let router = Router::new()
+        .route("/share/{token}", get(share::download))
+        .with_state(shares)
+        .merge(Router::new().route("/status", get(status)).with_state(mouse))
+        .merge(Router::new().route("/discovery", get(discovery)).with_state(ports))
         .fallback_service(static_service)
         .layer(SetResponseHeaderLayer::overriding(
             CACHE_CONTROL,
@@ -30,9 +91,12 @@ pub fn build_http_router() -> anyhow::Result<Router> {
     Ok(router)
 }
 
-/// Start the HTTP server on the given port.
-pub async fn serve_http(app: Router, port: u16) -> anyhow::Result<()> {
-    let listener = tokio::net::TcpListener::bind(("0.0.0.0", port)).await?;
+/// Run the HTTP server on `listener`, over HTTPS with `tls`.
+pub async fn serve_http(app: Router, listener: TcpListener, tls: Option<Tls>) -> anyhow::Result<()> {
+    if let Some(tls) = tls {
+        return tls.serve(app, listener).await;
+    }
+    let listener = tokio::net::TcpListener::from_std(listener)?;
     axum::serve(listener, app).await?;
     Ok(())
 }
@@ -0,0 +1,292 @@
+use enigo::Key;
+use serde::Deserialize;
+use std::time::Duration;
+
+/// A key that clients can press by name.
+///
+/// Names are case-insensitive: a single character (`"a"`, `"7"`, `"/"`) types that
+/// character's key, everything else must be one of the named keys in [`KeyCode::parse`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct KeyCode(Key);
+
+impl KeyCode {
+    pub fn parse(name: &str) -> Option<Self> {
+        let mut chars = name.chars();
+        if let (Some(c), None) = (chars.next(), chars.clone().next()) {
+            return Some(Self(Key::Unicode(c.to_ascii_lowercase())));
+        }
+
+        let lower = name.to_ascii_lowercase();
+        let key = match lower.as_str() {
+            "enter" | "return" => Key::Return,
+            "escape" | "esc" => Key::Escape,
+            "tab" => Key::Tab,
+            "backspace" => Key::Backspace,
+            "delete" | "del" => Key::Delete,
+            "space" => Key::Space,
+            "up" | "arrowup" => Key::UpArrow,
+            "down" | "arrowdown" => Key::DownArrow,
+            "left" | "arrowleft" => Key::LeftArrow,
+            "right" | "arrowright" => Key::RightArrow,
+            "home" => Key::Home,
+            "end" => Key::End,
+            "pageup" => Key::PageUp,
+            "pagedown" => Key::PageDown,
+            "capslock" => Key::CapsLock,
+            "shift" => Key::Shift,
+            "control" | "ctrl" => Key::Control,
+            "alt" | "option" => Key::Alt,
+            "meta" | "super" | "win" | "cmd" | "command" => Key::Meta,
+            #[cfg(not(target_os = "macos"))]
+            "insert" => Key::Insert,
+            #[cfg(not(target_os = "macos"))]
+            "printscreen" => Key::PrintScr,
+            _ => return Self::parse_function_key(&lower),
+        };
+        Some(Self(key))
+    }
+
+    /// `f1`..`f20`, the range every platform supports.
+    fn parse_function_key(name: &str) -> Option<Self> {
+        const KEYS: [Key; 20] = [
+            Key::F1,
+            Key::F2,
+            Key::F3,
+            Key::F4,
+            Key::F5,
+            Key::F6,
+            Key::F7,
+            Key::F8,
+            Key::F9,
+            Key::F10,
+            Key::F11,
+            Key::F12,
+            Key::F13,
+            Key::F14,
+            Key::F15,
+            Key::F16,
+            Key::F17,
+            Key::F18,
+            Key::F19,
+            Key::F20,
+        ];
+        let n: usize = name.strip_prefix('f')?.parse().ok()?;
+        KEYS.get(n.checked_sub(1)?).copied().map(Self)
+    }
+
+    /// The modifier this key is, if it is one.
+    pub fn modifier(self) -> Option<Modifier> {
+        Modifier::ALL.into_iter().find(|m| m.to_enigo() == self.0)
+    }
+
+    pub(crate) fn to_enigo(self) -> Key {
+        self.0
+    }
+}
+
+impl TryFrom<String> for KeyCode {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        Self::parse(&name).ok_or_else(|| format!("unknown key: {name}"))
+    }
+}
+
+/// Modifier that can be combined with a key in a shortcut.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Modifier {
+    Ctrl,
+    Alt,
+    Shift,
+    Meta,
+}
+
+impl Modifier {
+    pub const ALL: [Modifier; 4] = [
+        Modifier::Ctrl,
+        Modifier::Alt,
+        Modifier::Shift,
+        Modifier::Meta,
+    ];
+
+    /// Case-insensitive name, with the same aliases as the matching [`KeyCode`] names.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "ctrl" | "control" => Some(Modifier::Ctrl),
+            "alt" | "option" => Some(Modifier::Alt),
+            "shift" => Some(Modifier::Shift),
+            "meta" | "super" | "win" | "cmd" | "command" => Some(Modifier::Meta),
+            _ => None,
+        }
+    }
+
+    /// Bit used in the UDP shortcut modifier mask.
+    pub fn bit(self) -> u8 {
+        match self {
+            Modifier::Ctrl => 1 << 0,
+            Modifier::Alt => 1 << 1,
+            Modifier::Shift => 1 << 2,
+            Modifier::Meta => 1 << 3,
+        }
+    }
+
+    pub(crate) fn to_enigo(self) -> Key {
+        match self {
+            Modifier::Ctrl => Key::Control,
+            Modifier::Alt => Key::Alt,
+            Modifier::Shift => Key::Shift,
+            Modifier::Meta => Key::Meta,
+        }
+    }
+}
+
+impl TryFrom<String> for Modifier {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        Self::parse(&name).ok_or_else(|| format!("unknown modifier: {name}"))
+    }
+}
+
+/// Media / volume control, sent as a dedicated message so clients need no key names.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MediaAction {
+    PlayPause,
+    Next,
+    Previous,
+    VolumeUp,
+    VolumeDown,
+    Mute,
+}
+
+impl MediaAction {
+    /// Decode the UDP action byte.
+    pub fn from_code(code: u8) -> Option<Self> {
+        match code {
+            0 => Some(MediaAction::PlayPause),
+            1 => Some(MediaAction::Next),
+            2 => Some(MediaAction::Previous),
+            3 => Some(MediaAction::VolumeUp),
+            4 => Some(MediaAction::VolumeDown),
+            5 => Some(MediaAction::Mute),
+            _ => None,
+        }
+    }
+
+    pub fn key(self) -> KeyCode {
+        KeyCode(match self {
+            MediaAction::PlayPause => Key::MediaPlayPause,
+            MediaAction::Next => Key::MediaNextTrack,
+            MediaAction::Previous => Key::MediaPrevTrack,
+            MediaAction::VolumeUp => Key::VolumeUp,
+            MediaAction::VolumeDown => Key::VolumeDown,
+            MediaAction::Mute => Key::VolumeMute,
+        })
+    }
+}
+
+/// Auto-repeat timing for keys started with `key_hold`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct KeyRepeat {
+    /// Pause between the press and the first repeat.
+    pub delay: Duration,
+    /// Pause between repeats.
+    pub interval: Duration,
+}
+
+impl Default for KeyRepeat {
+    /// Typical desktop defaults: 500 ms delay, 30 repeats per second.
+    fn default() -> Self {
+        Self {
+            delay: Duration::from_millis(500),
+            interval: Duration::from_micros(1_000_000 / 30),
+        }
+    }
+}
+
+impl KeyRepeat {
+    /// Build from a delay in milliseconds and a rate in repeats per second (1–100).
+    pub fn new(delay_ms: u64, rate: f64) -> Option<Self> {
+        if !(1.0..=100.0).contains(&rate) || delay_ms > 5_000 {
+            return None;
+        }
+        Some(Self {
+            delay: Duration::from_millis(delay_ms),
+            interval: Duration::from_secs_f64(1.0 / rate),
+        })
+    }
+}
+
+/// Keys held down by one client session, released on teardown like [`crate::mouse::HeldButtons`].
+#[derive(Debug, Default, Clone)]
+pub struct HeldKeys {
+    keys: Vec<KeyCode>,
+}
+
+impl HeldKeys {
+    /// Mark `key` as held. Returns false if it already was.
+    pub fn insert(&mut self, key: KeyCode) -> bool {
+        if self.keys.contains(&key) {
+            return false;
+        }
+        self.keys.push(key);
+        true
+    }
+
+    /// Mark `key` as released. Returns false if it was not held.
+    pub fn remove(&mut self, key: KeyCode) -> bool {
+        let before = self.keys.len();
+        self.keys.retain(|k| *k != key);
+        self.keys.len() != before
+    }
+
+    /// Take every held key, most recently pressed first.
+    pub fn drain(&mut self) -> impl Iterator<Item = KeyCode> + '_ {
+        self.keys.drain(..).rev()
+    }
+}
+
+/// Sticky-keys accessibility mode for one session.
+///
+/// While enabled, tapping a modifier latches it instead of pressing it, and the next
+/// non-modifier tap is sent together with every latched modifier, which then unlatch.
+/// This lets a single finger enter combos such as Ctrl+Alt+Del one key at a time.
+#[derive(Debug, Default, Clone)]
+pub struct StickyModifiers {
+    enabled: bool,
+    latched: Vec<Modifier>,
+}
+
+impl StickyModifiers {
+    /// Turn the mode on or off. Turning it off drops any latched modifiers.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.latched.clear();
+        }
+    }
+
+    /// Feed one key tap and return the chord to send, if any.
+    ///
+    /// Tapping a latched modifier again unlatches it. With the mode off, every tap
+    /// passes through unchanged.
+    pub fn tap(&mut self, key: KeyCode) -> Option<(Vec<Modifier>, KeyCode)> {
+        if !self.enabled {
+            return Some((Vec::new(), key));
+        }
+        match key.modifier() {
+            Some(m) => {
+                if let Some(i) = self.latched.iter().position(|l| *l == m) {
+                    self.latched.remove(i);
+                } else {
+                    self.latched.push(m);
+                }
+                None
+            }
+            None => Some((std::mem::take(&mut self.latched), key)),
+        }
+    }
+}
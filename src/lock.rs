@@ -0,0 +1,150 @@
+use crate::mouse::MouseController;
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// How often the lock state is re-read; none of the platforms offer a change notification
+/// that works from a plain background process.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Whether the session is locked or its screensaver is running.
+pub fn is_locked() -> io::Result<bool> {
+    platform::is_locked()
+}
+
+/// Keep the controller told whether the screen is locked, so it refuses input meanwhile.
+/// Stops checking (and leaves input allowed) if the platform can't say.
+pub async fn watch(mouse: Arc<MouseController>) {
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        let locked = match tokio::task::spawn_blocking(is_locked).await {
+            Ok(Ok(locked)) => locked,
+            Ok(Err(err)) => {
+                warn!("Can't tell whether the screen is locked ({err}); not guarding it");
+                mouse.set_screen_locked(false);
+                return;
+            }
+            Err(_) => continue,
+        };
+        if mouse.set_screen_locked(locked) {
+            if locked {
+                info!("🔒 Screen locked; refusing client input");
+            } else {
+                info!("🔓 Screen unlocked; accepting client input");
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::io;
+    use std::process::{Command, Stdio};
+
+    /// logind's `LockedHint`, which GNOME, KDE and other logind-aware lockers maintain.
+    pub fn is_locked() -> io::Result<bool> {
+        let session = std::env::var("XDG_SESSION_ID").unwrap_or_else(|_| "auto".into());
+        let out = Command::new("loginctl")
+            .args(["show-session", &session, "--property=LockedHint", "--value"])
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()?;
+        if !out.status.success() {
+            return Err(io::Error::other(format!(
+                "loginctl exited with {}",
+                out.status
+            )));
+        }
+        Ok(String::from_utf8_lossy(&out.stdout).trim() == "yes")
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use std::io;
+    use windows_sys::Win32::System::StationsAndDesktops::{
+        CloseDesktop, DESKTOP_READOBJECTS, GetUserObjectInformationW, OpenInputDesktop, UOI_NAME,
+    };
+    use windows_sys::Win32::UI::WindowsAndMessaging::{
+        SPI_GETSCREENSAVERRUNNING, SystemParametersInfoW,
+    };
+
+    /// Locked when input goes to a desktop other than the user's (Winlogon's lock screen
+    /// can't even be opened), or when the screensaver runs.
+    pub fn is_locked() -> io::Result<bool> {
+        let mut saver = 0i32;
+        // SAFETY: `saver` is the BOOL this query writes.
+        unsafe {
+            SystemParametersInfoW(SPI_GETSCREENSAVERRUNNING, 0, (&raw mut saver).cast(), 0);
+        }
+        if saver != 0 {
+            return Ok(true);
+        }
+
+        // SAFETY: the handle is checked for null and closed below.
+        let desktop = unsafe { OpenInputDesktop(0, 0, DESKTOP_READOBJECTS) };
+        if desktop.is_null() {
+            return Ok(true);
+        }
+        let mut name = [0u16; 64];
+        let mut needed = 0u32;
+        // SAFETY: the buffer and its byte length match; `desktop` is a live handle.
+        let ok = unsafe {
+            let ok = GetUserObjectInformationW(
+                desktop,
+                UOI_NAME,
+                name.as_mut_ptr().cast(),
+                size_of_val(&name) as u32,
+                &mut needed,
+            );
+            CloseDesktop(desktop);
+            ok
+        };
+        if ok == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let len = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+        Ok(!String::from_utf16_lossy(&name[..len]).eq_ignore_ascii_case("Default"))
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use core_foundation::base::{CFType, TCFType};
+    use core_foundation::boolean::CFBoolean;
+    use core_foundation::dictionary::{CFDictionary, CFDictionaryRef};
+    use core_foundation::string::CFString;
+    use std::io;
+
+    #[link(name = "CoreGraphics", kind = "framework")]
+    unsafe extern "C" {
+        fn CGSessionCopyCurrentDictionary() -> CFDictionaryRef;
+    }
+
+    pub fn is_locked() -> io::Result<bool> {
+        // SAFETY: takes no arguments and returns an owned dictionary or null.
+        let dict = unsafe { CGSessionCopyCurrentDictionary() };
+        if dict.is_null() {
+            return Err(io::Error::other("not running in a window server session"));
+        }
+        // SAFETY: non-null and owned by us, so the wrapper takes over releasing it.
+        let dict: CFDictionary<CFString, CFType> =
+            unsafe { CFDictionary::wrap_under_create_rule(dict) };
+        Ok(dict
+            .find(CFString::from_static_string("CGSSessionScreenIsLocked"))
+            .and_then(|v| v.downcast::<CFBoolean>())
+            .is_some_and(bool::from))
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "windows", target_os = "macos")))]
+mod platform {
+    use std::io;
+
+    pub fn is_locked() -> io::Result<bool> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
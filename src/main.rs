@@ -1,85 +1,574 @@
+mod actions;
+mod air;
+mod audit;
+mod backend;
+mod browser;
+mod clipboard;
+mod clock;
 mod connection;
+mod corners;
+mod cursor;
+mod dialog;
+mod display;
+mod gamepad;
+mod gesture;
+mod hotkey;
 mod http;
+mod ipfilter;
+mod keyboard;
+mod lock;
+mod mapping;
+mod mdns;
 mod mouse;
+mod net;
+mod noise;
+mod notify;
+mod pointer;
+mod power;
+mod predict;
+mod proto;
+mod qr;
+mod quality;
+mod quic;
+mod ratelimit;
+mod region;
+mod replay;
+mod resample;
+mod sched;
+mod share;
+mod smoothing;
+mod stylus;
+mod tls;
+mod touch;
+mod trust;
 mod udp;
+#[cfg(target_os = "linux")]
+mod uinput;
+mod webhook;
+mod webrtc;
 mod websocket;
+mod webtransport;
+mod window;
 
-use crate::connection::{ApprovalBroker, ConnectionSlot, approval_worker};
-use crate::mouse::MouseController;
+use crate::actions::ActionRegistry;
+use crate::audit::{AuditLevel, AuditLog};
+use crate::backend::BackendKind;
+use crate::clipboard::Clipboard;
+use crate::connection::{
+    ApprovalBroker, ApprovalUi, Arbitration, Pairing, PairingCode, SessionManager, approval_worker,
+};
+use crate::hotkey::{DEFAULT_PANIC_KEY, Hotkey};
+use crate::keyboard::KeyRepeat;
+use crate::mapping::{Rotation, Transform};
+use crate::mdns::Advertisement;
+use crate::mouse::{DEFAULT_SENSITIVITY, MouseController};
+use crate::ipfilter::{Cidr, IpFilter};
+use crate::noise::Encryption;
+use crate::qr::PairingLinks;
+use crate::region::Region;
+use crate::resample::RESAMPLE_RATES;
+use crate::stylus::PressureCurve;
+use crate::share::FileShares;
+use crate::smoothing::OneEuro;
+use crate::trust::TrustStore;
+use crate::tls::Tls;
+use crate::webhook::Webhooks;
 use crate::websocket::build_ws_router;
-use std::net::{IpAddr, Ipv4Addr};
+use reqwest::Url;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::task::JoinSet;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 use tracing_subscriber::FmtSubscriber;
 
+/// Longest wait on shutdown for the sessions to say goodbye to their clients and let go
+/// of their input.
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(2);
+
 #[derive(Debug, Clone)]
 struct Settings {
     http_port: u16,
-    ws_port: u16,
+    /// Own port for the WebSocket server (`--ws-port`); `None` serves it on the HTTP port.
+    ws_port: Option<u16>,
     udp_port: u16,
+    /// Port of the QUIC endpoint for native clients (`--quic-port`); off if `None`.
+    quic_port: Option<u16>,
+    /// Port of the experimental WebTransport endpoint (`--webtransport-port`); off if `None`.
+    webtransport_port: Option<u16>,
+    /// UDP port WebRTC data channels from browsers connect to (`--webrtc-port`); off if `None`.
+    webrtc_port: Option<u16>,
+    bind: Bind,
     auto_approve: bool,
+    sensitivity: f64,
+    pressure_curve: PressureCurve,
+    key_repeat: KeyRepeat,
+    actions: Option<PathBuf>,
+    clipboard_sync: bool,
+    region: Option<Region>,
+    transform: Transform,
+    dead_zone: f64,
+    smoothing: Option<OneEuro>,
+    prediction: Duration,
+    resample: Option<u32>,
+    max_rate: Option<u32>,
+    display: Option<usize>,
+    backend: BackendKind,
+    realtime: bool,
+    pin_cpu: Option<usize>,
+    local_pause: Duration,
+    allow_locked: bool,
+    panic_key: Option<Hotkey>,
+    arbitration: Arbitration,
+    approval_timeout: Option<Duration>,
+    reapprove_after: Option<Duration>,
+    approve_on_timeout: bool,
+    notify: bool,
+    approval_ui: ApprovalUi,
+    admin_key: Option<String>,
+    access_token: Option<String>,
+    trusted: Option<PathBuf>,
+    audit_log: Option<PathBuf>,
+    audit_level: AuditLevel,
+    webhooks: Vec<Url>,
+    pairing: Pairing,
+    udp_encryption: Encryption,
+    udp_rate_limit: Option<u32>,
+    tls: bool,
+    ip_filter: IpFilter,
+    /// Whether to advertise the server over mDNS (`--no-mdns` turns it off).
+    mdns: bool,
+    /// Instance name the server is advertised as (`--name`).
+    name: Option<String>,
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     init_tracing();
     let settings = parse_args();
+    let binds = settings.bind.resolve()?;
+    // With one listener, the WebSocket is wherever HTTP is.
+    let ws_bind = if settings.ws_port.is_some() { binds.ws } else { binds.http };
+    // Bound up front, so what is printed and advertised is the port taken when the one
+    // asked for was busy.
+    let http_listener = net::tcp_listener(SocketAddr::new(binds.http, settings.http_port), "HTTP")?;
+    let ws_listener = settings
+        .ws_port
+        .map(|port| net::tcp_listener(SocketAddr::new(ws_bind, port), "WebSocket"))
+        .transpose()?;
+    let udp_socket = net::udp_socket(SocketAddr::new(binds.udp, settings.udp_port), "UDP")?;
+    let http_port = http_listener.local_addr()?.port();
+    let ws_port = match &ws_listener {
+        Some(listener) => listener.local_addr()?.port(),
+        None => http_port,
+    };
+    let udp_port = udp_socket.local_addr()?.port();
+    let http_hosts = public_hosts(binds.http);
+    let http_host = http_hosts[0];
+
+    let tls = if !settings.tls {
+        None
+    } else if !tls::AVAILABLE {
+        warn!("Ignoring --tls: built without the tls feature");
+        None
+    } else {
+        let mut names: Vec<String> = http_hosts.iter().map(IpAddr::to_string).collect();
+        names.extend(["localhost".to_owned(), "127.0.0.1".to_owned(), "::1".to_owned()]);
+        Some(Tls::load_or_generate(names)?)
+    };
+    let (http_scheme, ws_scheme) = if tls.is_some() { ("https", "wss") } else { ("http", "ws") };
+    let quic = match settings.quic_port {
+        None => None,
+        Some(_) if !quic::AVAILABLE => {
+            warn!("Ignoring --quic-port: built without the quic feature");
+            None
+        }
+        Some(port) => {
+            let socket = net::udp_socket(SocketAddr::new(binds.udp, port), "QUIC")?;
+            let mut names: Vec<String> = public_hosts(binds.udp).iter().map(IpAddr::to_string).collect();
+            names.extend(["localhost".to_owned(), "127.0.0.1".to_owned(), "::1".to_owned()]);
+            Some(quic::Listener::bind(socket, names)?)
+        }
+    };
+    let quic_port = match &quic {
+        Some(listener) => Some(listener.port()?),
+        None => None,
+    };
+    let webtransport = match settings.webtransport_port {
+        None => None,
+        Some(_) if !webtransport::AVAILABLE => {
+            warn!("Ignoring --webtransport-port: built without the webtransport feature");
+            None
+        }
+        Some(port) => {
+            let socket = net::udp_socket(SocketAddr::new(binds.udp, port), "WebTransport")?;
+            let mut names: Vec<String> = public_hosts(binds.udp).iter().map(IpAddr::to_string).collect();
+            names.extend(["localhost".to_owned(), "127.0.0.1".to_owned(), "::1".to_owned()]);
+            Some(webtransport::Listener::bind(socket, names)?)
+        }
+    };
+    let webtransport_endpoint = match &webtransport {
+        Some(listener) => Some(http::WebTransportEndpoint {
+            port: listener.port()?,
+            cert_hash: listener.cert_hash(),
+        }),
+        None => None,
+    };
+    let webrtc = match settings.webrtc_port {
+        None => None,
+        Some(_) if !webrtc::AVAILABLE => {
+            warn!("Ignoring --webrtc-port: built without the webrtc feature");
+            None
+        }
+        Some(port) => {
+            let socket = net::udp_socket(SocketAddr::new(binds.udp, port), "WebRTC")?;
+            Some(webrtc::Listener::bind(socket, &public_hosts(binds.udp))?)
+        }
+    };
+
+    let mut audit = AuditLog::new(settings.audit_level);
+    if let Some(path) = &settings.audit_log {
+        audit = audit.with_file(path)?;
+        info!("📝 Audit log: {}", path.display());
+    }
+    if !settings.webhooks.is_empty() {
+        audit = audit.with_webhooks(Webhooks::start(&settings.webhooks)?);
+        info!("🪝 Posting session events to {} webhook(s)", settings.webhooks.len());
+    }
+    let audit = Arc::new(audit);
+    let sessions = Arc::new(SessionManager::new(settings.arbitration, audit.clone()));
+    let shares = Arc::new(FileShares::new(format!(
+        "{}://{}",
+        http_scheme,
+        SocketAddr::new(http_host, http_port)
+    )));
+    let admin_key: Arc<str> = match &settings.admin_key {
+        Some(key) => key.as_str().into(),
+        None => format!("{:032x}", rand::random::<u128>()).into(),
+    };
+    let ip_filter = Arc::new(settings.ip_filter.clone());
+    if !ip_filter.is_empty() {
+        let list = |blocks: &[Cidr]| blocks.iter().map(Cidr::to_string).collect::<Vec<_>>().join(", ");
+        info!(
+            "🚧 Connections allowed from: {}; denied from: {}",
+            if ip_filter.allow.is_empty() { "anywhere".to_owned() } else { list(&ip_filter.allow) },
+            if ip_filter.deny.is_empty() { "nowhere".to_owned() } else { list(&ip_filter.deny) },
+        );
+    }
+    let access_token: Option<Arc<str>> = settings.access_token.as_deref().map(Into::into);
+    let page_url = |host: IpAddr| {
+        format!(
+            "{}://{}/?ws={}&udp={}{}",
+            http_scheme,
+            SocketAddr::new(host, http_port),
+            ws_port,
+            udp_port,
+            access_token
+                .as_ref()
+                .map(|token| format!("&access_token={token}"))
+                .unwrap_or_default()
+        )
+    };
+    let web_url = page_url(http_host);
+    let trusted = match &settings.trusted {
+        Some(path) => {
+            let store = TrustStore::load(path.clone())?;
+            info!(
+                "Trusting {} device(s) from {}",
+                store.devices().len(),
+                path.display()
+            );
+            store
+        }
+        None => TrustStore::default(),
+    };
+    let pairing = (settings.pairing == Pairing::Pin).then(|| Arc::new(PairingCode::new()));
+    let links = Arc::new(PairingLinks::new(web_url.clone()));
+    let (approval_broker, approval_inbox) = ApprovalBroker::new(
+        settings.auto_approve,
+        settings.approval_timeout,
+        settings.approve_on_timeout,
+        Arc::new(trusted),
+        pairing.clone(),
+        links.clone(),
+        audit,
+    );
+    if let Some(hotkey) = settings.panic_key.clone() {
+        let sessions = sessions.clone();
+        match hotkey::listen(hotkey.clone(), move || sessions.halt()) {
+            Ok(()) => info!("Press {hotkey} to cut off remote input"),
+            Err(err) => warn!("Failed to register panic key {hotkey}: {err}"),
+        }
+    }
 
-    let connection_slot = Arc::new(ConnectionSlot::new());
-    let (approval_broker, approval_rx) = ApprovalBroker::new(settings.auto_approve);
-    tokio::spawn(approval_worker(approval_rx));
+    let actions = match &settings.actions {
+        Some(path) => {
+            let registry = ActionRegistry::load(path)?;
+            info!(
+                "Loaded {} action(s) from {}",
+                registry.names().count(),
+                path.display()
+            );
+            registry
+        }
+        None => ActionRegistry::default(),
+    };
+    let actions = Arc::new(actions);
 
-    let mouse = Arc::new(MouseController::new()?);
+    let clipboard = Clipboard::new(settings.clipboard_sync);
+    let mouse = Arc::new(MouseController::new(settings.backend)?);
+    if backend::input_permission() == Some(false) {
+        let exe = std::env::current_exe().unwrap_or_default();
+        warn!(
+            "⚠️  Penput may not control this Mac, so the cursor won't move. Allow {} under System Settings → Privacy & Security → Accessibility, then restart the server.",
+            exe.display()
+        );
+    }
+    mouse.set_sensitivity(settings.sensitivity);
+    mouse.set_pressure_curve(settings.pressure_curve);
+    mouse.set_key_repeat(settings.key_repeat);
+    mouse.set_region_limit(settings.region);
+    mouse.set_base_transform(settings.transform);
+    mouse.set_dead_zone(settings.dead_zone);
+    mouse.set_base_smoothing(settings.smoothing);
+    mouse.set_prediction(settings.prediction);
+    mouse.set_resample_rate(settings.resample);
+    mouse.set_max_rate(settings.max_rate);
+    mouse.set_scheduling(settings.realtime, settings.pin_cpu);
+    mouse.set_local_pause(settings.local_pause);
+    if let Some(index) = settings.display
+        && !mouse.set_default_display(index)
+    {
+        warn!("Ignoring --display {index}: there are {} display(s)", mouse.displays().len());
+    }
+    for d in mouse.displays() {
+        let default = if d.index == mouse.default_display() {
+            " (default)"
+        } else {
+            ""
+        };
+        info!(
+            "🖥️  [{}] {}: {}x{} at {},{}{}",
+            d.index, d.name, d.width, d.height, d.x, d.y, default
+        );
+    }
+    tokio::spawn(display::watch(mouse.clone()));
+    if !settings.allow_locked {
+        tokio::spawn(lock::watch(mouse.clone()));
+    }
+    mouse.set_hot_corners(actions.hot_corners());
+    tokio::spawn(actions.clone().run_hot_corners(mouse.clone()));
+    if settings.notify && !notify::AVAILABLE {
+        warn!("Ignoring --notify: built without the notify feature");
+    }
+    let mut approval_ui = settings.approval_ui;
+    if approval_ui == ApprovalUi::Dialog && !dialog::AVAILABLE {
+        warn!("Ignoring --approval-ui dialog: built without the dialog feature");
+        approval_ui = ApprovalUi::Terminal;
+    }
+    tokio::spawn(approval_worker(
+        approval_inbox,
+        shares.clone(),
+        sessions.clone(),
+        mouse.clone(),
+        approval_ui,
+        settings.notify && notify::AVAILABLE,
+    ));
 
     info!("🖱️  Penput");
     info!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
     info!(
-        "Server running at:\n  HTTP: http://{}:{}\n  WebSocket: ws://{}:{}/ws\n  UDP (iOS): udp://{}:{}",
-        local_ip(),
-        settings.http_port,
-        local_ip(),
-        settings.ws_port,
-        local_ip(),
-        settings.udp_port,
+        "Server running at:\n  HTTP: {}://{}\n  WebSocket: {}://{}/ws\n  UDP (iOS): udp://{}",
+        http_scheme,
+        SocketAddr::new(http_host, http_port),
+        ws_scheme,
+        SocketAddr::new(public_host(ws_bind), ws_port),
+        SocketAddr::new(public_host(binds.udp), udp_port),
     );
-    info!("Open this URL on your mobile browser.");
+    if let Some(port) = quic_port {
+        info!("  QUIC (native): quic://{}", SocketAddr::new(public_host(binds.udp), port));
+    }
+    if let Some(endpoint) = &webtransport_endpoint {
+        info!(
+            "  WebTransport (experimental): https://{}/",
+            SocketAddr::new(public_host(binds.udp), endpoint.port)
+        );
+    }
+    if let Some(listener) = &webrtc {
+        info!("  WebRTC: udp://{}", SocketAddr::new(public_host(binds.udp), listener.port()?));
+    }
+    info!("Open this URL on your mobile browser: {}", web_url);
+    for host in &http_hosts[1..] {
+        info!("  or over IPv6: {}", page_url(*host));
+    }
+    if let Some(tls) = &tls {
+        info!("🔒 Certificate fingerprint (SHA-256): {}", tls.fingerprint());
+    } else if let Some(listener) = &quic {
+        info!("🔒 Certificate fingerprint (SHA-256): {}", listener.fingerprint());
+    }
+    // The approval page only answers loopback connections, which a listener on another
+    // address never sees.
+    if reaches_loopback(binds.http) && reaches_loopback(ws_bind) {
+        info!(
+            "🔑 Approve connections in a browser on this computer: {}://127.0.0.1:{}/admin.html?key={}&ws={}",
+            http_scheme, http_port, admin_key, ws_port
+        );
+    } else {
+        info!("🔑 The approval page is off: --bind or --interface leaves out 127.0.0.1");
+    }
+    if let Some(pairing) = &pairing {
+        info!("🔢 Pairing code: {} (enter it on the phone to connect)", pairing.code());
+    }
+    links.show();
+    let advertisement = if settings.mdns {
+        let name = settings
+            .name
+            .clone()
+            .or_else(mdns::hostname)
+            .unwrap_or_else(|| "Penput".to_owned());
+        let version = ("version", env!("CARGO_PKG_VERSION").to_owned());
+        let mut udp_properties =
+            vec![version.clone(), ("encryption", settings.udp_encryption.to_string())];
+        if let Some(port) = quic_port {
+            udp_properties.push(("quic", port.to_string()));
+        }
+        let services = vec![
+            mdns::Service {
+                ty: mdns::UDP_SERVICE,
+                bind: binds.udp,
+                port: udp_port,
+                properties: udp_properties,
+            },
+            mdns::Service {
+                ty: mdns::WS_SERVICE,
+                bind: ws_bind,
+                port: ws_port,
+                properties: vec![
+                    version,
+                    ("path", "/ws".to_owned()),
+                    ("tls", u8::from(tls.is_some()).to_string()),
+                    ("http", http_port.to_string()),
+                ],
+            },
+        ];
+        match Advertisement::start(&name, services) {
+            Ok(advertisement) => Some(advertisement),
+            Err(err) => {
+                warn!("Failed to advertise over mDNS: {err}");
+                None
+            }
+        }
+    } else {
+        None
+    };
     info!("Press Ctrl+C to stop.");
 
     let mut tasks = JoinSet::new();
     {
         let state = websocket::AppState {
-            slot: connection_slot.clone(),
+            sessions: sessions.clone(),
             broker: approval_broker.clone(),
+            admin_key: admin_key.clone(),
+            access_token: access_token.clone(),
+            ip_filter: ip_filter.clone(),
+            reapprove_after: settings.reapprove_after,
             mouse: mouse.clone(),
+            actions: actions.clone(),
+            clipboard: clipboard.clone(),
+            shares: shares.clone(),
+            webrtc: webrtc.as_ref().map(webrtc::Listener::peers),
         };
         let ws_router = build_ws_router(state)?;
-        tasks.spawn(websocket::serve_ws(ws_router, settings.ws_port));
+        let ports = http::Discovery {
+            http: http_port,
+            ws: ws_port,
+            udp: udp_port,
+            webtransport: webtransport_endpoint,
+        };
+        let http_router = http::build_http_router(shares.clone(), mouse.clone(), ports)?;
+        match ws_listener {
+            Some(ws_listener) => {
+                tasks.spawn(websocket::serve_ws(ws_router, ws_listener, tls.clone()));
+                tasks.spawn(http::serve_http(http_router, http_listener, tls.clone()));
+            }
+            // One listener: `/ws` and `/admin` are upgrade routes next to the pages.
+            None => {
+                let router = http_router.merge(ws_router);
+                tasks.spawn(websocket::serve_ws(router, http_listener, tls.clone()));
+            }
+        }
     }
 
-    {
-        let http_router = http::build_http_router()?;
-        tasks.spawn(http::serve_http(http_router, settings.http_port));
+    let (quic_sessions, datagrams) = quic::channel();
+    if let Some(listener) = quic {
+        tasks.spawn(listener.serve(quic_sessions.clone(), ip_filter.clone()));
+    }
+    if let Some(listener) = webtransport {
+        tasks.spawn(listener.serve(quic_sessions, ip_filter.clone()));
+    }
+    if let Some(listener) = webrtc {
+        tasks.spawn(listener.serve());
     }
-
     {
         let state = udp::UdpState {
-            slot: connection_slot.clone(),
+            sessions: sessions.clone(),
             broker: approval_broker.clone(),
             mouse: mouse.clone(),
+            actions: actions.clone(),
+            clipboard: clipboard.clone(),
+            encryption: settings.udp_encryption,
+            ip_filter: ip_filter.clone(),
+            reapprove_after: settings.reapprove_after,
+            rate_limit: settings.udp_rate_limit,
         };
-        tasks.spawn(udp::serve_udp(state, settings.udp_port));
+        tasks.spawn(udp::serve_udp(state, udp_socket, datagrams));
     }
 
-    while let Some(res) = tasks.join_next().await {
-        if let Err(err) = res {
-            error!("Server task failed: {err}");
+    let servers = async {
+        while let Some(res) = tasks.join_next().await {
+            if let Err(err) = res {
+                error!("Server task failed: {err}");
+            }
         }
+    };
+    tokio::select! {
+        _ = servers => {}
+        _ = shutdown_signal() => info!("Shutting down"),
+    }
+
+    // WebSocket clients get a close frame and UDP clients GOODBYE; each session then
+    // releases its place and held input.
+    sessions.shut_down();
+    if tokio::time::timeout(SHUTDOWN_GRACE, sessions.all_gone()).await.is_err() {
+        warn!("Some clients didn't disconnect in time");
     }
 
+    // Stop the servers first so nothing enqueues while the worker drains and releases input.
+    tasks.abort_all();
+    if let Some(advertisement) = advertisement {
+        advertisement.stop();
+    }
+    mouse.shutdown();
     Ok(())
 }
 
+/// Resolves on Ctrl+C, or on SIGTERM where there is one (`kill`, systemd, Docker).
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+        if let Ok(mut term) = signal(SignalKind::terminate()) {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = term.recv() => {}
+            }
+            return;
+        }
+    }
+    let _ = tokio::signal::ctrl_c().await;
+}
+
 fn init_tracing() {
     let subscriber = FmtSubscriber::builder()
         .with_max_level(tracing::Level::INFO)
@@ -89,9 +578,52 @@ fn init_tracing() {
 
 fn parse_args() -> Settings {
     let mut http_port = 8080u16;
-    let mut ws_port = 9001u16;
+    let mut ws_port = None;
     let mut udp_port = 9002u16;
+    let mut quic_port = None;
+    let mut webtransport_port = None;
+    let mut webrtc_port = None;
+    let mut bind = Bind::default();
     let mut auto_approve = false;
+    let mut sensitivity = DEFAULT_SENSITIVITY;
+    let mut pressure_curve = PressureCurve::default();
+    let mut repeat_delay_ms = KeyRepeat::default().delay.as_millis() as u64;
+    let mut repeat_rate = 1.0 / KeyRepeat::default().interval.as_secs_f64();
+    let mut actions = None;
+    let mut clipboard_sync = false;
+    let mut region = None;
+    let mut transform = Transform::default();
+    let mut dead_zone = 0.0;
+    let mut smoothing = None;
+    let mut prediction_ms = 0u64;
+    let mut resample = None;
+    let mut max_rate = None;
+    let mut display = None;
+    let mut backend = BackendKind::default();
+    let mut realtime = false;
+    let mut pin_cpu = None;
+    let mut local_pause = Duration::ZERO;
+    let mut allow_locked = false;
+    let mut panic_key = Hotkey::parse(DEFAULT_PANIC_KEY);
+    let mut arbitration = Arbitration::default();
+    let mut ip_filter = IpFilter::default();
+    let mut approval_timeout = None;
+    let mut reapprove_after = None;
+    let mut approve_on_timeout = false;
+    let mut notify = false;
+    let mut approval_ui = ApprovalUi::default();
+    let mut admin_key = None;
+    let mut trusted = trust::default_path();
+    let mut audit_log = None;
+    let mut audit_level = AuditLevel::default();
+    let mut webhooks = Vec::new();
+    let mut access_token = Some(format!("{:032x}", rand::random::<u128>()));
+    let mut pairing = Pairing::default();
+    let mut udp_encryption = Encryption::default();
+    let mut udp_rate_limit = Some(ratelimit::DEFAULT_RATE);
+    let mut tls = false;
+    let mut mdns = true;
+    let mut name = None;
 
     let mut args = std::env::args().skip(1);
     while let Some(arg) = args.next() {
@@ -103,7 +635,10 @@ fn parse_args() -> Settings {
             }
             "--ws-port" => {
                 if let Some(val) = args.next() {
-                    ws_port = val.parse().unwrap_or(ws_port);
+                    match val.parse() {
+                        Ok(port) => ws_port = Some(port),
+                        Err(_) => warn!("Ignoring invalid --ws-port: {val}"),
+                    }
                 }
             }
             "--udp-port" => {
@@ -111,21 +646,506 @@ fn parse_args() -> Settings {
                     udp_port = val.parse().unwrap_or(udp_port);
                 }
             }
+            "--quic-port" => {
+                if let Some(val) = args.next() {
+                    match val.parse() {
+                        Ok(port) => quic_port = Some(port),
+                        Err(_) => warn!("Ignoring invalid --quic-port: {val}"),
+                    }
+                }
+            }
+            "--webtransport-port" => {
+                if let Some(val) = args.next() {
+                    match val.parse() {
+                        Ok(port) => webtransport_port = Some(port),
+                        Err(_) => warn!("Ignoring invalid --webtransport-port: {val}"),
+                    }
+                }
+            }
+            "--webrtc-port" => {
+                if let Some(val) = args.next() {
+                    match val.parse() {
+                        Ok(port) => webrtc_port = Some(port),
+                        Err(_) => warn!("Ignoring invalid --webrtc-port: {val}"),
+                    }
+                }
+            }
+            "--bind" => {
+                if let Some(val) = args.next() {
+                    let (service, ip) = match val.split_once('=') {
+                        Some((service, ip)) => (Some(service), ip),
+                        None => (None, val.as_str()),
+                    };
+                    let slot = match service {
+                        None => Some(&mut bind.all),
+                        Some("http") => Some(&mut bind.http),
+                        Some("ws") => Some(&mut bind.ws),
+                        Some("udp") => Some(&mut bind.udp),
+                        Some(_) => None,
+                    };
+                    match (slot, ip.parse::<IpAddr>()) {
+                        (Some(slot), Ok(ip)) => *slot = Some(ip),
+                        _ => warn!("Ignoring invalid --bind: {val}"),
+                    }
+                }
+            }
+            "--interface" => {
+                if let Some(val) = args.next() {
+                    bind.interface = Some(val);
+                }
+            }
             "--auto-approve" => {
                 auto_approve = true;
             }
+            "--sensitivity" => {
+                if let Some(val) = args.next() {
+                    sensitivity = val.parse().unwrap_or(sensitivity);
+                }
+            }
+            "--pressure-curve" => {
+                if let Some(val) = args.next() {
+                    match PressureCurve::parse(&val) {
+                        Some(curve) => pressure_curve = curve,
+                        None => warn!("Ignoring invalid --pressure-curve: {val}"),
+                    }
+                }
+            }
+            "--repeat-delay" => {
+                if let Some(val) = args.next() {
+                    repeat_delay_ms = val.parse().unwrap_or(repeat_delay_ms);
+                }
+            }
+            "--repeat-rate" => {
+                if let Some(val) = args.next() {
+                    repeat_rate = val.parse().unwrap_or(repeat_rate);
+                }
+            }
+            "--clipboard-sync" => {
+                clipboard_sync = true;
+            }
+            "--region" => {
+                if let Some(val) = args.next() {
+                    match Region::parse(&val) {
+                        Some(r) => region = Some(r),
+                        None => warn!("Ignoring invalid --region: {val}"),
+                    }
+                }
+            }
+            "--rotate" => {
+                if let Some(val) = args.next() {
+                    match val.parse().ok().and_then(Rotation::from_degrees) {
+                        Some(r) => transform.rotate = r,
+                        None => warn!("Ignoring invalid --rotate: {val}"),
+                    }
+                }
+            }
+            "--invert-x" => {
+                transform.invert_x = true;
+            }
+            "--invert-y" => {
+                transform.invert_y = true;
+            }
+            "--dead-zone" => {
+                if let Some(val) = args.next() {
+                    dead_zone = val.parse().unwrap_or(dead_zone);
+                }
+            }
+            "--smoothing" => {
+                if let Some(val) = args.next() {
+                    match OneEuro::parse(&val) {
+                        Some(params) => smoothing = Some(params),
+                        None => warn!("Ignoring invalid --smoothing: {val}"),
+                    }
+                }
+            }
+            "--predict" => {
+                if let Some(val) = args.next() {
+                    prediction_ms = val.parse().unwrap_or(prediction_ms);
+                }
+            }
+            "--resample" => {
+                if let Some(val) = args.next() {
+                    match val.parse().ok().filter(|r| RESAMPLE_RATES.contains(r)) {
+                        Some(rate) => resample = Some(rate),
+                        None => warn!("Ignoring invalid --resample: {val}"),
+                    }
+                }
+            }
+            "--max-rate" => {
+                if let Some(val) = args.next() {
+                    match val.parse().ok().filter(|r| *r > 0) {
+                        Some(rate) => max_rate = Some(rate),
+                        None => warn!("Ignoring invalid --max-rate: {val}"),
+                    }
+                }
+            }
+            "--display" => {
+                if let Some(val) = args.next() {
+                    match val.parse() {
+                        Ok(index) => display = Some(index),
+                        Err(_) => warn!("Ignoring invalid --display: {val}"),
+                    }
+                }
+            }
+            "--backend" => {
+                if let Some(val) = args.next() {
+                    match BackendKind::parse(&val) {
+                        Some(kind) => backend = kind,
+                        None => warn!("Ignoring invalid --backend: {val}"),
+                    }
+                }
+            }
+            "--realtime" => realtime = true,
+            "--pin-cpu" => {
+                if let Some(val) = args.next() {
+                    match val.parse() {
+                        Ok(cpu) => pin_cpu = Some(cpu),
+                        Err(_) => warn!("Ignoring invalid --pin-cpu: {val}"),
+                    }
+                }
+            }
+            "--local-pause" => {
+                if let Some(val) = args.next() {
+                    match val.parse() {
+                        Ok(ms) => local_pause = Duration::from_millis(ms),
+                        Err(_) => warn!("Ignoring invalid --local-pause: {val}"),
+                    }
+                }
+            }
+            "--allow-locked" => allow_locked = true,
+            "--panic-key" => {
+                if let Some(val) = args.next() {
+                    if val == "off" {
+                        panic_key = None;
+                    } else {
+                        match Hotkey::parse(&val) {
+                            Some(hotkey) => panic_key = Some(hotkey),
+                            None => warn!("Ignoring invalid --panic-key: {val}"),
+                        }
+                    }
+                }
+            }
+            "--allow" | "--deny" => {
+                if let Some(val) = args.next() {
+                    let blocks = if arg == "--allow" {
+                        &mut ip_filter.allow
+                    } else {
+                        &mut ip_filter.deny
+                    };
+                    for block in val.split(',').map(str::trim).filter(|b| !b.is_empty()) {
+                        match Cidr::parse(block) {
+                            Some(cidr) => blocks.push(cidr),
+                            None => warn!("Ignoring invalid {arg} address: {block}"),
+                        }
+                    }
+                }
+            }
+            "--arbitration" => {
+                if let Some(val) = args.next() {
+                    match Arbitration::parse(&val) {
+                        Some(policy) => arbitration = policy,
+                        None => warn!("Ignoring invalid --arbitration: {val}"),
+                    }
+                }
+            }
+            "--approval-timeout" => {
+                if let Some(val) = args.next() {
+                    if val == "off" {
+                        approval_timeout = None;
+                    } else {
+                        match parse_duration(&val) {
+                            Some(timeout) if !timeout.is_zero() => {
+                                approval_timeout = Some(timeout)
+                            }
+                            _ => warn!("Ignoring invalid --approval-timeout: {val}"),
+                        }
+                    }
+                }
+            }
+            "--approve-on-timeout" => approve_on_timeout = true,
+            "--reapprove-after" => {
+                if let Some(val) = args.next() {
+                    if val == "off" {
+                        reapprove_after = None;
+                    } else {
+                        match parse_duration(&val) {
+                            Some(after) if !after.is_zero() => reapprove_after = Some(after),
+                            _ => warn!("Ignoring invalid --reapprove-after: {val}"),
+                        }
+                    }
+                }
+            }
+            "--notify" => notify = true,
+            "--approval-ui" => {
+                if let Some(val) = args.next() {
+                    match ApprovalUi::parse(&val) {
+                        Some(ui) => approval_ui = ui,
+                        None => warn!("Ignoring invalid --approval-ui: {val}"),
+                    }
+                }
+            }
+            "--admin-key" => {
+                if let Some(val) = args.next() {
+                    if val.is_empty() {
+                        warn!("Ignoring empty --admin-key");
+                    } else {
+                        admin_key = Some(val);
+                    }
+                }
+            }
+            "--access-token" => {
+                if let Some(val) = args.next() {
+                    if val.is_empty() {
+                        warn!("Ignoring empty --access-token");
+                    } else if val == "off" {
+                        access_token = None;
+                    } else {
+                        access_token = Some(val);
+                    }
+                }
+            }
+            "--trusted" => {
+                if let Some(val) = args.next() {
+                    trusted = if val == "off" {
+                        None
+                    } else {
+                        Some(PathBuf::from(val))
+                    };
+                }
+            }
+            "--audit-log" => {
+                if let Some(val) = args.next() {
+                    audit_log = Some(PathBuf::from(val));
+                }
+            }
+            "--audit-level" => {
+                if let Some(val) = args.next() {
+                    match AuditLevel::parse(&val) {
+                        Some(level) => audit_level = level,
+                        None => warn!("Ignoring invalid --audit-level: {val}"),
+                    }
+                }
+            }
+            "--webhook" => {
+                if let Some(val) = args.next() {
+                    match Url::parse(&val) {
+                        Ok(url) if url.scheme() == "https" && !tls::AVAILABLE => {
+                            warn!("Ignoring --webhook {}: https needs the tls feature", url.origin().ascii_serialization());
+                        }
+                        Ok(url) if matches!(url.scheme(), "http" | "https") => webhooks.push(url),
+                        _ => warn!("Ignoring invalid --webhook: not an http(s) URL"),
+                    }
+                }
+            }
+            "--pairing" => {
+                if let Some(val) = args.next() {
+                    match Pairing::parse(&val) {
+                        Some(mode) => pairing = mode,
+                        None => warn!("Ignoring invalid --pairing: {val}"),
+                    }
+                }
+            }
+            "--udp-encryption" => {
+                if let Some(val) = args.next() {
+                    match Encryption::parse(&val) {
+                        Some(mode) => udp_encryption = mode,
+                        None => warn!("Ignoring invalid --udp-encryption: {val}"),
+                    }
+                }
+            }
+            "--udp-rate-limit" => {
+                if let Some(val) = args.next() {
+                    if val == "off" {
+                        udp_rate_limit = None;
+                    } else {
+                        match val.parse().ok().filter(|r| *r > 0) {
+                            Some(rate) => udp_rate_limit = Some(rate),
+                            None => warn!("Ignoring invalid --udp-rate-limit: {val}"),
+                        }
+                    }
+                }
+            }
+            "--tls" => tls = true,
+            "--no-mdns" => mdns = false,
+            "--name" => {
+                if let Some(val) = args.next() {
+                    if val.trim().is_empty() {
+                        warn!("Ignoring empty --name");
+                    } else {
+                        name = Some(val);
+                    }
+                }
+            }
+            "--actions" => {
+                if let Some(val) = args.next() {
+                    actions = Some(PathBuf::from(val));
+                }
+            }
             _ => {}
         }
     }
 
+    let key_repeat = KeyRepeat::new(repeat_delay_ms, repeat_rate).unwrap_or_else(|| {
+        warn!("Ignoring out-of-range key repeat settings; using defaults");
+        KeyRepeat::default()
+    });
+
     Settings {
         http_port,
         ws_port,
         udp_port,
+        quic_port,
+        webtransport_port,
+        webrtc_port,
+        bind,
         auto_approve,
+        sensitivity,
+        pressure_curve,
+        key_repeat,
+        actions,
+        clipboard_sync,
+        region,
+        transform,
+        dead_zone,
+        smoothing,
+        prediction: Duration::from_millis(prediction_ms),
+        resample,
+        max_rate,
+        display,
+        backend,
+        realtime,
+        pin_cpu,
+        local_pause,
+        allow_locked,
+        panic_key,
+        arbitration,
+        approval_timeout,
+        reapprove_after,
+        approve_on_timeout,
+        notify,
+        approval_ui,
+        admin_key,
+        access_token,
+        trusted,
+        audit_log,
+        audit_level,
+        webhooks,
+        pairing,
+        udp_encryption,
+        udp_rate_limit,
+        tls,
+        ip_filter,
+        mdns,
+        name,
+    }
+}
+
+/// A duration such as `500ms`, `30s` or `2m`; a bare number is seconds.
+fn parse_duration(val: &str) -> Option<Duration> {
+    let (num, unit) = match val.find(|c: char| c.is_ascii_alphabetic()) {
+        Some(i) => val.split_at(i),
+        None => (val, "s"),
+    };
+    let num: f64 = num.trim().parse().ok()?;
+    let secs = match unit {
+        "ms" => num / 1000.0,
+        "s" => num,
+        "m" => num * 60.0,
+        _ => return None,
+    };
+    Duration::try_from_secs_f64(secs).ok()
+}
+
+/// This computer's addresses on the network: its IPv4 address and one IPv6 address, for
+/// IPv6-only networks. Link-local IPv6 addresses are left out, as a URL can't carry
+/// their zone for the phone.
+fn local_ips() -> Vec<IpAddr> {
+    let mut ips: Vec<IpAddr> = local_ip_address::local_ip().into_iter().collect();
+    let ipv6 = local_ip_address::list_afinet_netifas()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(_, ip)| ip)
+        .find(|ip| match ip {
+            IpAddr::V6(ip) => !ip.is_loopback() && !ip.is_unicast_link_local(),
+            IpAddr::V4(_) => false,
+        });
+    ips.extend(ipv6);
+    ips
+}
+
+/// Where the servers listen (`--bind`, `--interface`). Unset, they listen on every
+/// address.
+#[derive(Debug, Clone, Default)]
+struct Bind {
+    all: Option<IpAddr>,
+    http: Option<IpAddr>,
+    ws: Option<IpAddr>,
+    udp: Option<IpAddr>,
+    /// Network interface whose address replaces `all`.
+    interface: Option<String>,
+}
+
+/// The address each server listens on.
+struct Binds {
+    http: IpAddr,
+    ws: IpAddr,
+    udp: IpAddr,
+}
+
+impl Bind {
+    fn resolve(&self) -> anyhow::Result<Binds> {
+        let all = match &self.interface {
+            Some(name) => {
+                let ip = interface_ip(name)?;
+                info!("Listening only on {name} ({ip})");
+                ip
+            }
+            // Dual stack: IPv4 clients connect to it too.
+            None => self.all.unwrap_or(IpAddr::V6(Ipv6Addr::UNSPECIFIED)),
+        };
+        Ok(Binds {
+            http: self.http.unwrap_or(all),
+            ws: self.ws.unwrap_or(all),
+            udp: self.udp.unwrap_or(all),
+        })
+    }
+}
+
+/// The address of network interface `name`, IPv4 if it has one.
+fn interface_ip(name: &str) -> anyhow::Result<IpAddr> {
+    let interfaces = local_ip_address::list_afinet_netifas()
+        .map_err(|err| anyhow::anyhow!("Failed to list network interfaces: {err}"))?;
+    let ips: Vec<IpAddr> = interfaces.iter().filter(|(n, _)| n == name).map(|(_, ip)| *ip).collect();
+    match ips.iter().find(|ip| ip.is_ipv4()).or(ips.first()) {
+        Some(ip) => Ok(*ip),
+        None => {
+            let mut names: Vec<&str> = interfaces.iter().map(|(n, _)| n.as_str()).collect();
+            names.sort_unstable();
+            names.dedup();
+            anyhow::bail!("No network interface named {name} (have: {})", names.join(", "))
+        }
+    }
+}
+
+/// The address clients reach a server listening on `bind` at.
+fn public_host(bind: IpAddr) -> IpAddr {
+    public_hosts(bind)[0]
+}
+
+/// The addresses clients reach a server listening on `bind` at, the one to show first.
+/// Everywhere on IPv6 (`::`) takes IPv4 too, so it is reached over both.
+fn public_hosts(bind: IpAddr) -> Vec<IpAddr> {
+    if !bind.is_unspecified() {
+        return vec![bind];
     }
+    let ips: Vec<IpAddr> = local_ips()
+        .into_iter()
+        .filter(|ip| bind.is_ipv6() || ip.is_ipv4())
+        .collect();
+    if ips.is_empty() { vec![IpAddr::V4(Ipv4Addr::LOCALHOST)] } else { ips }
 }
 
-fn local_ip() -> IpAddr {
-    local_ip_address::local_ip().unwrap_or(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)))
+/// Whether a server listening on `bind` accepts connections to 127.0.0.1.
+fn reaches_loopback(bind: IpAddr) -> bool {
+    bind.is_unspecified() || bind.is_loopback()
 }
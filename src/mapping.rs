@@ -0,0 +1,244 @@
+use serde::{Deserialize, Serialize};
+
+/// Clockwise rotation of the client's coordinate space, in quarter turns.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "u16")]
+pub enum Rotation {
+    #[default]
+    None,
+    Cw90,
+    Cw180,
+    Cw270,
+}
+
+impl Rotation {
+    pub fn from_degrees(degrees: u16) -> Option<Self> {
+        match degrees {
+            0 => Some(Rotation::None),
+            90 => Some(Rotation::Cw90),
+            180 => Some(Rotation::Cw180),
+            270 => Some(Rotation::Cw270),
+            _ => None,
+        }
+    }
+
+    /// Decode a count of quarter turns (the UDP encoding).
+    pub fn from_quarter_turns(turns: u8) -> Option<Self> {
+        Self::from_degrees(u16::from(turns) * 90)
+    }
+}
+
+impl TryFrom<u16> for Rotation {
+    type Error = &'static str;
+
+    fn try_from(degrees: u16) -> Result<Self, Self::Error> {
+        Self::from_degrees(degrees).ok_or("rotation must be 0, 90, 180 or 270")
+    }
+}
+
+/// Axis inversion followed by rotation, for rotated monitors or a phone held sideways.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct Transform {
+    pub rotate: Rotation,
+    pub invert_x: bool,
+    pub invert_y: bool,
+}
+
+impl Transform {
+    /// Map a point given as fractions (0–1) of the client's width and height.
+    pub fn apply(&self, u: f64, v: f64) -> (f64, f64) {
+        let u = if self.invert_x { 1.0 - u } else { u };
+        let v = if self.invert_y { 1.0 - v } else { v };
+        match self.rotate {
+            Rotation::None => (u, v),
+            Rotation::Cw90 => (1.0 - v, u),
+            Rotation::Cw180 => (1.0 - u, 1.0 - v),
+            Rotation::Cw270 => (v, 1.0 - u),
+        }
+    }
+
+    /// Map a relative move, so trackpad motion turns with the coordinate space.
+    pub fn apply_delta(&self, dx: f64, dy: f64) -> (f64, f64) {
+        let dx = if self.invert_x { -dx } else { dx };
+        let dy = if self.invert_y { -dy } else { dy };
+        match self.rotate {
+            Rotation::None => (dx, dy),
+            Rotation::Cw90 => (-dy, dx),
+            Rotation::Cw180 => (-dx, -dy),
+            Rotation::Cw270 => (dy, -dx),
+        }
+    }
+
+    /// Whether the client's width ends up along the desktop's vertical axis.
+    pub fn swaps_axes(&self) -> bool {
+        matches!(self.rotate, Rotation::Cw90 | Rotation::Cw270)
+    }
+}
+
+/// A rectangle in desktop pixels, fractional so scaling stays exact until the end.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Rect {
+    pub x: f64,
+    pub y: f64,
+    pub w: f64,
+    pub h: f64,
+}
+
+impl Rect {
+    /// The desktop pixel at fractions `u`/`v` of the rectangle.
+    pub fn at(&self, u: f64, v: f64) -> (i32, i32) {
+        ((self.x + u * self.w) as i32, (self.y + v * self.h) as i32)
+    }
+}
+
+/// How the client's viewport is laid over the mapped area when their shapes differ.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AspectMode {
+    /// Stretch to cover the whole area; simple, but circles drawn on the phone become ovals.
+    #[default]
+    Fill,
+    /// Largest centered rectangle with the client's shape; the rest of the area is unreachable.
+    Fit,
+    /// One client pixel per desktop pixel around the center; the rest of the area is cropped.
+    Crop,
+}
+
+impl AspectMode {
+    /// Decode the UDP mode byte.
+    pub fn from_code(code: u8) -> Option<Self> {
+        match code {
+            0 => Some(AspectMode::Fill),
+            1 => Some(AspectMode::Fit),
+            2 => Some(AspectMode::Crop),
+            _ => None,
+        }
+    }
+
+    pub fn code(self) -> u8 {
+        match self {
+            AspectMode::Fill => 0,
+            AspectMode::Fit => 1,
+            AspectMode::Crop => 2,
+        }
+    }
+
+    /// Where a `client_w`x`client_h` viewport lands within `area`.
+    pub fn place(self, client_w: f64, client_h: f64, area: Rect) -> Rect {
+        let (w, h) = match self {
+            AspectMode::Fill => return area,
+            AspectMode::Fit => {
+                let scale = (area.w / client_w).min(area.h / client_h);
+                (client_w * scale, client_h * scale)
+            }
+            AspectMode::Crop => (client_w, client_h),
+        };
+        Rect {
+            x: area.x + (area.w - w) / 2.0,
+            y: area.y + (area.h - h) / 2.0,
+            w,
+            h,
+        }
+    }
+}
+
+/// Where the four calibration targets sit, as fractions of the mapped area: top-left,
+/// top-right, bottom-right, bottom-left, inset so they are easy to hit.
+pub const CALIBRATION_TARGETS: [(f64, f64); 4] = [(0.1, 0.1), (0.9, 0.1), (0.9, 0.9), (0.1, 0.9)];
+
+/// Projective map from client fractions to desktop fractions, built by calibration.
+///
+/// Unlike plain scaling it corrects any mismatch between what the client shows and the
+/// screen: offsets, aspect ratio, rotation, even a keystoned camera view.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Homography([f64; 9]);
+
+impl Homography {
+    /// Fit the map from the client points tapped for each of [`CALIBRATION_TARGETS`], given
+    /// as fractions of the client's size. Fails unless they form a convex quadrilateral.
+    pub fn calibrate(points: [(f64, f64); 4]) -> Option<Self> {
+        if !points.iter().all(|(x, y)| x.is_finite() && y.is_finite()) || !is_convex(&points) {
+            return None;
+        }
+        let mut h = Self::from_points(points, CALIBRATION_TARGETS)?;
+        // Scale so the projective weight is positive over the tapped area; `apply` treats
+        // a non-positive weight as beyond the horizon.
+        let (cx, cy) = points
+            .iter()
+            .fold((0.0, 0.0), |(sx, sy), (x, y)| (sx + x / 4.0, sy + y / 4.0));
+        if h.weight(cx, cy) < 0.0 {
+            h.0.iter_mut().for_each(|v| *v = -*v);
+        }
+        Some(h)
+    }
+
+    /// [`Homography::calibrate`] for points in pixels of a `width`x`height` client viewport.
+    pub fn from_client_points(points: [(u16, u16); 4], width: u16, height: u16) -> Option<Self> {
+        if width == 0 || height == 0 {
+            return None;
+        }
+        let (w, h) = (f64::from(width), f64::from(height));
+        Self::calibrate(points.map(|(x, y)| (f64::from(x) / w, f64::from(y) / h)))
+    }
+
+    /// Solve for the map taking each `src` point to the matching `dst` point.
+    fn from_points(src: [(f64, f64); 4], dst: [(f64, f64); 4]) -> Option<Self> {
+        // Eight equations in h0..h7 with h8 fixed at 1.
+        let mut m = [[0.0f64; 9]; 8];
+        for (i, ((x, y), (u, v))) in src.into_iter().zip(dst).enumerate() {
+            m[2 * i] = [x, y, 1.0, 0.0, 0.0, 0.0, -x * u, -y * u, u];
+            m[2 * i + 1] = [0.0, 0.0, 0.0, x, y, 1.0, -x * v, -y * v, v];
+        }
+
+        // Gaussian elimination with partial pivoting.
+        for col in 0..8 {
+            let pivot = (col..8).max_by(|&a, &b| m[a][col].abs().total_cmp(&m[b][col].abs()))?;
+            if m[pivot][col].abs() < 1e-12 {
+                return None;
+            }
+            m.swap(col, pivot);
+            let pivot_row = m[col];
+            for (r, row) in m.iter_mut().enumerate() {
+                if r != col {
+                    let factor = row[col] / pivot_row[col];
+                    for (v, p) in row[col..].iter_mut().zip(&pivot_row[col..]) {
+                        *v -= factor * p;
+                    }
+                }
+            }
+        }
+
+        let mut h = [1.0; 9];
+        for (i, row) in m.iter().enumerate() {
+            h[i] = row[8] / row[i];
+        }
+        h.iter().all(|v| v.is_finite()).then_some(Self(h))
+    }
+
+    fn weight(&self, x: f64, y: f64) -> f64 {
+        self.0[6] * x + self.0[7] * y + self.0[8]
+    }
+
+    /// Map a client point, or `None` if it lies past the horizon of a strongly skewed view.
+    pub fn apply(&self, x: f64, y: f64) -> Option<(f64, f64)> {
+        let h = &self.0;
+        let w = self.weight(x, y);
+        (w > 1e-9).then(|| {
+            (
+                (h[0] * x + h[1] * y + h[2]) / w,
+                (h[3] * x + h[4] * y + h[5]) / w,
+            )
+        })
+    }
+}
+
+/// Whether the points, in order, turn the same way at every corner.
+fn is_convex(points: &[(f64, f64); 4]) -> bool {
+    let turn = |i: usize| {
+        let (a, b, c) = (points[i], points[(i + 1) % 4], points[(i + 2) % 4]);
+        (b.0 - a.0) * (c.1 - b.1) - (b.1 - a.1) * (c.0 - b.0)
+    };
+    let turns = [turn(0), turn(1), turn(2), turn(3)];
+    turns.iter().all(|t| *t > 0.0) || turns.iter().all(|t| *t < 0.0)
+}
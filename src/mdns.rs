@@ -0,0 +1,69 @@
+use mdns_sd::{ServiceDaemon, ServiceInfo};
+use std::net::IpAddr;
+use tracing::{info, warn};
+
+/// Service type the UDP server (native clients) is advertised as.
+pub const UDP_SERVICE: &str = "_penput._udp.local.";
+/// Service type the WebSocket server (web client) is advertised as.
+pub const WS_SERVICE: &str = "_penput-ws._tcp.local.";
+
+/// A server to advertise: where it listens and what its TXT record says about it.
+pub struct Service {
+    pub ty: &'static str,
+    pub bind: IpAddr,
+    pub port: u16,
+    pub properties: Vec<(&'static str, String)>,
+}
+
+/// The server advertised over mDNS/Bonjour, so clients on the local network find it by
+/// name instead of by address. Withdrawn when [`stop`](Self::stop)ped.
+pub struct Advertisement {
+    daemon: ServiceDaemon,
+}
+
+impl Advertisement {
+    /// Advertise `services` under the instance name `name`. Services listening everywhere
+    /// announce every address of this computer, and follow them as they change.
+    pub fn start(name: &str, services: Vec<Service>) -> anyhow::Result<Self> {
+        let daemon = ServiceDaemon::new()?;
+        // Not this computer's own host name, which its mDNS responder already answers for.
+        let host = format!("penput-{}.local.", host_label(name));
+        for service in services {
+            let properties = service.properties.as_slice();
+            let info = if service.bind.is_unspecified() {
+                ServiceInfo::new(service.ty, name, &host, (), service.port, properties)?
+                    .enable_addr_auto()
+            } else {
+                ServiceInfo::new(service.ty, name, &host, service.bind, service.port, properties)?
+            };
+            daemon.register(info)?;
+        }
+        info!("📡 Advertising as \"{name}\" over mDNS");
+        Ok(Self { daemon })
+    }
+
+    /// Withdraw the services, telling clients the server is gone.
+    pub fn stop(self) {
+        if let Err(err) = self.daemon.shutdown() {
+            warn!("Failed to stop mDNS advertisement: {err}");
+        }
+    }
+}
+
+/// The name this computer goes by, for the default instance name.
+pub fn hostname() -> Option<String> {
+    let out = std::process::Command::new("hostname").output().ok()?;
+    let name = String::from_utf8(out.stdout).ok()?;
+    let name = name.trim().split('.').next().unwrap_or_default();
+    (!name.is_empty()).then(|| name.to_owned())
+}
+
+/// `name` as a DNS label: letters, digits and hyphens only.
+fn host_label(name: &str) -> String {
+    let label: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '-' })
+        .collect();
+    let label = label.trim_matches('-');
+    if label.is_empty() { "server".to_owned() } else { label.to_owned() }
+}
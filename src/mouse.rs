@@ -1,8 +1,67 @@
-use anyhow::{Context, Result};
-use display_info::DisplayInfo;
-use enigo::{Coordinate, Enigo, Mouse};
-use std::sync::{Arc, Condvar, Mutex};
-use std::thread;
+use crate::backend::{self, BackendKind, PointerBackend};
+use crate::corners::{Corner, HotCorners};
+use crate::cursor::CursorPos;
+use crate::display::Display;
+use crate::gamepad::{self, GamepadBackend, GamepadState};
+use crate::keyboard::{HeldKeys, KeyCode, KeyRepeat, Modifier, StickyModifiers};
+use crate::mapping::{AspectMode, Homography, Rect, Transform};
+use crate::pointer::PointerProfile;
+use crate::predict::Predictor;
+use crate::region::Region;
+use crate::resample::Resampler;
+use crate::sched;
+use crate::smoothing::{OneEuro, OneEuroFilter};
+use crate::stylus::{self, PenBackend, PenReport, PressureCurve, StylusSample};
+use crate::touch::{self, MAX_CONTACTS, TouchBackend, TouchContact, TouchPoint};
+use crate::window::{FocusedWindow, MapTarget};
+use anyhow::{Context, Result, anyhow, bail};
+use enigo::{Axis, Coordinate, Direction, Enigo, Keyboard, Mouse};
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread::{self, Thread};
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, oneshot};
+use tracing::{info, warn};
+
+/// High-resolution scroll units per wheel notch (matches Windows `WHEEL_DELTA`).
+pub const SCROLL_UNITS_PER_NOTCH: i32 = 120;
+
+/// Highest click count accepted (triple click selects a paragraph; more is never useful).
+pub const MAX_CLICK_COUNT: u8 = 3;
+
+/// Gap between the clicks of a multi-click, well inside every OS double-click threshold.
+const MULTI_CLICK_GAP: Duration = Duration::from_millis(40);
+
+/// Interval between momentum scroll steps (about one display frame).
+const MOMENTUM_TICK: Duration = Duration::from_millis(16);
+
+/// Time for fling velocity to decay to ~37%; close to native trackpad friction.
+const MOMENTUM_TIME_CONSTANT: f64 = 0.325;
+
+/// Momentum stops once it slows below this many scroll units per second.
+const MOMENTUM_MIN_VELOCITY: f64 = 60.0;
+
+/// Fling velocities are clamped to this many scroll units per second.
+const MAX_FLING_VELOCITY: f64 = 120.0 * 200.0;
+
+/// Waits between attempts to connect to the input system at startup.
+const ENIGO_RETRY_DELAYS: [Duration; 5] = [
+    Duration::from_millis(250),
+    Duration::from_millis(500),
+    Duration::from_secs(1),
+    Duration::from_secs(2),
+    Duration::from_secs(4),
+];
+
+/// How far the cursor may stray from where remote input put it, in desktop pixels, before
+/// it counts as moved by the local mouse (rounding and sub-pixel backends drift a little).
+const LOCAL_MOTION_TOLERANCE: i32 = 2;
+
+/// Default multiplier applied to relative (trackpad) deltas.
+pub const DEFAULT_SENSITIVITY: f64 = 1.0;
 
 #[derive(Debug, Copy, Clone)]
 struct MoveCmd {
@@ -12,69 +71,1479 @@ struct MoveCmd {
     y: u16,
 }
 
-struct SharedMove {
-    latest: Mutex<Option<MoveCmd>>,
-    cv: Condvar,
+impl MoveCmd {
+    /// Packed into one word for [`MoveSlot`]; `0` is free to mean empty since moves with a
+    /// zero client size are never queued.
+    fn to_bits(self) -> u64 {
+        u64::from(self.client_w) << 48
+            | u64::from(self.client_h) << 32
+            | u64::from(self.x) << 16
+            | u64::from(self.y)
+    }
+
+    fn from_bits(bits: u64) -> Option<Self> {
+        (bits != 0).then_some(MoveCmd {
+            client_w: (bits >> 48) as u16,
+            client_h: (bits >> 32) as u16,
+            x: (bits >> 16) as u16,
+            y: bits as u16,
+        })
+    }
 }
 
-/// Mouse controller that maps client coordinates to desktop absolute positions.
-#[derive(Clone)]
-pub struct MouseController {
-    shared: Arc<SharedMove>,
-    screen_w: u16,
-    screen_h: u16,
+/// Lock-free single-value exchange for the latest absolute move: writers overwrite it, the
+/// worker swaps it out, and neither ever waits on the other.
+#[derive(Default)]
+struct MoveSlot {
+    cmd: AtomicU64,
+    /// Moves put in, and those overwritten before the worker got to them.
+    received: AtomicU64,
+    coalesced: AtomicU64,
 }
 
-impl MouseController {
-    /// Create a new controller and spawn a dedicated worker thread for mouse moves.
-    pub fn new() -> Result<Self> {
-        let display = DisplayInfo::all()
-            .context("Failed to enumerate displays")?
+impl MoveSlot {
+    fn put(&self, cmd: MoveCmd) {
+        let previous = self.cmd.swap(cmd.to_bits(), Ordering::AcqRel);
+        self.received.fetch_add(1, Ordering::Relaxed);
+        if previous != 0 {
+            self.coalesced.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn take(&self) -> Option<MoveCmd> {
+        MoveCmd::from_bits(self.cmd.swap(0, Ordering::AcqRel))
+    }
+
+    fn is_empty(&self) -> bool {
+        self.cmd.load(Ordering::Acquire) == 0
+    }
+
+    fn stats(&self) -> MoveStats {
+        MoveStats {
+            received: self.received.load(Ordering::Relaxed),
+            coalesced: self.coalesced.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Absolute moves handed to the worker so far, and how many of them it never got to
+/// because a newer one overwrote them first.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MoveStats {
+    pub received: u64,
+    pub coalesced: u64,
+}
+
+/// Coalescable pointer motion: absolute moves overwrite, relative moves add up.
+#[derive(Debug, Copy, Clone)]
+enum Motion {
+    Abs(MoveCmd),
+    Rel { dx: f64, dy: f64 },
+}
+
+/// Mouse button as exposed to clients.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+}
+
+impl MouseButton {
+    const ALL: [MouseButton; 3] = [MouseButton::Left, MouseButton::Right, MouseButton::Middle];
+
+    fn to_enigo(self) -> enigo::Button {
+        match self {
+            MouseButton::Left => enigo::Button::Left,
+            MouseButton::Right => enigo::Button::Right,
+            MouseButton::Middle => enigo::Button::Middle,
+        }
+    }
+
+    fn bit(self) -> u8 {
+        match self {
+            MouseButton::Left => 1 << 0,
+            MouseButton::Right => 1 << 1,
+            MouseButton::Middle => 1 << 2,
+        }
+    }
+}
+
+/// Buttons held down by one client session.
+///
+/// Sessions track their own presses so teardown (disconnect or timeout) can release
+/// exactly what they latched and never leave the desktop with a stuck button.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct HeldButtons {
+    mask: u8,
+}
+
+impl HeldButtons {
+    /// Mark `button` as held. Returns false if it already was.
+    pub fn insert(&mut self, button: MouseButton) -> bool {
+        let was_held = self.contains(button);
+        self.mask |= button.bit();
+        !was_held
+    }
+
+    /// Mark `button` as released. Returns false if it was not held.
+    pub fn remove(&mut self, button: MouseButton) -> bool {
+        let was_held = self.contains(button);
+        self.mask &= !button.bit();
+        was_held
+    }
+
+    pub fn contains(&self, button: MouseButton) -> bool {
+        self.mask & button.bit() != 0
+    }
+}
+
+/// One session's mapping settings. Sessions keep their own, so whoever is in control gets
+/// theirs back when control passes to them.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SessionMapping {
+    /// Display picked with DISPLAY; the server default until then.
+    pub display: Option<usize>,
+    pub region: Option<Region>,
+    pub target: MapTarget,
+    pub transform: Transform,
+    pub calibration: Option<Homography>,
+    pub aspect: AspectMode,
+    /// Smoothing override; `None` keeps the server's.
+    pub smoothing: Option<Option<OneEuro>>,
+}
+
+/// Commands that must be executed in order (unlike moves, which coalesce).
+#[derive(Debug)]
+enum Cmd {
+    Motion(Motion),
+    /// Absolute moves that each count, with when they were taken; see
+    /// [`MouseController::move_absolute_batch`].
+    MoveBatch(Vec<(Instant, MoveCmd)>),
+    Button(MouseButton, Direction),
+    MultiClick(MouseButton, u8),
+    Scroll {
+        dx: i32,
+        dy: i32,
+    },
+    Fling {
+        vx: f64,
+        vy: f64,
+    },
+    Key(KeyCode, Direction),
+    /// Press and start auto-repeating until the key's release.
+    KeyHold(KeyCode, KeyRepeat),
+    Text(String),
+    Shortcut(Vec<Modifier>, KeyCode),
+    Stylus {
+        client_w: u16,
+        client_h: u16,
+        sample: StylusSample,
+    },
+    StylusLeave,
+    Touch {
+        client_w: u16,
+        client_h: u16,
+        contacts: Vec<TouchContact>,
+    },
+    Gamepad(GamepadState),
+    /// Server-wide confinement limit.
+    RegionLimit(Option<Region>),
+    /// The session's own confinement, applied within the limit.
+    Confine(Option<Region>),
+    MapTarget(MapTarget),
+    /// Server-wide transform, for a rotated monitor.
+    BaseTransform(Transform),
+    /// The session's transform, for how the phone is held; applied before the base one.
+    Transform(Transform),
+    Calibrate(Option<Homography>),
+    Aspect(AspectMode),
+    DeadZone(f64),
+    Prediction(Duration),
+    Resample(Option<u32>),
+    MaxRate(Option<u32>),
+    /// Grace period after local mouse use; zero turns detection off.
+    LocalPause(Duration),
+    /// Read back where the OS cursor is, after everything queued before it.
+    CursorPos(oneshot::Sender<Option<(i32, i32)>>),
+    /// Raise the worker's priority and/or pin it to a CPU core.
+    Scheduling {
+        realtime: bool,
+        cpu: Option<usize>,
+    },
+    /// Server-wide display choice.
+    BaseDisplay(usize),
+    /// The session's display; `None` falls back to the server's.
+    Display(Option<usize>),
+    /// A new display layout, with the server's display in it.
+    Displays(Vec<Display>, usize),
+    /// Server-wide smoothing.
+    BaseSmoothing(Option<OneEuro>),
+    /// The session's smoothing; `None` falls back to the server's.
+    Smoothing(Option<Option<OneEuro>>),
+}
+
+impl Cmd {
+    /// Whether this starts new input rather than ending what is already held or changing
+    /// settings; only ends of input go through while the screen is locked.
+    fn is_new_input(&self) -> bool {
+        match self {
+            Cmd::Motion(_)
+            | Cmd::MoveBatch(_)
+            | Cmd::MultiClick(..)
+            | Cmd::Scroll { .. }
+            | Cmd::Fling { .. }
+            | Cmd::KeyHold(..)
+            | Cmd::Text(_)
+            | Cmd::Shortcut(..) => true,
+            Cmd::Button(_, direction) | Cmd::Key(_, direction) => *direction != Direction::Release,
+            Cmd::Stylus { sample, .. } => sample.in_contact(),
+            Cmd::Touch { contacts, .. } => !contacts.is_empty(),
+            Cmd::Gamepad(state) => *state != GamepadState::default(),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Pending {
+    /// Relative motion summed since the worker last took it.
+    latest_rel: Option<(f64, f64)>,
+    queue: VecDeque<Cmd>,
+}
+
+struct Shared {
+    /// Absolute moves skip the lock; they arrive far more often than anything else.
+    latest_abs: MoveSlot,
+    pending: Mutex<Pending>,
+    /// The worker thread, unparked whenever there is new work for it.
+    worker: OnceLock<Thread>,
+    /// Set once the controller shuts down, under the `pending` lock so nothing is queued
+    /// behind it; the worker finishes what is queued and exits.
+    stopped: AtomicBool,
+    /// While set, new input is dropped; see [`crate::lock`].
+    screen_locked: AtomicBool,
+    /// `f64` bits of the relative-move multiplier.
+    sensitivity: AtomicU64,
+    pressure_curve: Mutex<PressureCurve>,
+    key_repeat: Mutex<KeyRepeat>,
+    hot_corners: Mutex<HotCorners>,
+    corner_hits: broadcast::Sender<Corner>,
+    displays: Mutex<Vec<Display>>,
+    /// Index of the display sessions map to until they pick one.
+    default_display: AtomicUsize,
+    display_changes: broadcast::Sender<()>,
+    /// The pointer backend in use, enigo unless a native one is open.
+    backend: Mutex<BackendKind>,
+    /// Why the last pointer injection failed, until one succeeds again.
+    backend_error: Mutex<Option<String>>,
+}
+
+impl Shared {
+    fn check_running(&self) -> Result<()> {
+        if self.stopped.load(Ordering::Acquire) {
+            bail!("Mouse worker has stopped");
+        }
+        Ok(())
+    }
+
+    fn wake_worker(&self) {
+        if let Some(worker) = self.worker.get() {
+            worker.unpark();
+        }
+    }
+
+    /// Take the pending motion. When both kinds are waiting the absolute one wins: a
+    /// relative move queues any absolute one ahead of it, so the absolute one is newer.
+    fn take_motion(&self, pending: &mut Pending) -> Option<Motion> {
+        let rel = pending.latest_rel.take();
+        self.latest_abs
+            .take()
+            .map(Motion::Abs)
+            .or(rel.map(|(dx, dy)| Motion::Rel { dx, dy }))
+    }
+}
+
+/// Inertial scroll left running after a fling.
+#[derive(Debug, Clone, Copy)]
+struct Momentum {
+    /// Scroll units per second.
+    velocity: (f64, f64),
+    /// Fractional units not yet handed to the scroll accumulator.
+    carry: (f64, f64),
+    last: Instant,
+    next_tick: Instant,
+}
+
+/// The held key currently auto-repeating. Like an OS, only the newest held key repeats.
+#[derive(Debug, Clone, Copy)]
+struct Repeating {
+    key: KeyCode,
+    interval: Duration,
+    next: Instant,
+}
+
+/// Native pen device, opened on first use so mouse-only sessions never create one.
+enum PenSlot {
+    Untried,
+    Open(Box<dyn PenBackend>),
+    /// No native backend: pen samples drive the mouse. Tip contact presses the left button,
+    /// or the right one if the barrel button is held when the tip lands.
+    Fallback {
+        pressed: Option<MouseButton>,
+    },
+}
+
+/// Native touchscreen, opened on first use like [`PenSlot`].
+enum TouchSlot {
+    Untried,
+    Open(Box<dyn TouchBackend>),
+    /// No native backend: the first finger down drives the mouse with the left button held,
+    /// until it lifts. Other fingers are ignored.
+    Fallback {
+        driver: Option<u8>,
+    },
+}
+
+/// Virtual game controller, created on the first non-neutral report.
+enum GamepadSlot {
+    Untried,
+    Open(Box<dyn GamepadBackend>),
+    /// No backend; reports are dropped. Unlike pen and touch there is no mouse equivalent.
+    Unavailable,
+}
+
+/// Where pointer output goes: the native device when one is open, enigo otherwise.
+struct Output {
+    enigo: Enigo,
+    native: Option<Box<dyn PointerBackend>>,
+    /// Desktop extent the native device spans.
+    native_size: (u16, u16),
+    shared: Arc<Shared>,
+}
+
+impl Output {
+    fn new(
+        enigo: Enigo,
+        kind: BackendKind,
+        desktop_size: (u16, u16),
+        shared: &Arc<Shared>,
+    ) -> Self {
+        let native = match backend::open_backend(kind, desktop_size.0, desktop_size.1) {
+            Ok(dev) => {
+                info!("🖱️  Virtual pointer device ready");
+                Some(dev)
+            }
+            // Nothing native to offer; only worth a word if one was asked for.
+            Err(_) if kind == BackendKind::Enigo => None,
+            Err(err) if kind == BackendKind::Auto && err.kind() == io::ErrorKind::Unsupported => {
+                None
+            }
+            Err(err) => {
+                warn!("Pointer backend {kind} unavailable ({err}); using enigo");
+                None
+            }
+        };
+        let output = Self {
+            enigo,
+            native,
+            native_size: desktop_size,
+            shared: shared.clone(),
+        };
+        output.publish();
+        output
+    }
+
+    /// Record which backend is in use, for the status API.
+    fn publish(&self) {
+        let kind = self
+            .native
+            .as_ref()
+            .map_or(BackendKind::Enigo, |dev| dev.kind());
+        *self.shared.backend.lock().unwrap() = kind;
+    }
+
+    /// Let the native device follow a change in the desktop extent its axes span.
+    fn resize(&mut self, desktop_size: (u16, u16)) {
+        let Some(dev) = &mut self.native else {
+            return;
+        };
+        if self.native_size == desktop_size {
+            return;
+        }
+        self.native_size = desktop_size;
+        if let Err(err) = dev.resize(desktop_size.0, desktop_size.1) {
+            warn!("Virtual pointer unavailable ({err}); using enigo");
+            self.native = None;
+            self.publish();
+        }
+    }
+
+    /// Keep the status API's error current, logging each failure once rather than per event.
+    fn record(&mut self, res: Result<(), String>) {
+        let error = res.err();
+        let mut current = self.shared.backend_error.lock().unwrap();
+        if *current == error {
+            return;
+        }
+        match &error {
+            Some(err) => warn!("Pointer injection failed: {err}"),
+            None => info!("Pointer injection recovered"),
+        }
+        *current = error;
+    }
+
+    fn move_abs(&mut self, x: f64, y: f64) {
+        let res = match &mut self.native {
+            Some(dev) => dev.move_abs(x, y).map_err(|e| e.to_string()),
+            None => self
+                .enigo
+                .move_mouse(x.round() as i32, y.round() as i32, Coordinate::Abs)
+                .map_err(|e| e.to_string()),
+        };
+        self.record(res);
+    }
+
+    fn move_rel(&mut self, dx: i32, dy: i32) {
+        if let Some(dev) = &mut self.native {
+            match dev.move_rel(dx, dy) {
+                Err(err) if err.kind() == io::ErrorKind::Unsupported => {}
+                res => return self.record(res.map_err(|e| e.to_string())),
+            }
+        }
+        let res = self.enigo.move_mouse(dx, dy, Coordinate::Rel);
+        self.record(res.map_err(|e| e.to_string()));
+    }
+
+    fn button(&mut self, button: MouseButton, direction: Direction) {
+        let res = match &mut self.native {
+            Some(dev) => match direction {
+                Direction::Press => dev.button(button, true),
+                Direction::Release => dev.button(button, false),
+                Direction::Click => dev
+                    .button(button, true)
+                    .and_then(|()| dev.button(button, false)),
+            }
+            .map_err(|e| e.to_string()),
+            None => self
+                .enigo
+                .button(button.to_enigo(), direction)
+                .map_err(|e| e.to_string()),
+        };
+        self.record(res);
+    }
+
+    fn scroll(&mut self, notches_x: i32, notches_y: i32) {
+        let res = match &mut self.native {
+            Some(dev) => dev.scroll(notches_x, notches_y).map_err(|e| e.to_string()),
+            None => [(notches_x, Axis::Horizontal), (notches_y, Axis::Vertical)]
+                .into_iter()
+                .filter(|(notches, _)| *notches != 0)
+                .try_for_each(|(notches, axis)| self.enigo.scroll(notches, axis))
+                .map_err(|e| e.to_string()),
+        };
+        self.record(res);
+    }
+}
+
+/// State owned by the worker thread.
+struct Worker {
+    output: Output,
+    displays: Vec<Display>,
+    base_display: usize,
+    /// The session's pick, overriding `base_display` while set.
+    session_display: Option<usize>,
+    // Sub-pixel / sub-notch remainders, carried over so slow gestures still add up.
+    rel_acc: (f64, f64),
+    scroll_acc: (i32, i32),
+    momentum: Option<Momentum>,
+    repeating: Option<Repeating>,
+    pen: PenSlot,
+    touch: TouchSlot,
+    gamepad: GamepadSlot,
+    /// Hot corner the cursor is in, so each corner fires once per entry.
+    corner: Option<Corner>,
+    region_limit: Option<Region>,
+    session_region: Option<Region>,
+    map_target: MapTarget,
+    focused_window: FocusedWindow,
+    base_transform: Transform,
+    session_transform: Transform,
+    /// Session calibration; replaces both transforms while set.
+    calibration: Option<Homography>,
+    aspect: AspectMode,
+    /// Moves shorter than this many desktop pixels are dropped as jitter.
+    dead_zone: f64,
+    /// Where the last absolute move put the cursor, the anchor for the dead zone.
+    last_abs: Option<(i32, i32)>,
+    base_smoothing: Option<OneEuro>,
+    /// Overrides `base_smoothing` while set; `Some(None)` turns smoothing off for the session.
+    session_smoothing: Option<Option<OneEuro>>,
+    smoother: OneEuroFilter,
+    predictor: Predictor,
+    /// Where the cursor really belongs after a predicted move, and when to put it there if no
+    /// newer move arrives, so a stopped finger doesn't leave the cursor overshot.
+    settle: Option<(Instant, (i32, i32))>,
+    resampler: Resampler,
+    /// Least time between client-driven moves; zero for no limit.
+    motion_interval: Duration,
+    /// When the next coalesced motion may be injected, while rate limited.
+    next_motion: Option<Instant>,
+    /// How long client motion is held off after the local mouse moves; zero when off.
+    local_pause: Duration,
+    /// Until when client motion is being dropped for the local mouse.
+    paused_until: Option<Instant>,
+    /// Where the last two client-driven moves put the cursor, newest first. Two, because
+    /// some backends apply a move only after the next read-back.
+    injected: [Option<(i32, i32)>; 2],
+}
+
+impl Worker {
+    fn run(mut self, shared: Arc<Shared>) {
+        let mut batch = VecDeque::new();
+        loop {
+            let (latest_motion, stopped) = loop {
+                let mut guard = shared.pending.lock().unwrap();
+                let stopped = shared.stopped.load(Ordering::Acquire);
+                let motion_pending = guard.latest_rel.is_some() || !shared.latest_abs.is_empty();
+                // A motion held back by the rate limit keeps coalescing until it is due.
+                // On the way out, the last motion goes through regardless.
+                let held_until = self
+                    .next_motion
+                    .filter(|t| motion_pending && !stopped && *t > Instant::now());
+                // Flings and key repeats wake the worker on their own schedule.
+                let due = self.next_timer().into_iter().chain(held_until).min();
+                let ready = stopped
+                    || !guard.queue.is_empty()
+                    || (motion_pending && held_until.is_none())
+                    || due.is_some_and(|t| t <= Instant::now());
+                if ready {
+                    std::mem::swap(&mut batch, &mut guard.queue);
+                    let motion = if held_until.is_none() {
+                        shared.take_motion(&mut guard)
+                    } else {
+                        None
+                    };
+                    break (motion, stopped);
+                }
+                drop(guard);
+                // Unparks that land before this still count, so no wakeup is lost.
+                match due {
+                    Some(due) => {
+                        thread::park_timeout(due.saturating_duration_since(Instant::now()))
+                    }
+                    None => thread::park(),
+                }
+            };
+
+            let locked = shared.screen_locked.load(Ordering::Acquire);
+            if locked {
+                self.halt_ongoing();
+            }
+            // Ordered commands first; the coalesced motion is always newer than anything queued.
+            for cmd in batch.drain(..).chain(latest_motion.map(Cmd::Motion)) {
+                if locked && cmd.is_new_input() {
+                    continue;
+                }
+                let moved = matches!(cmd, Cmd::Motion(_) | Cmd::MoveBatch(_));
+                self.execute(cmd);
+                if moved {
+                    self.check_corners(&shared);
+                    if !self.motion_interval.is_zero() {
+                        self.next_motion = Some(Instant::now() + self.motion_interval);
+                    }
+                }
+            }
+            // Dropping the worker closes enigo and native devices, which releases anything
+            // still held.
+            if stopped {
+                return;
+            }
+            self.step_momentum();
+            self.step_repeat();
+            self.step_settle();
+            self.step_resample();
+        }
+    }
+
+    /// Publish a hit when the cursor enters a live hot corner.
+    fn check_corners(&mut self, shared: &Shared) {
+        let corners = shared.hot_corners.lock().unwrap();
+        if corners.corners.is_empty() {
+            return;
+        }
+        // Read back rather than tracked, so relative moves and clamping at the edges count.
+        let Ok((x, y)) = self.output.enigo.location() else {
+            return;
+        };
+        let screen = self.screen();
+        let (w, h) = (screen.width as i32, screen.height as i32);
+        let hit = corners.hit(x - screen.x, y - screen.y, w, h);
+        drop(corners);
+        if hit != self.corner {
+            self.corner = hit;
+            if let Some(corner) = hit {
+                let _ = shared.corner_hits.send(corner);
+            }
+        }
+    }
+
+    fn next_timer(&self) -> Option<Instant> {
+        let fling = self.momentum.map(|m| m.next_tick);
+        let repeat = self.repeating.map(|r| r.next);
+        let settle = self.settle.map(|(at, _)| at);
+        let resample = self.resampler.next_tick();
+        fling
             .into_iter()
-            .next()
-            .context("No displays found")?;
+            .chain(repeat)
+            .chain(settle)
+            .chain(resample)
+            .min()
+    }
 
-        // Keep only the latest move request to avoid backlog (which can cause periodic stutter).
-        let shared = Arc::new(SharedMove {
-            latest: Mutex::new(None),
-            cv: Condvar::new(),
+    fn execute(&mut self, cmd: Cmd) {
+        match cmd {
+            Cmd::Motion(_) | Cmd::MoveBatch(_) if self.local_mouse_active() => {}
+            Cmd::Motion(Motion::Abs(m)) => self.move_to(m, Instant::now()),
+            Cmd::MoveBatch(moves) => {
+                for (at, m) in moves {
+                    self.move_to(m, at);
+                }
+            }
+            Cmd::Motion(Motion::Rel { dx, dy }) => {
+                let (dx, dy) = self.session_transform.apply_delta(dx, dy);
+                let (dx, dy) = self.base_transform.apply_delta(dx, dy);
+                self.forget_abs();
+                self.rel_acc.0 += dx;
+                self.rel_acc.1 += dy;
+                // Hold small moves back until they add up, so a resting finger's noise cancels out.
+                if self.rel_acc.0.hypot(self.rel_acc.1) < self.dead_zone {
+                    return;
+                }
+                let step_x = self.rel_acc.0.trunc();
+                let step_y = self.rel_acc.1.trunc();
+                self.rel_acc.0 -= step_x;
+                self.rel_acc.1 -= step_y;
+                if step_x == 0.0 && step_y == 0.0 {
+                    return;
+                }
+                if let Some(region) = self.region()
+                    && let Ok((x, y)) = self.output.enigo.location()
+                {
+                    let (x, y) = region.clamp(x + step_x as i32, y + step_y as i32);
+                    self.inject_abs(f64::from(x), f64::from(y));
+                } else {
+                    self.output.move_rel(step_x as i32, step_y as i32);
+                    if !self.local_pause.is_zero()
+                        && let Ok((x, y)) = self.output.enigo.location()
+                    {
+                        // Read back rather than predicted, so acceleration and edges count.
+                        self.note_injected(x, y);
+                    }
+                }
+            }
+            Cmd::Button(button, direction) => {
+                // Pressing a button catches a fling, like touching a trackpad does.
+                if direction != Direction::Release {
+                    self.momentum = None;
+                }
+                self.output.button(button, direction);
+            }
+            Cmd::MultiClick(button, count) => {
+                self.momentum = None;
+                // Runs as one step so no move can land between the clicks and break the sequence.
+                for i in 0..count {
+                    if i > 0 {
+                        thread::sleep(MULTI_CLICK_GAP);
+                    }
+                    self.output.button(button, Direction::Click);
+                }
+            }
+            Cmd::Scroll { dx, dy } => {
+                self.momentum = None;
+                self.scroll_units(dx, dy);
+            }
+            Cmd::Fling { vx, vy } => {
+                let now = Instant::now();
+                self.momentum = (vx.hypot(vy) >= MOMENTUM_MIN_VELOCITY).then_some(Momentum {
+                    velocity: (vx, vy),
+                    carry: (0.0, 0.0),
+                    last: now,
+                    next_tick: now,
+                });
+            }
+            Cmd::Key(key, direction) => {
+                if direction == Direction::Release && self.repeating.is_some_and(|r| r.key == key) {
+                    self.repeating = None;
+                }
+                let _ = self.output.enigo.key(key.to_enigo(), direction);
+            }
+            Cmd::KeyHold(key, repeat) => {
+                let _ = self.output.enigo.key(key.to_enigo(), Direction::Press);
+                self.repeating = Some(Repeating {
+                    key,
+                    interval: repeat.interval,
+                    next: Instant::now() + repeat.delay,
+                });
+            }
+            Cmd::Text(text) => {
+                let _ = self.output.enigo.text(&text);
+            }
+            Cmd::Shortcut(modifiers, key) => {
+                for m in &modifiers {
+                    let _ = self.output.enigo.key(m.to_enigo(), Direction::Press);
+                }
+                let _ = self.output.enigo.key(key.to_enigo(), Direction::Click);
+                for m in modifiers.iter().rev() {
+                    let _ = self.output.enigo.key(m.to_enigo(), Direction::Release);
+                }
+            }
+            Cmd::Stylus {
+                client_w,
+                client_h,
+                sample,
+            } => {
+                self.forget_abs();
+                // The pen moves the cursor too, so none of that is the local mouse.
+                self.injected = [None; 2];
+                let (x, y) = self.map_point(client_w, client_h, sample.x, sample.y);
+                let (x, y) = self.confine(x, y);
+                self.stylus(PenReport {
+                    x,
+                    y,
+                    pressure: sample.pressure,
+                    tilt_x: sample.tilt_x,
+                    tilt_y: sample.tilt_y,
+                    contact: sample.in_contact(),
+                    eraser: sample.eraser,
+                    barrel: sample.barrel,
+                });
+            }
+            Cmd::StylusLeave => match &mut self.pen {
+                PenSlot::Untried => {}
+                PenSlot::Open(pen) => {
+                    let _ = pen.leave();
+                }
+                PenSlot::Fallback { pressed } => {
+                    if let Some(button) = pressed.take() {
+                        self.output.button(button, Direction::Release);
+                    }
+                }
+            },
+            Cmd::Touch {
+                client_w,
+                client_h,
+                contacts,
+            } => {
+                self.forget_abs();
+                self.injected = [None; 2];
+                let points: Vec<TouchPoint> = contacts
+                    .iter()
+                    .map(|c| {
+                        let (x, y) = self.map_point(client_w, client_h, c.x, c.y);
+                        let (x, y) = self.confine(x, y);
+                        TouchPoint { id: c.id, x, y }
+                    })
+                    .collect();
+                self.touch(&points);
+            }
+            Cmd::Gamepad(state) => self.gamepad(&state),
+            Cmd::RegionLimit(region) => self.region_limit = region,
+            Cmd::Confine(region) => self.session_region = region,
+            Cmd::MapTarget(target) => self.map_target = target,
+            Cmd::BaseTransform(t) => self.base_transform = t,
+            Cmd::Transform(t) => self.session_transform = t,
+            Cmd::Calibrate(h) => self.calibration = h,
+            Cmd::Aspect(mode) => self.aspect = mode,
+            Cmd::DeadZone(pixels) => self.dead_zone = pixels,
+            Cmd::Resample(rate) => self.resampler.set_rate(rate),
+            Cmd::BaseDisplay(index) => self.base_display = index,
+            Cmd::Display(index) => {
+                self.session_display = index;
+                self.forget_abs();
+            }
+            Cmd::Displays(displays, base) => {
+                self.displays = displays;
+                self.base_display = base;
+                if self.session_display >= Some(self.displays.len()) {
+                    self.session_display = None;
+                }
+                self.output.resize(self.desktop_size());
+                self.forget_abs();
+            }
+            Cmd::LocalPause(grace) => {
+                self.local_pause = grace;
+                self.paused_until = None;
+                self.injected = [None; 2];
+            }
+            Cmd::MaxRate(rate) => {
+                self.motion_interval = rate
+                    .filter(|r| *r > 0)
+                    .map_or(Duration::ZERO, |r| Duration::from_secs(1) / r);
+                self.next_motion = None;
+            }
+            Cmd::CursorPos(reply) => {
+                let _ = reply.send(self.output.enigo.location().ok());
+            }
+            Cmd::Scheduling { realtime, cpu } => {
+                if realtime {
+                    match sched::raise_priority() {
+                        Ok(()) => info!("Mouse worker running at raised priority"),
+                        Err(err) => warn!("Failed to raise mouse worker priority: {err}"),
+                    }
+                }
+                if let Some(cpu) = cpu {
+                    match sched::pin_to_cpu(cpu) {
+                        Ok(()) => info!("Mouse worker pinned to CPU {cpu}"),
+                        Err(err) => warn!("Failed to pin mouse worker to CPU {cpu}: {err}"),
+                    }
+                }
+            }
+            Cmd::Prediction(horizon) => {
+                self.predictor.set_horizon(horizon);
+                self.settle = None;
+            }
+            Cmd::BaseSmoothing(params) => {
+                self.base_smoothing = params;
+                self.smoother.reset();
+            }
+            Cmd::Smoothing(params) => {
+                self.session_smoothing = params;
+                self.smoother.reset();
+            }
+        }
+    }
+
+    /// Run an absolute position through the One-Euro filter, if one is on.
+    fn smooth(&mut self, x: i32, y: i32) -> (i32, i32) {
+        let Some(params) = self.session_smoothing.unwrap_or(self.base_smoothing) else {
+            return (x, y);
+        };
+        let (x, y) = self
+            .smoother
+            .filter(params, f64::from(x), f64::from(y), Instant::now());
+        (x.round() as i32, y.round() as i32)
+    }
+
+    /// Extrapolate an absolute position ahead by the prediction horizon, if prediction is on,
+    /// and arrange to land on the real position if the pointer stops there.
+    fn predict(&mut self, x: i32, y: i32) -> (i32, i32) {
+        let horizon = self.predictor.horizon();
+        if horizon.is_zero() {
+            return (x, y);
+        }
+        let now = Instant::now();
+        let (px, py) = self.predictor.predict(f64::from(x), f64::from(y), now);
+        let (px, py) = (px.round() as i32, py.round() as i32);
+        self.settle = ((px, py) != (x, y)).then_some((now + horizon, (x, y)));
+        (px, py)
+    }
+
+    fn step_settle(&mut self) {
+        let Some((at, (x, y))) = self.settle else {
+            return;
+        };
+        if Instant::now() < at {
+            return;
+        }
+        self.settle = None;
+        let (x, y) = self.confine(x, y);
+        self.last_abs = Some((x, y));
+        self.place(x, y, Instant::now());
+    }
+
+    /// Move to a client point taken at `at`.
+    fn move_to(&mut self, m: MoveCmd, at: Instant) {
+        let (screen_x, screen_y) = self.map_point(m.client_w, m.client_h, m.x, m.y);
+        let (screen_x, screen_y) = self.smooth(screen_x, screen_y);
+        let (screen_x, screen_y) = self.predict(screen_x, screen_y);
+        let (screen_x, screen_y) = self.confine(screen_x, screen_y);
+        if self.in_dead_zone(screen_x, screen_y) {
+            return;
+        }
+        self.last_abs = Some((screen_x, screen_y));
+        self.place(screen_x, screen_y, at);
+    }
+
+    /// Put the cursor at an absolute position now, or glide there if resampling is on,
+    /// timed from when the point was taken.
+    fn place(&mut self, x: i32, y: i32, at: Instant) {
+        if self.resampler.is_on() {
+            let Some((x, y)) = self.resampler.push(f64::from(x), f64::from(y), at)
+            else {
+                return;
+            };
+            self.inject_abs(x, y);
+        } else {
+            self.inject_abs(f64::from(x), f64::from(y));
+        }
+    }
+
+    /// Send the next resampled move, if it is due.
+    fn step_resample(&mut self) {
+        if let Some((x, y)) = self.resampler.step(Instant::now()) {
+            self.inject_abs(x, y);
+        }
+    }
+
+    /// Stop everything that keeps producing input on its own: flings, key repeat, glides
+    /// and predicted-move corrections.
+    fn halt_ongoing(&mut self) {
+        self.momentum = None;
+        if let Some(r) = self.repeating.take() {
+            let _ = self.output.enigo.key(r.key.to_enigo(), Direction::Release);
+        }
+        self.forget_abs();
+    }
+
+    /// Move the cursor for a client, remembering where it should end up.
+    fn inject_abs(&mut self, x: f64, y: f64) {
+        self.output.move_abs(x, y);
+        if !self.local_pause.is_zero() {
+            let (x, y) = Display::snap(&self.displays, x.round() as i32, y.round() as i32);
+            self.note_injected(x, y);
+        }
+    }
+
+    fn note_injected(&mut self, x: i32, y: i32) {
+        self.injected = [Some((x, y)), self.injected[0]];
+    }
+
+    /// Whether client motion should be dropped because someone is using the physical
+    /// mouse: the cursor is somewhere client input didn't put it, or was within the grace
+    /// period. Each local move restarts the grace period.
+    fn local_mouse_active(&mut self) -> bool {
+        if self.local_pause.is_zero() {
+            return false;
+        }
+        let Ok((x, y)) = self.output.enigo.location() else {
+            return false;
+        };
+        let now = Instant::now();
+        let ours = self.injected.iter().flatten().any(|&(ix, iy)| {
+            (x - ix).abs() <= LOCAL_MOTION_TOLERANCE && (y - iy).abs() <= LOCAL_MOTION_TOLERANCE
         });
-        let worker_shared = shared.clone();
+        if !ours {
+            // Nothing to compare against yet just anchors the position.
+            if self.injected[0].is_some() {
+                if self.paused_until.is_none() {
+                    info!("🖱️  Local mouse in use; pausing remote pointer control");
+                }
+                self.paused_until = Some(now + self.local_pause);
+                self.forget_abs();
+            }
+            self.injected = [Some((x, y)), None];
+        }
+        match self.paused_until {
+            Some(until) if now < until => true,
+            Some(_) => {
+                info!("🖱️  Resuming remote pointer control");
+                self.paused_until = None;
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// Drop absolute-move history once something else has moved the cursor.
+    fn forget_abs(&mut self) {
+        self.last_abs = None;
+        self.smoother.reset();
+        self.predictor.reset();
+        self.settle = None;
+        self.resampler.reset();
+    }
+
+    /// Whether an absolute move to `x`/`y` is too small to be anything but jitter.
+    fn in_dead_zone(&self, x: i32, y: i32) -> bool {
+        self.last_abs
+            .is_some_and(|(lx, ly)| f64::from(x - lx).hypot(f64::from(y - ly)) < self.dead_zone)
+    }
+
+    fn scroll_units(&mut self, dx: i32, dy: i32) {
+        self.scroll_acc.0 = self.scroll_acc.0.saturating_add(dx);
+        self.scroll_acc.1 = self.scroll_acc.1.saturating_add(dy);
+        let notches_x = self.scroll_acc.0 / SCROLL_UNITS_PER_NOTCH;
+        let notches_y = self.scroll_acc.1 / SCROLL_UNITS_PER_NOTCH;
+        self.scroll_acc.0 -= notches_x * SCROLL_UNITS_PER_NOTCH;
+        self.scroll_acc.1 -= notches_y * SCROLL_UNITS_PER_NOTCH;
+        if notches_x != 0 || notches_y != 0 {
+            self.output.scroll(notches_x, notches_y);
+        }
+    }
+
+    /// Advance a running fling by the time elapsed since its last step.
+    fn step_momentum(&mut self) {
+        let Some(mut m) = self.momentum else {
+            return;
+        };
+        let now = Instant::now();
+        if now < m.next_tick {
+            return;
+        }
+
+        // Exact integral of an exponentially decaying velocity over the elapsed time.
+        let dt = now.duration_since(m.last).as_secs_f64();
+        let decay = (-dt / MOMENTUM_TIME_CONSTANT).exp();
+        let travelled = MOMENTUM_TIME_CONSTANT * (1.0 - decay);
+        m.carry.0 += m.velocity.0 * travelled;
+        m.carry.1 += m.velocity.1 * travelled;
+        m.velocity.0 *= decay;
+        m.velocity.1 *= decay;
+        let step = (m.carry.0.trunc(), m.carry.1.trunc());
+        m.carry.0 -= step.0;
+        m.carry.1 -= step.1;
+        m.last = now;
+        m.next_tick = now + MOMENTUM_TICK;
+
+        self.momentum = (m.velocity.0.hypot(m.velocity.1) >= MOMENTUM_MIN_VELOCITY).then_some(m);
+        self.scroll_units(step.0 as i32, step.1 as i32);
+    }
+
+    /// Send the next auto-repeat of the held key, if it is due.
+    fn step_repeat(&mut self) {
+        let Some(r) = self.repeating.as_mut() else {
+            return;
+        };
+        let now = Instant::now();
+        if now < r.next {
+            return;
+        }
+        // Repeats are extra key-downs without a key-up, exactly what OS auto-repeat produces.
+        let key = r.key;
+        r.next = now + r.interval;
+        let _ = self.output.enigo.key(key.to_enigo(), Direction::Press);
+    }
+
+    /// Where injected input is confined: the session's region within the server limit.
+    fn region(&self) -> Option<Region> {
+        match (self.region_limit, self.session_region) {
+            // A session region outside the limit can't widen it.
+            (Some(limit), Some(session)) => Some(limit.intersect(&session).unwrap_or(limit)),
+            (limit, session) => limit.or(session),
+        }
+    }
+
+    fn confine(&self, x: i32, y: i32) -> (i32, i32) {
+        match self.region() {
+            Some(region) => region.clamp(x, y),
+            None => (x, y),
+        }
+    }
+
+    /// The display client coordinates map to.
+    fn screen(&self) -> &Display {
+        let index = self.session_display.unwrap_or(self.base_display);
+        &self.displays[index]
+    }
+
+    /// Extent of the whole desktop from its origin, for native devices whose axes span every
+    /// display.
+    fn desktop_size(&self) -> (u16, u16) {
+        desktop_size(&self.displays)
+    }
+
+    fn map_point(&mut self, client_w: u16, client_h: u16, x: u16, y: u16) -> (i32, i32) {
+        let ratio_x = x as f64 / client_w as f64;
+        let ratio_y = y as f64 / client_h as f64;
+        let window = match self.map_target {
+            MapTarget::Screen | MapTarget::Desktop => None,
+            MapTarget::Window => self.focused_window.bounds(),
+        };
+        let area = match window {
+            Some(w) => Rect {
+                x: w.x.into(),
+                y: w.y.into(),
+                w: w.width.into(),
+                h: w.height.into(),
+            },
+            None if self.map_target == MapTarget::Desktop => Display::bounds(&self.displays),
+            None => self.screen().rect(),
+        };
+
+        // A calibration already accounts for orientation and shape.
+        let (x, y) = match self.calibration.and_then(|h| h.apply(ratio_x, ratio_y)) {
+            Some((u, v)) => area.at(u, v),
+            None => {
+                let (u, v) = self.session_transform.apply(ratio_x, ratio_y);
+                let (u, v) = self.base_transform.apply(u, v);
+                let (mut w, mut h) = (f64::from(client_w), f64::from(client_h));
+                if self.session_transform.swaps_axes() != self.base_transform.swaps_axes() {
+                    std::mem::swap(&mut w, &mut h);
+                }
+                self.aspect.place(w, h, area).at(u, v)
+            }
+        };
+        match self.map_target {
+            MapTarget::Desktop => Display::snap(&self.displays, x, y),
+            MapTarget::Screen | MapTarget::Window => (x, y),
+        }
+    }
+
+    fn stylus(&mut self, report: PenReport) {
+        if let PenSlot::Untried = self.pen {
+            let (w, h) = self.desktop_size();
+            self.pen = match stylus::open_backend(w, h) {
+                Ok(pen) => {
+                    info!("🖊️  Virtual pen device ready");
+                    PenSlot::Open(pen)
+                }
+                Err(err) => {
+                    warn!("Virtual pen unavailable ({err}); stylus input falls back to the mouse");
+                    PenSlot::Fallback { pressed: None }
+                }
+            };
+        }
 
-        let screen_w = display.width as f64;
-        let screen_h = display.height as f64;
-        let screen_w_u16 = (screen_w.round().clamp(1.0, 65535.0)) as u16;
-        let screen_h_u16 = (screen_h.round().clamp(1.0, 65535.0)) as u16;
-
-        thread::spawn(move || {
-            let enigo_settings = enigo::Settings::default();
-            let mut enigo = Enigo::new(&enigo_settings).unwrap();
-            loop {
-                let cmd = {
-                    let mut guard = worker_shared.latest.lock().unwrap();
-                    while guard.is_none() {
-                        guard = worker_shared.cv.wait(guard).unwrap();
+        match &mut self.pen {
+            PenSlot::Untried => {}
+            PenSlot::Open(pen) => {
+                if let Err(err) = pen.report(&report) {
+                    warn!("Virtual pen write failed: {err}");
+                }
+            }
+            PenSlot::Fallback { pressed } => {
+                self.output
+                    .move_abs(f64::from(report.x), f64::from(report.y));
+                match (*pressed, report.contact) {
+                    (None, true) => {
+                        let button = if report.barrel {
+                            MouseButton::Right
+                        } else {
+                            MouseButton::Left
+                        };
+                        self.output.button(button, Direction::Press);
+                        *pressed = Some(button);
                     }
-                    guard.take().unwrap()
-                };
+                    (Some(button), false) => {
+                        self.output.button(button, Direction::Release);
+                        *pressed = None;
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    fn touch(&mut self, points: &[TouchPoint]) {
+        if let TouchSlot::Untried = self.touch {
+            // Lifting every finger needs no device; don't create one just for that.
+            if points.is_empty() {
+                return;
+            }
+            let (w, h) = self.desktop_size();
+            self.touch = match touch::open_backend(w, h) {
+                Ok(dev) => {
+                    info!("👆 Virtual touchscreen ready");
+                    TouchSlot::Open(dev)
+                }
+                Err(err) => {
+                    warn!("Virtual touchscreen unavailable ({err}); touch falls back to the mouse");
+                    TouchSlot::Fallback { driver: None }
+                }
+            };
+        }
 
-                let ratio_x = cmd.x as f64 / cmd.client_w as f64;
-                let ratio_y = cmd.y as f64 / cmd.client_h as f64;
-                let screen_x = (ratio_x * screen_w) as i32;
-                let screen_y = (ratio_y * screen_h) as i32;
-                let _ = enigo.move_mouse(screen_x, screen_y, Coordinate::Abs);
+        match &mut self.touch {
+            TouchSlot::Untried => {}
+            TouchSlot::Open(dev) => {
+                if let Err(err) = dev.frame(points) {
+                    warn!("Virtual touchscreen write failed: {err}");
+                }
+            }
+            TouchSlot::Fallback { driver } => {
+                let current = driver.and_then(|id| points.iter().find(|p| p.id == id));
+                match (current, points.first()) {
+                    (Some(p), _) => {
+                        self.output.move_abs(f64::from(p.x), f64::from(p.y));
+                    }
+                    (None, next) => {
+                        if driver.take().is_some() {
+                            self.output.button(MouseButton::Left, Direction::Release);
+                        }
+                        if let Some(p) = next {
+                            self.output.move_abs(f64::from(p.x), f64::from(p.y));
+                            self.output.button(MouseButton::Left, Direction::Press);
+                            *driver = Some(p.id);
+                        }
+                    }
+                }
             }
+        }
+    }
+
+    fn gamepad(&mut self, state: &GamepadState) {
+        if let GamepadSlot::Untried = self.gamepad {
+            // Centering a controller that was never used needs no device.
+            if state.is_neutral() {
+                return;
+            }
+            self.gamepad = match gamepad::open_backend() {
+                Ok(dev) => {
+                    info!("🎮 Virtual gamepad ready");
+                    GamepadSlot::Open(dev)
+                }
+                Err(err) => {
+                    warn!("Virtual gamepad unavailable ({err}); gamepad input is ignored");
+                    GamepadSlot::Unavailable
+                }
+            };
+        }
+        if let GamepadSlot::Open(dev) = &mut self.gamepad
+            && let Err(err) = dev.report(state)
+        {
+            warn!("Virtual gamepad write failed: {err}");
+        }
+    }
+}
+
+/// Connect to the OS input system, retrying for a while since at login the display server
+/// may not be accepting clients yet.
+fn connect_enigo() -> Result<Enigo> {
+    let settings = enigo::Settings::default();
+    let mut delays = ENIGO_RETRY_DELAYS.iter();
+    loop {
+        match Enigo::new(&settings) {
+            Ok(enigo) => return Ok(enigo),
+            // Waiting won't grant a permission.
+            Err(err @ enigo::NewConError::NoPermission) => {
+                return Err(anyhow!("Failed to connect to the input system: {err}"));
+            }
+            Err(err) => {
+                let Some(delay) = delays.next() else {
+                    return Err(anyhow!("Failed to connect to the input system: {err}"));
+                };
+                warn!("Failed to connect to the input system ({err}); retrying in {delay:?}");
+                thread::sleep(*delay);
+            }
+        }
+    }
+}
+
+fn desktop_size(displays: &[Display]) -> (u16, u16) {
+    let extent = |end: i64| end.clamp(1, u16::MAX.into()) as u16;
+    let (right, bottom) = displays
+        .iter()
+        .fold((1, 1), |(r, b), d| (d.right().max(r), d.bottom().max(b)));
+    (extent(right), extent(bottom))
+}
+
+/// Input controller that maps client coordinates to desktop absolute positions
+/// and injects buttons, scrolls and keys in the order they were received.
+pub struct MouseController {
+    shared: Arc<Shared>,
+    worker: Mutex<Option<thread::JoinHandle<()>>>,
+}
+
+impl MouseController {
+    /// Create a new controller and spawn a dedicated worker thread for mouse moves, injecting
+    /// through `backend`.
+    pub fn new(backend: BackendKind) -> Result<Self> {
+        // Without this, Windows reports and injects scaled-down coordinates on HiDPI monitors.
+        #[cfg(target_os = "windows")]
+        let _ = enigo::set_dpi_awareness();
+        let displays = Display::all()?;
+        let default_display = Display::primary_index(&displays);
+
+        // Keep only the latest move request to avoid backlog (which can cause periodic stutter).
+        let (corner_hits, _) = broadcast::channel(4);
+        let (display_changes, _) = broadcast::channel(4);
+        let shared = Arc::new(Shared {
+            latest_abs: MoveSlot::default(),
+            pending: Mutex::new(Pending::default()),
+            worker: OnceLock::new(),
+            stopped: AtomicBool::new(false),
+            screen_locked: AtomicBool::new(false),
+            sensitivity: AtomicU64::new(DEFAULT_SENSITIVITY.to_bits()),
+            pressure_curve: Mutex::new(PressureCurve::default()),
+            key_repeat: Mutex::new(KeyRepeat::default()),
+            hot_corners: Mutex::new(HotCorners::default()),
+            corner_hits,
+            displays: Mutex::new(displays.clone()),
+            default_display: AtomicUsize::new(default_display),
+            display_changes,
+            backend: Mutex::new(BackendKind::Enigo),
+            backend_error: Mutex::new(None),
         });
+        let worker_shared = shared.clone();
+
+        // The worker owns the connection, so it reports back whether it got one.
+        let (ready_tx, ready_rx) = std::sync::mpsc::sync_channel(1);
+        let worker = thread::spawn(move || {
+            let enigo = match connect_enigo() {
+                Ok(enigo) => {
+                    let _ = ready_tx.send(Ok(()));
+                    enigo
+                }
+                Err(err) => {
+                    let _ = ready_tx.send(Err(err));
+                    return;
+                }
+            };
+            let output = Output::new(enigo, backend, desktop_size(&displays), &worker_shared);
+            let worker = Worker {
+                output,
+                displays,
+                base_display: default_display,
+                session_display: None,
+                rel_acc: (0.0, 0.0),
+                scroll_acc: (0, 0),
+                momentum: None,
+                repeating: None,
+                pen: PenSlot::Untried,
+                touch: TouchSlot::Untried,
+                gamepad: GamepadSlot::Untried,
+                corner: None,
+                region_limit: None,
+                session_region: None,
+                map_target: MapTarget::Screen,
+                focused_window: FocusedWindow::default(),
+                base_transform: Transform::default(),
+                session_transform: Transform::default(),
+                calibration: None,
+                aspect: AspectMode::Fill,
+                dead_zone: 0.0,
+                last_abs: None,
+                base_smoothing: None,
+                session_smoothing: None,
+                smoother: OneEuroFilter::default(),
+                predictor: Predictor::default(),
+                settle: None,
+                resampler: Resampler::default(),
+                motion_interval: Duration::ZERO,
+                next_motion: None,
+                local_pause: Duration::ZERO,
+                paused_until: None,
+                injected: [None; 2],
+            };
+            worker.run(worker_shared);
+        });
+        let _ = shared.worker.set(worker.thread().clone());
+        ready_rx
+            .recv()
+            .context("Mouse worker exited during startup")??;
 
         Ok(Self {
             shared,
-            screen_w: screen_w_u16,
-            screen_h: screen_h_u16,
+            worker: Mutex::new(Some(worker)),
         })
     }
 
+    /// Stop the worker once it has run everything already queued, and wait for it. Anything
+    /// still held is released; later calls fail. Safe to call more than once.
+    pub fn shutdown(&self) {
+        {
+            let _guard = self.shared.pending.lock().unwrap();
+            self.shared.stopped.store(true, Ordering::Release);
+        }
+        self.shared.wake_worker();
+        if let Some(worker) = self.worker.lock().unwrap().take() {
+            let _ = worker.join();
+        }
+    }
+
+    /// The pointer backend in use.
+    pub fn backend(&self) -> BackendKind {
+        *self.shared.backend.lock().unwrap()
+    }
+
+    /// Why pointer injection is failing, if it is.
+    pub fn backend_error(&self) -> Option<String> {
+        self.shared.backend_error.lock().unwrap().clone()
+    }
+
+    /// Size of the display sessions start on.
     pub fn screen_size(&self) -> (u16, u16) {
-        (self.screen_w, self.screen_h)
+        self.display(None).map_or((1, 1), |d| d.size())
+    }
+
+    /// Every display, as last enumerated.
+    pub fn displays(&self) -> Vec<Display> {
+        self.shared.displays.lock().unwrap().clone()
+    }
+
+    /// Where the OS cursor is now, wherever it was moved from: by clients, or by a physical
+    /// mouse. `None` if the platform can't say.
+    pub async fn cursor_position(&self) -> Option<CursorPos> {
+        let (tx, rx) = oneshot::channel();
+        self.enqueue(Cmd::CursorPos(tx)).ok()?;
+        let (x, y) = rx.await.ok()??;
+        let display = Display::containing(&self.shared.displays.lock().unwrap(), x, y);
+        Some(CursorPos { x, y, display })
+    }
+
+    /// Display `index`, or the default one for `None`.
+    pub fn display(&self, index: Option<usize>) -> Option<Display> {
+        let displays = self.shared.displays.lock().unwrap();
+        displays
+            .get(index.unwrap_or_else(|| self.default_display()))
+            .cloned()
+    }
+
+    /// Index of the display sessions start on.
+    pub fn default_display(&self) -> usize {
+        self.shared.default_display.load(Ordering::Relaxed)
+    }
+
+    /// Choose the display sessions start on. Returns `false` if there is no such display.
+    pub fn set_default_display(&self, index: usize) -> bool {
+        if index >= self.shared.displays.lock().unwrap().len() {
+            return false;
+        }
+        self.shared.default_display.store(index, Ordering::Relaxed);
+        let _ = self.enqueue(Cmd::BaseDisplay(index));
+        true
+    }
+
+    /// Map a session's coordinates onto display `index`; `None` goes back to the default.
+    /// Returns the display now in use, or `None` if there is no such display.
+    pub fn select_display(&self, index: Option<usize>) -> Option<Display> {
+        let display = self.display(index)?;
+        let _ = self.enqueue(Cmd::Display(index));
+        Some(display)
+    }
+
+    /// Adopt a freshly enumerated display layout. Returns `false` if nothing changed.
+    ///
+    /// A default display that no longer exists falls back to the primary one, as does a
+    /// session's pick; subscribers are told so they can resend screen info.
+    pub fn update_displays(&self, displays: Vec<Display>) -> bool {
+        let mut current = self.shared.displays.lock().unwrap();
+        if *current == displays || displays.is_empty() {
+            return false;
+        }
+        let mut default = self.default_display();
+        if default >= displays.len() {
+            default = Display::primary_index(&displays);
+            self.shared
+                .default_display
+                .store(default, Ordering::Relaxed);
+        }
+        *current = displays.clone();
+        drop(current);
+        let _ = self.enqueue(Cmd::Displays(displays, default));
+        let _ = self.shared.display_changes.send(());
+        true
+    }
+
+    /// Receive a notification each time the display layout changes.
+    pub fn subscribe_displays(&self) -> broadcast::Receiver<()> {
+        self.shared.display_changes.subscribe()
+    }
+
+    /// Multiplier applied to relative moves.
+    pub fn sensitivity(&self) -> f64 {
+        f64::from_bits(self.shared.sensitivity.load(Ordering::Relaxed))
+    }
+
+    /// Change the relative-move multiplier. Non-finite or non-positive values are ignored.
+    pub fn set_sensitivity(&self, sensitivity: f64) {
+        if sensitivity.is_finite() && sensitivity > 0.0 {
+            self.shared
+                .sensitivity
+                .store(sensitivity.to_bits(), Ordering::Relaxed);
+        }
     }
 
     /// Queue a mouse move; computation is done in the worker thread to avoid blocking async tasks.
@@ -84,15 +1553,457 @@ impl MouseController {
         }
 
         // Overwrite the latest value; intermediate points are intentionally dropped.
-        let mut guard = self.shared.latest.lock().unwrap();
-        *guard = Some(MoveCmd {
+        // A pending relative move is superseded too, since the absolute target wins anyway.
+        self.shared.check_running()?;
+        self.shared.latest_abs.put(MoveCmd {
             client_w,
             client_h,
             x,
             y,
         });
+        self.shared.wake_worker();
+        Ok(())
+    }
+
+    /// Queue absolute moves that each count, unlike [`move_absolute`](Self::move_absolute),
+    /// with when each was taken, so resampling spaces them as they were drawn.
+    pub fn move_absolute_batch(
+        &self,
+        client_w: u16,
+        client_h: u16,
+        moves: impl IntoIterator<Item = (Instant, u16, u16)>,
+    ) -> Result<()> {
+        if client_w == 0 || client_h == 0 {
+            return Ok(());
+        }
+        let moves = moves
+            .into_iter()
+            .map(|(at, x, y)| (at, MoveCmd { client_w, client_h, x, y }))
+            .collect();
+        let mut guard = self.shared.pending.lock().unwrap();
+        self.shared.check_running()?;
+        // A move still waiting in the slot is older than the batch, so goes first.
+        if let Some(motion) = self.shared.take_motion(&mut guard) {
+            guard.queue.push_back(Cmd::Motion(motion));
+        }
+        guard.queue.push_back(Cmd::MoveBatch(moves));
+        drop(guard);
+        self.shared.wake_worker();
+        Ok(())
+    }
+
+    /// How many absolute moves came in, and how many the worker was too busy to act on.
+    pub fn move_stats(&self) -> MoveStats {
+        self.shared.latest_abs.stats()
+    }
+
+    /// Queue a relative (trackpad-style) move of `dx`/`dy` client pixels for a session,
+    /// scaled by its sensitivity (the server's unless overridden) and acceleration.
+    pub fn move_relative(&self, pointer: &mut PointerProfile, dx: f64, dy: f64) -> Result<()> {
+        if !dx.is_finite() || !dy.is_finite() || (dx == 0.0 && dy == 0.0) {
+            return Ok(());
+        }
+
+        let sensitivity = pointer.sensitivity().unwrap_or_else(|| self.sensitivity());
+        let scale = sensitivity * pointer.gain(dx, dy, Instant::now());
+        let (dx, dy) = (dx * scale, dy * scale);
+
+        // Unlike absolute moves, deltas must not be dropped: merge them into the pending one.
+        let mut guard = self.shared.pending.lock().unwrap();
+        self.shared.check_running()?;
+        let (px, py) = match self.shared.take_motion(&mut guard) {
+            Some(Motion::Rel { dx, dy }) => (dx, dy),
+            Some(abs @ Motion::Abs(_)) => {
+                guard.queue.push_back(Cmd::Motion(abs));
+                (0.0, 0.0)
+            }
+            None => (0.0, 0.0),
+        };
+        guard.latest_rel = Some((px + dx, py + dy));
         drop(guard);
-        self.shared.cv.notify_one();
+        self.shared.wake_worker();
         Ok(())
     }
+
+    /// Click a button `count` times at the current cursor position (2 = double, 3 = triple).
+    ///
+    /// Counts are clamped to `1..=MAX_CLICK_COUNT`; multi-clicks are spaced so the OS
+    /// recognizes them as one gesture.
+    pub fn click(&self, button: MouseButton, count: u8) -> Result<()> {
+        match count.clamp(1, MAX_CLICK_COUNT) {
+            1 => self.enqueue(Cmd::Button(button, Direction::Click)),
+            n => self.enqueue(Cmd::MultiClick(button, n)),
+        }
+    }
+
+    /// Press a button without releasing it.
+    pub fn button_down(&self, button: MouseButton) -> Result<()> {
+        self.enqueue(Cmd::Button(button, Direction::Press))
+    }
+
+    /// Release a previously pressed button.
+    pub fn button_up(&self, button: MouseButton) -> Result<()> {
+        self.enqueue(Cmd::Button(button, Direction::Release))
+    }
+
+    /// Press `button` for a session, ignoring repeats so a lost button-up cannot double-latch.
+    pub fn press_held(&self, held: &mut HeldButtons, button: MouseButton) -> Result<()> {
+        if !held.insert(button) {
+            return Ok(());
+        }
+        self.button_down(button)
+    }
+
+    /// Release `button` for a session. Releases are always forwarded, even if untracked.
+    pub fn release_held(&self, held: &mut HeldButtons, button: MouseButton) -> Result<()> {
+        held.remove(button);
+        self.button_up(button)
+    }
+
+    /// Force-release every button a session still holds (disconnect / timeout path).
+    pub fn release_all(&self, held: &mut HeldButtons) {
+        for button in MouseButton::ALL {
+            if held.remove(button) {
+                let _ = self.button_up(button);
+            }
+        }
+    }
+
+    /// Scroll by high-resolution deltas (`SCROLL_UNITS_PER_NOTCH` units = one wheel notch).
+    ///
+    /// Positive `dy` scrolls down, positive `dx` scrolls right.
+    pub fn scroll(&self, dx: i32, dy: i32) -> Result<()> {
+        if dx == 0 && dy == 0 {
+            return Ok(());
+        }
+        self.enqueue(Cmd::Scroll { dx, dy })
+    }
+
+    /// Start an inertial scroll at `vx`/`vy` scroll units per second that decays like a native
+    /// trackpad fling.
+    ///
+    /// A new scroll, fling or button press stops the previous one; a zero velocity just stops it.
+    pub fn fling(&self, vx: f64, vy: f64) -> Result<()> {
+        if !vx.is_finite() || !vy.is_finite() {
+            return Ok(());
+        }
+        self.enqueue(Cmd::Fling {
+            vx: vx.clamp(-MAX_FLING_VELOCITY, MAX_FLING_VELOCITY),
+            vy: vy.clamp(-MAX_FLING_VELOCITY, MAX_FLING_VELOCITY),
+        })
+    }
+
+    /// Stop any running fling (teardown path).
+    pub fn stop_fling(&self) {
+        let _ = self.enqueue(Cmd::Fling { vx: 0.0, vy: 0.0 });
+    }
+
+    /// Press and release a key.
+    pub fn tap_key(&self, key: KeyCode) -> Result<()> {
+        self.enqueue(Cmd::Key(key, Direction::Click))
+    }
+
+    /// Tap `key` for a session, through its sticky modifiers (see [`StickyModifiers`]).
+    pub fn tap_key_sticky(&self, sticky: &mut StickyModifiers, key: KeyCode) -> Result<()> {
+        match sticky.tap(key) {
+            None => Ok(()),
+            Some((modifiers, key)) if modifiers.is_empty() => self.tap_key(key),
+            Some((modifiers, key)) => self.shortcut(&modifiers, key),
+        }
+    }
+
+    /// Type a Unicode string (emoji and non-ASCII included) using the OS text input path.
+    pub fn type_text(&self, text: &str) -> Result<()> {
+        if text.is_empty() {
+            return Ok(());
+        }
+        self.enqueue(Cmd::Text(text.to_owned()))
+    }
+
+    /// Press `modifiers`, tap `key`, then release the modifiers in reverse order.
+    ///
+    /// The whole combo runs as one worker step, so no other input can interleave with it.
+    pub fn shortcut(&self, modifiers: &[Modifier], key: KeyCode) -> Result<()> {
+        let mut unique = Vec::with_capacity(modifiers.len());
+        for m in modifiers {
+            if !unique.contains(m) {
+                unique.push(*m);
+            }
+        }
+        self.enqueue(Cmd::Shortcut(unique, key))
+    }
+
+    /// Press `key` for a session, ignoring repeats (auto-repeat is the OS's job).
+    pub fn press_key(&self, held: &mut HeldKeys, key: KeyCode) -> Result<()> {
+        if !held.insert(key) {
+            return Ok(());
+        }
+        self.enqueue(Cmd::Key(key, Direction::Press))
+    }
+
+    /// Replace the delay and rate used for [`MouseController::hold_key`].
+    pub fn set_key_repeat(&self, repeat: KeyRepeat) {
+        *self.shared.key_repeat.lock().unwrap() = repeat;
+    }
+
+    /// Press `key` for a session and auto-repeat it until [`MouseController::release_key`],
+    /// so a held on-screen arrow button scrolls like a held physical key.
+    pub fn hold_key(&self, held: &mut HeldKeys, key: KeyCode) -> Result<()> {
+        if !held.insert(key) {
+            return Ok(());
+        }
+        let repeat = *self.shared.key_repeat.lock().unwrap();
+        self.enqueue(Cmd::KeyHold(key, repeat))
+    }
+
+    /// Release `key` for a session. Releases are always forwarded, even if untracked.
+    pub fn release_key(&self, held: &mut HeldKeys, key: KeyCode) -> Result<()> {
+        held.remove(key);
+        self.enqueue(Cmd::Key(key, Direction::Release))
+    }
+
+    /// Force-release every key a session still holds (disconnect / timeout path).
+    pub fn release_keys(&self, held: &mut HeldKeys) {
+        for key in held.drain() {
+            let _ = self.enqueue(Cmd::Key(key, Direction::Release));
+        }
+    }
+
+    /// Confine every injected position to `region` for all sessions (`None` lifts the limit).
+    pub fn set_region_limit(&self, region: Option<Region>) {
+        let _ = self.enqueue(Cmd::RegionLimit(region));
+    }
+
+    /// Confine a session's input to `region`, within the server limit; `None` clears it.
+    ///
+    /// Absolute positions are clamped into the rectangle and relative moves stop at its edges.
+    pub fn confine(&self, region: Option<Region>) -> Result<()> {
+        self.enqueue(Cmd::Confine(region))
+    }
+
+    /// Choose what absolute, stylus and touch coordinates are stretched over.
+    ///
+    /// With [`MapTarget::Window`] the client space covers the focused window's content
+    /// area, followed as focus moves; without a focused window it falls back to the screen.
+    pub fn set_map_target(&self, target: MapTarget) -> Result<()> {
+        self.enqueue(Cmd::MapTarget(target))
+    }
+
+    /// Rotate or mirror every client's coordinates, e.g. for a monitor mounted in portrait.
+    pub fn set_base_transform(&self, transform: Transform) {
+        let _ = self.enqueue(Cmd::BaseTransform(transform));
+    }
+
+    /// Rotate or mirror a session's coordinates and relative moves, e.g. to follow the phone's
+    /// orientation. Applied before the base transform; [`Transform::default`] clears it.
+    pub fn set_transform(&self, transform: Transform) -> Result<()> {
+        self.enqueue(Cmd::Transform(transform))
+    }
+
+    /// Choose how a session's viewport is laid over the screen when their shapes differ.
+    pub fn set_aspect_mode(&self, mode: AspectMode) -> Result<()> {
+        self.enqueue(Cmd::Aspect(mode))
+    }
+
+    /// Map a session's absolute coordinates through a calibration instead of plain scaling
+    /// and transforms; `None` clears it.
+    pub fn calibrate(&self, calibration: Option<Homography>) -> Result<()> {
+        self.enqueue(Cmd::Calibrate(calibration))
+    }
+
+    /// Drop pointer moves shorter than `pixels` desktop pixels, so a finger resting on the
+    /// screen doesn't make the cursor tremble. Relative moves are held back until they add up
+    /// past it. Non-finite or negative values are ignored; 0 turns the filter off.
+    pub fn set_dead_zone(&self, pixels: f64) {
+        if pixels.is_finite() && pixels >= 0.0 {
+            let _ = self.enqueue(Cmd::DeadZone(pixels));
+        }
+    }
+
+    /// Smooth every client's absolute moves with a One-Euro filter; `None` turns it off.
+    pub fn set_base_smoothing(&self, params: Option<OneEuro>) {
+        let _ = self.enqueue(Cmd::BaseSmoothing(params));
+    }
+
+    /// Override the server's smoothing for a session; `None` turns it off.
+    pub fn set_smoothing(&self, params: Option<OneEuro>) -> Result<()> {
+        self.enqueue(Cmd::Smoothing(Some(params)))
+    }
+
+    /// Drop a session's smoothing override, back to the server's (teardown path).
+    pub fn clear_smoothing(&self) {
+        let _ = self.enqueue(Cmd::Smoothing(None));
+    }
+
+    /// Replace every session mapping setting with `mapping`'s: region, map target,
+    /// transform, calibration, aspect mode, smoothing and display.
+    pub fn apply_mapping(&self, mapping: &SessionMapping) {
+        let _ = self.confine(mapping.region);
+        let _ = self.set_map_target(mapping.target);
+        let _ = self.set_transform(mapping.transform);
+        let _ = self.calibrate(mapping.calibration);
+        let _ = self.set_aspect_mode(mapping.aspect);
+        match mapping.smoothing {
+            Some(params) => {
+                let _ = self.set_smoothing(params);
+            }
+            None => self.clear_smoothing(),
+        }
+        let _ = self.select_display(mapping.display);
+    }
+
+    /// Undo every session mapping setting, once no controller is left.
+    pub fn reset_session_mapping(&self) {
+        self.apply_mapping(&SessionMapping::default());
+    }
+
+    /// Move every client's absolute pointer `horizon` ahead along its recent velocity, to hide
+    /// network latency (capped at [`crate::predict::MAX_HORIZON`]); zero turns prediction off.
+    pub fn set_prediction(&self, horizon: Duration) {
+        let _ = self.enqueue(Cmd::Prediction(horizon));
+    }
+
+    /// Emit absolute moves at a steady `rate` Hz (within [`crate::resample::RESAMPLE_RATES`]),
+    /// gliding between received points so irregular client send rates don't show as
+    /// stepping; `None` moves once per received point.
+    pub fn set_resample_rate(&self, rate: Option<u32>) {
+        let _ = self.enqueue(Cmd::Resample(rate));
+    }
+
+    /// Inject client-driven moves at most `rate` times per second, merging whatever arrives
+    /// in between (absolute moves keep the newest point, relative ones add up), since some
+    /// platforms stutter when flooded with input events; `None` lifts the limit.
+    pub fn set_max_rate(&self, rate: Option<u32>) {
+        let _ = self.enqueue(Cmd::MaxRate(rate));
+    }
+
+    /// Run the worker at raised (real-time where allowed) priority and/or on one CPU core,
+    /// so injection keeps its timing under load. Failures are logged, not returned.
+    pub fn set_scheduling(&self, realtime: bool, cpu: Option<usize>) {
+        if realtime || cpu.is_some() {
+            let _ = self.enqueue(Cmd::Scheduling { realtime, cpu });
+        }
+    }
+
+    /// Record whether the screen is locked; while it is, new input is dropped and only
+    /// releases go through. Returns whether that changed.
+    pub fn set_screen_locked(&self, locked: bool) -> bool {
+        let changed = self.shared.screen_locked.swap(locked, Ordering::AcqRel) != locked;
+        if changed {
+            self.shared.wake_worker();
+        }
+        changed
+    }
+
+    /// Drop client motion while the local mouse is in use and for `grace` after it was last
+    /// moved, so remote input doesn't fight the person at the desk. Zero turns this off.
+    pub fn set_local_pause(&self, grace: Duration) {
+        let _ = self.enqueue(Cmd::LocalPause(grace));
+    }
+
+    /// Replace the hot corners checked after every client-driven move.
+    pub fn set_hot_corners(&self, corners: HotCorners) {
+        *self.shared.hot_corners.lock().unwrap() = corners;
+    }
+
+    /// Receive a [`Corner`] each time the cursor enters a live hot corner.
+    pub fn subscribe_hot_corners(&self) -> broadcast::Receiver<Corner> {
+        self.shared.corner_hits.subscribe()
+    }
+
+    /// Replace the curve applied to stylus pressure before injection.
+    pub fn set_pressure_curve(&self, curve: PressureCurve) {
+        *self.shared.pressure_curve.lock().unwrap() = curve;
+    }
+
+    /// Queue a pen sample; `x`/`y` use the same client space as [`MouseController::move_absolute`].
+    ///
+    /// Samples are injected through a virtual tablet when the platform has one, so drawing
+    /// apps see real pressure and tilt; otherwise they drive the mouse.
+    pub fn stylus(&self, client_w: u16, client_h: u16, sample: StylusSample) -> Result<()> {
+        if client_w == 0 || client_h == 0 {
+            return Ok(());
+        }
+        let mut sample = sample.sanitized();
+        sample.pressure = self
+            .shared
+            .pressure_curve
+            .lock()
+            .unwrap()
+            .apply(sample.pressure);
+        self.enqueue(Cmd::Stylus {
+            client_w,
+            client_h,
+            sample,
+        })
+    }
+
+    /// Take the pen out of range, lifting it first if it is still touching (teardown path).
+    pub fn stylus_leave(&self) {
+        let _ = self.enqueue(Cmd::StylusLeave);
+    }
+
+    /// Queue a touch frame listing every finger currently down; fingers missing from the
+    /// frame are lifted, so an empty frame ends the gesture.
+    ///
+    /// Frames go to a virtual touchscreen when the platform has one, so pinch-zoom and other
+    /// multi-finger gestures reach apps as real touch. Contacts past [`MAX_CONTACTS`] and
+    /// duplicate ids are dropped.
+    pub fn touch(&self, client_w: u16, client_h: u16, contacts: &[TouchContact]) -> Result<()> {
+        if client_w == 0 || client_h == 0 {
+            return Ok(());
+        }
+        let mut unique: Vec<TouchContact> = Vec::with_capacity(contacts.len().min(MAX_CONTACTS));
+        for c in contacts {
+            if unique.len() == MAX_CONTACTS {
+                break;
+            }
+            if !unique.iter().any(|u| u.id == c.id) {
+                unique.push(*c);
+            }
+        }
+        self.enqueue(Cmd::Touch {
+            client_w,
+            client_h,
+            contacts: unique,
+        })
+    }
+
+    /// Lift every finger still down (teardown path).
+    pub fn touch_release(&self) {
+        let _ = self.enqueue(Cmd::Touch {
+            client_w: 1,
+            client_h: 1,
+            contacts: Vec::new(),
+        });
+    }
+
+    /// Replace the virtual controller's state. The device is created on first use.
+    pub fn gamepad(&self, state: GamepadState) -> Result<()> {
+        self.enqueue(Cmd::Gamepad(state))
+    }
+
+    /// Center the sticks and release every gamepad button (teardown path).
+    pub fn gamepad_release(&self) {
+        let _ = self.enqueue(Cmd::Gamepad(GamepadState::default()));
+    }
+
+    /// Queue an ordered command behind any pending move, so it lands where the cursor was sent.
+    fn enqueue(&self, cmd: Cmd) -> Result<()> {
+        let mut guard = self.shared.pending.lock().unwrap();
+        self.shared.check_running()?;
+        if let Some(m) = self.shared.take_motion(&mut guard) {
+            guard.queue.push_back(Cmd::Motion(m));
+        }
+        guard.queue.push_back(cmd);
+        drop(guard);
+        self.shared.wake_worker();
+        Ok(())
+    }
+}
+
+impl Drop for MouseController {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
 }
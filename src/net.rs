@@ -0,0 +1,65 @@
+use socket2::{Domain, Protocol, Socket, Type};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use tracing::warn;
+
+/// A TCP listener on `addr` for `service`. On the unspecified IPv6 address it takes IPv4
+/// connections as well, and on a machine without IPv6 it falls back to the unspecified
+/// IPv4 address. If the port is taken, the system picks a free one instead.
+pub fn tcp_listener(addr: SocketAddr, service: &str) -> io::Result<std::net::TcpListener> {
+    let socket = bind_any_port(addr, Type::STREAM, Protocol::TCP, service)?;
+    socket.listen(1024)?;
+    Ok(socket.into())
+}
+
+/// A UDP socket on `addr` for `service`, dual-stack and on a free port if the one asked
+/// for is taken, like [`tcp_listener`].
+pub fn udp_socket(addr: SocketAddr, service: &str) -> io::Result<std::net::UdpSocket> {
+    Ok(bind_any_port(addr, Type::DGRAM, Protocol::UDP, service)?.into())
+}
+
+fn bind_any_port(addr: SocketAddr, ty: Type, protocol: Protocol, service: &str) -> io::Result<Socket> {
+    match bind(addr, ty, protocol) {
+        Err(err) if err.kind() == io::ErrorKind::AddrInUse && addr.port() != 0 => {
+            let socket = bind(SocketAddr::new(addr.ip(), 0), ty, protocol)?;
+            let port = socket.local_addr()?.as_socket().map_or(0, |addr| addr.port());
+            warn!("{service} port {} is taken; using {port} instead", addr.port());
+            Ok(socket)
+        }
+        res => res,
+    }
+}
+
+fn bind(addr: SocketAddr, ty: Type, protocol: Protocol) -> io::Result<Socket> {
+    let dual_stack = addr.ip() == IpAddr::V6(Ipv6Addr::UNSPECIFIED);
+    match bind_once(addr, ty, protocol, dual_stack) {
+        Err(err) if dual_stack && err.kind() != io::ErrorKind::AddrInUse => {
+            warn!("IPv6 is unavailable ({err}); listening on IPv4 only");
+            let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), addr.port());
+            bind_once(addr, ty, protocol, false)
+        }
+        res => res,
+    }
+}
+
+fn bind_once(addr: SocketAddr, ty: Type, protocol: Protocol, dual_stack: bool) -> io::Result<Socket> {
+    let socket = Socket::new(Domain::for_address(addr), ty, Some(protocol))?;
+    if dual_stack {
+        // Windows and some BSDs default to IPv6 only.
+        socket.set_only_v6(false)?;
+    }
+    // As std and tokio do, so a restart can take the port straight back.
+    #[cfg(not(windows))]
+    if ty == Type::STREAM {
+        socket.set_reuse_address(true)?;
+    }
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    Ok(socket)
+}
+
+/// `addr` with an IPv4-mapped IPv6 address (how a dual-stack socket sees IPv4 peers)
+/// turned back into IPv4, so filters, logs and the loopback check see what the user does.
+pub fn canonical(addr: SocketAddr) -> SocketAddr {
+    SocketAddr::new(addr.ip().to_canonical(), addr.port())
+}
@@ -0,0 +1,301 @@
+use crate::ipfilter::IpFilter;
+use crate::net;
+use crate::ratelimit::RateLimiter;
+use crate::replay::NonceWindow;
+use crate::proto::{MSG_HANDSHAKE, MSG_HELLO, MSG_REJECT, MSG_SEALED, MSG_TAKEOVER};
+use snow::{Builder, HandshakeState, Keypair, StatelessTransportState};
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+use tracing::{info, warn};
+
+/// Noise XX: each side learns the other's static key, and nothing is sent in the clear
+/// after the first message.
+const NOISE_PARAMS: &str = "Noise_XX_25519_ChaChaPoly_BLAKE2s";
+
+/// Mixed into every handshake, so one made for something else doesn't fit.
+const PROLOGUE: &[u8] = b"penput-udp";
+
+/// Size of the nonce in front of a sealed packet.
+const NONCE_LEN: usize = 8;
+
+/// Authentication tag Noise adds to every sealed packet.
+const TAG_LEN: usize = 16;
+
+/// A channel nobody used for this long is forgotten, and so is a handshake nobody
+/// finished.
+const CHANNEL_IDLE: Duration = Duration::from_secs(2 * 60);
+
+/// Most channels and handshakes kept at once, so a flood of handshakes can't grow the
+/// table without bound.
+const MAX_CHANNELS: usize = 256;
+
+/// Whether UDP clients may, or must, encrypt their packets (`--udp-encryption`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Encryption {
+    /// Handshakes are ignored; everything is in the clear.
+    Off,
+    /// Clients that shake hands get an encrypted channel; others are served as before.
+    #[default]
+    Optional,
+    /// Only packets sealed in a channel count; a plain HELLO is rejected.
+    Required,
+}
+
+impl Encryption {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "off" => Some(Self::Off),
+            "optional" => Some(Self::Optional),
+            "required" => Some(Self::Required),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Encryption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Off => "off",
+            Self::Optional => "optional",
+            Self::Required => "required",
+        })
+    }
+}
+
+/// An encrypted channel with one client address, or the handshake setting it up.
+struct Channel {
+    /// The handshake waiting for the client's last message. It replaces `open` once done,
+    /// so a forged first message can't tear down a working channel.
+    handshake: Option<Box<HandshakeState>>,
+    open: Option<Open>,
+    last_used: Instant,
+}
+
+struct Open {
+    transport: Box<StatelessTransportState>,
+    /// The nonce on the next packet sent.
+    send_nonce: u64,
+    received: NonceWindow,
+}
+
+/// The UDP socket with Noise on top: handshakes are answered here, sealed packets come
+/// out of [`recv_from`](Self::recv_from) opened, and whatever is sent to a client with a
+/// channel goes out sealed. Other clients are served in the clear, unless encryption is
+/// required. Packets from addresses the filter rules out, or beyond an address's rate
+/// limit, are dropped before any of that. Clients are known by their
+/// [canonical](net::canonical) address.
+pub struct SecureSocket {
+    socket: UdpSocket,
+    /// Whether the socket is IPv6, so IPv4 clients are sent to at their mapped address.
+    ipv6: bool,
+    mode: Encryption,
+    filter: Arc<IpFilter>,
+    limiter: Option<RateLimiter>,
+    keys: Keypair,
+    channels: Mutex<HashMap<SocketAddr, Channel>>,
+}
+
+impl SecureSocket {
+    pub fn new(
+        socket: UdpSocket,
+        mode: Encryption,
+        filter: Arc<IpFilter>,
+        limiter: Option<RateLimiter>,
+    ) -> anyhow::Result<Self> {
+        let keys = Builder::new(NOISE_PARAMS.parse()?).generate_keypair()?;
+        if mode != Encryption::Off {
+            let key: String = keys.public.iter().map(|b| format!("{b:02x}")).collect();
+            info!("🔐 UDP encryption key (new every start): {key}");
+        }
+        Ok(Self {
+            ipv6: socket.local_addr()?.is_ipv6(),
+            socket,
+            mode,
+            filter,
+            limiter,
+            keys,
+            channels: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// The next packet for the server loop, opened if it was sealed. Handshakes are dealt
+    /// with here, and packets that don't count are dropped.
+    pub async fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        loop {
+            let (len, addr) = self.socket.recv_from(buf).await?;
+            let addr = net::canonical(addr);
+            if !self.filter.permits(addr.ip())
+                || self.limiter.as_ref().is_some_and(|l| !l.allow(addr.ip()))
+            {
+                continue;
+            }
+            if self.mode == Encryption::Off || len == 0 {
+                return Ok((len, addr));
+            }
+            match buf[0] {
+                MSG_HANDSHAKE => {
+                    if let Some(reply) = self.handshake(addr, &buf[1..len]) {
+                        let _ = self.socket.send_to(&reply, self.wire(addr)).await;
+                    }
+                }
+                MSG_SEALED => {
+                    if let Some(len) = self.open(addr, buf, len) {
+                        return Ok((len, addr));
+                    }
+                }
+                _ if self.accepts_plain(addr) => return Ok((len, addr)),
+                MSG_HELLO | MSG_TAKEOVER if self.mode == Encryption::Required => {
+                    let _ = self.socket.send_to(&[MSG_REJECT], self.wire(addr)).await;
+                }
+                // In the clear from a client with a channel, so most likely forged.
+                _ => {}
+            }
+        }
+    }
+
+    /// How many packets from `addr` the rate limit dropped so far.
+    pub fn rate_limited(&self, addr: SocketAddr) -> u64 {
+        self.limiter.as_ref().map_or(0, |l| l.dropped(addr.ip()))
+    }
+
+    /// Send `buf` to `addr`, sealed if the client has a channel.
+    pub async fn send_to(&self, buf: &[u8], addr: SocketAddr) -> io::Result<usize> {
+        match self.seal(addr, buf) {
+            Ok(Some(sealed)) => self.socket.send_to(&sealed, self.wire(addr)).await,
+            Ok(None) => self.socket.send_to(buf, self.wire(addr)).await,
+            Err(err) => Err(io::Error::other(err)),
+        }
+    }
+
+    /// Where a client at `addr` is sent to on this socket.
+    fn wire(&self, addr: SocketAddr) -> SocketAddr {
+        match addr.ip() {
+            IpAddr::V4(ip) if self.ipv6 => SocketAddr::new(IpAddr::V6(ip.to_ipv6_mapped()), addr.port()),
+            _ => addr,
+        }
+    }
+
+    /// Whether a plain packet from `addr` counts.
+    fn accepts_plain(&self, addr: SocketAddr) -> bool {
+        if self.mode == Encryption::Required {
+            return false;
+        }
+        let mut channels = self.channels.lock().unwrap();
+        match channels.get(&addr) {
+            Some(channel) if channel.open.is_some() => {
+                if channel.last_used.elapsed() < CHANNEL_IDLE {
+                    return false;
+                }
+                // Long unused; the client went back to plain packets.
+                channels.remove(&addr);
+                true
+            }
+            _ => true,
+        }
+    }
+
+    /// Take handshake message `msg` from `addr`; returns the packet to answer with. The
+    /// last message opens the channel, anything else starts a new handshake.
+    fn handshake(&self, addr: SocketAddr, msg: &[u8]) -> Option<Vec<u8>> {
+        let mut channels = self.channels.lock().unwrap();
+        let now = Instant::now();
+
+        if let Some(channel) = channels.get_mut(&addr)
+            && let Some(mut handshake) = channel.handshake.take()
+        {
+            let mut payload = [0u8; 64];
+            if handshake.read_message(msg, &mut payload).is_ok()
+                && handshake.is_handshake_finished()
+            {
+                match handshake.into_stateless_transport_mode() {
+                    Ok(transport) => {
+                        channel.open = Some(Open {
+                            transport: Box::new(transport),
+                            send_nonce: 0,
+                            received: NonceWindow::default(),
+                        });
+                        channel.last_used = now;
+                        info!("🔐 Encrypted UDP channel with {addr}");
+                    }
+                    Err(err) => warn!("Failed to finish UDP handshake with {addr}: {err}"),
+                }
+                return None;
+            }
+        }
+
+        channels.retain(|_, c| c.last_used.elapsed() < CHANNEL_IDLE);
+        if !channels.contains_key(&addr) && channels.len() >= MAX_CHANNELS {
+            return None;
+        }
+
+        let mut handshake = Builder::new(NOISE_PARAMS.parse().ok()?)
+            .local_private_key(&self.keys.private)
+            .prologue(PROLOGUE)
+            .build_responder()
+            .ok()?;
+        let mut payload = [0u8; 64];
+        handshake.read_message(msg, &mut payload).ok()?;
+        let mut reply = vec![0u8; 1 + 128];
+        reply[0] = MSG_HANDSHAKE;
+        let len = handshake.write_message(&[], &mut reply[1..]).ok()?;
+        reply.truncate(1 + len);
+
+        let channel = channels.entry(addr).or_insert_with(|| Channel {
+            handshake: None,
+            open: None,
+            last_used: now,
+        });
+        channel.handshake = Some(Box::new(handshake));
+        if channel.open.is_none() {
+            channel.last_used = now;
+        }
+        Some(reply)
+    }
+
+    /// Open the sealed packet in `buf[..len]` in place; returns the length of the packet
+    /// inside.
+    fn open(&self, addr: SocketAddr, buf: &mut [u8], len: usize) -> Option<usize> {
+        if len < 1 + NONCE_LEN + TAG_LEN {
+            return None;
+        }
+        let nonce = u64::from_be_bytes(buf[1..1 + NONCE_LEN].try_into().ok()?);
+        let mut channels = self.channels.lock().unwrap();
+        let channel = channels.get_mut(&addr)?;
+        let open = channel.open.as_mut()?;
+        let mut plain = vec![0u8; len];
+        let plain_len = open
+            .transport
+            .read_message(nonce, &buf[1 + NONCE_LEN..len], &mut plain)
+            .ok()?;
+        // Only once it is known to be genuine, so forged nonces can't use up real ones.
+        if plain_len == 0 || !open.received.accept(nonce) {
+            return None;
+        }
+        channel.last_used = Instant::now();
+        buf[..plain_len].copy_from_slice(&plain[..plain_len]);
+        Some(plain_len)
+    }
+
+    /// `buf` sealed for `addr`, or `None` if it has no channel.
+    fn seal(&self, addr: SocketAddr, buf: &[u8]) -> Result<Option<Vec<u8>>, snow::Error> {
+        let mut channels = self.channels.lock().unwrap();
+        let Some(open) = channels.get_mut(&addr).and_then(|c| c.open.as_mut()) else {
+            return Ok(None);
+        };
+        let nonce = open.send_nonce;
+        let mut sealed = vec![0u8; 1 + NONCE_LEN + buf.len() + TAG_LEN];
+        sealed[0] = MSG_SEALED;
+        sealed[1..1 + NONCE_LEN].copy_from_slice(&nonce.to_be_bytes());
+        let len = open
+            .transport
+            .write_message(nonce, buf, &mut sealed[1 + NONCE_LEN..])?;
+        open.send_nonce += 1;
+        sealed.truncate(1 + NONCE_LEN + len);
+        Ok(Some(sealed))
+    }
+}
@@ -0,0 +1,78 @@
+use crate::connection::Answer;
+use tokio::sync::mpsc;
+
+/// Whether this build can raise desktop notifications (the `notify` feature).
+pub const AVAILABLE: bool = cfg!(feature = "notify");
+
+/// Raise a desktop notification for approval request `number`. Clicking Approve or Deny
+/// sends the answer to `answers`; dismissing it leaves the request to the terminal.
+/// Failures are only logged, since the terminal prompt is still there.
+pub fn request(number: u32, summary: String, body: String, answers: mpsc::UnboundedSender<Answer>) {
+    platform::request(number, summary, body, answers);
+}
+
+#[cfg(feature = "notify")]
+mod platform {
+    use crate::connection::Answer;
+    use notify_rust::Notification;
+    use tokio::sync::mpsc;
+    use tracing::warn;
+
+    pub fn request(
+        number: u32,
+        summary: String,
+        body: String,
+        answers: mpsc::UnboundedSender<Answer>,
+    ) {
+        // Showing blocks on the notification service, and waiting for a click may never end.
+        std::thread::spawn(move || {
+            let mut notification = Notification::new();
+            notification.appname("Penput").summary(&summary).body(&body);
+            #[cfg(not(target_os = "macos"))]
+            notification.action(APPROVE, "Approve").action(DENY, "Deny");
+            match notification.show() {
+                #[cfg(not(target_os = "macos"))]
+                Ok(handle) => handle.wait_for_action(|action| {
+                    if let Some(decision) = decision(action) {
+                        let _ = answers.send((number, decision));
+                    }
+                }),
+                // macOS only reports clicks to apps running a main run loop, which the
+                // server doesn't, so there the notification just points at the terminal.
+                #[cfg(target_os = "macos")]
+                Ok(_) => drop(answers),
+                Err(err) => warn!("Failed to show a notification for request #{number}: {err}"),
+            }
+        });
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    const APPROVE: &str = "approve";
+    #[cfg(not(target_os = "macos"))]
+    const DENY: &str = "deny";
+
+    #[cfg(not(target_os = "macos"))]
+    fn decision(action: &str) -> Option<crate::connection::Decision> {
+        use crate::connection::Decision;
+
+        match action {
+            APPROVE => Some(Decision::Approved),
+            DENY => Some(Decision::Rejected),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(not(feature = "notify"))]
+mod platform {
+    use crate::connection::Answer;
+    use tokio::sync::mpsc;
+
+    pub fn request(
+        _number: u32,
+        _summary: String,
+        _body: String,
+        _answers: mpsc::UnboundedSender<Answer>,
+    ) {
+    }
+}
@@ -0,0 +1,117 @@
+use crate::smoothing::OneEuro;
+use serde::{Deserialize, Deserializer};
+use std::time::{Duration, Instant};
+
+/// Highest sensitivity a client may pick.
+const MAX_SENSITIVITY: f64 = 20.0;
+
+/// Highest acceleration a client may pick.
+const MAX_ACCELERATION: f64 = 4.0;
+
+/// Highest scroll speed multiplier a client may pick.
+const MAX_SCROLL_SPEED: f64 = 10.0;
+
+/// Hand speed, in client pixels per second, at which acceleration adds its full amount.
+const REFERENCE_SPEED: f64 = 1000.0;
+
+/// Acceleration never multiplies a move by more than this.
+const MAX_GAIN: f64 = 4.0;
+
+/// Moves further apart than this start a new stroke and are not accelerated.
+const STROKE_GAP: Duration = Duration::from_millis(100);
+
+/// A `settings` message: each field present replaces the session's current value.
+#[derive(Debug, Copy, Clone, Default, Deserialize)]
+pub struct PointerSettings {
+    /// Multiplier for relative moves, replacing `--sensitivity` for this session.
+    pub sensitivity: Option<f64>,
+    /// Extra gain for fast strokes; 0 turns acceleration off.
+    pub acceleration: Option<f64>,
+    /// Multiplier for scroll and fling deltas.
+    pub scroll_speed: Option<f64>,
+    /// One-Euro smoothing of absolute moves; `null` turns it off for this session. Applied
+    /// by the input worker rather than [`PointerProfile`].
+    #[serde(default, deserialize_with = "present")]
+    pub smoothing: Option<Option<OneEuro>>,
+}
+
+/// Tell a field sent as `null` apart from one left out.
+fn present<'de, D: Deserializer<'de>, T: Deserialize<'de>>(d: D) -> Result<Option<T>, D::Error> {
+    T::deserialize(d).map(Some)
+}
+
+/// One session's pointer feel, applied to its relative moves and scrolls.
+#[derive(Debug, Clone)]
+pub struct PointerProfile {
+    sensitivity: Option<f64>,
+    acceleration: f64,
+    scroll_speed: f64,
+    last_move: Option<Instant>,
+}
+
+impl Default for PointerProfile {
+    fn default() -> Self {
+        Self {
+            sensitivity: None,
+            acceleration: 0.0,
+            scroll_speed: 1.0,
+            last_move: None,
+        }
+    }
+}
+
+impl PointerProfile {
+    /// Apply a `settings` message. Out-of-range values are ignored.
+    pub fn update(&mut self, settings: PointerSettings) {
+        if let Some(s) = settings
+            .sensitivity
+            .filter(|s| *s > 0.0 && *s <= MAX_SENSITIVITY)
+        {
+            self.sensitivity = Some(s);
+        }
+        if let Some(a) = settings
+            .acceleration
+            .filter(|a| (0.0..=MAX_ACCELERATION).contains(a))
+        {
+            self.acceleration = a;
+        }
+        if let Some(s) = settings
+            .scroll_speed
+            .filter(|s| *s > 0.0 && *s <= MAX_SCROLL_SPEED)
+        {
+            self.scroll_speed = s;
+        }
+    }
+
+    /// The session's sensitivity, if it overrides the server's.
+    pub fn sensitivity(&self) -> Option<f64> {
+        self.sensitivity
+    }
+
+    /// Gain for a move of `dx`/`dy` client pixels arriving at `now`.
+    pub fn gain(&mut self, dx: f64, dy: f64, now: Instant) -> f64 {
+        let prev = self.last_move.replace(now);
+        if self.acceleration == 0.0 {
+            return 1.0;
+        }
+        let Some(dt) = prev
+            .map(|p| now.duration_since(p))
+            .filter(|dt| !dt.is_zero() && *dt <= STROKE_GAP)
+        else {
+            return 1.0;
+        };
+        let speed = dx.hypot(dy) / dt.as_secs_f64();
+        (1.0 + self.acceleration * speed / REFERENCE_SPEED).min(MAX_GAIN)
+    }
+
+    /// Scale a scroll delta, in scroll units, by the session's scroll speed.
+    pub fn scroll(&self, dx: i32, dy: i32) -> (i32, i32) {
+        let scale = |d: i32| (f64::from(d) * self.scroll_speed).round() as i32;
+        (scale(dx), scale(dy))
+    }
+
+    /// Scale a fling velocity by the session's scroll speed.
+    pub fn fling(&self, vx: f64, vy: f64) -> (f64, f64) {
+        (vx * self.scroll_speed, vy * self.scroll_speed)
+    }
+}
@@ -0,0 +1,130 @@
+use serde::Deserialize;
+use std::io;
+
+/// Power and session actions. Each must be enabled in the `[system]` table of the
+/// actions file before a client can trigger it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SystemAction {
+    LockScreen,
+    Suspend,
+    DisplayOff,
+}
+
+impl SystemAction {
+    pub fn name(self) -> &'static str {
+        match self {
+            SystemAction::LockScreen => "lock_screen",
+            SystemAction::Suspend => "suspend",
+            SystemAction::DisplayOff => "display_off",
+        }
+    }
+
+    /// Carry out the action with the platform's native mechanism.
+    pub fn perform(self) -> io::Result<()> {
+        platform::perform(self)
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use super::SystemAction;
+    use std::io;
+    use windows_sys::Win32::System::Power::SetSuspendState;
+    use windows_sys::Win32::System::Shutdown::LockWorkStation;
+    use windows_sys::Win32::UI::WindowsAndMessaging::{
+        HWND_BROADCAST, PostMessageW, SC_MONITORPOWER, WM_SYSCOMMAND,
+    };
+
+    /// `SC_MONITORPOWER` argument that turns displays off.
+    const MONITOR_OFF: isize = 2;
+
+    pub fn perform(action: SystemAction) -> io::Result<()> {
+        // SAFETY: plain FFI calls without pointers.
+        let ok = unsafe {
+            match action {
+                SystemAction::LockScreen => LockWorkStation() != 0,
+                SystemAction::Suspend => SetSuspendState(false, false, false),
+                SystemAction::DisplayOff => {
+                    PostMessageW(
+                        HWND_BROADCAST,
+                        WM_SYSCOMMAND,
+                        SC_MONITORPOWER as usize,
+                        MONITOR_OFF,
+                    ) != 0
+                }
+            }
+        };
+        if ok {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+mod platform {
+    use super::SystemAction;
+    use std::io;
+    use std::process::{Command, Stdio};
+
+    #[cfg(target_os = "macos")]
+    fn command(action: SystemAction) -> Command {
+        match action {
+            SystemAction::LockScreen => {
+                let mut cmd = Command::new("osascript");
+                cmd.args([
+                    "-e",
+                    r#"tell application "System Events" to keystroke "q" using {control down, command down}"#,
+                ]);
+                cmd
+            }
+            SystemAction::Suspend => {
+                let mut cmd = Command::new("pmset");
+                cmd.arg("sleepnow");
+                cmd
+            }
+            SystemAction::DisplayOff => {
+                let mut cmd = Command::new("pmset");
+                cmd.arg("displaysleepnow");
+                cmd
+            }
+        }
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    fn command(action: SystemAction) -> Command {
+        match action {
+            SystemAction::LockScreen => {
+                let mut cmd = Command::new("loginctl");
+                cmd.arg("lock-session");
+                cmd
+            }
+            SystemAction::Suspend => {
+                let mut cmd = Command::new("systemctl");
+                cmd.arg("suspend");
+                cmd
+            }
+            // X11 only; Wayland compositors have no common equivalent.
+            SystemAction::DisplayOff => {
+                let mut cmd = Command::new("xset");
+                cmd.args(["dpms", "force", "off"]);
+                cmd
+            }
+        }
+    }
+
+    pub fn perform(action: SystemAction) -> io::Result<()> {
+        let status = command(action)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(io::Error::other(format!("exited with {status}")))
+        }
+    }
+}
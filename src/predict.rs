@@ -0,0 +1,61 @@
+use std::time::{Duration, Instant};
+
+/// Longest horizon the predictor accepts; further out, guesses overshoot more than they help.
+pub const MAX_HORIZON: Duration = Duration::from_millis(100);
+
+/// Samples further apart than this start over, so a stale velocity never flings the cursor.
+const RESTART_GAP: Duration = Duration::from_millis(100);
+
+/// Weight of the newest velocity reading against the running estimate.
+const VELOCITY_WEIGHT: f64 = 0.5;
+
+/// Extrapolates absolute positions along their recent velocity to hide network latency.
+#[derive(Debug, Clone, Default)]
+pub struct Predictor {
+    horizon: Duration,
+    last: Option<(Instant, (f64, f64))>,
+    /// Pixels per second.
+    vel: (f64, f64),
+}
+
+impl Predictor {
+    /// How far ahead to predict, capped at [`MAX_HORIZON`]; zero turns prediction off.
+    pub fn set_horizon(&mut self, horizon: Duration) {
+        self.horizon = horizon.min(MAX_HORIZON);
+        self.reset();
+    }
+
+    pub fn horizon(&self) -> Duration {
+        self.horizon
+    }
+
+    /// Forget the motion so far, e.g. after something else moved the cursor.
+    pub fn reset(&mut self) {
+        self.last = None;
+        self.vel = (0.0, 0.0);
+    }
+
+    /// Where the pointer observed at `x`/`y` at `now` should be one horizon from now.
+    pub fn predict(&mut self, x: f64, y: f64, now: Instant) -> (f64, f64) {
+        if self.horizon.is_zero() {
+            return (x, y);
+        }
+        let prev = self.last.replace((now, (x, y)));
+        let Some((at, (px, py))) = prev.filter(|(at, _)| {
+            let gap = now.duration_since(*at);
+            !gap.is_zero() && gap <= RESTART_GAP
+        }) else {
+            self.vel = (0.0, 0.0);
+            return (x, y);
+        };
+
+        let dt = now.duration_since(at).as_secs_f64();
+        let blend = |old: f64, new: f64| old + VELOCITY_WEIGHT * (new - old);
+        self.vel = (
+            blend(self.vel.0, (x - px) / dt),
+            blend(self.vel.1, (y - py) / dt),
+        );
+        let ahead = self.horizon.as_secs_f64();
+        (x + self.vel.0 * ahead, y + self.vel.1 * ahead)
+    }
+}
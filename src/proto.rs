@@ -0,0 +1,1839 @@
+use crate::air::MotionSample;
+use crate::audit::InputKind;
+use crate::connection::Device;
+use crate::cursor::CursorPos;
+use crate::display::Display;
+use crate::gamepad::GamepadState;
+use crate::gesture::Feedback;
+use crate::keyboard::{KeyCode, MediaAction, Modifier};
+use crate::mapping::{AspectMode, Rotation, Transform};
+use crate::mouse::MouseButton;
+use crate::pointer::PointerSettings;
+use crate::quality::Report;
+use crate::region::Region;
+use crate::smoothing::OneEuro;
+use crate::stylus::StylusSample;
+use crate::touch::{MAX_CONTACTS, TouchContact};
+use crate::window::MapTarget;
+use serde::{Deserialize, Serialize};
+
+/// Newest version of the client protocol this server speaks. Goes up whenever a packet or
+/// message changes in a way an older client would misread.
+pub const VERSION: u8 = 1;
+
+/// Oldest version still served. Clients that didn't say speak version 1.
+pub const MIN_VERSION: u8 = 1;
+
+/// The version to speak with a client whose newest is `client`: the highest both sides
+/// know, or `None` if the client is too old for this server.
+pub fn negotiate(client: Option<u8>) -> Option<u8> {
+    let client = client.unwrap_or(1);
+    (client >= MIN_VERSION).then(|| client.min(VERSION))
+}
+
+// Packet types (client -> server)
+pub const MSG_HELLO: u8 = 0x01; // [type=1][w:u16be][h:u16be][mode:u8, optional][token:u128be, optional][device:utf8][0][name:utf8][0][platform:utf8][0][pin:utf8][0][pair:utf8][0][version:u8] (each optional)
+const MSG_MOVE: u8 = 0x02; // [type=2][x:u16be][y:u16be][seq:u32be, optional][t:u32be ms, optional]
+const MSG_PING: u8 = 0x03; // [type=3][t:u64be][rtt_ms:u16be, optional]
+const MSG_CLICK: u8 = 0x04; // [type=4][button:u8][count:u8, optional][seq:u32be, optional][t:u32be ms, optional]
+const MSG_BUTTON_DOWN: u8 = 0x05; // [type=5][button:u8]
+const MSG_BUTTON_UP: u8 = 0x06; // [type=6][button:u8]
+const MSG_SCROLL: u8 = 0x07; // [type=7][dx:i16be][dy:i16be] (120 units = one notch)
+const MSG_MOVE_REL: u8 = 0x08; // [type=8][dx:i16be][dy:i16be]
+const MSG_KEY: u8 = 0x09; // [type=9][action:u8][name:utf8...]
+const MSG_SHORTCUT: u8 = 0x0A; // [type=0x0A][modifiers:u8 bitmask][name:utf8...]
+const MSG_MEDIA: u8 = 0x0B; // [type=0x0B][action:u8]
+const MSG_STYLUS: u8 = 0x0C; // [type=0x0C][x:u16be][y:u16be][pressure:u16be][tilt_x:i8][tilt_y:i8][flags:u8]
+const MSG_TOUCH: u8 = 0x0D; // [type=0x0D][count:u8] then count x [id:u8][x:u16be][y:u16be]
+const MSG_TOUCHPAD: u8 = 0x0E; // same layout as MSG_TOUCH
+const MSG_FLING: u8 = 0x0F; // [type=0x0F][vx:i16be][vy:i16be] (scroll units per second)
+// 0x10..=0x1F and 0x32 up are server -> client, listed below.
+const MSG_MOTION: u8 = 0x20; // [type=0x20][t:u32be ms][gx,gy,gz:i16be mrad/s][ax,ay,az:i16be mm/s^2]
+const MSG_ACTION: u8 = 0x21; // [type=0x21][name:utf8...]
+const MSG_CLIPBOARD_SET: u8 = 0x22; // [type=0x22][text:utf8...]
+const MSG_OPEN_URL: u8 = 0x23; // [type=0x23][url:utf8...]
+const MSG_GAMEPAD: u8 = 0x24; // [type=0x24][buttons:u16be][lx,ly,rx,ry:i16be][lt:u8][rt:u8]
+const MSG_STICKY_KEYS: u8 = 0x25; // [type=0x25][enabled:u8]
+const MSG_REGION: u8 = 0x26; // [type=0x26][x:i32be][y:i32be][w:u16be][h:u16be] (w or h = 0 clears)
+const MSG_MAP_TARGET: u8 = 0x27; // [type=0x27][target:u8] (0 = screen, 1 = focused window, 2 = all displays)
+const MSG_TRANSFORM: u8 = 0x28; // [type=0x28][quarter_turns_cw:u8][flags:u8]
+const MSG_CALIBRATE: u8 = 0x29; // [type=0x29][count:u8 = 0 or 4] then count x [x:u16be][y:u16be]
+const MSG_SETTINGS: u8 = 0x2A; // [type=0x2A][sensitivity:u16be][acceleration:u16be][scroll_speed:u16be][min_cutoff:u16be][beta:u16be, optional pair]
+const MSG_DISPLAY: u8 = 0x2B; // [type=0x2B][index:u8] (0xFF = next display)
+const MSG_CURSOR_POS: u8 = 0x2C; // [type=0x2C]
+const MSG_CURSOR_STREAM: u8 = 0x2D; // [type=0x2D][interval_ms:u16be] (0 stops)
+const MSG_CONTROL: u8 = 0x2E; // [type=0x2E][action:u8] (0 = release the token, 1 = request it)
+pub const MSG_TAKEOVER: u8 = 0x2F; // same layout as MSG_HELLO; replaces the client in control when there is no room
+// Both directions, handled by SecureSocket before the packet reaches the server loop.
+pub const MSG_HANDSHAKE: u8 = 0x30; // [type=0x30][noise message] (Noise XX; client sends messages 1 and 3, server answers with 2)
+pub const MSG_SEALED: u8 = 0x31; // [type=0x31][nonce:u64be][ciphertext] (any other packet, encrypted)
+
+// Packet types (server -> client)
+const MSG_ACCEPT: u8 = 0x10; // [type=0x10][remote_w:u16be][remote_h:u16be][mode:u8][version:u8]
+pub const MSG_REJECT: u8 = 0x11; // [type=0x11]
+const MSG_BUSY: u8 = 0x12; // [type=0x12]
+const MSG_PONG: u8 = 0x13; // [type=0x13][t:u64be]
+const MSG_FEEDBACK: u8 = 0x14; // [type=0x14][event:u8]
+const MSG_CALIBRATION: u8 = 0x15; // [type=0x15][ok:u8]
+const MSG_DISPLAY_INFO: u8 = 0x16; // [type=0x16][index:u8][x:i32be][y:i32be][w:u16be][h:u16be]
+const MSG_DISPLAYS: u8 = 0x17; // [type=0x17][count:u8][current:u8] then count x [x:i32be][y:i32be][w:u16be][h:u16be][primary:u8]
+const MSG_SCREEN: u8 = 0x18; // [type=0x18][w:u16be][h:u16be] (the display in use changed size)
+const MSG_CURSOR: u8 = 0x19; // [type=0x19][x:i32be][y:i32be][display:u8] (0xFF = off every display; bare type = unknown)
+const MSG_CONTROL_STATE: u8 = 0x1A; // [type=0x1A][in_control:u8] (only when control is shared)
+const MSG_TAKEOVER_REQUEST: u8 = 0x1B; // [type=0x1B] (another client asked for this one's place)
+const MSG_TAKEN_OVER: u8 = 0x1C; // [type=0x1C] (the place was handed over; the session has ended)
+const MSG_SESSION: u8 = 0x1D; // [type=0x1D][token:u128be] (resume with it in HELLO)
+const MSG_TIMEOUT: u8 = 0x1E; // [type=0x1E] (nobody answered the approval prompt in time)
+const MSG_KICKED: u8 = 0x1F; // [type=0x1F] (disconnected from the server console)
+const MSG_IDLE: u8 = 0x32; // [type=0x32] (no input for --reapprove-after; the session has ended)
+const MSG_UNSUPPORTED_VERSION: u8 = 0x33; // [type=0x33][min:u8][max:u8] (the HELLO's version is too old)
+const MSG_NACK: u8 = 0x34; // [type=0x34][reason:u8][packet_type:u8] (the packet was not acted on)
+const MSG_QUALITY: u8 = 0x35; // [type=0x35][rtt_ms:u16be][jitter_ms:u16be][loss:u16be per mille][dropped:u32be] (0xFFFF = unknown)
+const MSG_RATE_HINT: u8 = 0x36; // [type=0x36][max_hz:u16be] (0 = no limit)
+const MSG_GOODBYE: u8 = 0x37; // [type=0x37][reason:u8] (the server ended the session; last packet of it)
+const MSG_KEEPALIVE: u8 = 0x38; // [type=0x38] (nothing heard for a while; answer with a PING)
+
+// Flag bits used by stylus packets.
+const STYLUS_HOVER: u8 = 1 << 0;
+const STYLUS_ERASER: u8 = 1 << 1;
+const STYLUS_BARREL: u8 = 1 << 2;
+
+// Flag bits used by transform packets.
+const TRANSFORM_INVERT_X: u8 = 1 << 0;
+const TRANSFORM_INVERT_Y: u8 = 1 << 1;
+
+// MSG_SETTINGS field value that leaves the setting unchanged.
+const SETTING_UNCHANGED: u16 = u16::MAX;
+
+// MSG_DISPLAY index that moves on to the next display.
+const DISPLAY_NEXT: u8 = u8::MAX;
+
+// Actions used by control packets.
+const CONTROL_RELEASE: u8 = 0;
+const CONTROL_REQUEST: u8 = 1;
+
+// Key actions used by key packets.
+const KEY_UP: u8 = 0;
+const KEY_DOWN: u8 = 1;
+const KEY_TAP: u8 = 2;
+const KEY_HOLD: u8 = 3;
+
+// Button codes used by click packets.
+const BUTTON_LEFT: u8 = 0;
+const BUTTON_RIGHT: u8 = 1;
+const BUTTON_MIDDLE: u8 = 2;
+
+// Event codes used by feedback packets.
+const FEEDBACK_DRAG_START: u8 = 0;
+const FEEDBACK_DRAG_END: u8 = 1;
+
+/// Why a packet or message was not acted on: the reason byte of a UDP NACK, and the
+/// `code` of a WebSocket `error` message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Nack {
+    /// Too short, fields of the wrong kind, or values out of range.
+    Malformed,
+    /// A type this server doesn't know.
+    UnknownType,
+    /// Not something this sender may do: no session yet, or a viewer sending input.
+    Unauthorized,
+    /// Input while another client holds shared control.
+    NotInControl,
+    /// Refused for now because earlier requests are still being worked through.
+    Busy,
+}
+
+impl Nack {
+    fn code(self) -> u8 {
+        match self {
+            Self::Malformed => 0,
+            Self::UnknownType => 1,
+            Self::Unauthorized => 2,
+            Self::NotInControl => 3,
+            Self::Busy => 4,
+        }
+    }
+}
+
+/// Why the server ended a session: the reason byte of a UDP GOODBYE.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Goodbye {
+    /// The server is shutting down.
+    Shutdown,
+    /// Disconnected from the server console.
+    Kicked,
+    /// Another client took the place over.
+    TakenOver,
+    /// Nothing heard from the client for too long.
+    TimedOut,
+    /// No input for `--reapprove-after`.
+    Idle,
+    /// Remote input was cut off with an emergency stop.
+    Halted,
+}
+
+impl Goodbye {
+    fn code(self) -> u8 {
+        match self {
+            Self::Shutdown => 0,
+            Self::Kicked => 1,
+            Self::TakenOver => 2,
+            Self::TimedOut => 3,
+            Self::Idle => 4,
+            Self::Halted => 5,
+        }
+    }
+}
+
+/// A HELLO or TAKEOVER: who the client is and how it wants its input mapped.
+#[derive(Debug)]
+pub struct Hello {
+    /// Sent as TAKEOVER, asking for the place of the client in control if there is no room.
+    pub takeover: bool,
+    pub w: u16,
+    pub h: u16,
+    /// Unknown modes fall back to fill; ACCEPT reports what was applied.
+    pub mode: AspectMode,
+    /// Token from SESSION, to resume a session.
+    pub token: Option<u128>,
+    pub device: Device,
+    /// Newest protocol version the client speaks.
+    pub version: Option<u8>,
+}
+
+/// Encoding of WebSocket client messages, asked for in `init`. Server messages stay JSON
+/// text.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Encoding {
+    /// MessagePack in binary frames, with the same fields as the JSON form.
+    Msgpack,
+    /// Text frames; also what an encoding this server doesn't know falls back to.
+    #[default]
+    #[serde(other)]
+    Json,
+}
+
+/// What a `control` message or CONTROL packet asks for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ControlAction {
+    /// Ask for the control token.
+    Request,
+    /// Give it up.
+    Release,
+}
+
+/// A message from a client, decoded: a UDP packet through [`ClientMessage::decode`], or
+/// a WebSocket message from JSON or MessagePack, discriminated by `type`. Both transports
+/// share the variants; the few that only one of them can send say so.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    /// UDP only; a WebSocket client says who it is in the upgrade request.
+    #[serde(skip)]
+    Hello(Box<Hello>),
+    /// WebSocket only: the viewport and what the client speaks.
+    Init {
+        width: u16,
+        height: u16,
+        /// How the viewport is laid over the screen; confirmed with a `mapping` reply.
+        #[serde(default)]
+        mode: AspectMode,
+        /// Newest protocol version the client speaks; answered with the one agreed on.
+        #[serde(default)]
+        version: Option<u8>,
+        /// Encoding for the messages that follow; answered with the one applied.
+        #[serde(default)]
+        encoding: Encoding,
+        /// Whether the client would send moves in batches; answered with whether it may.
+        #[serde(default)]
+        batch: bool,
+    },
+    /// UDP only; WebSocket moves are binary frames (see [`decode_ws_move`]).
+    /// `t`: low 32 bits of the client's ping clock when the move was made.
+    #[serde(skip)]
+    Move {
+        x: u16,
+        y: u16,
+        seq: Option<u32>,
+        t: Option<u32>,
+    },
+    Ping {
+        t: u64,
+        /// The round trip the client measured from its previous pong, for `status`.
+        #[serde(default)]
+        rtt_ms: Option<u64>,
+    },
+    Click {
+        button: MouseButton,
+        #[serde(default = "default_click_count")]
+        count: u8,
+        #[serde(skip)]
+        seq: Option<u32>,
+        #[serde(skip)]
+        t: Option<u32>,
+    },
+    ButtonDown {
+        button: MouseButton,
+    },
+    ButtonUp {
+        button: MouseButton,
+    },
+    /// High-resolution units, 120 to a notch.
+    Scroll {
+        #[serde(default)]
+        dx: i32,
+        #[serde(default)]
+        dy: i32,
+    },
+    MoveRel {
+        dx: f64,
+        dy: f64,
+    },
+    /// `down` omitted means press and release.
+    Key {
+        code: KeyCode,
+        down: Option<bool>,
+    },
+    /// Press `code` and auto-repeat it server-side until `key_release`.
+    KeyHold {
+        code: KeyCode,
+    },
+    KeyRelease {
+        code: KeyCode,
+    },
+    /// WebSocket only: type a string.
+    Text {
+        value: String,
+    },
+    Shortcut {
+        #[serde(default)]
+        modifiers: Vec<Modifier>,
+        key: KeyCode,
+    },
+    Media {
+        action: MediaAction,
+    },
+    /// Pen sample in the same coordinate space as moves.
+    Stylus(StylusSample),
+    /// Every finger currently down; an empty list lifts them all.
+    Touch {
+        #[serde(default)]
+        contacts: Vec<TouchContact>,
+    },
+    /// Like `Touch`, but interpreted server-side as a touchpad (taps click, long press drags).
+    Touchpad {
+        #[serde(default)]
+        contacts: Vec<TouchContact>,
+    },
+    /// Inertial scroll velocity in scroll units per second; zero stops a running fling.
+    Fling {
+        #[serde(default)]
+        vx: f64,
+        #[serde(default)]
+        vy: f64,
+    },
+    /// Phone IMU reading for air-mouse pointing.
+    Motion(MotionSample),
+    /// Run a macro from the server's actions file.
+    Action {
+        name: String,
+    },
+    /// Put text on the desktop clipboard.
+    ClipboardSet {
+        text: String,
+    },
+    /// Open an http(s) link in the desktop's default browser.
+    OpenUrl {
+        url: String,
+    },
+    /// Full virtual controller state; omitted fields are neutral.
+    Gamepad(GamepadState),
+    /// Latch modifier taps until the next key tap (accessibility).
+    StickyKeys {
+        enabled: bool,
+    },
+    /// Confine this session's input to a desktop rectangle; `None` clears it.
+    Region {
+        region: Option<Region>,
+    },
+    /// Stretch coordinates over the whole screen or the focused window.
+    MapTarget {
+        target: MapTarget,
+    },
+    /// Rotate/mirror this session's coordinates.
+    Transform(Transform),
+    /// Client points tapped for the calibration targets; none clears it, and anything
+    /// but four is refused.
+    Calibrate {
+        #[serde(default)]
+        points: Vec<(u16, u16)>,
+    },
+    /// Tune this session's pointer feel; omitted fields keep their current value.
+    Settings(PointerSettings),
+    /// Ask for the list of displays.
+    Displays,
+    /// Map coordinates onto display `index` (from the `displays` list).
+    Display {
+        index: usize,
+    },
+    /// Move on to the next display, wrapping around.
+    NextDisplay,
+    /// Ask where the OS cursor is.
+    CursorPos,
+    /// Report the cursor position every `interval_ms` while it moves; 0 stops.
+    CursorStream {
+        interval_ms: u64,
+    },
+    /// Ask for or give up the control token (`--arbitration token`).
+    Control {
+        action: ControlAction,
+    },
+    /// WebSocket only: a WebRTC offer opening a data channel for moves, answered with
+    /// `rtc_answer`.
+    RtcOffer {
+        sdp: String,
+    },
+    /// WebSocket only: an ICE candidate the browser gathered for that connection.
+    RtcCandidate {
+        candidate: String,
+    },
+    /// UDP only: HANDSHAKE or SEALED, which only get this far with encryption off.
+    #[serde(skip)]
+    Encrypted,
+}
+
+fn default_click_count() -> u8 {
+    1
+}
+
+impl ClientMessage {
+    /// Decode a UDP packet, telling why if it can't be.
+    pub fn decode(pkt: &[u8]) -> Result<Self, Nack> {
+        let (&ty, body) = pkt.split_first().ok_or(Nack::Malformed)?;
+        let mut r = Reader(body);
+        let msg = match ty {
+            MSG_HELLO | MSG_TAKEOVER => {
+                Self::Hello(Box::new(decode_hello(ty == MSG_TAKEOVER, pkt)?))
+            }
+            MSG_MOVE => Self::Move {
+                x: r.u16()?,
+                y: r.u16()?,
+                seq: r.u32().ok(),
+                t: r.u32().ok(),
+            },
+            MSG_PING => Self::Ping {
+                t: r.u64()?,
+                rtt_ms: r.u16().ok().map(u64::from),
+            },
+            MSG_CLICK => {
+                let button = button(r.u8()?)?;
+                let count = r.u8().unwrap_or(1);
+                Self::Click {
+                    button,
+                    count,
+                    seq: r.u32().ok(),
+                    t: r.u32().ok(),
+                }
+            }
+            MSG_BUTTON_DOWN => Self::ButtonDown {
+                button: button(r.u8()?)?,
+            },
+            MSG_BUTTON_UP => Self::ButtonUp {
+                button: button(r.u8()?)?,
+            },
+            MSG_SCROLL => Self::Scroll {
+                dx: r.i16()?.into(),
+                dy: r.i16()?.into(),
+            },
+            MSG_MOVE_REL => Self::MoveRel {
+                dx: r.i16()?.into(),
+                dy: r.i16()?.into(),
+            },
+            MSG_KEY => {
+                let action = r.u8()?;
+                let code = r.key()?;
+                match action {
+                    KEY_UP => Self::Key {
+                        code,
+                        down: Some(false),
+                    },
+                    KEY_DOWN => Self::Key {
+                        code,
+                        down: Some(true),
+                    },
+                    KEY_TAP => Self::Key { code, down: None },
+                    KEY_HOLD => Self::KeyHold { code },
+                    _ => return Err(Nack::Malformed),
+                }
+            }
+            MSG_SHORTCUT => {
+                let bits = r.u8()?;
+                let modifiers = Modifier::ALL
+                    .into_iter()
+                    .filter(|m| bits & m.bit() != 0)
+                    .collect();
+                Self::Shortcut {
+                    modifiers,
+                    key: r.key()?,
+                }
+            }
+            MSG_MEDIA => Self::Media {
+                action: MediaAction::from_code(r.u8()?).ok_or(Nack::Malformed)?,
+            },
+            MSG_STYLUS => {
+                let x = r.u16()?;
+                let y = r.u16()?;
+                let pressure = r.u16()?;
+                let tilt_x = r.u8()? as i8;
+                let tilt_y = r.u8()? as i8;
+                let flags = r.u8()?;
+                Self::Stylus(StylusSample {
+                    x,
+                    y,
+                    pressure: f32::from(pressure) / f32::from(u16::MAX),
+                    tilt_x: f32::from(tilt_x),
+                    tilt_y: f32::from(tilt_y),
+                    hover: flags & STYLUS_HOVER != 0,
+                    eraser: flags & STYLUS_ERASER != 0,
+                    barrel: flags & STYLUS_BARREL != 0,
+                })
+            }
+            MSG_TOUCH => Self::Touch {
+                contacts: decode_contacts(&mut r)?,
+            },
+            MSG_TOUCHPAD => Self::Touchpad {
+                contacts: decode_contacts(&mut r)?,
+            },
+            MSG_FLING => Self::Fling {
+                vx: r.i16()?.into(),
+                vy: r.i16()?.into(),
+            },
+            MSG_MOTION => {
+                let t = r.u32()?;
+                let mut milli = || r.i16().map(|v| f64::from(v) / 1000.0);
+                Self::Motion(MotionSample {
+                    t: t.into(),
+                    gx: milli()?,
+                    gy: milli()?,
+                    gz: milli()?,
+                    ax: milli()?,
+                    ay: milli()?,
+                    az: milli()?,
+                })
+            }
+            MSG_ACTION => match r.text()? {
+                "" => return Err(Nack::Malformed),
+                name => Self::Action { name: name.into() },
+            },
+            MSG_CLIPBOARD_SET => Self::ClipboardSet {
+                text: r.text()?.into(),
+            },
+            MSG_OPEN_URL => Self::OpenUrl {
+                url: r.text()?.into(),
+            },
+            MSG_GAMEPAD => Self::Gamepad(GamepadState {
+                buttons: r.u16()?,
+                lx: r.i16()?,
+                ly: r.i16()?,
+                rx: r.i16()?,
+                ry: r.i16()?,
+                lt: r.u8()?,
+                rt: r.u8()?,
+            }),
+            MSG_STICKY_KEYS => Self::StickyKeys {
+                enabled: r.u8()? != 0,
+            },
+            MSG_REGION => {
+                let x = r.i32()?;
+                let y = r.i32()?;
+                Self::Region {
+                    region: Region::new(x, y, r.u16()?.into(), r.u16()?.into()),
+                }
+            }
+            MSG_MAP_TARGET => Self::MapTarget {
+                target: match r.u8()? {
+                    0 => MapTarget::Screen,
+                    1 => MapTarget::Window,
+                    2 => MapTarget::Desktop,
+                    _ => return Err(Nack::Malformed),
+                },
+            },
+            MSG_TRANSFORM => {
+                let rotate = Rotation::from_quarter_turns(r.u8()?).ok_or(Nack::Malformed)?;
+                let flags = r.u8()?;
+                Self::Transform(Transform {
+                    rotate,
+                    invert_x: flags & TRANSFORM_INVERT_X != 0,
+                    invert_y: flags & TRANSFORM_INVERT_Y != 0,
+                })
+            }
+            MSG_CALIBRATE => {
+                let count = r.u8()?;
+                let points = (0..count)
+                    .map(|_| Ok((r.u16()?, r.u16()?)))
+                    .collect::<Result<_, _>>()?;
+                Self::Calibrate { points }
+            }
+            MSG_SETTINGS => {
+                let mut field = || {
+                    r.u16()
+                        .map(|v| (v != SETTING_UNCHANGED).then(|| f64::from(v) / 100.0))
+                };
+                let (sensitivity, acceleration, scroll_speed) = (field()?, field()?, field()?);
+                // Optional trailer: smoothing cutoff and beta. A zero cutoff turns it off.
+                let smoothing = match (r.u16(), r.u16()) {
+                    (Ok(cutoff), Ok(beta)) if cutoff != SETTING_UNCHANGED => Some(OneEuro::new(
+                        f64::from(cutoff) / 100.0,
+                        f64::from(beta) / 10_000.0,
+                    )),
+                    _ => None,
+                };
+                Self::Settings(PointerSettings {
+                    sensitivity,
+                    acceleration,
+                    scroll_speed,
+                    smoothing,
+                })
+            }
+            MSG_DISPLAY => match r.u8()? {
+                DISPLAY_NEXT => Self::NextDisplay,
+                index => Self::Display {
+                    index: index.into(),
+                },
+            },
+            MSG_CURSOR_POS => Self::CursorPos,
+            MSG_CURSOR_STREAM => Self::CursorStream {
+                interval_ms: r.u16()?.into(),
+            },
+            MSG_CONTROL => Self::Control {
+                action: match r.u8()? {
+                    CONTROL_RELEASE => ControlAction::Release,
+                    CONTROL_REQUEST => ControlAction::Request,
+                    _ => return Err(Nack::Malformed),
+                },
+            },
+            MSG_HANDSHAKE | MSG_SEALED => Self::Encrypted,
+            _ => return Err(Nack::UnknownType),
+        };
+        Ok(msg)
+    }
+
+    /// What the message does to the desktop, for the audit log. `Some` means it needs its
+    /// session to be in control.
+    pub fn input_kind(&self) -> Option<InputKind> {
+        match self {
+            Self::Move { .. }
+            | Self::Click { .. }
+            | Self::ButtonDown { .. }
+            | Self::ButtonUp { .. }
+            | Self::Scroll { .. }
+            | Self::MoveRel { .. }
+            | Self::Fling { .. }
+            | Self::Stylus(_)
+            | Self::Touch { .. }
+            | Self::Touchpad { .. }
+            | Self::Motion(_) => Some(InputKind::Pointer),
+            Self::Key { .. }
+            | Self::KeyHold { .. }
+            | Self::KeyRelease { .. }
+            | Self::Text { .. }
+            | Self::Shortcut { .. }
+            | Self::Media { .. } => Some(InputKind::Keyboard),
+            Self::ClipboardSet { .. } => Some(InputKind::Clipboard),
+            Self::OpenUrl { .. } => Some(InputKind::OpenUrl),
+            Self::Action { .. } => Some(InputKind::Action),
+            Self::Gamepad(_) => Some(InputKind::Gamepad),
+            Self::Region { .. }
+            | Self::MapTarget { .. }
+            | Self::Transform(_)
+            | Self::Calibrate { .. }
+            | Self::Settings(_)
+            | Self::Display { .. }
+            | Self::NextDisplay => Some(InputKind::Settings),
+            Self::Hello(_)
+            | Self::Init { .. }
+            | Self::Ping { .. }
+            | Self::StickyKeys { .. }
+            | Self::Displays
+            | Self::CursorPos
+            | Self::CursorStream { .. }
+            | Self::Control { .. }
+            | Self::RtcOffer { .. }
+            | Self::RtcCandidate { .. }
+            | Self::Encrypted => None,
+        }
+    }
+}
+
+/// The fields of a HELLO or TAKEOVER, `pkt` being the whole packet.
+fn decode_hello(takeover: bool, pkt: &[u8]) -> Result<Hello, Nack> {
+    let mut r = Reader(&pkt[1..]);
+    let w = r.u16()?;
+    let h = r.u16()?;
+    let mode = pkt
+        .get(5)
+        .and_then(|&code| AspectMode::from_code(code))
+        .unwrap_or_default();
+    let token = pkt
+        .get(6..22)
+        .and_then(|t| t.try_into().ok())
+        .map(u128::from_be_bytes);
+    // NUL-separated `[device][0][name][0][platform][0][pin][0][pair][0][version]` after
+    // the token. A field that is missing, empty or not UTF-8 is left out.
+    let mut fields = pkt.get(22..).unwrap_or_default().split(|&b| b == 0);
+    let mut text = || {
+        fields
+            .next()
+            .and_then(|f| std::str::from_utf8(f).ok())
+            .filter(|f| !f.is_empty())
+    };
+    let (id, name, platform, pin, pair) = (text(), text(), text(), text(), text());
+    let device = Device::new(id, name, platform)
+        .with_pin(pin)
+        .with_pair(pair);
+    let version = match fields.next() {
+        Some(&[version]) => Some(version),
+        _ => None,
+    };
+    Ok(Hello {
+        takeover,
+        w,
+        h,
+        mode,
+        token,
+        device,
+        version,
+    })
+}
+
+/// The `[count:u8]` + `count` x `[id:u8][x:u16be][y:u16be]` body of touch packets.
+fn decode_contacts(r: &mut Reader) -> Result<Vec<TouchContact>, Nack> {
+    let count = usize::from(r.u8()?);
+    if count > MAX_CONTACTS {
+        return Err(Nack::Malformed);
+    }
+    (0..count)
+        .map(|_| {
+            Ok(TouchContact {
+                id: r.u8()?,
+                x: r.u16()?,
+                y: r.u16()?,
+            })
+        })
+        .collect()
+}
+
+fn button(code: u8) -> Result<MouseButton, Nack> {
+    match code {
+        BUTTON_LEFT => Ok(MouseButton::Left),
+        BUTTON_RIGHT => Ok(MouseButton::Right),
+        BUTTON_MIDDLE => Ok(MouseButton::Middle),
+        _ => Err(Nack::Malformed),
+    }
+}
+
+/// Big-endian fields read off the front of a packet body; running out is `Malformed`.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], Nack> {
+        let (head, rest) = self.0.split_first_chunk::<N>().ok_or(Nack::Malformed)?;
+        self.0 = rest;
+        Ok(*head)
+    }
+
+    fn u8(&mut self) -> Result<u8, Nack> {
+        self.take::<1>().map(|[b]| b)
+    }
+
+    fn u16(&mut self) -> Result<u16, Nack> {
+        self.take().map(u16::from_be_bytes)
+    }
+
+    fn i16(&mut self) -> Result<i16, Nack> {
+        self.take().map(i16::from_be_bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, Nack> {
+        self.take().map(u32::from_be_bytes)
+    }
+
+    fn i32(&mut self) -> Result<i32, Nack> {
+        self.take().map(i32::from_be_bytes)
+    }
+
+    fn u64(&mut self) -> Result<u64, Nack> {
+        self.take().map(u64::from_be_bytes)
+    }
+
+    /// The rest of the packet as UTF-8 text.
+    fn text(&mut self) -> Result<&'a str, Nack> {
+        let text = std::str::from_utf8(self.0).map_err(|_| Nack::Malformed)?;
+        self.0 = &[];
+        Ok(text)
+    }
+
+    /// The rest of the packet as a key name.
+    fn key(&mut self) -> Result<KeyCode, Nack> {
+        KeyCode::parse(self.text()?).ok_or(Nack::Malformed)
+    }
+}
+
+/// A packet for a UDP client.
+pub enum ServerMessage<'a> {
+    /// Desktop size, the mapping mode applied and the protocol version agreed on.
+    Accept {
+        w: u16,
+        h: u16,
+        mode: AspectMode,
+        version: u8,
+    },
+    Reject,
+    /// No room for another client.
+    Busy,
+    /// The timestamp from a PING, echoed.
+    Pong {
+        t: u64,
+    },
+    Feedback(Feedback),
+    /// Whether calibration points were applied.
+    Calibration(bool),
+    /// The display now in use.
+    DisplayInfo(&'a Display),
+    Displays {
+        displays: &'a [Display],
+        current: usize,
+    },
+    /// The display in use changed size.
+    Screen {
+        w: u16,
+        h: u16,
+    },
+    /// `None` when the platform can't read the cursor back.
+    Cursor(Option<CursorPos>),
+    ControlState {
+        in_control: bool,
+    },
+    TakeoverRequest,
+    TakenOver,
+    Session {
+        token: u128,
+    },
+    Timeout,
+    Kicked,
+    Idle,
+    UnsupportedVersion,
+    /// A packet of type `ty` was not acted on.
+    Nack {
+        reason: Nack,
+        ty: u8,
+    },
+    /// How the connection has been doing over the last second.
+    Quality(Report),
+    /// Most moves a second the client should send; `None` lifts the limit.
+    RateHint(Option<u32>),
+    /// The session has ended; nothing more will come for it.
+    Goodbye(Goodbye),
+    /// Asks a quiet client to show it's still there.
+    Keepalive,
+}
+
+impl ServerMessage<'_> {
+    pub fn encode(&self) -> Vec<u8> {
+        match *self {
+            Self::Accept {
+                w,
+                h,
+                mode,
+                version,
+            } => {
+                let mut pkt = vec![MSG_ACCEPT];
+                pkt.extend(w.to_be_bytes());
+                pkt.extend(h.to_be_bytes());
+                pkt.extend([mode.code(), version]);
+                pkt
+            }
+            Self::Reject => vec![MSG_REJECT],
+            Self::Busy => vec![MSG_BUSY],
+            Self::Pong { t } => [MSG_PONG].into_iter().chain(t.to_be_bytes()).collect(),
+            Self::Feedback(feedback) => {
+                let event = match feedback {
+                    Feedback::DragStart => FEEDBACK_DRAG_START,
+                    Feedback::DragEnd => FEEDBACK_DRAG_END,
+                };
+                vec![MSG_FEEDBACK, event]
+            }
+            Self::Calibration(ok) => vec![MSG_CALIBRATION, u8::from(ok)],
+            Self::DisplayInfo(display) => {
+                let index = u8::try_from(display.index).unwrap_or(u8::MAX);
+                [MSG_DISPLAY_INFO, index]
+                    .into_iter()
+                    .chain(display_geometry(display))
+                    .collect()
+            }
+            Self::Displays { displays, current } => {
+                // Indices above 254 can't be addressed by MSG_DISPLAY, so they aren't listed.
+                let listed = &displays[..displays.len().min(usize::from(DISPLAY_NEXT))];
+                let mut pkt = vec![MSG_DISPLAYS, listed.len() as u8, current as u8];
+                for display in listed {
+                    pkt.extend(display_geometry(display));
+                    pkt.push(u8::from(display.primary));
+                }
+                pkt
+            }
+            Self::Screen { w, h } => [MSG_SCREEN]
+                .into_iter()
+                .chain(w.to_be_bytes())
+                .chain(h.to_be_bytes())
+                .collect(),
+            Self::Cursor(None) => vec![MSG_CURSOR],
+            Self::Cursor(Some(pos)) => {
+                let display = pos
+                    .display
+                    .and_then(|i| u8::try_from(i).ok())
+                    .unwrap_or(u8::MAX);
+                [MSG_CURSOR]
+                    .into_iter()
+                    .chain(pos.x.to_be_bytes())
+                    .chain(pos.y.to_be_bytes())
+                    .chain([display])
+                    .collect()
+            }
+            Self::ControlState { in_control } => vec![MSG_CONTROL_STATE, u8::from(in_control)],
+            Self::TakeoverRequest => vec![MSG_TAKEOVER_REQUEST],
+            Self::TakenOver => vec![MSG_TAKEN_OVER],
+            Self::Session { token } => [MSG_SESSION]
+                .into_iter()
+                .chain(token.to_be_bytes())
+                .collect(),
+            Self::Timeout => vec![MSG_TIMEOUT],
+            Self::Kicked => vec![MSG_KICKED],
+            Self::Idle => vec![MSG_IDLE],
+            Self::UnsupportedVersion => vec![MSG_UNSUPPORTED_VERSION, MIN_VERSION, VERSION],
+            Self::Nack { reason, ty } => vec![MSG_NACK, reason.code(), ty],
+            Self::Quality(report) => {
+                let known =
+                    |v: Option<u64>| v.map_or(u16::MAX, |v| v.min(u64::from(u16::MAX - 1)) as u16);
+                let loss = report.loss.map(|loss| (loss * 1000.0).round() as u64);
+                [MSG_QUALITY]
+                    .into_iter()
+                    .chain(known(report.rtt_ms).to_be_bytes())
+                    .chain(known(report.jitter_ms).to_be_bytes())
+                    .chain(known(loss).to_be_bytes())
+                    .chain((report.dropped.min(u64::from(u32::MAX)) as u32).to_be_bytes())
+                    .collect()
+            }
+            Self::RateHint(max_hz) => {
+                let max_hz = max_hz.map_or(0, |hz| hz.min(u32::from(u16::MAX)) as u16);
+                [MSG_RATE_HINT]
+                    .into_iter()
+                    .chain(max_hz.to_be_bytes())
+                    .collect()
+            }
+            Self::Goodbye(reason) => vec![MSG_GOODBYE, reason.code()],
+            Self::Keepalive => vec![MSG_KEEPALIVE],
+        }
+    }
+}
+
+/// Geometry of one display, as sent in DISPLAY_INFO and DISPLAYS.
+fn display_geometry(display: &Display) -> impl Iterator<Item = u8> {
+    let (w, h) = display.size();
+    display
+        .x
+        .to_be_bytes()
+        .into_iter()
+        .chain(display.y.to_be_bytes())
+        .chain(w.to_be_bytes())
+        .chain(h.to_be_bytes())
+}
+
+/// A WebSocket binary move: `[x:u16be][y:u16be]`, the body of a UDP MOVE.
+pub fn decode_ws_move(bin: &[u8]) -> Result<(u16, u16), Nack> {
+    let mut r = Reader(bin);
+    let moved = (r.u16()?, r.u16()?);
+    if !r.0.is_empty() {
+        return Err(Nack::Malformed);
+    }
+    Ok(moved)
+}
+
+/// First byte of a WebSocket move batch, once `init` agreed to batching:
+/// `[0x02][count:u8]` then count x `[age:u16be ms][x:u16be][y:u16be]`, oldest first.
+/// Never 4 bytes long, so never mistaken for a single move.
+pub const WS_MOVE_BATCH: u8 = 0x02;
+
+/// One point of a WebSocket move batch, taken `age_ms` before the frame was sent.
+#[derive(Debug, Clone, Copy)]
+pub struct MoveSample {
+    pub age_ms: u16,
+    pub x: u16,
+    pub y: u16,
+}
+
+pub fn decode_ws_batch(bin: &[u8]) -> Result<Vec<MoveSample>, Nack> {
+    let mut r = Reader(bin);
+    if r.u8()? != WS_MOVE_BATCH {
+        return Err(Nack::Malformed);
+    }
+    let count = r.u8()?;
+    let samples = (0..count)
+        .map(|_| {
+            Ok(MoveSample {
+                age_ms: r.u16()?,
+                x: r.u16()?,
+                y: r.u16()?,
+            })
+        })
+        .collect::<Result<Vec<_>, Nack>>()?;
+    if samples.is_empty() || !r.0.is_empty() {
+        return Err(Nack::Malformed);
+    }
+    Ok(samples)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(pkt: &[u8]) -> ClientMessage {
+        ClientMessage::decode(pkt).unwrap()
+    }
+
+    fn nack(pkt: &[u8]) -> Nack {
+        ClientMessage::decode(pkt).unwrap_err()
+    }
+
+    fn key(name: &str) -> KeyCode {
+        KeyCode::parse(name).unwrap()
+    }
+
+    fn display(index: usize, x: i32, y: i32, width: u32, height: u32, primary: bool) -> Display {
+        Display {
+            index,
+            name: String::new(),
+            x,
+            y,
+            width,
+            height,
+            primary,
+            scale_factor: 1.0,
+        }
+    }
+
+    #[test]
+    fn empty_and_unknown_packets() {
+        assert_eq!(nack(&[]), Nack::Malformed);
+        assert_eq!(nack(&[MSG_ACCEPT]), Nack::UnknownType);
+        assert_eq!(nack(&[0xFF, 1, 2, 3]), Nack::UnknownType);
+    }
+
+    #[test]
+    fn move_with_optional_fields() {
+        assert!(matches!(
+            decode(&[MSG_MOVE, 0x01, 0x02, 0x03, 0x04]),
+            ClientMessage::Move {
+                x: 0x0102,
+                y: 0x0304,
+                seq: None,
+                t: None
+            }
+        ));
+        assert!(matches!(
+            decode(&[MSG_MOVE, 0, 1, 0, 2, 0, 0, 0, 7, 0, 0, 1, 0]),
+            ClientMessage::Move {
+                x: 1,
+                y: 2,
+                seq: Some(7),
+                t: Some(256)
+            }
+        ));
+        assert_eq!(nack(&[MSG_MOVE, 0, 1, 0]), Nack::Malformed);
+    }
+
+    #[test]
+    fn ping() {
+        let mut pkt = vec![MSG_PING];
+        pkt.extend(42u64.to_be_bytes());
+        assert!(matches!(
+            decode(&pkt),
+            ClientMessage::Ping {
+                t: 42,
+                rtt_ms: None
+            }
+        ));
+        pkt.extend(15u16.to_be_bytes());
+        assert!(matches!(
+            decode(&pkt),
+            ClientMessage::Ping {
+                t: 42,
+                rtt_ms: Some(15)
+            }
+        ));
+        assert_eq!(nack(&pkt[..5]), Nack::Malformed);
+    }
+
+    #[test]
+    fn clicks_and_buttons() {
+        assert!(matches!(
+            decode(&[MSG_CLICK, BUTTON_LEFT]),
+            ClientMessage::Click {
+                button: MouseButton::Left,
+                count: 1,
+                seq: None,
+                t: None
+            }
+        ));
+        assert!(matches!(
+            decode(&[MSG_CLICK, BUTTON_RIGHT, 2, 0, 0, 0, 9, 0, 0, 0, 3]),
+            ClientMessage::Click {
+                button: MouseButton::Right,
+                count: 2,
+                seq: Some(9),
+                t: Some(3)
+            }
+        ));
+        assert_eq!(nack(&[MSG_CLICK]), Nack::Malformed);
+        assert_eq!(nack(&[MSG_CLICK, 3]), Nack::Malformed);
+        assert!(matches!(
+            decode(&[MSG_BUTTON_DOWN, BUTTON_MIDDLE]),
+            ClientMessage::ButtonDown {
+                button: MouseButton::Middle
+            }
+        ));
+        assert!(matches!(
+            decode(&[MSG_BUTTON_UP, BUTTON_LEFT]),
+            ClientMessage::ButtonUp {
+                button: MouseButton::Left
+            }
+        ));
+        assert_eq!(nack(&[MSG_BUTTON_UP, 7]), Nack::Malformed);
+    }
+
+    #[test]
+    fn relative_motion_is_signed() {
+        assert!(matches!(
+            decode(&[MSG_SCROLL, 0xFF, 0x88, 0x00, 0x78]),
+            ClientMessage::Scroll { dx: -120, dy: 120 }
+        ));
+        assert!(matches!(
+            decode(&[MSG_MOVE_REL, 0xFF, 0xFF, 0x00, 0x02]),
+            ClientMessage::MoveRel { dx: -1.0, dy: 2.0 }
+        ));
+        assert!(matches!(
+            decode(&[MSG_FLING, 0x03, 0xE8, 0xFC, 0x18]),
+            ClientMessage::Fling {
+                vx: 1000.0,
+                vy: -1000.0
+            }
+        ));
+        assert_eq!(nack(&[MSG_SCROLL, 0, 1]), Nack::Malformed);
+    }
+
+    #[test]
+    fn key_actions() {
+        let with = |action: u8| [&[MSG_KEY, action][..], b"enter"].concat();
+        let enter = key("enter");
+        assert!(matches!(
+            decode(&with(KEY_UP)),
+            ClientMessage::Key { code, down: Some(false) } if code == enter
+        ));
+        assert!(matches!(
+            decode(&with(KEY_DOWN)),
+            ClientMessage::Key { code, down: Some(true) } if code == enter
+        ));
+        assert!(matches!(
+            decode(&with(KEY_TAP)),
+            ClientMessage::Key { code, down: None } if code == enter
+        ));
+        assert!(matches!(
+            decode(&with(KEY_HOLD)),
+            ClientMessage::KeyHold { code } if code == enter
+        ));
+        assert_eq!(nack(&with(4)), Nack::Malformed);
+        assert_eq!(nack(&[MSG_KEY, KEY_TAP]), Nack::Malformed);
+        assert_eq!(nack(b"\x09\x02no-such-key"), Nack::Malformed);
+        assert_eq!(nack(&[MSG_KEY, KEY_TAP, 0xC3]), Nack::Malformed);
+    }
+
+    #[test]
+    fn shortcut_and_media() {
+        let pkt = [
+            &[MSG_SHORTCUT, Modifier::Ctrl.bit() | Modifier::Shift.bit()][..],
+            b"c",
+        ]
+        .concat();
+        let c = key("c");
+        assert!(matches!(
+            decode(&pkt),
+            ClientMessage::Shortcut { modifiers, key }
+                if modifiers == [Modifier::Ctrl, Modifier::Shift] && key == c
+        ));
+        assert!(matches!(
+            decode(&[MSG_MEDIA, 0]),
+            ClientMessage::Media {
+                action: MediaAction::PlayPause
+            }
+        ));
+        assert_eq!(nack(&[MSG_MEDIA, 99]), Nack::Malformed);
+    }
+
+    #[test]
+    fn stylus() {
+        let pkt = [
+            MSG_STYLUS,
+            0,
+            10,
+            0,
+            20,
+            0xFF,
+            0xFF,
+            (-10i8) as u8,
+            45,
+            STYLUS_HOVER | STYLUS_BARREL,
+        ];
+        let ClientMessage::Stylus(sample) = decode(&pkt) else {
+            panic!("not a stylus sample");
+        };
+        assert_eq!((sample.x, sample.y), (10, 20));
+        assert_eq!(sample.pressure, 1.0);
+        assert_eq!((sample.tilt_x, sample.tilt_y), (-10.0, 45.0));
+        assert!(sample.hover && sample.barrel && !sample.eraser);
+        assert_eq!(nack(&pkt[..9]), Nack::Malformed);
+    }
+
+    #[test]
+    fn touch_contacts() {
+        let pkt = [MSG_TOUCH, 2, 0, 0, 1, 0, 2, 1, 0, 3, 0, 4];
+        let expected = [
+            TouchContact { id: 0, x: 1, y: 2 },
+            TouchContact { id: 1, x: 3, y: 4 },
+        ];
+        assert!(matches!(
+            decode(&pkt),
+            ClientMessage::Touch { contacts } if contacts == expected
+        ));
+        let mut pad = pkt;
+        pad[0] = MSG_TOUCHPAD;
+        assert!(matches!(
+            decode(&pad),
+            ClientMessage::Touchpad { contacts } if contacts == expected
+        ));
+        assert!(matches!(
+            decode(&[MSG_TOUCH, 0]),
+            ClientMessage::Touch { contacts } if contacts.is_empty()
+        ));
+        assert_eq!(nack(&pkt[..pkt.len() - 1]), Nack::Malformed);
+
+        let mut oversized = vec![MSG_TOUCH, MAX_CONTACTS as u8 + 1];
+        for id in 0..=MAX_CONTACTS as u8 {
+            oversized.extend([id, 0, 0, 0, 0]);
+        }
+        assert_eq!(nack(&oversized), Nack::Malformed);
+    }
+
+    #[test]
+    fn motion_is_in_thousandths() {
+        let pkt = [
+            MSG_MOTION, 0, 0, 0, 100, 0x05, 0xDC, 0xFA, 0x24, 0, 0, 0, 0, 0, 0, 0x26, 0x52,
+        ];
+        let ClientMessage::Motion(sample) = decode(&pkt) else {
+            panic!("not a motion sample");
+        };
+        assert_eq!(sample.t, 100);
+        assert_eq!((sample.gx, sample.gy, sample.gz), (1.5, -1.5, 0.0));
+        assert_eq!((sample.ax, sample.ay, sample.az), (0.0, 0.0, 9.81));
+        assert_eq!(nack(&pkt[..pkt.len() - 1]), Nack::Malformed);
+    }
+
+    #[test]
+    fn text_packets() {
+        assert!(matches!(
+            decode(b"\x21lock"),
+            ClientMessage::Action { name } if name == "lock"
+        ));
+        assert_eq!(nack(&[MSG_ACTION]), Nack::Malformed);
+        assert!(matches!(
+            decode("\x22héllo".as_bytes()),
+            ClientMessage::ClipboardSet { text } if text == "héllo"
+        ));
+        assert!(matches!(
+            decode(&[MSG_CLIPBOARD_SET]),
+            ClientMessage::ClipboardSet { text } if text.is_empty()
+        ));
+        assert!(matches!(
+            decode(b"\x23https://example.com"),
+            ClientMessage::OpenUrl { url } if url == "https://example.com"
+        ));
+        assert_eq!(nack(&[MSG_OPEN_URL, 0xFF]), Nack::Malformed);
+    }
+
+    #[test]
+    fn gamepad_and_sticky_keys() {
+        let pkt = [
+            MSG_GAMEPAD,
+            0x10,
+            0x01,
+            0x80,
+            0x00,
+            0x7F,
+            0xFF,
+            0,
+            0,
+            0,
+            1,
+            255,
+            0,
+        ];
+        let expected = GamepadState {
+            buttons: 0x1001,
+            lx: i16::MIN,
+            ly: i16::MAX,
+            rx: 0,
+            ry: 1,
+            lt: 255,
+            rt: 0,
+        };
+        assert!(matches!(decode(&pkt), ClientMessage::Gamepad(state) if state == expected));
+        assert_eq!(nack(&pkt[..pkt.len() - 1]), Nack::Malformed);
+        assert!(matches!(
+            decode(&[MSG_STICKY_KEYS, 1]),
+            ClientMessage::StickyKeys { enabled: true }
+        ));
+        assert!(matches!(
+            decode(&[MSG_STICKY_KEYS, 0]),
+            ClientMessage::StickyKeys { enabled: false }
+        ));
+    }
+
+    #[test]
+    fn region_and_map_target() {
+        let mut pkt = vec![MSG_REGION];
+        pkt.extend((-1920i32).to_be_bytes());
+        pkt.extend(0i32.to_be_bytes());
+        pkt.extend([0x07, 0x80, 0x04, 0x38]);
+        assert!(matches!(
+            decode(&pkt),
+            ClientMessage::Region { region } if region == Region::new(-1920, 0, 1920, 1080)
+        ));
+        // A zero width clears the region.
+        pkt[9] = 0;
+        pkt[10] = 0;
+        assert!(matches!(
+            decode(&pkt),
+            ClientMessage::Region { region: None }
+        ));
+        assert_eq!(nack(&pkt[..pkt.len() - 1]), Nack::Malformed);
+
+        for (code, expected) in [
+            (0, MapTarget::Screen),
+            (1, MapTarget::Window),
+            (2, MapTarget::Desktop),
+        ] {
+            assert!(matches!(
+                decode(&[MSG_MAP_TARGET, code]),
+                ClientMessage::MapTarget { target } if target == expected
+            ));
+        }
+        assert_eq!(nack(&[MSG_MAP_TARGET, 3]), Nack::Malformed);
+    }
+
+    #[test]
+    fn transform() {
+        let expected = Transform {
+            rotate: Rotation::Cw90,
+            invert_x: false,
+            invert_y: true,
+        };
+        assert!(matches!(
+            decode(&[MSG_TRANSFORM, 1, TRANSFORM_INVERT_Y]),
+            ClientMessage::Transform(transform) if transform == expected
+        ));
+        assert_eq!(nack(&[MSG_TRANSFORM, 4, 0]), Nack::Malformed);
+        assert_eq!(nack(&[MSG_TRANSFORM, 1]), Nack::Malformed);
+    }
+
+    #[test]
+    fn calibrate() {
+        let pkt = [
+            MSG_CALIBRATE,
+            4,
+            0,
+            1,
+            0,
+            2,
+            0,
+            3,
+            0,
+            4,
+            0,
+            5,
+            0,
+            6,
+            0,
+            7,
+            0,
+            8,
+        ];
+        assert!(matches!(
+            decode(&pkt),
+            ClientMessage::Calibrate { points } if points == [(1, 2), (3, 4), (5, 6), (7, 8)]
+        ));
+        assert!(matches!(
+            decode(&[MSG_CALIBRATE, 0]),
+            ClientMessage::Calibrate { points } if points.is_empty()
+        ));
+        assert_eq!(nack(&pkt[..pkt.len() - 2]), Nack::Malformed);
+    }
+
+    #[test]
+    fn settings() {
+        let base = [MSG_SETTINGS, 0, 150, 0xFF, 0xFF, 0, 200];
+        let ClientMessage::Settings(settings) = decode(&base) else {
+            panic!("not settings");
+        };
+        assert_eq!(settings.sensitivity, Some(1.5));
+        assert_eq!(settings.acceleration, None);
+        assert_eq!(settings.scroll_speed, Some(2.0));
+        assert_eq!(settings.smoothing, None);
+
+        let smoothed = [&base[..], &[0, 100, 0, 70]].concat();
+        let ClientMessage::Settings(settings) = decode(&smoothed) else {
+            panic!("not settings");
+        };
+        assert_eq!(settings.smoothing, Some(OneEuro::new(1.0, 0.007)));
+
+        let off = [&base[..], &[0, 0, 0, 0]].concat();
+        let ClientMessage::Settings(settings) = decode(&off) else {
+            panic!("not settings");
+        };
+        assert_eq!(settings.smoothing, Some(None));
+
+        let unchanged = [&base[..], &[0xFF, 0xFF, 0, 0]].concat();
+        let ClientMessage::Settings(settings) = decode(&unchanged) else {
+            panic!("not settings");
+        };
+        assert_eq!(settings.smoothing, None);
+
+        assert_eq!(nack(&base[..5]), Nack::Malformed);
+    }
+
+    #[test]
+    fn display_cursor_and_control() {
+        assert!(matches!(
+            decode(&[MSG_DISPLAY, 1]),
+            ClientMessage::Display { index: 1 }
+        ));
+        assert!(matches!(
+            decode(&[MSG_DISPLAY, DISPLAY_NEXT]),
+            ClientMessage::NextDisplay
+        ));
+        assert_eq!(nack(&[MSG_DISPLAY]), Nack::Malformed);
+        assert!(matches!(
+            decode(&[MSG_CURSOR_POS]),
+            ClientMessage::CursorPos
+        ));
+        assert!(matches!(
+            decode(&[MSG_CURSOR_STREAM, 0x01, 0xF4]),
+            ClientMessage::CursorStream { interval_ms: 500 }
+        ));
+        assert!(matches!(
+            decode(&[MSG_CONTROL, CONTROL_RELEASE]),
+            ClientMessage::Control {
+                action: ControlAction::Release
+            }
+        ));
+        assert!(matches!(
+            decode(&[MSG_CONTROL, CONTROL_REQUEST]),
+            ClientMessage::Control {
+                action: ControlAction::Request
+            }
+        ));
+        assert_eq!(nack(&[MSG_CONTROL, 2]), Nack::Malformed);
+    }
+
+    #[test]
+    fn encrypted_packets() {
+        assert!(matches!(
+            decode(&[MSG_HANDSHAKE, 1, 2, 3]),
+            ClientMessage::Encrypted
+        ));
+        assert!(matches!(decode(&[MSG_SEALED]), ClientMessage::Encrypted));
+    }
+
+    #[test]
+    fn input_kinds() {
+        assert_eq!(
+            decode(&[MSG_MOVE, 0, 0, 0, 0]).input_kind(),
+            Some(InputKind::Pointer)
+        );
+        assert_eq!(decode(b"\x09\x02a").input_kind(), Some(InputKind::Keyboard));
+        assert_eq!(
+            decode(&[MSG_DISPLAY, 0]).input_kind(),
+            Some(InputKind::Settings)
+        );
+        assert_eq!(decode(&[MSG_CONTROL, 1]).input_kind(), None);
+    }
+
+    #[test]
+    fn hello_minimal() {
+        let ClientMessage::Hello(hello) = decode(&[MSG_HELLO, 0x04, 0x38, 0x09, 0x24]) else {
+            panic!("not a hello");
+        };
+        assert!(!hello.takeover);
+        assert_eq!((hello.w, hello.h), (1080, 2340));
+        assert_eq!(hello.mode, AspectMode::Fill);
+        assert_eq!(hello.token, None);
+        assert_eq!(hello.device, Device::default());
+        assert_eq!(hello.version, None);
+        assert_eq!(nack(&[MSG_HELLO, 0x04, 0x38, 0x09]), Nack::Malformed);
+    }
+
+    #[test]
+    fn hello_all_fields() {
+        let mut pkt = vec![MSG_TAKEOVER, 0x04, 0x38, 0x09, 0x24, 1];
+        pkt.extend(0x0123_4567_89AB_CDEF_u128.to_be_bytes());
+        pkt.extend(b"device-0123456789\0Dana's\x07 phone\0iPhone\x00123456\0abcd\0\x01");
+        let ClientMessage::Hello(hello) = decode(&pkt) else {
+            panic!("not a hello");
+        };
+        assert!(hello.takeover);
+        assert_eq!(hello.mode, AspectMode::Fit);
+        assert_eq!(hello.token, Some(0x0123_4567_89AB_CDEF));
+        assert_eq!(hello.device.id.as_deref(), Some("device-0123456789"));
+        assert_eq!(hello.device.name.as_deref(), Some("Dana's phone"));
+        assert_eq!(hello.device.platform.as_deref(), Some("iPhone"));
+        assert_eq!(hello.device.pin.as_deref(), Some("123456"));
+        assert_eq!(hello.device.pair.as_deref(), Some("abcd"));
+        assert_eq!(hello.version, Some(1));
+    }
+
+    #[test]
+    fn hello_tolerates_odd_fields() {
+        // Unknown mode, a token cut short, then nothing else.
+        let ClientMessage::Hello(hello) = decode(&[MSG_HELLO, 0, 1, 0, 1, 9, 0xAB, 0xCD]) else {
+            panic!("not a hello");
+        };
+        assert_eq!(hello.mode, AspectMode::Fill);
+        assert_eq!(hello.token, None);
+
+        // An ID too short to name a device, a name that isn't UTF-8, an empty platform and
+        // a version field longer than a byte.
+        let mut pkt = vec![MSG_HELLO, 0, 1, 0, 1, 2];
+        pkt.extend(0u128.to_be_bytes());
+        pkt.extend(b"short\0\xFF\xFE\0\0\0\0\x01\x02");
+        let ClientMessage::Hello(hello) = decode(&pkt) else {
+            panic!("not a hello");
+        };
+        assert_eq!(hello.mode, AspectMode::Crop);
+        assert_eq!(hello.token, Some(0));
+        assert_eq!(hello.device.id, None);
+        assert_eq!(hello.device.name, None);
+        assert_eq!(hello.device.platform, None);
+        assert_eq!(hello.version, None);
+    }
+
+    #[test]
+    fn websocket_messages_deserialize() {
+        let json = |text: &str| serde_json::from_str::<ClientMessage>(text);
+        assert!(matches!(
+            json(r#"{"type":"click","button":"left"}"#).unwrap(),
+            ClientMessage::Click {
+                button: MouseButton::Left,
+                count: 1,
+                seq: None,
+                t: None
+            }
+        ));
+        assert!(matches!(
+            json(r#"{"type":"init","width":390,"height":844}"#).unwrap(),
+            ClientMessage::Init { .. }
+        ));
+        assert!(matches!(
+            json(r#"{"type":"control","action":"release"}"#).unwrap(),
+            ClientMessage::Control {
+                action: ControlAction::Release
+            }
+        ));
+        assert!(matches!(
+            json(r#"{"type":"next_display"}"#).unwrap(),
+            ClientMessage::NextDisplay
+        ));
+        assert!(matches!(
+            json(r#"{"type":"rtc_candidate","candidate":"candidate:1 1 udp 1 10.0.0.2 5000 typ host"}"#)
+                .unwrap(),
+            ClientMessage::RtcCandidate { candidate } if candidate.ends_with("typ host")
+        ));
+        // UDP-only variants can't be sent as JSON.
+        assert!(json(r#"{"type":"hello","w":1,"h":1}"#).is_err());
+        assert!(json(r#"{"type":"move","x":1,"y":1}"#).is_err());
+        assert!(json(r#"{"type":"encrypted"}"#).is_err());
+
+        let packed = rmp_serde::to_vec_named(&serde_json::json!({
+            "type": "scroll",
+            "dx": -120,
+            "dy": 0,
+        }))
+        .unwrap();
+        assert!(matches!(
+            rmp_serde::from_slice::<ClientMessage>(&packed).unwrap(),
+            ClientMessage::Scroll { dx: -120, dy: 0 }
+        ));
+    }
+
+    #[test]
+    fn encode_fixed_packets() {
+        assert_eq!(
+            ServerMessage::Accept {
+                w: 1920,
+                h: 1080,
+                mode: AspectMode::Crop,
+                version: VERSION,
+            }
+            .encode(),
+            [MSG_ACCEPT, 0x07, 0x80, 0x04, 0x38, 2, VERSION]
+        );
+        assert_eq!(ServerMessage::Reject.encode(), [0x11]);
+        assert_eq!(ServerMessage::Busy.encode(), [0x12]);
+        assert_eq!(ServerMessage::TakeoverRequest.encode(), [0x1B]);
+        assert_eq!(ServerMessage::TakenOver.encode(), [0x1C]);
+        assert_eq!(ServerMessage::Timeout.encode(), [0x1E]);
+        assert_eq!(ServerMessage::Kicked.encode(), [0x1F]);
+        assert_eq!(ServerMessage::Idle.encode(), [0x32]);
+        assert_eq!(ServerMessage::Keepalive.encode(), [0x38]);
+        assert_eq!(
+            ServerMessage::UnsupportedVersion.encode(),
+            [0x33, MIN_VERSION, VERSION]
+        );
+    }
+
+    #[test]
+    fn encode_small_fields() {
+        assert_eq!(
+            ServerMessage::Pong { t: 0x0102 }.encode(),
+            [MSG_PONG, 0, 0, 0, 0, 0, 0, 1, 2]
+        );
+        assert_eq!(
+            ServerMessage::Feedback(Feedback::DragStart).encode(),
+            [MSG_FEEDBACK, 0]
+        );
+        assert_eq!(
+            ServerMessage::Feedback(Feedback::DragEnd).encode(),
+            [MSG_FEEDBACK, 1]
+        );
+        assert_eq!(
+            ServerMessage::Calibration(true).encode(),
+            [MSG_CALIBRATION, 1]
+        );
+        assert_eq!(
+            ServerMessage::ControlState { in_control: false }.encode(),
+            [MSG_CONTROL_STATE, 0]
+        );
+        assert_eq!(
+            ServerMessage::Screen { w: 2560, h: 1440 }.encode(),
+            [MSG_SCREEN, 0x0A, 0x00, 0x05, 0xA0]
+        );
+        let session = ServerMessage::Session { token: 1 }.encode();
+        assert_eq!(session.len(), 17);
+        assert_eq!(session[0], MSG_SESSION);
+        assert_eq!(session[16], 1);
+        assert_eq!(
+            ServerMessage::Nack {
+                reason: Nack::NotInControl,
+                ty: MSG_MOVE,
+            }
+            .encode(),
+            [MSG_NACK, 3, MSG_MOVE]
+        );
+        for (reason, code) in [
+            (Goodbye::Shutdown, 0),
+            (Goodbye::Kicked, 1),
+            (Goodbye::TakenOver, 2),
+            (Goodbye::TimedOut, 3),
+            (Goodbye::Idle, 4),
+            (Goodbye::Halted, 5),
+        ] {
+            assert_eq!(ServerMessage::Goodbye(reason).encode(), [MSG_GOODBYE, code]);
+        }
+    }
+
+    #[test]
+    fn encode_displays() {
+        let left = display(1, -1920, 0, 1920, 1080, false);
+        assert_eq!(
+            ServerMessage::DisplayInfo(&left).encode(),
+            [
+                MSG_DISPLAY_INFO,
+                1,
+                0xFF,
+                0xFF,
+                0xF8,
+                0x80,
+                0,
+                0,
+                0,
+                0,
+                0x07,
+                0x80,
+                0x04,
+                0x38
+            ]
+        );
+
+        let displays = [display(0, 0, 0, 100_000, 0, true), left];
+        assert_eq!(
+            ServerMessage::Displays {
+                displays: &displays,
+                current: 1,
+            }
+            .encode(),
+            [
+                MSG_DISPLAYS,
+                2,
+                1,
+                // Sizes clamped to 1..=u16::MAX.
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0xFF,
+                0xFF,
+                0,
+                1,
+                1,
+                0xFF,
+                0xFF,
+                0xF8,
+                0x80,
+                0,
+                0,
+                0,
+                0,
+                0x07,
+                0x80,
+                0x04,
+                0x38,
+                0,
+            ]
+        );
+
+        let many: Vec<_> = (0..300).map(|i| display(i, 0, 0, 1, 1, false)).collect();
+        let pkt = ServerMessage::Displays {
+            displays: &many,
+            current: 0,
+        }
+        .encode();
+        assert_eq!(pkt[1], 255);
+        assert_eq!(pkt.len(), 3 + 255 * 13);
+    }
+
+    #[test]
+    fn encode_cursor() {
+        assert_eq!(ServerMessage::Cursor(None).encode(), [MSG_CURSOR]);
+        let pos = CursorPos {
+            x: -1,
+            y: 2,
+            display: Some(1),
+        };
+        assert_eq!(
+            ServerMessage::Cursor(Some(pos)).encode(),
+            [MSG_CURSOR, 0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, 2, 1]
+        );
+        let off = CursorPos {
+            display: None,
+            ..pos
+        };
+        assert_eq!(ServerMessage::Cursor(Some(off)).encode()[9], 0xFF);
+    }
+
+    #[test]
+    fn encode_quality_and_rate_hint() {
+        let report = Report {
+            rtt_ms: Some(30),
+            jitter_ms: Some(100_000),
+            loss: Some(0.0125),
+            dropped: u64::MAX,
+        };
+        assert_eq!(
+            ServerMessage::Quality(report).encode(),
+            [
+                MSG_QUALITY,
+                0,
+                30,
+                0xFF,
+                0xFE,
+                0,
+                13,
+                0xFF,
+                0xFF,
+                0xFF,
+                0xFF
+            ]
+        );
+        let unknown = Report {
+            rtt_ms: None,
+            jitter_ms: None,
+            loss: None,
+            dropped: 0,
+        };
+        assert_eq!(
+            ServerMessage::Quality(unknown).encode(),
+            [MSG_QUALITY, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, 0]
+        );
+        assert_eq!(
+            ServerMessage::RateHint(Some(60)).encode(),
+            [MSG_RATE_HINT, 0, 60]
+        );
+        assert_eq!(
+            ServerMessage::RateHint(None).encode(),
+            [MSG_RATE_HINT, 0, 0]
+        );
+        assert_eq!(
+            ServerMessage::RateHint(Some(1_000_000)).encode(),
+            [MSG_RATE_HINT, 0xFF, 0xFF]
+        );
+    }
+
+    #[test]
+    fn ws_move() {
+        assert_eq!(decode_ws_move(&[0x01, 0x00, 0x00, 0x20]), Ok((256, 32)));
+        assert_eq!(decode_ws_move(&[]), Err(Nack::Malformed));
+        assert_eq!(decode_ws_move(&[0, 1, 0]), Err(Nack::Malformed));
+        assert_eq!(decode_ws_move(&[0, 1, 0, 2, 0]), Err(Nack::Malformed));
+    }
+
+    #[test]
+    fn ws_batch() {
+        let batch = [WS_MOVE_BATCH, 2, 0, 16, 0, 1, 0, 2, 0, 0, 0, 3, 0, 4];
+        let samples = decode_ws_batch(&batch).unwrap();
+        let fields: Vec<_> = samples.iter().map(|s| (s.age_ms, s.x, s.y)).collect();
+        assert_eq!(fields, [(16, 1, 2), (0, 3, 4)]);
+
+        assert_eq!(decode_ws_batch(&[]).unwrap_err(), Nack::Malformed);
+        assert_eq!(
+            decode_ws_batch(&[0x03, 1, 0, 0, 0, 1, 0, 2]).unwrap_err(),
+            Nack::Malformed
+        );
+        assert_eq!(
+            decode_ws_batch(&[WS_MOVE_BATCH]).unwrap_err(),
+            Nack::Malformed
+        );
+        assert_eq!(
+            decode_ws_batch(&[WS_MOVE_BATCH, 0]).unwrap_err(),
+            Nack::Malformed
+        );
+        assert_eq!(
+            decode_ws_batch(&batch[..batch.len() - 1]).unwrap_err(),
+            Nack::Malformed
+        );
+        let trailing = [&batch[..], &[0]].concat();
+        assert_eq!(decode_ws_batch(&trailing).unwrap_err(), Nack::Malformed);
+    }
+}
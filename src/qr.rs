@@ -0,0 +1,64 @@
+use qrcode::QrCode;
+use qrcode::render::unicode::Dense1x2;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// How long a pairing link works if nobody scans it.
+const PAIRING_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// One-time links to the web client, shown as QR codes in the terminal. Scanning one
+/// connects without an approval prompt: whoever can see the terminal may pair.
+#[derive(Debug)]
+pub struct PairingLinks {
+    /// The web client's address with the ports and access token, e.g.
+    /// `http://192.168.1.5:8080/?ws=8080&udp=9002&access_token=<32 hex digits>`.
+    base_url: String,
+    /// The token in the link on screen, until it is used or expires.
+    current: Mutex<Option<(u128, Instant)>>,
+}
+
+impl PairingLinks {
+    pub fn new(base_url: String) -> Self {
+        Self {
+            base_url,
+            current: Mutex::new(None),
+        }
+    }
+
+    /// Print a QR code for a fresh link. The previous link stops working.
+    pub fn show(&self) {
+        let token: u128 = rand::random();
+        *self.current.lock().unwrap() = Some((token, Instant::now() + PAIRING_TTL));
+        let url = format!("{}&pair={token:032x}", self.base_url);
+        match QrCode::new(url.as_bytes()) {
+            Ok(code) => {
+                // Inverted, since most terminals are dark and scanners expect dark on light.
+                let image = code
+                    .render::<Dense1x2>()
+                    .dark_color(Dense1x2::Light)
+                    .light_color(Dense1x2::Dark)
+                    .build();
+                println!("{image}");
+                println!("📷 Scan to connect without approval (works once, for 10 minutes):");
+                println!("   {url}");
+            }
+            Err(err) => warn!("Failed to make a pairing QR code: {err}"),
+        }
+    }
+
+    /// Whether `token` is the link on screen. It is used up if so.
+    pub fn redeem(&self, token: &str) -> bool {
+        let Ok(token) = u128::from_str_radix(token, 16) else {
+            return false;
+        };
+        let mut current = self.current.lock().unwrap();
+        match *current {
+            Some((valid, expires)) if valid == token && expires > Instant::now() => {
+                *current = None;
+                true
+            }
+            _ => false,
+        }
+    }
+}
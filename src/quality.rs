@@ -0,0 +1,169 @@
+use crate::mouse::MoveStats;
+use std::time::Duration;
+
+/// Weight of a new round-trip difference in the jitter estimate, as in RTP (RFC 3550).
+const JITTER_WEIGHT: f64 = 1.0 / 16.0;
+
+/// Share of a second's moves the pointer worker may overwrite before the client is asked
+/// to send fewer.
+const MAX_COALESCED: f64 = 0.3;
+
+/// Below this many moves a second, coalescing says too little to act on.
+const MIN_MOVES: u64 = 30;
+
+/// A round trip this many times the best one seen, and at least [`SPIKE_MARGIN`] more,
+/// counts as a spike.
+const SPIKE_FACTOR: u32 = 2;
+const SPIKE_MARGIN: Duration = Duration::from_millis(30);
+
+/// Lowest rate a client is asked to come down to, in Hz.
+const MIN_HINT: u32 = 30;
+
+/// From this rate on, a lifted hint is dropped altogether.
+const MAX_HINT: u32 = 240;
+
+/// Seconds without trouble before a hint is relaxed, by doubling it.
+const CALM_SECONDS: u32 = 5;
+
+/// How a session's connection has been doing, told to the client every second so it can
+/// show signal strength and slow down when packets get lost.
+#[derive(Debug, Default)]
+pub struct Quality {
+    rtt: Option<Duration>,
+    /// Mean difference between consecutive round trips, in ms.
+    jitter: Option<f64>,
+    /// Newest sequence number seen, for counting the gaps.
+    newest_seq: Option<u32>,
+    /// Numbered packets that arrived, and that were sent as far as the numbers tell, since
+    /// the last report.
+    received: u64,
+    expected: u64,
+    /// Packets not acted on since the last report.
+    dropped: u64,
+    /// The rate limiter's count for this client at the last report.
+    limited: u64,
+    best_rtt: Option<Duration>,
+    /// Moves from this client since the last report.
+    moves: u64,
+    /// The pointer worker's counts at the last report.
+    worker: MoveStats,
+    /// Most moves a second the client was last asked to send.
+    hint: Option<u32>,
+    calm: u32,
+}
+
+/// One `quality` report. `loss` is only known from numbered UDP packets.
+#[derive(Debug, Clone, Copy)]
+pub struct Report {
+    pub rtt_ms: Option<u64>,
+    pub jitter_ms: Option<u64>,
+    pub loss: Option<f64>,
+    pub dropped: u64,
+}
+
+impl Quality {
+    /// Start counting for a client the rate limiter has dropped `limited` packets of.
+    pub fn new(limited: u64) -> Self {
+        Self { limited, ..Self::default() }
+    }
+
+    /// Take a round trip the client measured.
+    pub fn rtt(&mut self, rtt: Duration) {
+        if let Some(last) = self.rtt {
+            let diff = (rtt.as_secs_f64() - last.as_secs_f64()).abs() * 1000.0;
+            let jitter = self.jitter.get_or_insert(diff);
+            *jitter += (diff - *jitter) * JITTER_WEIGHT;
+        }
+        self.rtt = Some(rtt);
+        if self.best_rtt.is_none_or(|best| rtt < best) {
+            self.best_rtt = Some(rtt);
+        }
+    }
+
+    /// Note `n` absolute moves from the client.
+    pub fn moved(&mut self, n: u64) {
+        self.moves += n;
+    }
+
+    /// Note a packet numbered `seq`; numbers skipped on the way count as lost until they
+    /// turn up.
+    pub fn numbered(&mut self, seq: u32) {
+        self.received += 1;
+        match self.newest_seq {
+            Some(newest) if (seq.wrapping_sub(newest) as i32) <= 0 => {}
+            Some(newest) => {
+                self.expected += u64::from(seq.wrapping_sub(newest));
+                self.newest_seq = Some(seq);
+            }
+            None => {
+                self.expected += 1;
+                self.newest_seq = Some(seq);
+            }
+        }
+    }
+
+    /// Note a packet that wasn't acted on.
+    pub fn dropped(&mut self) {
+        self.dropped += 1;
+    }
+
+    /// The report for the second gone by, given how many of the client's packets the rate
+    /// limiter dropped so far; starts the next one.
+    pub fn report(&mut self, limited: u64) -> Report {
+        let loss = (self.expected > 0)
+            .then(|| 1.0 - (self.received as f64 / self.expected as f64).min(1.0));
+        let report = Report {
+            rtt_ms: self.rtt.map(|rtt| rtt.as_millis() as u64),
+            jitter_ms: self.jitter.map(|jitter| jitter.round() as u64),
+            loss,
+            // The limiter's count starts over after a quiet minute.
+            dropped: self.dropped + limited.checked_sub(self.limited).unwrap_or(limited),
+        };
+        self.received = 0;
+        self.expected = 0;
+        self.dropped = 0;
+        self.limited = limited;
+        report
+    }
+
+    /// A new most moves a second to ask the client for, given the pointer worker's counts,
+    /// when it differs from the last one: lower while the worker can't keep up with this
+    /// client's moves or the round trip spikes, and back up once things calm down.
+    /// `Some(None)` lifts the limit. Call once a second.
+    pub fn rate_hint(&mut self, worker: MoveStats) -> Option<Option<u32>> {
+        let moves = std::mem::take(&mut self.moves);
+        let received = worker.received.saturating_sub(self.worker.received);
+        let coalesced = worker.coalesced.saturating_sub(self.worker.coalesced);
+        self.worker = worker;
+
+        // Only a client that is sending moves has a rate to bring down.
+        let sending = moves >= MIN_MOVES;
+        let busy = sending && received > 0 && coalesced as f64 / received as f64 > MAX_COALESCED;
+        let spike = match (self.rtt, self.best_rtt) {
+            (Some(rtt), Some(best)) => {
+                sending && rtt > best * SPIKE_FACTOR && rtt > best + SPIKE_MARGIN
+            }
+            _ => false,
+        };
+        let hint = if busy || spike {
+            self.calm = 0;
+            // What the worker managed, or half of what the client sent into a spike.
+            let target = if busy { received - coalesced } else { moves / 2 };
+            let target = u32::try_from(target).unwrap_or(u32::MAX).max(MIN_HINT);
+            Some(self.hint.map_or(target, |hint| hint.min(target)))
+        } else {
+            self.calm += 1;
+            match self.hint {
+                Some(hint) if self.calm >= CALM_SECONDS => {
+                    self.calm = 0;
+                    Some(hint * 2).filter(|hint| *hint < MAX_HINT)
+                }
+                hint => hint,
+            }
+        };
+        (hint != self.hint).then(|| {
+            self.hint = hint;
+            hint
+        })
+    }
+}
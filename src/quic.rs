@@ -0,0 +1,243 @@
+pub use platform::Listener;
+
+use crate::connection::Transport;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+
+/// Whether this build can serve native QUIC clients (the `quic` feature).
+pub const AVAILABLE: bool = cfg!(feature = "quic");
+
+/// ALPN protocol native clients ask for.
+#[cfg_attr(not(feature = "quic"), allow(dead_code))]
+const ALPN: &[u8] = b"penput";
+
+/// A packet from a QUIC connection, and the address the connection came from.
+type Incoming = (SocketAddr, Vec<u8>);
+
+/// Packets from QUIC connections (native or WebTransport), on their way into the UDP
+/// server: each datagram carries one packet of the UDP protocol, so sessions, approval and
+/// every packet type work the same as over UDP.
+pub struct Datagrams {
+    pub incoming: mpsc::Receiver<Incoming>,
+    pub peers: Peers,
+}
+
+/// The listeners' side of [`Datagrams`].
+#[derive(Clone)]
+#[cfg_attr(not(feature = "quic"), allow(dead_code))]
+pub struct Sessions {
+    incoming: mpsc::Sender<Incoming>,
+    peers: Peers,
+}
+
+#[cfg_attr(not(feature = "quic"), allow(dead_code))]
+impl Sessions {
+    /// Route packets for `addr` to the returned receiver until [`close`](Self::close)d.
+    pub fn open(&self, addr: SocketAddr, transport: Transport) -> mpsc::UnboundedReceiver<Vec<u8>> {
+        let (outgoing, rx) = mpsc::unbounded_channel();
+        self.peers.0.lock().unwrap().insert(
+            addr,
+            Peer {
+                outgoing,
+                transport,
+            },
+        );
+        rx
+    }
+
+    pub fn close(&self, addr: SocketAddr) {
+        self.peers.0.lock().unwrap().remove(&addr);
+    }
+
+    /// Hand `pkt` from `addr` to the UDP server; `false` once it has stopped.
+    pub async fn deliver(&self, addr: SocketAddr, pkt: Vec<u8>) -> bool {
+        self.incoming.send((addr, pkt)).await.is_ok()
+    }
+}
+
+/// Open QUIC connections by the address they came from, and the way back to each.
+#[derive(Clone, Default)]
+pub struct Peers(Arc<Mutex<HashMap<SocketAddr, Peer>>>);
+
+struct Peer {
+    outgoing: mpsc::UnboundedSender<Vec<u8>>,
+    transport: Transport,
+}
+
+impl Peers {
+    /// How `addr` is connected, if it is a QUIC connection rather than a UDP client.
+    pub fn transport(&self, addr: SocketAddr) -> Option<Transport> {
+        self.0.lock().unwrap().get(&addr).map(|peer| peer.transport)
+    }
+
+    /// Send `pkt` to the connection from `addr`; `false` if there is none.
+    pub fn send(&self, addr: SocketAddr, pkt: &[u8]) -> bool {
+        match self.0.lock().unwrap().get(&addr) {
+            Some(peer) => {
+                let _ = peer.outgoing.send(pkt.to_vec());
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Both ends between the QUIC listeners and the UDP server. Without a listener the UDP
+/// server simply never hears from them.
+pub fn channel() -> (Sessions, Datagrams) {
+    let (tx, rx) = mpsc::channel(256);
+    let peers = Peers::default();
+    let sessions = Sessions {
+        incoming: tx,
+        peers: peers.clone(),
+    };
+    (
+        sessions,
+        Datagrams {
+            incoming: rx,
+            peers,
+        },
+    )
+}
+
+#[cfg(feature = "quic")]
+mod platform {
+    use super::{ALPN, Sessions};
+    use crate::connection::Transport;
+    use crate::ipfilter::IpFilter;
+    use crate::{net, tls};
+    use anyhow::{Context, Result};
+    use rustls::ServerConfig;
+    use std::net::{SocketAddr, UdpSocket};
+    use std::sync::Arc;
+    use tracing::info;
+
+    /// The QUIC endpoint for native clients, with the kept certificate.
+    pub struct Listener {
+        endpoint: quinn::Endpoint,
+        fingerprint: String,
+    }
+
+    impl Listener {
+        /// Listen on `socket` with the certificate HTTPS uses, made for `names` if there
+        /// is none yet, so clients pin one fingerprint for both.
+        pub fn bind(socket: UdpSocket, names: Vec<String>) -> Result<Self> {
+            let (cert, key, fingerprint) = tls::kept_certificate(names)?;
+            let mut config = ServerConfig::builder_with_provider(Arc::new(
+                rustls::crypto::ring::default_provider(),
+            ))
+            .with_protocol_versions(&[&rustls::version::TLS13])?
+            .with_no_client_auth()
+            .with_single_cert(vec![cert], key)?;
+            config.alpn_protocols = vec![ALPN.to_vec()];
+            let crypto = quinn::crypto::rustls::QuicServerConfig::try_from(config)?;
+            let endpoint = quinn::Endpoint::new(
+                quinn::EndpointConfig::default(),
+                Some(quinn::ServerConfig::with_crypto(Arc::new(crypto))),
+                socket,
+                Arc::new(quinn::TokioRuntime),
+            )
+            .context("Failed to start the QUIC endpoint")?;
+            Ok(Self {
+                endpoint,
+                fingerprint,
+            })
+        }
+
+        pub fn port(&self) -> Result<u16> {
+            Ok(self.endpoint.local_addr()?.port())
+        }
+
+        /// SHA-256 of the certificate, for clients to pin.
+        pub fn fingerprint(&self) -> &str {
+            &self.fingerprint
+        }
+
+        /// Accept connections and hand their datagrams to the UDP server through `sessions`.
+        pub async fn serve(self, sessions: Sessions, ip_filter: Arc<IpFilter>) -> Result<()> {
+            info!("QUIC server listening on {}", self.endpoint.local_addr()?);
+            while let Some(incoming) = self.endpoint.accept().await {
+                let addr = net::canonical(incoming.remote_address());
+                if !ip_filter.permits(addr.ip()) {
+                    incoming.refuse();
+                    continue;
+                }
+                let sessions = sessions.clone();
+                tokio::spawn(async move {
+                    if let Err(err) = connection(incoming, addr, sessions).await {
+                        info!("✗ QUIC connection from {addr} failed: {err}");
+                    }
+                });
+            }
+            Ok(())
+        }
+    }
+
+    /// Pass datagrams both ways until either side goes away. The connection stays known
+    /// by the address it started from, so a client moving between networks keeps its
+    /// session.
+    async fn connection(
+        incoming: quinn::Incoming,
+        addr: SocketAddr,
+        sessions: Sessions,
+    ) -> Result<()> {
+        let conn = incoming.await?;
+        let mut rx = sessions.open(addr, Transport::Quic);
+        info!("📶 QUIC connection from {addr}");
+        let mut path = conn.remote_address();
+        let err = loop {
+            tokio::select! {
+                res = conn.read_datagram() => {
+                    let pkt = match res {
+                        Ok(pkt) => pkt,
+                        Err(err) => break err,
+                    };
+                    if conn.remote_address() != path {
+                        path = conn.remote_address();
+                        info!("📶 QUIC connection from {addr} moved to {path}");
+                    }
+                    if !sessions.deliver(addr, pkt.to_vec()).await {
+                        break quinn::ConnectionError::LocallyClosed;
+                    }
+                }
+                Some(pkt) = rx.recv() => {
+                    let _ = conn.send_datagram(pkt.into());
+                }
+            }
+        };
+        sessions.close(addr);
+        info!("✗ QUIC connection from {addr} closed: {err}");
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "quic"))]
+mod platform {
+    use super::Sessions;
+    use crate::ipfilter::IpFilter;
+    use anyhow::{Result, bail};
+    use std::net::UdpSocket;
+    use std::sync::Arc;
+
+    pub struct Listener;
+
+    impl Listener {
+        pub fn bind(_socket: UdpSocket, _names: Vec<String>) -> Result<Self> {
+            bail!("built without the quic feature")
+        }
+
+        pub fn port(&self) -> Result<u16> {
+            bail!("built without the quic feature")
+        }
+
+        pub fn fingerprint(&self) -> &str {
+            ""
+        }
+
+        pub async fn serve(self, _sessions: Sessions, _ip_filter: Arc<IpFilter>) -> Result<()> {
+            bail!("built without the quic feature")
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// Packets per second one address may send by default (`--udp-rate-limit`). A touchpad
+/// reporting at 240 Hz with clicks and pings stays well under it.
+pub const DEFAULT_RATE: u32 = 1000;
+
+/// Drops from one address are reported at most this often.
+const WARN_EVERY: Duration = Duration::from_secs(10);
+
+/// An address that sent nothing for this long is forgotten.
+const IDLE: Duration = Duration::from_secs(60);
+
+/// Most addresses tracked at once, so a spoofed flood can't grow the table without bound.
+/// Past it, new addresses share one bucket.
+const MAX_ADDRESSES: usize = 1024;
+
+/// Per-address token buckets for UDP packets: each address may send `rate` packets per
+/// second, in bursts of up to a second's worth. Packets beyond that are dropped unread.
+pub struct RateLimiter {
+    rate: f64,
+    buckets: Mutex<Buckets>,
+}
+
+#[derive(Default)]
+struct Buckets {
+    by_addr: HashMap<IpAddr, Bucket>,
+    /// Shared by new addresses while the table is full.
+    overflow: Option<Bucket>,
+    last_sweep: Option<Instant>,
+}
+
+struct Bucket {
+    tokens: f64,
+    last: Instant,
+    /// Packets dropped since the last warning.
+    dropped: u64,
+    /// Packets dropped in all.
+    total: u64,
+    warned: Option<Instant>,
+}
+
+impl Bucket {
+    fn full(rate: f64, now: Instant) -> Self {
+        Self {
+            tokens: rate,
+            last: now,
+            dropped: 0,
+            total: 0,
+            warned: None,
+        }
+    }
+
+    /// Take a token if there is one, topping the bucket up for the time that passed.
+    fn take(&mut self, rate: f64, now: Instant) -> bool {
+        let passed = now.duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + passed * rate).min(rate);
+        self.last = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl RateLimiter {
+    /// `None` lets everything through.
+    pub fn new(rate: Option<u32>) -> Option<Self> {
+        rate.map(|rate| Self {
+            rate: f64::from(rate),
+            buckets: Mutex::new(Buckets::default()),
+        })
+    }
+
+    /// Whether a packet from `ip` may be looked at.
+    pub fn allow(&self, ip: IpAddr) -> bool {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        let Buckets {
+            by_addr,
+            overflow,
+            last_sweep,
+        } = &mut *buckets;
+
+        if last_sweep.is_none_or(|t| now.duration_since(t) >= IDLE) {
+            by_addr.retain(|_, b| now.duration_since(b.last) < IDLE);
+            *last_sweep = Some(now);
+        }
+
+        let bucket = if by_addr.contains_key(&ip) || by_addr.len() < MAX_ADDRESSES {
+            by_addr
+                .entry(ip)
+                .or_insert_with(|| Bucket::full(self.rate, now))
+        } else {
+            overflow.get_or_insert_with(|| Bucket::full(self.rate, now))
+        };
+
+        if bucket.take(self.rate, now) {
+            return true;
+        }
+        bucket.dropped += 1;
+        bucket.total += 1;
+        if bucket
+            .warned
+            .is_none_or(|t| now.duration_since(t) >= WARN_EVERY)
+        {
+            warn!(
+                "🌊 {ip} sends more than {} UDP packets/s; dropped {} packet(s)",
+                self.rate, bucket.dropped
+            );
+            bucket.dropped = 0;
+            bucket.warned = Some(now);
+        }
+        false
+    }
+
+    /// How many packets from `ip` were dropped so far; counting starts over when it has
+    /// been quiet for a minute.
+    pub fn dropped(&self, ip: IpAddr) -> u64 {
+        let buckets = self.buckets.lock().unwrap();
+        buckets.by_addr.get(&ip).map_or(0, |b| b.total)
+    }
+}
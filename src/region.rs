@@ -0,0 +1,87 @@
+use serde::Deserialize;
+
+/// A rectangle of the desktop in pixels, used to confine injected input.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "RawRegion")]
+pub struct Region {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Deserialize)]
+struct RawRegion {
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+}
+
+impl TryFrom<RawRegion> for Region {
+    type Error = &'static str;
+
+    fn try_from(raw: RawRegion) -> Result<Self, Self::Error> {
+        Region::new(raw.x, raw.y, raw.width, raw.height).ok_or("region must not be empty")
+    }
+}
+
+impl Region {
+    /// `None` if the rectangle is empty or its far edge overflows.
+    pub fn new(x: i32, y: i32, width: u32, height: u32) -> Option<Self> {
+        if width == 0 || height == 0 {
+            return None;
+        }
+        x.checked_add_unsigned(width)?;
+        y.checked_add_unsigned(height)?;
+        Some(Self {
+            x,
+            y,
+            width,
+            height,
+        })
+    }
+
+    /// Parse `x,y,width,height`, e.g. `0,0,1920,1080`.
+    pub fn parse(spec: &str) -> Option<Self> {
+        let mut parts = spec.split(',').map(str::trim);
+        let x = parts.next()?.parse().ok()?;
+        let y = parts.next()?.parse().ok()?;
+        let width = parts.next()?.parse().ok()?;
+        let height = parts.next()?.parse().ok()?;
+        if parts.next().is_some() {
+            return None;
+        }
+        Self::new(x, y, width, height)
+    }
+
+    fn right(&self) -> i32 {
+        self.x + self.width as i32
+    }
+
+    fn bottom(&self) -> i32 {
+        self.y + self.height as i32
+    }
+
+    /// The overlap of two regions, if they overlap at all.
+    pub fn intersect(&self, other: &Region) -> Option<Region> {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = self.right().min(other.right());
+        let bottom = self.bottom().min(other.bottom());
+        Region::new(
+            x,
+            y,
+            u32::try_from(right - x).ok()?,
+            u32::try_from(bottom - y).ok()?,
+        )
+    }
+
+    /// The nearest point inside the region.
+    pub fn clamp(&self, x: i32, y: i32) -> (i32, i32) {
+        (
+            x.clamp(self.x, self.right() - 1),
+            y.clamp(self.y, self.bottom() - 1),
+        )
+    }
+}
@@ -0,0 +1,121 @@
+/// How far behind the newest number a packet may be and still be told apart from a
+/// replay.
+const WINDOW: u64 = 64;
+
+/// Which of the last [`WINDOW`] numbers were seen. Bit `n` is set when the newest but
+/// `n` was.
+#[derive(Debug, Default)]
+struct Seen(u64);
+
+impl Seen {
+    /// Note a number `ahead` past the newest, which becomes the newest.
+    fn advance(&mut self, ahead: u64) {
+        self.0 = if ahead >= WINDOW { 0 } else { self.0 << ahead };
+        self.0 |= 1;
+    }
+
+    /// Note a number `behind` the newest. False if it was seen before or is too old.
+    fn mark(&mut self, behind: u64) -> bool {
+        if behind >= WINDOW {
+            return false;
+        }
+        let bit = 1 << behind;
+        if self.0 & bit != 0 {
+            return false;
+        }
+        self.0 |= bit;
+        true
+    }
+}
+
+/// The sequence numbers a UDP client put on its `MOVE` and `CLICK` packets, so duplicated,
+/// replayed and out-of-order ones can be dropped. Numbers wrap around.
+#[derive(Debug, Default)]
+pub struct SeqWindow {
+    /// The newest number seen, once the client numbers its packets.
+    newest: Option<u32>,
+    seen: Seen,
+    /// The newest number on a move, so an older move can't pull the cursor back.
+    newest_move: Option<u32>,
+}
+
+impl SeqWindow {
+    /// Whether a click numbered `seq` should be acted on. Clicks that arrive out of order
+    /// still count, unless they were seen before or are too old to tell. Once a client
+    /// numbers its packets, unnumbered ones are dropped.
+    pub fn check(&mut self, seq: Option<u32>) -> bool {
+        match seq {
+            Some(seq) => self.accept(seq),
+            None => self.newest.is_none(),
+        }
+    }
+
+    /// Whether a move numbered `seq` should be acted on: only if it is newer than every
+    /// move before it.
+    pub fn check_move(&mut self, seq: Option<u32>) -> bool {
+        let Some(seq) = seq else {
+            return self.newest.is_none();
+        };
+        if let Some(last) = self.newest_move
+            && !is_newer(seq, last)
+        {
+            return false;
+        }
+        if !self.accept(seq) {
+            return false;
+        }
+        self.newest_move = Some(seq);
+        true
+    }
+
+    /// Forget every number, for a client that starts counting again after a `HELLO`.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    fn accept(&mut self, seq: u32) -> bool {
+        let Some(newest) = self.newest else {
+            self.newest = Some(seq);
+            self.seen.advance(WINDOW);
+            return true;
+        };
+        if is_newer(seq, newest) {
+            self.seen.advance(seq.wrapping_sub(newest).into());
+            self.newest = Some(seq);
+            return true;
+        }
+        self.seen.mark(newest.wrapping_sub(seq).into())
+    }
+}
+
+/// The nonces on a client's sealed packets, so none is opened twice. Nonces count up
+/// from 0 and never wrap.
+#[derive(Debug, Default)]
+pub struct NonceWindow {
+    newest: Option<u64>,
+    seen: Seen,
+}
+
+impl NonceWindow {
+    /// Whether `nonce` wasn't seen before and isn't too old to tell.
+    pub fn accept(&mut self, nonce: u64) -> bool {
+        match self.newest {
+            Some(newest) if nonce <= newest => self.seen.mark(newest - nonce),
+            Some(newest) => {
+                self.seen.advance(nonce - newest);
+                self.newest = Some(nonce);
+                true
+            }
+            None => {
+                self.seen.advance(WINDOW);
+                self.newest = Some(nonce);
+                true
+            }
+        }
+    }
+}
+
+/// Whether `a` comes after `b`, counting across the wrap.
+fn is_newer(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) > 0
+}
@@ -0,0 +1,117 @@
+use std::time::{Duration, Instant};
+
+/// Output rates `--resample` accepts, in Hz.
+pub const RESAMPLE_RATES: std::ops::RangeInclusive<u32> = 60..=480;
+
+/// Longest a glide towards a new point may take; slower input is followed with that lag.
+const MAX_SPAN: Duration = Duration::from_millis(50);
+
+/// Input arriving after a pause longer than this jumps straight to its point.
+const RESTART_GAP: Duration = Duration::from_millis(100);
+
+/// Weight of the newest gap between input points against the running estimate.
+const GAP_WEIGHT: f64 = 0.25;
+
+/// Turns absolute points arriving at an irregular rate into moves at a steady rate.
+///
+/// Each new point starts a glide from where the cursor is towards it, spread over the usual
+/// gap between points, so the cursor keeps moving smoothly until the next one arrives. That
+/// costs about one input interval of lag.
+#[derive(Debug, Clone, Default)]
+pub struct Resampler {
+    /// Output period; zero when resampling is off.
+    tick: Duration,
+    /// Running estimate of the gap between input points.
+    gap: Option<Duration>,
+    last_input: Option<Instant>,
+    /// Last position handed out.
+    pos: Option<(f64, f64)>,
+    glide: Option<Glide>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Glide {
+    from: (f64, f64),
+    to: (f64, f64),
+    start: Instant,
+    span: Duration,
+    next_tick: Instant,
+}
+
+impl Resampler {
+    /// Emit at `rate` Hz, which must be within [`RESAMPLE_RATES`]; `None` turns resampling off.
+    pub fn set_rate(&mut self, rate: Option<u32>) {
+        self.tick = rate
+            .filter(|r| RESAMPLE_RATES.contains(r))
+            .map_or(Duration::ZERO, |r| Duration::from_secs(1) / r);
+        self.reset();
+    }
+
+    pub fn is_on(&self) -> bool {
+        !self.tick.is_zero()
+    }
+
+    /// Forget the motion so far, e.g. after something else moved the cursor.
+    pub fn reset(&mut self) {
+        self.gap = None;
+        self.last_input = None;
+        self.pos = None;
+        self.glide = None;
+    }
+
+    /// Take a new target point. Returns it straight away when there is nothing to glide from.
+    pub fn push(&mut self, x: f64, y: f64, now: Instant) -> Option<(f64, f64)> {
+        let gap = self
+            .last_input
+            .replace(now)
+            .map(|t| now.duration_since(t))
+            .filter(|gap| *gap <= RESTART_GAP);
+        let (Some(from), Some(gap)) = (self.pos, gap) else {
+            self.gap = None;
+            self.glide = None;
+            self.pos = Some((x, y));
+            return Some((x, y));
+        };
+
+        let gap = match self.gap {
+            Some(est) => est.mul_f64(1.0 - GAP_WEIGHT) + gap.mul_f64(GAP_WEIGHT),
+            None => gap,
+        };
+        self.gap = Some(gap);
+        self.glide = Some(Glide {
+            from,
+            to: (x, y),
+            start: now,
+            span: gap.clamp(self.tick, MAX_SPAN),
+            // Stay on the running cadence rather than re-phasing to every arrival.
+            next_tick: self.glide.map_or(now + self.tick, |g| g.next_tick),
+        });
+        None
+    }
+
+    /// When the next move is due, if a glide is running.
+    pub fn next_tick(&self) -> Option<Instant> {
+        self.glide.map(|g| g.next_tick)
+    }
+
+    /// The position to move to at `now`, if a tick is due.
+    pub fn step(&mut self, now: Instant) -> Option<(f64, f64)> {
+        let glide = self.glide.as_mut()?;
+        if now < glide.next_tick {
+            return None;
+        }
+        let t = (now.duration_since(glide.start).as_secs_f64() / glide.span.as_secs_f64()).min(1.0);
+        let pos = (
+            glide.from.0 + t * (glide.to.0 - glide.from.0),
+            glide.from.1 + t * (glide.to.1 - glide.from.1),
+        );
+        // Keep a steady cadence, but skip ticks rather than burst if the worker fell behind.
+        let next = glide.next_tick + self.tick;
+        glide.next_tick = if next > now { next } else { now + self.tick };
+        if t >= 1.0 {
+            self.glide = None;
+        }
+        self.pos = Some(pos);
+        Some(pos)
+    }
+}
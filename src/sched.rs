@@ -0,0 +1,131 @@
+use std::io;
+
+/// Raise the calling thread's scheduling priority so it keeps its timing while the rest of
+/// the system is busy: real-time where the OS allows it, otherwise the highest it grants.
+pub fn raise_priority() -> io::Result<()> {
+    platform::raise_priority()
+}
+
+/// Keep the calling thread on one CPU core, counted from 0.
+pub fn pin_to_cpu(cpu: usize) -> io::Result<()> {
+    platform::pin_to_cpu(cpu)
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::io;
+
+    /// Low in the 1–99 range: above every normal thread, below kernel and audio threads.
+    const FIFO_PRIORITY: libc::c_int = 10;
+
+    /// Fallback nice value when real-time scheduling is refused.
+    const NICE: libc::c_int = -10;
+
+    pub fn raise_priority() -> io::Result<()> {
+        let param = libc::sched_param {
+            sched_priority: FIFO_PRIORITY,
+        };
+        // SAFETY: `param` outlives the call and the thread handle is our own.
+        let err =
+            unsafe { libc::pthread_setschedparam(libc::pthread_self(), libc::SCHED_FIFO, &param) };
+        if err == 0 {
+            return Ok(());
+        }
+        // Without CAP_SYS_NICE or an rtprio limit, a lower nice value may still be allowed.
+        // SAFETY: plain syscalls without pointers; a thread id targets only this thread.
+        let nice =
+            unsafe { libc::setpriority(libc::PRIO_PROCESS, libc::gettid() as libc::id_t, NICE) };
+        if nice == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::from_raw_os_error(err))
+        }
+    }
+
+    pub fn pin_to_cpu(cpu: usize) -> io::Result<()> {
+        if cpu >= libc::CPU_SETSIZE as usize {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "no such CPU"));
+        }
+        // SAFETY: the set is zeroed before use and `cpu` is within its size.
+        unsafe {
+            let mut set: libc::cpu_set_t = std::mem::zeroed();
+            libc::CPU_SET(cpu, &mut set);
+            if libc::sched_setaffinity(0, size_of::<libc::cpu_set_t>(), &set) == 0 {
+                Ok(())
+            } else {
+                Err(io::Error::last_os_error())
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use std::io;
+    use windows_sys::Win32::System::Threading::{
+        GetCurrentThread, SetThreadAffinityMask, SetThreadPriority, THREAD_PRIORITY_TIME_CRITICAL,
+    };
+
+    pub fn raise_priority() -> io::Result<()> {
+        // SAFETY: the pseudo handle always refers to the calling thread.
+        if unsafe { SetThreadPriority(GetCurrentThread(), THREAD_PRIORITY_TIME_CRITICAL) } != 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+
+    pub fn pin_to_cpu(cpu: usize) -> io::Result<()> {
+        if cpu >= usize::BITS as usize {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "no such CPU"));
+        }
+        // SAFETY: as above; a mask naming a missing CPU is rejected by the call.
+        if unsafe { SetThreadAffinityMask(GetCurrentThread(), 1 << cpu) } != 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::io;
+
+    /// `QOS_CLASS_USER_INTERACTIVE`, the class the system schedules most eagerly.
+    const QOS_CLASS_USER_INTERACTIVE: u32 = 0x21;
+
+    unsafe extern "C" {
+        fn pthread_set_qos_class_self_np(qos_class: u32, relative_priority: i32) -> i32;
+    }
+
+    pub fn raise_priority() -> io::Result<()> {
+        // SAFETY: affects only the calling thread and takes no pointers.
+        let err = unsafe { pthread_set_qos_class_self_np(QOS_CLASS_USER_INTERACTIVE, 0) };
+        if err == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::from_raw_os_error(err))
+        }
+    }
+
+    pub fn pin_to_cpu(_cpu: usize) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "macOS doesn't let threads pin themselves to a core",
+        ))
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "windows", target_os = "macos")))]
+mod platform {
+    use std::io;
+
+    pub fn raise_priority() -> io::Result<()> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    pub fn pin_to_cpu(_cpu: usize) -> io::Result<()> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
@@ -0,0 +1,151 @@
+use axum::body::Body;
+use axum::extract::{Path, State};
+use axum::http::header::CONTENT_DISPOSITION;
+use axum::http::{HeaderValue, Request, StatusCode};
+use axum::response::{IntoResponse, Response};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+use tokio::time::{Duration, Instant};
+use tower::ServiceExt;
+use tower_http::services::ServeFile;
+use tracing::{info, warn};
+
+/// How long an unused share link stays valid.
+const SHARE_TTL: Duration = Duration::from_secs(15 * 60);
+
+/// A file offered to the phone, announced to the connected WebSocket client.
+#[derive(Debug, Clone)]
+pub struct FileOffer {
+    pub name: String,
+    pub url: String,
+}
+
+struct Share {
+    path: PathBuf,
+    expires: Instant,
+}
+
+/// Files exposed at one-time tokenized URLs under `/share/<token>` on the HTTP server.
+///
+/// Each link works for a single download and expires after [`SHARE_TTL`] if unused.
+pub struct FileShares {
+    base_url: String,
+    shares: Mutex<HashMap<String, Share>>,
+    offers: broadcast::Sender<FileOffer>,
+}
+
+impl FileShares {
+    /// `base_url` is the HTTP server's address as seen from the phone, without a trailing slash.
+    pub fn new(base_url: String) -> Self {
+        let (offers, _) = broadcast::channel(4);
+        Self {
+            base_url,
+            shares: Mutex::new(HashMap::new()),
+            offers,
+        }
+    }
+
+    /// Expose `path` for one download and announce it to subscribers.
+    pub fn offer(&self, path: PathBuf) -> anyhow::Result<FileOffer> {
+        let path = path.canonicalize()?;
+        if !path.is_file() {
+            anyhow::bail!("{} is not a file", path.display());
+        }
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        let token = format!("{:032x}", rand::random::<u128>());
+        let url = format!("{}/share/{token}", self.base_url);
+        let now = Instant::now();
+        let mut shares = self.shares.lock().unwrap();
+        shares.retain(|_, s| s.expires > now);
+        shares.insert(
+            token,
+            Share {
+                path,
+                expires: now + SHARE_TTL,
+            },
+        );
+        drop(shares);
+
+        let offer = FileOffer { name, url };
+        let _ = self.offers.send(offer.clone());
+        Ok(offer)
+    }
+
+    /// Receive offers as they are made, e.g. to forward them to the connected client.
+    pub fn subscribe(&self) -> broadcast::Receiver<FileOffer> {
+        self.offers.subscribe()
+    }
+
+    /// Redeem a token, invalidating it.
+    fn take(&self, token: &str) -> Option<PathBuf> {
+        let share = self.shares.lock().unwrap().remove(token)?;
+        (share.expires > Instant::now()).then_some(share.path)
+    }
+
+    /// Handle a `share <path>` console command.
+    pub fn run_command(&self, args: &str) {
+        let path = args.trim();
+        if path.is_empty() {
+            println!("usage: share <path>");
+            return;
+        }
+        match self.offer(PathBuf::from(path)) {
+            Ok(offer) => println!("📎 {} is available once at {}", offer.name, offer.url),
+            Err(err) => println!("✗ Cannot share {path}: {err}"),
+        }
+    }
+}
+
+/// `GET /share/{token}`: stream the shared file as an attachment, once.
+pub async fn download(
+    State(shares): State<Arc<FileShares>>,
+    Path(token): Path<String>,
+    req: Request<Body>,
+) -> Response {
+    let Some(path) = shares.take(&token) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let mut res = match ServeFile::new(&path).oneshot(req).await {
+        Ok(res) => res.map(Body::new),
+        Err(err) => {
+            warn!("Failed to serve {}: {err}", path.display());
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    if let Ok(value) = HeaderValue::from_str(&content_disposition(&name)) {
+        res.headers_mut().insert(CONTENT_DISPOSITION, value);
+    }
+    info!("📎 Shared file downloaded: {}", path.display());
+    res
+}
+
+/// `attachment` header with an ASCII fallback name plus the exact UTF-8 name (RFC 6266).
+fn content_disposition(name: &str) -> String {
+    let fallback: String = name
+        .chars()
+        .map(|c| match c {
+            ' '..='~' if c != '"' && c != '\\' => c,
+            _ => '_',
+        })
+        .collect();
+    let mut encoded = String::with_capacity(name.len() * 3);
+    for b in name.bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{b:02X}"));
+        }
+    }
+    format!("attachment; filename=\"{fallback}\"; filename*=UTF-8''{encoded}")
+}
@@ -0,0 +1,105 @@
+use serde::Deserialize;
+use std::f64::consts::TAU;
+use std::time::{Duration, Instant};
+
+/// Cutoff for the speed estimate itself, in Hz; the usual choice for the One-Euro filter.
+const DERIVATIVE_CUTOFF: f64 = 1.0;
+
+/// A pause longer than this starts over instead of easing in from the old position.
+const RESTART_GAP: Duration = Duration::from_millis(500);
+
+/// One-Euro filter tuning: heavy smoothing while the pointer creeps, little once it moves fast.
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "RawOneEuro")]
+pub struct OneEuro {
+    /// Cutoff frequency at rest, in Hz. Lower removes more jitter but lags more.
+    pub min_cutoff: f64,
+    /// How fast the cutoff rises with speed. Higher cuts lag on quick moves.
+    pub beta: f64,
+}
+
+#[derive(Deserialize)]
+struct RawOneEuro {
+    min_cutoff: f64,
+    beta: f64,
+}
+
+impl TryFrom<RawOneEuro> for OneEuro {
+    type Error = &'static str;
+
+    fn try_from(raw: RawOneEuro) -> Result<Self, Self::Error> {
+        OneEuro::new(raw.min_cutoff, raw.beta)
+            .ok_or("min_cutoff must be positive and beta non-negative")
+    }
+}
+
+impl OneEuro {
+    pub fn new(min_cutoff: f64, beta: f64) -> Option<Self> {
+        (min_cutoff.is_finite() && min_cutoff > 0.0 && beta.is_finite() && beta >= 0.0)
+            .then_some(Self { min_cutoff, beta })
+    }
+
+    /// Parse `min_cutoff,beta`, e.g. `1.0,0.007`.
+    pub fn parse(spec: &str) -> Option<Self> {
+        let (min_cutoff, beta) = spec.split_once(',')?;
+        Self::new(min_cutoff.trim().parse().ok()?, beta.trim().parse().ok()?)
+    }
+}
+
+/// Smoothing factor for a low-pass filter at `cutoff` Hz over a step of `dt` seconds.
+fn alpha(cutoff: f64, dt: f64) -> f64 {
+    1.0 / (1.0 + 1.0 / (TAU * cutoff * dt))
+}
+
+/// One-Euro filter over desktop positions; both axes share one cutoff so strokes keep their
+/// shape.
+#[derive(Debug, Clone, Default)]
+pub struct OneEuroFilter {
+    last: Option<Sample>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    at: Instant,
+    pos: (f64, f64),
+    /// Smoothed velocity in pixels per second.
+    vel: (f64, f64),
+}
+
+impl OneEuroFilter {
+    /// Forget the previous position, e.g. after something else moved the cursor.
+    pub fn reset(&mut self) {
+        self.last = None;
+    }
+
+    /// Smooth the position `x`/`y` observed at `now`.
+    pub fn filter(&mut self, params: OneEuro, x: f64, y: f64, now: Instant) -> (f64, f64) {
+        let prev = self.last.filter(|p| {
+            let gap = now.duration_since(p.at);
+            !gap.is_zero() && gap <= RESTART_GAP
+        });
+        let Some(prev) = prev else {
+            self.last = Some(Sample {
+                at: now,
+                pos: (x, y),
+                vel: (0.0, 0.0),
+            });
+            return (x, y);
+        };
+
+        let dt = now.duration_since(prev.at).as_secs_f64();
+        let lerp = |a: f64, new: f64, old: f64| old + a * (new - old);
+
+        let a_d = alpha(DERIVATIVE_CUTOFF, dt);
+        let vel = (
+            lerp(a_d, (x - prev.pos.0) / dt, prev.vel.0),
+            lerp(a_d, (y - prev.pos.1) / dt, prev.vel.1),
+        );
+        let cutoff = params.min_cutoff + params.beta * vel.0.hypot(vel.1);
+        let a = alpha(cutoff, dt);
+        let pos = (lerp(a, x, prev.pos.0), lerp(a, y, prev.pos.1));
+
+        self.last = Some(Sample { at: now, pos, vel });
+        pos
+    }
+}
@@ -0,0 +1,401 @@
+use serde::Deserialize;
+use std::io;
+
+/// One pen sample in client coordinates, as received from a transport.
+#[derive(Debug, Copy, Clone, Deserialize)]
+pub struct StylusSample {
+    pub x: u16,
+    pub y: u16,
+    /// Normalized tip pressure, `0.0..=1.0`.
+    #[serde(default)]
+    pub pressure: f32,
+    /// Tilt in degrees, `-90.0..=90.0` (positive = towards +x / +y).
+    #[serde(default)]
+    pub tilt_x: f32,
+    #[serde(default)]
+    pub tilt_y: f32,
+    /// Pen is in range but not touching the surface.
+    #[serde(default)]
+    pub hover: bool,
+    /// The eraser end is the active tool.
+    #[serde(default)]
+    pub eraser: bool,
+    /// The side (barrel) button is held.
+    #[serde(default)]
+    pub barrel: bool,
+}
+
+impl StylusSample {
+    /// Clamp client-supplied values into their documented ranges.
+    pub fn sanitized(mut self) -> Self {
+        self.pressure = finite_or_zero(self.pressure).clamp(0.0, 1.0);
+        self.tilt_x = finite_or_zero(self.tilt_x).clamp(-90.0, 90.0);
+        self.tilt_y = finite_or_zero(self.tilt_y).clamp(-90.0, 90.0);
+        self
+    }
+
+    pub fn in_contact(&self) -> bool {
+        !self.hover
+    }
+}
+
+fn finite_or_zero(v: f32) -> f32 {
+    if v.is_finite() { v } else { 0.0 }
+}
+
+/// Server-side remapping of normalized pen pressure, to tune soft/hard pen feel.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum PressureCurve {
+    #[default]
+    Linear,
+    /// `out = in^gamma`; below 1 feels softer, above 1 feels harder.
+    Gamma(f32),
+    /// CSS-style cubic bezier from (0,0) to (1,1) with control points (x1,y1) and (x2,y2).
+    Bezier { x1: f32, y1: f32, x2: f32, y2: f32 },
+}
+
+impl PressureCurve {
+    /// Parse `linear`, `gamma:<g>` or `bezier:<x1>,<y1>,<x2>,<y2>`.
+    pub fn parse(spec: &str) -> Option<Self> {
+        let (kind, args) = spec.split_once(':').unwrap_or((spec, ""));
+        let nums: Vec<f32> = if args.is_empty() {
+            Vec::new()
+        } else {
+            args.split(',')
+                .map(|v| v.trim().parse().ok().filter(|v: &f32| v.is_finite()))
+                .collect::<Option<_>>()?
+        };
+        match (kind.trim().to_ascii_lowercase().as_str(), nums.as_slice()) {
+            ("linear", []) => Some(Self::Linear),
+            ("gamma", &[g]) if g > 0.0 => Some(Self::Gamma(g)),
+            // x control values must stay in [0, 1] so the curve is a function of input pressure.
+            ("bezier", &[x1, y1, x2, y2])
+                if (0.0..=1.0).contains(&x1) && (0.0..=1.0).contains(&x2) =>
+            {
+                Some(Self::Bezier { x1, y1, x2, y2 })
+            }
+            _ => None,
+        }
+    }
+
+    pub fn apply(&self, pressure: f32) -> f32 {
+        let p = pressure.clamp(0.0, 1.0);
+        let out = match *self {
+            Self::Linear => p,
+            Self::Gamma(g) => p.powf(g),
+            Self::Bezier { x1, y1, x2, y2 } => {
+                let t = solve_bezier_t(p, x1, x2);
+                bezier(t, y1, y2)
+            }
+        };
+        out.clamp(0.0, 1.0)
+    }
+}
+
+/// One coordinate of a cubic bezier with endpoints 0 and 1.
+fn bezier(t: f32, c1: f32, c2: f32) -> f32 {
+    let u = 1.0 - t;
+    3.0 * u * u * t * c1 + 3.0 * u * t * t * c2 + t * t * t
+}
+
+/// Find `t` such that `bezier(t, x1, x2) == x`; x(t) is monotonic for x1, x2 in [0, 1].
+fn solve_bezier_t(x: f32, x1: f32, x2: f32) -> f32 {
+    let (mut lo, mut hi) = (0.0f32, 1.0f32);
+    for _ in 0..24 {
+        let mid = 0.5 * (lo + hi);
+        if bezier(mid, x1, x2) < x {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+    0.5 * (lo + hi)
+}
+
+/// A pen sample mapped to desktop pixels, ready for a platform backend.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct PenReport {
+    pub x: i32,
+    pub y: i32,
+    pub pressure: f32,
+    pub tilt_x: f32,
+    pub tilt_y: f32,
+    pub contact: bool,
+    pub eraser: bool,
+    pub barrel: bool,
+}
+
+/// Platform virtual-tablet device that can deliver real pressure and tilt.
+pub(crate) trait PenBackend {
+    fn report(&mut self, report: &PenReport) -> io::Result<()>;
+
+    /// Lift the pen out of range (end of stroke, or session teardown).
+    fn leave(&mut self) -> io::Result<()>;
+}
+
+/// Open the native pen backend for this platform, if there is one and it is usable.
+pub(crate) fn open_backend(screen_w: u16, screen_h: u16) -> io::Result<Box<dyn PenBackend>> {
+    #[cfg(target_os = "linux")]
+    {
+        Ok(Box::new(linux::UinputPen::new(screen_w, screen_h)?))
+    }
+    #[cfg(target_os = "windows")]
+    {
+        let _ = (screen_w, screen_h);
+        Ok(Box::new(windows::SyntheticPen::new()?))
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "windows")))]
+    {
+        let _ = (screen_w, screen_h);
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "no virtual tablet backend on this platform",
+        ))
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::{PenBackend, PenReport};
+    use crate::uinput::*;
+    use std::io;
+
+    const PRESSURE_MAX: i32 = 4095;
+
+    /// Virtual tablet mapped 1:1 onto the primary display.
+    pub struct UinputPen {
+        dev: UinputDevice,
+        /// Active tool (`BTN_TOOL_PEN` / `BTN_TOOL_RUBBER`) while in range.
+        tool: Option<u16>,
+        contact: bool,
+        barrel: bool,
+    }
+
+    impl UinputPen {
+        pub fn new(screen_w: u16, screen_h: u16) -> io::Result<Self> {
+            let axes = [
+                AbsAxis {
+                    code: ABS_X,
+                    min: 0,
+                    max: i32::from(screen_w.max(1)) - 1,
+                    resolution: 0,
+                },
+                AbsAxis {
+                    code: ABS_Y,
+                    min: 0,
+                    max: i32::from(screen_h.max(1)) - 1,
+                    resolution: 0,
+                },
+                AbsAxis {
+                    code: ABS_PRESSURE,
+                    min: 0,
+                    max: PRESSURE_MAX,
+                    resolution: 0,
+                },
+                AbsAxis {
+                    code: ABS_TILT_X,
+                    min: -90,
+                    max: 90,
+                    resolution: 57, // units per radian ≈ 1 unit per degree
+                },
+                AbsAxis {
+                    code: ABS_TILT_Y,
+                    min: -90,
+                    max: 90,
+                    resolution: 57,
+                },
+            ];
+            let dev = UinputDevice::create(&DeviceSpec {
+                name: "Penput Virtual Pen",
+                props: &[INPUT_PROP_DIRECT],
+                keys: &[BTN_TOOL_PEN, BTN_TOOL_RUBBER, BTN_TOUCH, BTN_STYLUS],
+                rels: &[],
+                axes: &axes,
+            })?;
+            Ok(Self {
+                dev,
+                tool: None,
+                contact: false,
+                barrel: false,
+            })
+        }
+    }
+
+    impl PenBackend for UinputPen {
+        fn report(&mut self, r: &PenReport) -> io::Result<()> {
+            let tool = if r.eraser {
+                BTN_TOOL_RUBBER
+            } else {
+                BTN_TOOL_PEN
+            };
+            if self.tool != Some(tool) {
+                // Switching ends between samples: take the old tool out of range first.
+                if self.tool.is_some() {
+                    self.leave()?;
+                }
+                self.dev.emit(EV_KEY, tool, 1);
+                self.tool = Some(tool);
+            }
+            self.dev.emit(EV_ABS, ABS_X, r.x);
+            self.dev.emit(EV_ABS, ABS_Y, r.y);
+            let pressure = if r.contact {
+                (r.pressure * PRESSURE_MAX as f32).round() as i32
+            } else {
+                0
+            };
+            self.dev.emit(EV_ABS, ABS_PRESSURE, pressure);
+            self.dev.emit(EV_ABS, ABS_TILT_X, r.tilt_x.round() as i32);
+            self.dev.emit(EV_ABS, ABS_TILT_Y, r.tilt_y.round() as i32);
+            if r.contact != self.contact {
+                self.dev.emit(EV_KEY, BTN_TOUCH, i32::from(r.contact));
+                self.contact = r.contact;
+            }
+            if r.barrel != self.barrel {
+                self.dev.emit(EV_KEY, BTN_STYLUS, i32::from(r.barrel));
+                self.barrel = r.barrel;
+            }
+            self.dev.sync()
+        }
+
+        fn leave(&mut self) -> io::Result<()> {
+            let Some(tool) = self.tool.take() else {
+                return Ok(());
+            };
+            if self.contact {
+                self.dev.emit(EV_ABS, ABS_PRESSURE, 0);
+                self.dev.emit(EV_KEY, BTN_TOUCH, 0);
+                self.contact = false;
+            }
+            if self.barrel {
+                self.dev.emit(EV_KEY, BTN_STYLUS, 0);
+                self.barrel = false;
+            }
+            self.dev.emit(EV_KEY, tool, 0);
+            self.dev.sync()
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod windows {
+    use super::{PenBackend, PenReport};
+    use std::io;
+    use windows_sys::Win32::UI::Controls::{
+        CreateSyntheticPointerDevice, DestroySyntheticPointerDevice, HSYNTHETICPOINTERDEVICE,
+        POINTER_FEEDBACK_DEFAULT, POINTER_TYPE_INFO,
+    };
+    use windows_sys::Win32::UI::Input::Pointer::{
+        InjectSyntheticPointerInput, POINTER_FLAG_DOWN, POINTER_FLAG_FIRSTBUTTON,
+        POINTER_FLAG_INCONTACT, POINTER_FLAG_INRANGE, POINTER_FLAG_PRIMARY, POINTER_FLAG_UP,
+        POINTER_FLAG_UPDATE, POINTER_FLAGS, POINTER_PEN_INFO,
+    };
+    use windows_sys::Win32::UI::WindowsAndMessaging::{
+        PEN_FLAG_BARREL, PEN_FLAG_ERASER, PEN_FLAG_INVERTED, PEN_FLAG_NONE, PEN_MASK_PRESSURE,
+        PEN_MASK_TILT_X, PEN_MASK_TILT_Y, PT_PEN,
+    };
+
+    /// Windows reports pen pressure in `0..=1024`.
+    const PRESSURE_MAX: f32 = 1024.0;
+
+    /// Synthetic pen device (Windows 10 1809+), seen by apps as a real Windows Ink pen.
+    pub struct SyntheticPen {
+        device: HSYNTHETICPOINTERDEVICE,
+        last: Option<PenReport>,
+    }
+
+    impl SyntheticPen {
+        pub fn new() -> io::Result<Self> {
+            // SAFETY: plain FFI call; a null handle signals failure.
+            let device =
+                unsafe { CreateSyntheticPointerDevice(PT_PEN, 1, POINTER_FEEDBACK_DEFAULT) };
+            if device.is_null() {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Self { device, last: None })
+        }
+
+        fn inject(&self, r: &PenReport, flags: POINTER_FLAGS) -> io::Result<()> {
+            let mut pen = POINTER_PEN_INFO::default();
+            pen.pointerInfo.pointerType = PT_PEN;
+            pen.pointerInfo.pointerFlags = flags | POINTER_FLAG_PRIMARY;
+            pen.pointerInfo.ptPixelLocation.x = r.x;
+            pen.pointerInfo.ptPixelLocation.y = r.y;
+            pen.penMask = PEN_MASK_PRESSURE | PEN_MASK_TILT_X | PEN_MASK_TILT_Y;
+            // INVERTED marks the eraser end in range; ERASER is added once it touches.
+            pen.penFlags = match (r.eraser, r.contact) {
+                (true, true) => PEN_FLAG_INVERTED | PEN_FLAG_ERASER,
+                (true, false) => PEN_FLAG_INVERTED,
+                (false, _) => PEN_FLAG_NONE,
+            };
+            if r.barrel {
+                pen.penFlags |= PEN_FLAG_BARREL;
+            }
+            pen.pressure = if r.contact {
+                (r.pressure * PRESSURE_MAX).round() as u32
+            } else {
+                0
+            };
+            pen.tiltX = r.tilt_x.round() as i32;
+            pen.tiltY = r.tilt_y.round() as i32;
+
+            let mut info = POINTER_TYPE_INFO {
+                r#type: PT_PEN,
+                ..Default::default()
+            };
+            info.Anonymous.penInfo = pen;
+            // SAFETY: `info` is fully initialized and outlives the call.
+            if unsafe { InjectSyntheticPointerInput(self.device, &info, 1) } == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        }
+    }
+
+    impl PenBackend for SyntheticPen {
+        fn report(&mut self, r: &PenReport) -> io::Result<()> {
+            // Flipping to the other end is a new pointer session for Windows Ink.
+            if self.last.is_some_and(|l| l.eraser != r.eraser) {
+                self.leave()?;
+            }
+            let was_contact = self.last.is_some_and(|l| l.contact);
+            let flags = match (was_contact, r.contact) {
+                (false, true) => {
+                    POINTER_FLAG_INRANGE
+                        | POINTER_FLAG_INCONTACT
+                        | POINTER_FLAG_FIRSTBUTTON
+                        | POINTER_FLAG_DOWN
+                }
+                (true, true) => {
+                    POINTER_FLAG_INRANGE
+                        | POINTER_FLAG_INCONTACT
+                        | POINTER_FLAG_FIRSTBUTTON
+                        | POINTER_FLAG_UPDATE
+                }
+                (true, false) => POINTER_FLAG_INRANGE | POINTER_FLAG_UP,
+                (false, false) => POINTER_FLAG_INRANGE | POINTER_FLAG_UPDATE,
+            };
+            self.inject(r, flags)?;
+            self.last = Some(*r);
+            Ok(())
+        }
+
+        fn leave(&mut self) -> io::Result<()> {
+            let Some(mut last) = self.last.take() else {
+                return Ok(());
+            };
+            if last.contact {
+                last.contact = false;
+                self.inject(&last, POINTER_FLAG_INRANGE | POINTER_FLAG_UP)?;
+            }
+            // An update without INRANGE takes the pen out of range.
+            self.inject(&last, POINTER_FLAG_UPDATE)
+        }
+    }
+
+    impl Drop for SyntheticPen {
+        fn drop(&mut self) {
+            // SAFETY: the handle came from CreateSyntheticPointerDevice and is dropped once.
+            unsafe { DestroySyntheticPointerDevice(self.device) };
+        }
+    }
+}
@@ -0,0 +1,143 @@
+pub use platform::Tls;
+
+/// Whether this build can serve HTTPS and WSS (the `tls` feature).
+pub const AVAILABLE: bool = cfg!(feature = "tls");
+
+#[cfg(any(feature = "tls", feature = "quic"))]
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+
+/// The certificate kept in the config directory, made for `names` (host names and
+/// addresses clients use) if there is none yet, and its SHA-256 fingerprint as browsers
+/// show it. HTTPS and QUIC share it, so clients pin one fingerprint.
+#[cfg(any(feature = "tls", feature = "quic"))]
+pub fn kept_certificate(
+    names: Vec<String>,
+) -> anyhow::Result<(CertificateDer<'static>, PrivateKeyDer<'static>, String)> {
+    use crate::trust;
+    use anyhow::Context;
+    use rustls::pki_types::pem::PemObject;
+    use sha2::{Digest, Sha256};
+    use std::path::PathBuf;
+    use tracing::info;
+
+    let paths = trust::config_dir().map(|dir| (dir.join("tls-cert.pem"), dir.join("tls-key.pem")));
+    let (cert, key) = match &paths {
+        Some((cert, key)) if cert.exists() && key.exists() => {
+            let pem = |path: &PathBuf| {
+                std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))
+            };
+            (pem(cert)?, pem(key)?)
+        }
+        _ => {
+            let generated = rcgen::generate_simple_self_signed(names)?;
+            let cert = generated.cert.pem().into_bytes();
+            let key = generated.key_pair.serialize_pem().into_bytes();
+            if let Some((cert_path, key_path)) = &paths {
+                trust::write_private(key_path, &key)
+                    .and_then(|()| trust::write_private(cert_path, &cert))
+                    .with_context(|| {
+                        format!("Failed to save the certificate to {}", cert_path.display())
+                    })?;
+                info!("🔒 Made a self-signed certificate: {}", cert_path.display());
+            }
+            (cert, key)
+        }
+    };
+
+    let cert =
+        CertificateDer::from_pem_slice(&cert).context("Failed to parse the TLS certificate")?;
+    let key = PrivateKeyDer::from_pem_slice(&key).context("Failed to parse the TLS key")?;
+    let fingerprint = Sha256::digest(&cert)
+        .iter()
+        .map(|b| format!("{b:02X}"))
+        .collect::<Vec<_>>()
+        .join(":");
+    Ok((cert, key, fingerprint))
+}
+
+#[cfg(feature = "tls")]
+mod platform {
+    use anyhow::Result;
+    use axum::Router;
+    use axum_server::tls_rustls::RustlsConfig;
+    use rustls::ServerConfig;
+    use std::net::{SocketAddr, TcpListener};
+    use std::sync::Arc;
+
+    /// The certificate HTTPS and WSS are served with, self-signed and kept in the config
+    /// directory so its fingerprint stays the same across restarts.
+    #[derive(Clone)]
+    pub struct Tls {
+        config: RustlsConfig,
+        fingerprint: String,
+    }
+
+    impl Tls {
+        /// Load the kept certificate, or make one for `names` (host names and addresses
+        /// clients use) and keep it.
+        pub fn load_or_generate(names: Vec<String>) -> Result<Self> {
+            let (cert, key, fingerprint) = super::kept_certificate(names)?;
+            let mut config = ServerConfig::builder_with_provider(Arc::new(
+                rustls::crypto::ring::default_provider(),
+            ))
+            .with_safe_default_protocol_versions()?
+            .with_no_client_auth()
+            .with_single_cert(vec![cert], key)?;
+            // WebSocket upgrades need HTTP/1.1.
+            config.alpn_protocols = vec![b"http/1.1".to_vec()];
+            Ok(Self {
+                config: RustlsConfig::from_config(Arc::new(config)),
+                fingerprint,
+            })
+        }
+
+        /// SHA-256 of the certificate, as browsers show it, for clients to pin.
+        pub fn fingerprint(&self) -> &str {
+            &self.fingerprint
+        }
+
+        /// Serve `app` over HTTPS on `listener`.
+        pub async fn serve(&self, app: Router, listener: TcpListener) -> Result<()> {
+            axum_server::from_tcp_rustls(listener, self.config.clone())
+                .serve(app.into_make_service())
+                .await?;
+            Ok(())
+        }
+
+        /// Serve `app` over HTTPS on `listener`, telling handlers who connected.
+        pub async fn serve_with_addr(&self, app: Router, listener: TcpListener) -> Result<()> {
+            axum_server::from_tcp_rustls(listener, self.config.clone())
+                .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+                .await?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(not(feature = "tls"))]
+mod platform {
+    use anyhow::{Result, bail};
+    use axum::Router;
+    use std::net::TcpListener;
+
+    #[derive(Clone)]
+    pub struct Tls;
+
+    impl Tls {
+        pub fn load_or_generate(_names: Vec<String>) -> Result<Self> {
+            bail!("built without the tls feature")
+        }
+
+        pub fn fingerprint(&self) -> &str {
+            ""
+        }
+
+        pub async fn serve(&self, _app: Router, _listener: TcpListener) -> Result<()> {
+            bail!("built without the tls feature")
+        }
+
+        pub async fn serve_with_addr(&self, _app: Router, _listener: TcpListener) -> Result<()> {
+            bail!("built without the tls feature")
+        }
+    }
+}
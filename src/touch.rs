@@ -0,0 +1,253 @@
+use serde::Deserialize;
+use std::io;
+
+/// Most simultaneous contacts accepted in one frame (ten fingers).
+pub const MAX_CONTACTS: usize = 10;
+
+/// One finger in client coordinates. `id` stays stable while the finger is down.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize)]
+pub struct TouchContact {
+    pub id: u8,
+    pub x: u16,
+    pub y: u16,
+}
+
+/// A contact mapped to desktop pixels, ready for a platform backend.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct TouchPoint {
+    pub id: u8,
+    pub x: i32,
+    pub y: i32,
+}
+
+/// Platform virtual touchscreen.
+///
+/// Each frame lists every finger currently down; fingers missing from the frame are lifted.
+pub(crate) trait TouchBackend {
+    fn frame(&mut self, points: &[TouchPoint]) -> io::Result<()>;
+}
+
+/// Open the native touch backend for this platform, if there is one and it is usable.
+pub(crate) fn open_backend(screen_w: u16, screen_h: u16) -> io::Result<Box<dyn TouchBackend>> {
+    #[cfg(target_os = "linux")]
+    {
+        Ok(Box::new(linux::UinputTouch::new(screen_w, screen_h)?))
+    }
+    #[cfg(target_os = "windows")]
+    {
+        let _ = (screen_w, screen_h);
+        Ok(Box::new(windows::InjectedTouch::new()?))
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "windows")))]
+    {
+        let _ = (screen_w, screen_h);
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "no virtual touchscreen backend on this platform",
+        ))
+    }
+}
+
+/// Assigns backend slots to client contact ids for the lifetime of each finger.
+#[derive(Debug, Default)]
+struct SlotTable {
+    slots: [Option<u8>; MAX_CONTACTS],
+}
+
+impl SlotTable {
+    fn find(&self, id: u8) -> Option<usize> {
+        self.slots.iter().position(|s| *s == Some(id))
+    }
+
+    /// Slot for `id`, allocating a free one if needed. Returns `(slot, newly_allocated)`.
+    fn get_or_insert(&mut self, id: u8) -> Option<(usize, bool)> {
+        if let Some(slot) = self.find(id) {
+            return Some((slot, false));
+        }
+        let slot = self.slots.iter().position(Option::is_none)?;
+        self.slots[slot] = Some(id);
+        Some((slot, true))
+    }
+
+    /// Free every slot whose contact is absent from `points`, returning the freed slots.
+    fn release_missing(&mut self, points: &[TouchPoint]) -> Vec<usize> {
+        let mut freed = Vec::new();
+        for (slot, entry) in self.slots.iter_mut().enumerate() {
+            if let Some(id) = *entry
+                && !points.iter().any(|p| p.id == id)
+            {
+                *entry = None;
+                freed.push(slot);
+            }
+        }
+        freed
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::{MAX_CONTACTS, SlotTable, TouchBackend, TouchPoint};
+    use crate::uinput::*;
+    use std::io;
+
+    /// Type-B multitouch touchscreen mapped 1:1 onto the primary display.
+    pub struct UinputTouch {
+        dev: UinputDevice,
+        slots: SlotTable,
+        next_tracking_id: i32,
+        touching: bool,
+    }
+
+    impl UinputTouch {
+        pub fn new(screen_w: u16, screen_h: u16) -> io::Result<Self> {
+            let max_x = i32::from(screen_w.max(1)) - 1;
+            let max_y = i32::from(screen_h.max(1)) - 1;
+            let axis = |code, max| AbsAxis {
+                code,
+                min: 0,
+                max,
+                resolution: 0,
+            };
+            let axes = [
+                axis(ABS_X, max_x),
+                axis(ABS_Y, max_y),
+                axis(ABS_MT_SLOT, MAX_CONTACTS as i32 - 1),
+                axis(ABS_MT_TRACKING_ID, u16::MAX as i32),
+                axis(ABS_MT_POSITION_X, max_x),
+                axis(ABS_MT_POSITION_Y, max_y),
+            ];
+            let dev = UinputDevice::create(&DeviceSpec {
+                name: "Penput Virtual Touchscreen",
+                props: &[INPUT_PROP_DIRECT],
+                keys: &[BTN_TOUCH, BTN_TOOL_FINGER],
+                rels: &[],
+                axes: &axes,
+            })?;
+            Ok(Self {
+                dev,
+                slots: SlotTable::default(),
+                next_tracking_id: 0,
+                touching: false,
+            })
+        }
+    }
+
+    impl TouchBackend for UinputTouch {
+        fn frame(&mut self, points: &[TouchPoint]) -> io::Result<()> {
+            for slot in self.slots.release_missing(points) {
+                self.dev.emit(EV_ABS, ABS_MT_SLOT, slot as i32);
+                self.dev.emit(EV_ABS, ABS_MT_TRACKING_ID, -1);
+            }
+            for p in points {
+                let Some((slot, new)) = self.slots.get_or_insert(p.id) else {
+                    continue;
+                };
+                self.dev.emit(EV_ABS, ABS_MT_SLOT, slot as i32);
+                if new {
+                    self.dev
+                        .emit(EV_ABS, ABS_MT_TRACKING_ID, self.next_tracking_id);
+                    self.next_tracking_id = (self.next_tracking_id + 1) & 0xffff;
+                }
+                self.dev.emit(EV_ABS, ABS_MT_POSITION_X, p.x);
+                self.dev.emit(EV_ABS, ABS_MT_POSITION_Y, p.y);
+            }
+
+            // Single-touch emulation for consumers that ignore MT axes.
+            let touching = !points.is_empty();
+            if touching != self.touching {
+                self.dev.emit(EV_KEY, BTN_TOUCH, i32::from(touching));
+                self.dev.emit(EV_KEY, BTN_TOOL_FINGER, i32::from(touching));
+                self.touching = touching;
+            }
+            if let Some(p) = points.first() {
+                self.dev.emit(EV_ABS, ABS_X, p.x);
+                self.dev.emit(EV_ABS, ABS_Y, p.y);
+            }
+            self.dev.sync()
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod windows {
+    use super::{MAX_CONTACTS, SlotTable, TouchBackend, TouchPoint};
+    use std::io;
+    use windows_sys::Win32::UI::Input::Pointer::{
+        InitializeTouchInjection, InjectTouchInput, POINTER_FLAG_DOWN, POINTER_FLAG_INCONTACT,
+        POINTER_FLAG_INRANGE, POINTER_FLAG_UP, POINTER_FLAG_UPDATE, POINTER_TOUCH_INFO,
+        TOUCH_FEEDBACK_DEFAULT,
+    };
+    use windows_sys::Win32::UI::WindowsAndMessaging::{PT_TOUCH, TOUCH_MASK_CONTACTAREA};
+
+    /// Half the side of the reported contact rectangle, in pixels.
+    const CONTACT_RADIUS: i32 = 2;
+
+    /// Touch injection (Windows 8+); Windows sees these as a real touchscreen.
+    pub struct InjectedTouch {
+        slots: SlotTable,
+        /// Last reported position per slot, needed to send the final `UP`.
+        last: [Option<(i32, i32)>; MAX_CONTACTS],
+    }
+
+    impl InjectedTouch {
+        pub fn new() -> io::Result<Self> {
+            // SAFETY: plain FFI call.
+            if unsafe { InitializeTouchInjection(MAX_CONTACTS as u32, TOUCH_FEEDBACK_DEFAULT) } == 0
+            {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Self {
+                slots: SlotTable::default(),
+                last: [None; MAX_CONTACTS],
+            })
+        }
+    }
+
+    fn contact(slot: usize, x: i32, y: i32, flags: u32) -> POINTER_TOUCH_INFO {
+        let mut info = POINTER_TOUCH_INFO::default();
+        info.pointerInfo.pointerType = PT_TOUCH;
+        info.pointerInfo.pointerId = slot as u32;
+        info.pointerInfo.pointerFlags = flags;
+        info.pointerInfo.ptPixelLocation.x = x;
+        info.pointerInfo.ptPixelLocation.y = y;
+        info.touchMask = TOUCH_MASK_CONTACTAREA;
+        info.rcContact.left = x - CONTACT_RADIUS;
+        info.rcContact.right = x + CONTACT_RADIUS;
+        info.rcContact.top = y - CONTACT_RADIUS;
+        info.rcContact.bottom = y + CONTACT_RADIUS;
+        info
+    }
+
+    impl TouchBackend for InjectedTouch {
+        fn frame(&mut self, points: &[TouchPoint]) -> io::Result<()> {
+            let mut infos = Vec::with_capacity(MAX_CONTACTS);
+            for slot in self.slots.release_missing(points) {
+                if let Some((x, y)) = self.last[slot].take() {
+                    infos.push(contact(slot, x, y, POINTER_FLAG_UP));
+                }
+            }
+            for p in points {
+                let Some((slot, new)) = self.slots.get_or_insert(p.id) else {
+                    continue;
+                };
+                let flags = POINTER_FLAG_INRANGE
+                    | POINTER_FLAG_INCONTACT
+                    | if new {
+                        POINTER_FLAG_DOWN
+                    } else {
+                        POINTER_FLAG_UPDATE
+                    };
+                infos.push(contact(slot, p.x, p.y, flags));
+                self.last[slot] = Some((p.x, p.y));
+            }
+            if infos.is_empty() {
+                return Ok(());
+            }
+            // SAFETY: `infos` is fully initialized and outlives the call.
+            if unsafe { InjectTouchInput(infos.len() as u32, infos.as_ptr()) } == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        }
+    }
+}
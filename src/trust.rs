@@ -0,0 +1,334 @@
+use crate::connection::{Device, Role};
+use anyhow::{Context, Result};
+use ed25519_dalek::{Signature, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{ErrorKind, Write};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::warn;
+
+/// Length of a device ID. The ID is the device's only credential, so it must not be
+/// guessable.
+const ID_LEN: std::ops::RangeInclusive<usize> = 16..=128;
+
+/// How much of a device ID the console shows.
+const SHORT_ID_LEN: usize = 8;
+
+/// How far the time in a signed handshake may be from the server's clock.
+const SIGNATURE_WINDOW: Duration = Duration::from_secs(2 * 60);
+
+/// Where the server keeps what it remembers between runs: `$XDG_CONFIG_HOME/penput`,
+/// by default `~/.config/penput`.
+pub fn config_dir() -> Option<PathBuf> {
+    let config = match std::env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => {
+            let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"))?;
+            PathBuf::from(home).join(".config")
+        }
+    };
+    Some(config.join("penput"))
+}
+
+/// Where trusted devices are kept unless `--trusted` says otherwise:
+/// `trusted.toml` in [`config_dir`].
+pub fn default_path() -> Option<PathBuf> {
+    Some(config_dir()?.join("trusted.toml"))
+}
+
+/// Whether a client-generated `id` can name a device: 16 to 128 ASCII letters, digits,
+/// `-` or `_`.
+pub fn is_valid_id(id: &str) -> bool {
+    ID_LEN.contains(&id.len())
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
+
+/// The start of a device ID, enough to tell devices apart without printing the secret.
+pub fn short_id(id: &str) -> &str {
+    &id[..id.len().min(SHORT_ID_LEN)]
+}
+
+/// A signed handshake, sent in place of a device ID as
+/// `ed25519:<public key hex>:<unix ms>:<signature hex>`. The device proves it holds the
+/// key by signing `penput-hello:<unix ms>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Proof {
+    key: [u8; 32],
+    ts: i64,
+    sig: [u8; 64],
+}
+
+impl Proof {
+    pub fn parse(s: &str) -> Option<Self> {
+        let mut parts = s.strip_prefix("ed25519:")?.split(':');
+        let key = decode_hex(parts.next()?)?.try_into().ok()?;
+        let ts = parts.next()?.parse().ok()?;
+        let sig = decode_hex(parts.next()?)?.try_into().ok()?;
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(Self { key, ts, sig })
+    }
+
+    /// The ID a device that signs with this key is trusted under: the key in hex.
+    pub fn id(&self) -> String {
+        self.key.iter().map(|b| format!("{b:02x}")).collect()
+    }
+}
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// A device the operator approved before.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrustedDevice {
+    /// What it was approved as. A device trusted to view still has to ask to control.
+    pub role: Role,
+    /// The ID is an Ed25519 public key, and the device has to sign its handshakes with it;
+    /// sending the ID alone doesn't get it in.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub signed: bool,
+    /// What it last called itself.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub platform: Option<String>,
+    /// When it was first approved, local time.
+    pub added: String,
+    /// Where it last connected from.
+    pub last_addr: IpAddr,
+    /// When it last connected, local time.
+    pub last_seen: String,
+}
+
+impl TrustedDevice {
+    fn seen(&mut self, addr: IpAddr, info: &Device, now: String) {
+        if info.name.is_some() {
+            self.name.clone_from(&info.name);
+        }
+        if info.platform.is_some() {
+            self.platform.clone_from(&info.platform);
+        }
+        self.last_addr = addr;
+        self.last_seen = now;
+    }
+
+    /// What the device calls itself, as [`Device::label`] puts it.
+    pub fn label(&self) -> Option<String> {
+        let device = Device {
+            name: self.name.clone(),
+            platform: self.platform.clone(),
+            ..Device::default()
+        };
+        device.label()
+    }
+}
+
+/// `trusted.toml`:
+///
+/// ```toml
+/// [devices.3f0c9a4e2b7d41e8a6c5f1d09b8e7a21]
+/// role = "controller"
+/// name = "Dana's phone"
+/// platform = "iPhone 15"
+/// added = "2026-10-17 09:12"
+/// last_addr = "192.168.1.23"
+/// last_seen = "2026-10-17 18:40"
+///
+/// [devices.d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a]
+/// role = "controller"
+/// signed = true
+/// added = "2026-10-17 09:30"
+/// last_addr = "192.168.1.40"
+/// last_seen = "2026-10-17 09:30"
+/// ```
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct TrustedFile {
+    #[serde(default)]
+    devices: BTreeMap<String, TrustedDevice>,
+}
+
+/// Devices let in again without asking, keyed by the ID each one generated for itself.
+/// Every change is written back to the file right away.
+#[derive(Debug, Default)]
+pub struct TrustStore {
+    /// `None` when devices aren't remembered (`--trusted off`).
+    path: Option<PathBuf>,
+    devices: Mutex<BTreeMap<String, TrustedDevice>>,
+    /// Signatures accepted within [`SIGNATURE_WINDOW`], by their time, so none is
+    /// accepted twice.
+    seen: Mutex<HashMap<[u8; 64], i64>>,
+}
+
+impl TrustStore {
+    /// Load the devices trusted in `path`. A missing file is an empty store.
+    pub fn load(path: PathBuf) -> Result<Self> {
+        let devices = match std::fs::read_to_string(&path) {
+            Ok(raw) => {
+                let file: TrustedFile = toml::from_str(&raw)
+                    .with_context(|| format!("Failed to parse {}", path.display()))?;
+                file.devices
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => BTreeMap::new(),
+            Err(err) => {
+                return Err(err).with_context(|| format!("Failed to read {}", path.display()));
+            }
+        };
+        Ok(Self {
+            path: Some(path),
+            devices: Mutex::new(devices),
+            seen: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Whether `proof` is a recent signature by its key that wasn't used before, so a
+    /// recorded handshake can't be replayed.
+    pub fn verify(&self, proof: &Proof) -> bool {
+        let now = chrono::Utc::now().timestamp_millis();
+        let window = SIGNATURE_WINDOW.as_millis() as u64;
+        // `ts` comes from the client, so no arithmetic on it that could overflow.
+        if proof.ts.abs_diff(now) > window {
+            return false;
+        }
+        let Ok(key) = VerifyingKey::from_bytes(&proof.key) else {
+            return false;
+        };
+        let message = format!("penput-hello:{}", proof.ts);
+        let signature = Signature::from_bytes(&proof.sig);
+        if key.verify_strict(message.as_bytes(), &signature).is_err() {
+            return false;
+        }
+        let mut seen = self.seen.lock().unwrap();
+        seen.retain(|_, ts| ts.abs_diff(now) <= window);
+        seen.insert(proof.sig, proof.ts).is_none()
+    }
+
+    /// Whether device `id` may connect as `role` without asking. Notes where it
+    /// connected from, and what it calls itself, if so.
+    pub fn admit(&self, id: &str, role: Role, addr: IpAddr, info: &Device) -> bool {
+        let mut devices = self.devices.lock().unwrap();
+        let Some(device) = devices.get_mut(id) else {
+            return false;
+        };
+        if device.role == Role::Viewer && role != Role::Viewer {
+            return false;
+        }
+        // A key's ID is public, so only a signed handshake counts.
+        if device.signed && !info.signed {
+            return false;
+        }
+        device.seen(addr, info, now());
+        self.save(&devices);
+        true
+    }
+
+    /// Trust device `id`, just approved as `role` from `addr`.
+    pub fn remember(&self, id: &str, role: Role, addr: IpAddr, info: &Device) {
+        if self.path.is_none() || !is_valid_id(id) {
+            return;
+        }
+        let mut devices = self.devices.lock().unwrap();
+        let now = now();
+        let device = devices
+            .entry(id.to_owned())
+            .or_insert_with(|| TrustedDevice {
+                role,
+                signed: false,
+                name: None,
+                platform: None,
+                added: now.clone(),
+                last_addr: addr,
+                last_seen: now.clone(),
+            });
+        if role == Role::Controller {
+            device.role = role;
+        }
+        if info.signed {
+            device.signed = true;
+        }
+        device.seen(addr, info, now);
+        self.save(&devices);
+    }
+
+    /// The trusted devices, by ID.
+    pub fn devices(&self) -> Vec<(String, TrustedDevice)> {
+        let devices = self.devices.lock().unwrap();
+        devices
+            .iter()
+            .map(|(id, d)| (id.clone(), d.clone()))
+            .collect()
+    }
+
+    /// Stop trusting device `id`. Returns false if it wasn't trusted.
+    pub fn revoke(&self, id: &str) -> bool {
+        let mut devices = self.devices.lock().unwrap();
+        if devices.remove(id).is_none() {
+            return false;
+        }
+        self.save(&devices);
+        true
+    }
+
+    /// Stop trusting every device. Returns how many were.
+    pub fn revoke_all(&self) -> usize {
+        let mut devices = self.devices.lock().unwrap();
+        let count = devices.len();
+        devices.clear();
+        self.save(&devices);
+        count
+    }
+
+    /// Write the devices back. Failures are only logged; the devices stay trusted until
+    /// the server stops.
+    fn save(&self, devices: &BTreeMap<String, TrustedDevice>) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let file = TrustedFile {
+            devices: devices.clone(),
+        };
+        let written = toml::to_string(&file)
+            .map_err(anyhow::Error::from)
+            .and_then(|raw| write_private(path, raw.as_bytes()));
+        if let Err(err) = written {
+            warn!(
+                "Failed to save trusted devices to {}: {err:#}",
+                path.display()
+            );
+        }
+    }
+}
+
+/// Write `contents` to `path`, readable only by the current user where the platform
+/// allows, for files that let devices in or hold private keys.
+pub fn write_private(path: &Path, contents: &[u8]) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(contents)?;
+    Ok(())
+}
+
+fn now() -> String {
+    chrono::Local::now().format("%Y-%m-%d %H:%M").to_string()
+}
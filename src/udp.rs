@@ -1,29 +1,67 @@
-use crate::connection::{ApprovalBroker, ConnectionSlot};
-use crate::mouse::MouseController;
+use crate::actions::{ActionQueue, ActionRegistry};
+use crate::air::AirMouse;
+use crate::browser;
+use crate::clipboard::Clipboard;
+use crate::clock::ClockSync;
+use crate::connection::{
+    ApprovalBroker, Claim, Decision, Device, Listing, Role, SessionEvent, SessionManager,
+    Transport,
+};
+use crate::cursor::CursorStream;
+use crate::gesture::GestureRecognizer;
+use crate::ipfilter::IpFilter;
+use crate::keyboard::{HeldKeys, StickyModifiers};
+use crate::mapping::{AspectMode, Homography};
+use crate::mouse::{HeldButtons, MouseController, SessionMapping};
+use crate::noise::{Encryption, SecureSocket};
+use crate::pointer::PointerProfile;
+use crate::quic::Datagrams;
+use crate::quality::Quality;
+use crate::proto::{self, ClientMessage, ControlAction, Goodbye, Nack, ServerMessage};
+use crate::ratelimit::RateLimiter;
+use crate::replay::SeqWindow;
+use std::collections::{HashMap, HashSet};
+use std::io;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use tokio::net::UdpSocket;
+use tokio::sync::mpsc;
 use tokio::time::{self, Duration, Instant};
 use tracing::{info, warn};
 
-// UDP packet types (client -> server)
-const MSG_HELLO: u8 = 0x01; // [type=1][w:u16be][h:u16be]
-const MSG_MOVE: u8 = 0x02; // [type=2][x:u16be][y:u16be]
-const MSG_PING: u8 = 0x03; // [type=3][t:u64be]
-
-// UDP packet types (server -> client)
-const MSG_ACCEPT: u8 = 0x10; // [type=0x10][remote_w:u16be][remote_h:u16be]
-const MSG_REJECT: u8 = 0x11; // [type=0x11]
-const MSG_BUSY: u8 = 0x12; // [type=0x12]
-const MSG_PONG: u8 = 0x13; // [type=0x13][t:u64be]
-
 const SESSION_TIMEOUT: Duration = Duration::from_secs(5);
 
+/// A session heard nothing from for this long is sent a KEEPALIVE, so a client that is
+/// still there answers before [`SESSION_TIMEOUT`].
+const KEEPALIVE_AFTER: Duration = Duration::from_secs(2);
+
 #[derive(Clone)]
 pub struct UdpState {
-    pub slot: Arc<ConnectionSlot>,
+    pub sessions: Arc<SessionManager>,
     pub broker: ApprovalBroker,
     pub mouse: Arc<MouseController>,
+    pub actions: Arc<ActionRegistry>,
+    pub clipboard: Clipboard,
+    pub encryption: Encryption,
+    pub ip_filter: Arc<IpFilter>,
+    /// A client sending no input for this long must be approved again
+    /// (`--reapprove-after`).
+    pub reapprove_after: Option<Duration>,
+    /// Packets per second one address may send (`--udp-rate-limit`); `None` for no limit.
+    pub rate_limit: Option<u32>,
+}
+
+/// A HELLO or TAKEOVER from a new client, once the approval prompt has been answered.
+struct Decided {
+    addr: SocketAddr,
+    w: u16,
+    h: u16,
+    mode: AspectMode,
+    /// Protocol version agreed on.
+    version: u8,
+    device: Device,
+    /// The place if the prompt was approved, otherwise how it ended.
+    claim: Result<Claim, Decision>,
 }
 
 struct UdpSession {
@@ -31,21 +69,64 @@ struct UdpSession {
     client_w: u16,
     client_h: u16,
     last_seen: Instant,
+    /// When the client last sent input, for `--reapprove-after`.
+    last_input: Instant,
+    held: HeldButtons,
+    held_keys: HeldKeys,
+    sticky: StickyModifiers,
+    gestures: GestureRecognizer,
+    air: AirMouse,
+    pointer: PointerProfile,
+    /// Display, region, transform and the like, put back when control passes here.
+    mapping: SessionMapping,
+    cursor_stream: Option<CursorStream>,
+    in_control: bool,
+    claim: Claim,
+    listing: Listing,
+    device: Device,
+    seq: SeqWindow,
+    clock: ClockSync,
+    quality: Quality,
+    /// Macros asked for with ACTION, run one after another.
+    actions: ActionQueue,
 }
 
-/// Start UDP server on given port.
+/// Run the UDP server on `socket`.
 ///
 /// This path is intended for the iOS native client to avoid WebKit-induced stutter.
-/// The server enforces a single active client using the shared ConnectionSlot.
-pub async fn serve_udp(state: UdpState, port: u16) -> anyhow::Result<()> {
-    let socket = UdpSocket::bind(("0.0.0.0", port)).await?;
-    info!("UDP server listening on 0.0.0.0:{}", port);
+/// How many clients may connect, and whose input counts, is up to the SessionManager.
+pub async fn serve_udp(
+    state: UdpState,
+    socket: std::net::UdpSocket,
+    quic: Datagrams,
+) -> anyhow::Result<()> {
+    let addr = socket.local_addr()?;
+    let socket = SecureSocket::new(
+        UdpSocket::from_std(socket)?,
+        state.encryption,
+        state.ip_filter.clone(),
+        RateLimiter::new(state.rate_limit),
+    )?;
+    let mut socket = Link { socket, quic };
+    info!("UDP server listening on {}", addr);
 
-    let mut buf = [0u8; 64];
+    // Large enough for a clipboard packet filling one Ethernet frame.
+    let mut buf = [0u8; 1500];
     let mut tick = time::interval(Duration::from_secs(1));
-    let mut session: Option<UdpSession> = None;
+    let mut sessions: HashMap<SocketAddr, UdpSession> = HashMap::new();
+    let mut pending: HashSet<SocketAddr> = HashSet::new();
+    let (decided_tx, mut decided_rx) = mpsc::channel::<Decided>(16);
+    let mut display_changes = state.mouse.subscribe_displays();
+    let mut control = state.sessions.subscribe_controller();
+    let mut session_events = state.sessions.subscribe_events();
 
     loop {
+        let gesture_deadline = sessions.values().filter_map(|s| s.gestures.deadline()).min();
+        let cursor_deadline = sessions
+            .values()
+            .filter_map(|s| s.cursor_stream.as_ref())
+            .map(CursorStream::deadline)
+            .min();
         tokio::select! {
             res = socket.recv_from(&mut buf) => {
                 let (len, addr) = match res {
@@ -63,107 +144,611 @@ pub async fn serve_udp(state: UdpState, port: u16) -> anyhow::Result<()> {
                 let now = Instant::now();
                 let pkt = &buf[..len];
 
-                match pkt[0] {
-                    MSG_HELLO => {
-                        if len < 5 {
-                            continue;
+                // Say why a packet won't be acted on, so client bugs don't go unnoticed.
+                let msg = match ClientMessage::decode(pkt) {
+                    // Only reach here with encryption off; silence tells the client so.
+                    Ok(ClientMessage::Encrypted) => continue,
+                    Ok(ClientMessage::Hello(hello)) => ClientMessage::Hello(hello),
+                    Ok(_) if !sessions.contains_key(&addr) => {
+                        socket.send(ServerMessage::Nack { reason: Nack::Unauthorized, ty: pkt[0] }, addr).await;
+                        continue;
+                    }
+                    Ok(msg) => msg,
+                    Err(reason) => {
+                        if let Some(s) = sessions.get_mut(&addr) {
+                            s.quality.dropped();
                         }
+                        socket.send(ServerMessage::Nack { reason, ty: pkt[0] }, addr).await;
+                        continue;
+                    }
+                };
 
-                        let w = u16::from_be_bytes([pkt[1], pkt[2]]);
-                        let h = u16::from_be_bytes([pkt[3], pkt[4]]);
+                if let Some(kind) = msg.input_kind()
+                    && let Some(s) = sessions.get_mut(&addr)
+                {
+                    s.last_input = now;
+                    if !state.sessions.take_turn(addr, &state.mouse, &s.mapping) {
+                        // Still connected, just not in control.
+                        s.last_seen = now;
+                        s.quality.dropped();
+                        socket.send(ServerMessage::Nack { reason: Nack::NotInControl, ty: pkt[0] }, addr).await;
+                        continue;
+                    }
+                    s.listing.count(kind);
+                }
 
-                        let (screen_w, screen_h) = state.mouse.screen_size();
-                        let screen_w_be = screen_w.to_be_bytes();
-                        let screen_h_be = screen_h.to_be_bytes();
-                        let accept = [MSG_ACCEPT, screen_w_be[0], screen_w_be[1], screen_h_be[0], screen_h_be[1]];
+                if let ClientMessage::Hello(hello) = msg {
+                    let hello = *hello;
+                    let (w, h, mode) = (hello.w, hello.h, hello.mode);
+                    let Some(version) = proto::negotiate(hello.version) else {
+                        socket.send(ServerMessage::UnsupportedVersion, addr).await;
+                        continue;
+                    };
 
-                        match session.as_mut() {
-                            Some(s) if s.addr == addr => {
+                    match sessions.get_mut(&addr) {
+                        Some(s) => {
+                            s.client_w = w;
+                            s.client_h = h;
+                            s.last_seen = now;
+                            // A client that says hello again counts from scratch.
+                            s.seq.reset();
+                            s.set_aspect_mode(&state, mode);
+                            socket.send(accept(&state.mouse, s.mapping.display, mode, version), addr).await;
+                            let current = s.mapping.display.unwrap_or_else(|| state.mouse.default_display());
+                            let displays = state.mouse.displays();
+                            socket.send(ServerMessage::Displays { displays: &displays, current }, addr).await;
+                        }
+                        // Still waiting for the prompt; the answer comes once it is decided.
+                        None if pending.contains(&addr) => {}
+                        None if state.sessions.is_halted() => {
+                            socket.send(ServerMessage::Reject, addr).await;
+                        }
+                        None => {
+                            let device = hello.device;
+                            // A known token picks the session back up without asking, e.g.
+                            // after iOS moved the app to a new source port.
+                            if let Some(token) = hello.token
+                                && let Some(resumed) = state.sessions.resume_session(token, addr)
+                            {
+                                let mut s = match resumed.from.and_then(|from| sessions.remove(&from)) {
+                                    Some(mut s) => {
+                                        s.claim = resumed.claim;
+                                        s.listing = state.sessions.list(addr, socket.transport(addr), Role::Controller, &device);
+                                        s.device = device;
+                                        s
+                                    }
+                                    None => {
+                                        let listing = state.sessions.list(addr, socket.transport(addr), Role::Controller, &device);
+                                        let actions = ActionQueue::new(state.actions.clone(), state.mouse.clone());
+                                        UdpSession::new(addr, w, h, resumed.claim, listing, device, actions)
+                                    }
+                                };
+                                s.addr = addr;
+                                s.seq.reset();
                                 s.client_w = w;
                                 s.client_h = h;
                                 s.last_seen = now;
-                                let _ = socket.send_to(&accept, addr).await;
+                                state.sessions.activate(addr);
+                                s.in_control = state.sessions.in_control(addr);
+                                info!("↩ UDP session resumed by {}", s.device.describe(addr));
+                                s.set_aspect_mode(&state, mode);
+                                welcome(&socket, &state, &s, mode, version, token).await;
+                                sessions.insert(addr, s);
+                                continue;
                             }
-                            Some(_) => {
-                                let _ = socket.send_to(&[MSG_BUSY], addr).await;
+
+                            let claim = state.sessions.claim(addr);
+                            if claim.is_none() && !hello.takeover {
+                                socket.send(ServerMessage::Busy, addr).await;
+                                continue;
+                            }
+
+                            // Ask on the side so clients already connected aren't stalled.
+                            pending.insert(addr);
+                            let broker = state.broker.clone();
+                            let sessions = state.sessions.clone();
+                            let decided = decided_tx.clone();
+                            tokio::spawn(async move {
+                                let claim = match claim {
+                                    Some(claim) => {
+                                        match broker.request_approval(addr, Role::Controller, &device).await {
+                                            Decision::Approved => Ok(claim),
+                                            decision => Err(decision),
+                                        }
+                                    }
+                                    None => broker.request_takeover(&sessions, addr, &device).await,
+                                };
+                                let hello = Decided { addr, w, h, mode, version, device, claim };
+                                let _ = decided.send(hello).await;
+                            });
+                        }
+                    }
+                    continue;
+                }
+
+                let Some(s) = sessions.get_mut(&addr) else {
+                    continue;
+                };
+                s.last_seen = now;
+
+                match msg {
+                    ClientMessage::Move { x, y, seq, t } => {
+                        if let Some(seq) = seq {
+                            s.quality.numbered(seq);
+                        }
+                        // Stale or replayed; a late move would jump the cursor backwards.
+                        if !s.seq.check_move(seq) || !s.clock.check_move(t) {
+                            s.quality.dropped();
+                            continue;
+                        }
+                        let (w, h) = (s.client_w, s.client_h);
+                        // Stamped moves are placed at the time they were made, for resampling.
+                        let now = now.into_std();
+                        s.quality.moved(1);
+                        match t.and_then(|t| s.clock.local(t, now)) {
+                            Some(at) => {
+                                s.clock.arrived(at, now);
+                                let _ = state.mouse.move_absolute_batch(w, h, [(at, x, y)]);
                             }
                             None => {
-                                if !state.slot.try_claim(addr).await {
-                                    let _ = socket.send_to(&[MSG_BUSY], addr).await;
-                                    continue;
-                                }
-
-                                let approved = state.broker.request_approval(addr).await;
-                                if !approved {
-                                    state.slot.release().await;
-                                    let _ = socket.send_to(&[MSG_REJECT], addr).await;
-                                    continue;
-                                }
-
-                                session = Some(UdpSession {
-                                    addr,
-                                    client_w: w,
-                                    client_h: h,
-                                    last_seen: now,
-                                });
-
-                                info!("✓ UDP client approved: {} ({}x{})", addr, w, h);
-                                let _ = socket.send_to(&accept, addr).await;
+                                let _ = state.mouse.move_absolute(w, h, x, y);
                             }
                         }
                     }
-                    MSG_MOVE => {
-                        if len < 5 {
-                            continue;
+                    ClientMessage::Ping { t, rtt_ms } => {
+                        let rtt = rtt_ms.map(Duration::from_millis);
+                        s.clock.ping(t, rtt, now.into_std());
+                        if let Some(rtt) = rtt {
+                            s.quality.rtt(rtt);
+                            state.sessions.report_rtt(addr, rtt);
                         }
-
-                        let Some(s) = session.as_mut() else {
-                            continue;
+                        if let Some(latency) = s.clock.latency() {
+                            state.sessions.report_latency(addr, latency);
+                        }
+                        // Echo the timestamp back for RTT measurement.
+                        socket.send(ServerMessage::Pong { t }, addr).await;
+                    }
+                    ClientMessage::Click { button, count, seq, t } => {
+                        let now = now.into_std();
+                        if let Some(at) = t.and_then(|t| s.clock.local(t, now)) {
+                            s.clock.arrived(at, now);
+                        }
+                        if let Some(seq) = seq {
+                            s.quality.numbered(seq);
+                        }
+                        if s.seq.check(seq) {
+                            let _ = state.mouse.click(button, count);
+                        } else {
+                            s.quality.dropped();
+                        }
+                    }
+                    ClientMessage::ButtonDown { button } => {
+                        let _ = state.mouse.press_held(&mut s.held, button);
+                    }
+                    ClientMessage::ButtonUp { button } => {
+                        let _ = state.mouse.release_held(&mut s.held, button);
+                    }
+                    ClientMessage::MoveRel { dx, dy } => {
+                        let _ = state.mouse.move_relative(&mut s.pointer, dx, dy);
+                    }
+                    ClientMessage::Key { code, down } => {
+                        let _ = match down {
+                            Some(true) => state.mouse.press_key(&mut s.held_keys, code),
+                            Some(false) => state.mouse.release_key(&mut s.held_keys, code),
+                            None => state.mouse.tap_key_sticky(&mut s.sticky, code),
                         };
-                        if s.addr != addr {
-                            continue;
+                    }
+                    ClientMessage::KeyHold { code } => {
+                        let _ = state.mouse.hold_key(&mut s.held_keys, code);
+                    }
+                    ClientMessage::KeyRelease { code } => {
+                        let _ = state.mouse.release_key(&mut s.held_keys, code);
+                    }
+                    ClientMessage::StickyKeys { enabled } => s.sticky.set_enabled(enabled),
+                    ClientMessage::Region { region } => {
+                        s.mapping.region = region;
+                        let _ = state.mouse.confine(region);
+                    }
+                    ClientMessage::MapTarget { target } => {
+                        s.mapping.target = target;
+                        let _ = state.mouse.set_map_target(target);
+                    }
+                    ClientMessage::Transform(transform) => {
+                        s.mapping.transform = transform;
+                        let _ = state.mouse.set_transform(transform);
+                    }
+                    ClientMessage::Calibrate { points } => {
+                        // Zero points clears; four must fit or nothing changes.
+                        let fit = points.as_slice().try_into().ok().and_then(|points| {
+                            Homography::from_client_points(points, s.client_w, s.client_h)
+                        });
+                        let ok = (points.is_empty() || fit.is_some()) && state.mouse.calibrate(fit).is_ok();
+                        if ok {
+                            s.mapping.calibration = fit;
                         }
-
-                        s.last_seen = now;
-                        let x = u16::from_be_bytes([pkt[1], pkt[2]]);
-                        let y = u16::from_be_bytes([pkt[3], pkt[4]]);
-
-                        if s.client_w > 0 && s.client_h > 0 {
-                            let _ = state.mouse.move_absolute(s.client_w, s.client_h, x, y);
+                        socket.send(ServerMessage::Calibration(ok), addr).await;
+                    }
+                    ClientMessage::Settings(settings) => {
+                        if let Some(smoothing) = settings.smoothing {
+                            s.mapping.smoothing = Some(smoothing);
+                            let _ = state.mouse.set_smoothing(smoothing);
                         }
+                        s.pointer.update(settings);
                     }
-                    MSG_PING => {
-                        if len < 9 {
-                            continue;
+                    ClientMessage::Display { index } => {
+                        // An unknown index leaves the current display in place.
+                        if index < state.mouse.displays().len() {
+                            s.mapping.display = Some(index);
                         }
-
-                        let Some(s) = session.as_mut() else {
-                            continue;
-                        };
-                        if s.addr != addr {
-                            continue;
+                        if let Some(display) = state.mouse.select_display(s.mapping.display) {
+                            socket.send(ServerMessage::DisplayInfo(&display), addr).await;
                         }
+                    }
+                    ClientMessage::NextDisplay => {
+                        let current = s.mapping.display.unwrap_or_else(|| state.mouse.default_display());
+                        s.mapping.display = Some((current + 1) % state.mouse.displays().len());
+                        if let Some(display) = state.mouse.select_display(s.mapping.display) {
+                            socket.send(ServerMessage::DisplayInfo(&display), addr).await;
+                        }
+                    }
+                    ClientMessage::CursorPos => {
+                        let pos = state.mouse.cursor_position().await;
+                        socket.send(ServerMessage::Cursor(pos), addr).await;
+                    }
+                    ClientMessage::CursorStream { interval_ms } => {
+                        s.cursor_stream = CursorStream::new(Duration::from_millis(interval_ms));
+                    }
+                    ClientMessage::Control { action: ControlAction::Request } => state.sessions.request_control(addr),
+                    ClientMessage::Control { action: ControlAction::Release } => state.sessions.release_control(addr),
+                    ClientMessage::Shortcut { modifiers, key } => {
+                        let _ = state.mouse.shortcut(&modifiers, key);
+                    }
+                    ClientMessage::Media { action } => {
+                        let _ = state.mouse.tap_key(action.key());
+                    }
+                    ClientMessage::Stylus(sample) => {
+                        let _ = state.mouse.stylus(s.client_w, s.client_h, sample);
+                    }
+                    ClientMessage::Touch { contacts } => {
+                        let _ = state.mouse.touch(s.client_w, s.client_h, &contacts);
+                    }
+                    ClientMessage::Touchpad { contacts } => {
+                        for action in s.gestures.frame(&contacts, now) {
+                            action.apply(&state.mouse, &mut s.held, &mut s.pointer);
+                            if let Some(feedback) = action.feedback() {
+                                socket.send(ServerMessage::Feedback(feedback), addr).await;
+                            }
+                        }
+                    }
+                    ClientMessage::Motion(sample) => {
+                        if let Some((dx, dy)) = s.air.sample(sample) {
+                            let _ = state.mouse.move_relative(&mut s.pointer, dx, dy);
+                        }
+                    }
+                    ClientMessage::Action { name } => {
+                        if !s.actions.push(name) {
+                            s.quality.dropped();
+                            socket.send(ServerMessage::Nack { reason: Nack::Busy, ty: pkt[0] }, addr).await;
+                        }
+                    }
+                    ClientMessage::ClipboardSet { text } => state.clipboard.set_text(text),
+                    ClientMessage::OpenUrl { url } => browser::open_url(&url),
+                    ClientMessage::Gamepad(pad) => {
+                        let _ = state.mouse.gamepad(pad);
+                    }
+                    ClientMessage::Scroll { dx, dy } => {
+                        let (dx, dy) = s.pointer.scroll(dx, dy);
+                        let _ = state.mouse.scroll(dx, dy);
+                    }
+                    ClientMessage::Fling { vx, vy } => {
+                        let (vx, vy) = s.pointer.fling(vx, vy);
+                        let _ = state.mouse.fling(vx, vy);
+                    }
+                    // No UDP packet decodes to these.
+                    ClientMessage::Hello(_)
+                    | ClientMessage::Init { .. }
+                    | ClientMessage::Text { .. }
+                    | ClientMessage::Displays
+                    | ClientMessage::RtcOffer { .. }
+                    | ClientMessage::RtcCandidate { .. }
+                    | ClientMessage::Encrypted => {}
+                }
+            }
+            Some(hello) = decided_rx.recv() => {
+                let addr = hello.addr;
+                pending.remove(&addr);
+                // The emergency stop may have been hit while the prompt was open.
+                let claim = match hello.claim {
+                    Ok(claim) if !state.sessions.is_halted() => claim,
+                    Err(Decision::TimedOut) => {
+                        socket.send(ServerMessage::Timeout, addr).await;
+                        continue;
+                    }
+                    _ => {
+                        socket.send(ServerMessage::Reject, addr).await;
+                        continue;
+                    }
+                };
+                state.sessions.activate(addr);
+                let token = state.sessions.issue_token(addr);
+                let listing = state.sessions.list(addr, socket.transport(addr), Role::Controller, &hello.device);
+                let from = hello.device.describe(addr);
+                let actions = ActionQueue::new(state.actions.clone(), state.mouse.clone());
+                let mut s = UdpSession::new(addr, hello.w, hello.h, claim, listing, hello.device, actions);
+                s.quality = Quality::new(socket.rate_limited(addr));
+                s.in_control = state.sessions.in_control(addr);
 
-                        s.last_seen = now;
-
-                        // Echo the timestamp back for RTT measurement.
-                        let mut out = [0u8; 9];
-                        out[0] = MSG_PONG;
-                        out[1..9].copy_from_slice(&pkt[1..9]);
-                        let _ = socket.send_to(&out, addr).await;
+                info!("✓ UDP client approved: {} ({}x{})", from, hello.w, hello.h);
+                s.set_aspect_mode(&state, hello.mode);
+                welcome(&socket, &state, &s, hello.mode, hello.version, token).await;
+                sessions.insert(addr, s);
+            }
+            _ = time::sleep_until(gesture_deadline.unwrap_or_else(Instant::now)),
+                if gesture_deadline.is_some() =>
+            {
+                let now = Instant::now();
+                for s in sessions.values_mut() {
+                    if let Some(action) = s.gestures.poll(now) {
+                        action.apply(&state.mouse, &mut s.held, &mut s.pointer);
+                        if let Some(feedback) = action.feedback() {
+                            socket.send(ServerMessage::Feedback(feedback), s.addr).await;
+                        }
+                    }
+                }
+            }
+            _ = time::sleep_until(cursor_deadline.unwrap_or_else(Instant::now)),
+                if cursor_deadline.is_some() =>
+            {
+                let pos = state.mouse.cursor_position().await;
+                let now = Instant::now();
+                for s in sessions.values_mut() {
+                    if let Some(stream) = s.cursor_stream.as_mut()
+                        && stream.deadline() <= now
+                        && let Some(pos) = stream.update(pos)
+                    {
+                        socket.send(ServerMessage::Cursor(Some(pos)), s.addr).await;
+                    }
+                }
+            }
+            Ok(()) = display_changes.recv() => {
+                let displays = state.mouse.displays();
+                for s in sessions.values_mut() {
+                    if s.mapping.display >= Some(displays.len()) {
+                        s.mapping.display = None;
+                    }
+                    let current = s.mapping.display.unwrap_or_else(|| state.mouse.default_display());
+                    if let Some(display) = displays.get(current) {
+                        let (w, h) = display.size();
+                        socket.send(ServerMessage::Screen { w, h }, s.addr).await;
+                    }
+                    socket.send(ServerMessage::Displays { displays: &displays, current }, s.addr).await;
+                }
+            }
+            Ok(()) = control.changed(), if state.sessions.is_shared() => {
+                let controller = *control.borrow_and_update();
+                for s in sessions.values_mut() {
+                    let in_control = controller == Some(s.addr);
+                    if s.in_control != in_control {
+                        s.in_control = in_control;
+                        socket.send(ServerMessage::ControlState { in_control }, s.addr).await;
                     }
-                    _ => {}
                 }
             }
+            Ok(event) = session_events.recv() => match event {
+                SessionEvent::TakeoverRequested { target, .. } if sessions.contains_key(&target) => {
+                    socket.send(ServerMessage::TakeoverRequest, target).await;
+                }
+                SessionEvent::Evicted { target } => {
+                    if let Some(s) = sessions.remove(&target) {
+                        info!("✗ Taken over: disconnecting UDP client {}", s.addr);
+                        socket.send(ServerMessage::TakenOver, s.addr).await;
+                        socket.send(ServerMessage::Goodbye(Goodbye::TakenOver), s.addr).await;
+                        end_session(&state, s);
+                    }
+                }
+                // Resumed over WebSocket; a UDP resume has already moved the session.
+                SessionEvent::Resumed { from, to } => {
+                    if let Some(s) = sessions.remove(&from) {
+                        info!("✗ Session resumed by {}: dropping UDP client {}", to, s.addr);
+                        end_session(&state, s);
+                    }
+                }
+                SessionEvent::Kicked { target } => {
+                    if let Some(s) = sessions.remove(&target) {
+                        info!("✗ Kicked: disconnecting UDP client {}", s.addr);
+                        socket.send(ServerMessage::Kicked, s.addr).await;
+                        socket.send(ServerMessage::Goodbye(Goodbye::Kicked), s.addr).await;
+                        end_session(&state, s);
+                    }
+                }
+                _ => {}
+            },
+            _ = state.sessions.halted(), if !sessions.is_empty() => {
+                for (_, s) in sessions.drain() {
+                    info!("✗ Emergency stop: disconnecting UDP client {}", s.addr);
+                    socket.send(ServerMessage::Reject, s.addr).await;
+                    socket.send(ServerMessage::Goodbye(Goodbye::Halted), s.addr).await;
+                    end_session(&state, s);
+                }
+            }
+            _ = state.sessions.shutting_down() => {
+                for (_, s) in sessions.drain() {
+                    info!("✗ Shutting down: disconnecting UDP client {}", s.addr);
+                    socket.send(ServerMessage::Goodbye(Goodbye::Shutdown), s.addr).await;
+                    end_session(&state, s);
+                }
+                return Ok(());
+            }
             _ = tick.tick() => {
-                if let Some(s) = &session {
-                    if s.last_seen.elapsed() > SESSION_TIMEOUT {
-                        info!("✗ UDP client timed out: {}", s.addr);
-                        session = None;
-                        state.slot.release().await;
+                let moves = state.mouse.move_stats();
+                for s in sessions.values_mut() {
+                    let report = s.quality.report(socket.rate_limited(s.addr));
+                    socket.send(ServerMessage::Quality(report), s.addr).await;
+                    if let Some(max_hz) = s.quality.rate_hint(moves) {
+                        socket.send(ServerMessage::RateHint(max_hz), s.addr).await;
+                    }
+                    if s.last_seen.elapsed() > KEEPALIVE_AFTER {
+                        socket.send(ServerMessage::Keepalive, s.addr).await;
+                    }
+                }
+                let expired: Vec<SocketAddr> = sessions
+                    .values()
+                    .filter(|s| s.last_seen.elapsed() > SESSION_TIMEOUT)
+                    .map(|s| s.addr)
+                    .collect();
+                for addr in expired {
+                    if let Some(s) = sessions.remove(&addr) {
+                        info!("✗ UDP client timed out: {}", s.device.describe(s.addr));
+                        socket.send(ServerMessage::Goodbye(Goodbye::TimedOut), addr).await;
+                        end_session(&state, s);
                     }
                 }
+                if let Some(after) = state.reapprove_after {
+                    let idle: Vec<SocketAddr> = sessions
+                        .values()
+                        .filter(|s| s.last_input.elapsed() > after)
+                        .map(|s| s.addr)
+                        .collect();
+                    for addr in idle {
+                        if let Some(s) = sessions.remove(&addr) {
+                            info!("⏲ No input for a while: disconnecting UDP client {}, to be approved again", s.device.describe(s.addr));
+                            state.sessions.revoke(addr);
+                            state.broker.require_approval(&s.device);
+                            socket.send(ServerMessage::Idle, addr).await;
+                            socket.send(ServerMessage::Goodbye(Goodbye::Idle), addr).await;
+                            end_session(&state, s);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl UdpSession {
+    fn new(
+        addr: SocketAddr,
+        client_w: u16,
+        client_h: u16,
+        claim: Claim,
+        listing: Listing,
+        device: Device,
+        actions: ActionQueue,
+    ) -> Self {
+        UdpSession {
+            addr,
+            client_w,
+            client_h,
+            last_seen: Instant::now(),
+            last_input: Instant::now(),
+            held: HeldButtons::default(),
+            held_keys: HeldKeys::default(),
+            sticky: StickyModifiers::default(),
+            gestures: GestureRecognizer::default(),
+            air: AirMouse::default(),
+            pointer: PointerProfile::default(),
+            mapping: SessionMapping::default(),
+            cursor_stream: None,
+            in_control: false,
+            claim,
+            listing,
+            device,
+            seq: SeqWindow::default(),
+            clock: ClockSync::default(),
+            quality: Quality::default(),
+            actions,
+        }
+    }
+
+    /// Use aspect `mode` from now on; applied at once if the input worker has this
+    /// session's mapping, otherwise when it next takes its turn.
+    fn set_aspect_mode(&mut self, state: &UdpState, mode: AspectMode) {
+        self.mapping.aspect = mode;
+        if state.sessions.has_mapping(self.addr) {
+            let _ = state.mouse.set_aspect_mode(mode);
+        }
+    }
+}
+
+/// Greet a client that now holds a session: ACCEPT, DISPLAYS, its control state when
+/// control is shared, and the token to resume with.
+async fn welcome(
+    socket: &Link,
+    state: &UdpState,
+    s: &UdpSession,
+    mode: AspectMode,
+    version: u8,
+    token: u128,
+) {
+    socket.send(accept(&state.mouse, s.mapping.display, mode, version), s.addr).await;
+    let current = s.mapping.display.unwrap_or_else(|| state.mouse.default_display());
+    let displays = state.mouse.displays();
+    socket.send(ServerMessage::Displays { displays: &displays, current }, s.addr).await;
+    if state.sessions.is_shared() {
+        socket.send(ServerMessage::ControlState { in_control: s.in_control }, s.addr).await;
+    }
+    socket.send(ServerMessage::Session { token }, s.addr).await;
+}
+
+/// ACCEPT with the size of the session's display as it is now.
+fn accept(
+    mouse: &MouseController,
+    display: Option<usize>,
+    mode: AspectMode,
+    version: u8,
+) -> ServerMessage<'static> {
+    let (w, h) = mouse.display(display).map_or((1, 1), |d| d.size());
+    ServerMessage::Accept { w, h, mode, version }
+}
+
+/// Where packets travel: the UDP socket, and QUIC connections (native or WebTransport)
+/// whose datagrams carry the same packets.
+struct Link {
+    socket: SecureSocket,
+    quic: Datagrams,
+}
+
+impl Link {
+    /// The next packet from either, copied into `buf`.
+    async fn recv_from(&mut self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        tokio::select! {
+            res = self.socket.recv_from(buf) => res,
+            Some((addr, pkt)) = self.quic.incoming.recv() => {
+                let len = pkt.len().min(buf.len());
+                buf[..len].copy_from_slice(&pkt[..len]);
+                Ok((len, addr))
             }
         }
     }
+
+    /// Send `msg` to `addr`. Nothing is resent; a lost packet is the client's to notice.
+    async fn send(&self, msg: ServerMessage<'_>, addr: SocketAddr) {
+        let pkt = msg.encode();
+        if !self.quic.peers.send(addr, &pkt) {
+            let _ = self.socket.send_to(&pkt, addr).await;
+        }
+    }
+
+    /// How many packets from `addr` the rate limit dropped; QUIC has its own flow control.
+    fn rate_limited(&self, addr: SocketAddr) -> u64 {
+        if self.quic.peers.transport(addr).is_some() { 0 } else { self.socket.rate_limited(addr) }
+    }
+
+    fn transport(&self, addr: SocketAddr) -> Transport {
+        self.quic.peers.transport(addr).unwrap_or(Transport::Udp)
+    }
+}
+
+/// Release everything the session still holds and free its place; mapping settings are
+/// reset once the last controller has gone.
+fn end_session(state: &UdpState, mut s: UdpSession) {
+    state.mouse.release_all(&mut s.held);
+    state.mouse.release_keys(&mut s.held_keys);
+    state.mouse.stylus_leave();
+    state.mouse.touch_release();
+    state.mouse.stop_fling();
+    state.mouse.gamepad_release();
+    if s.claim.release() {
+        state.mouse.reset_session_mapping();
+    }
 }
@@ -0,0 +1,218 @@
+//! Minimal Linux uinput wrapper for creating virtual input devices.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::OpenOptionsExt;
+
+pub const EV_SYN: u16 = 0x00;
+pub const EV_KEY: u16 = 0x01;
+pub const EV_REL: u16 = 0x02;
+pub const EV_ABS: u16 = 0x03;
+pub const SYN_REPORT: u16 = 0;
+
+pub const BTN_TOOL_PEN: u16 = 0x140;
+pub const BTN_TOOL_RUBBER: u16 = 0x141;
+pub const BTN_TOUCH: u16 = 0x14a;
+pub const BTN_STYLUS: u16 = 0x14b;
+pub const BTN_TOOL_FINGER: u16 = 0x145;
+pub const BTN_A: u16 = 0x130;
+pub const BTN_B: u16 = 0x131;
+pub const BTN_X: u16 = 0x133;
+pub const BTN_Y: u16 = 0x134;
+pub const BTN_TL: u16 = 0x136;
+pub const BTN_TR: u16 = 0x137;
+pub const BTN_SELECT: u16 = 0x13a;
+pub const BTN_START: u16 = 0x13b;
+pub const BTN_MODE: u16 = 0x13c;
+pub const BTN_THUMBL: u16 = 0x13d;
+pub const BTN_THUMBR: u16 = 0x13e;
+
+pub const ABS_X: u16 = 0x00;
+pub const ABS_Y: u16 = 0x01;
+pub const ABS_Z: u16 = 0x02;
+pub const ABS_RX: u16 = 0x03;
+pub const ABS_RY: u16 = 0x04;
+pub const ABS_RZ: u16 = 0x05;
+pub const ABS_HAT0X: u16 = 0x10;
+pub const ABS_HAT0Y: u16 = 0x11;
+pub const ABS_PRESSURE: u16 = 0x18;
+pub const ABS_TILT_X: u16 = 0x1a;
+pub const ABS_TILT_Y: u16 = 0x1b;
+pub const ABS_MT_SLOT: u16 = 0x2f;
+pub const ABS_MT_POSITION_X: u16 = 0x35;
+pub const ABS_MT_POSITION_Y: u16 = 0x36;
+pub const ABS_MT_TRACKING_ID: u16 = 0x39;
+
+pub const INPUT_PROP_DIRECT: u16 = 0x01;
+
+// Only the native pointer backend uses these.
+#[cfg(feature = "backend-uinput")]
+pub use pointer_codes::*;
+#[cfg(feature = "backend-uinput")]
+mod pointer_codes {
+    pub const BTN_LEFT: u16 = 0x110;
+    pub const BTN_RIGHT: u16 = 0x111;
+    pub const BTN_MIDDLE: u16 = 0x112;
+    pub const REL_HWHEEL: u16 = 0x06;
+    pub const REL_WHEEL: u16 = 0x08;
+}
+
+const BUS_VIRTUAL: u16 = 0x06;
+
+// ioctl request numbers from <linux/uinput.h>.
+const fn ioc(dir: u64, nr: u64, size: usize) -> u64 {
+    (dir << 30) | ((size as u64) << 16) | ((b'U' as u64) << 8) | nr
+}
+const IOC_WRITE: u64 = 1;
+const UI_DEV_CREATE: u64 = ioc(0, 1, 0);
+const UI_DEV_DESTROY: u64 = ioc(0, 2, 0);
+const UI_DEV_SETUP: u64 = ioc(IOC_WRITE, 3, size_of::<libc::uinput_setup>());
+const UI_ABS_SETUP: u64 = ioc(IOC_WRITE, 4, size_of::<libc::uinput_abs_setup>());
+const UI_SET_EVBIT: u64 = ioc(IOC_WRITE, 100, size_of::<libc::c_int>());
+const UI_SET_KEYBIT: u64 = ioc(IOC_WRITE, 101, size_of::<libc::c_int>());
+const UI_SET_RELBIT: u64 = ioc(IOC_WRITE, 102, size_of::<libc::c_int>());
+const UI_SET_ABSBIT: u64 = ioc(IOC_WRITE, 103, size_of::<libc::c_int>());
+const UI_SET_PROPBIT: u64 = ioc(IOC_WRITE, 110, size_of::<libc::c_int>());
+
+/// Range of one absolute axis.
+#[derive(Debug, Clone, Copy)]
+pub struct AbsAxis {
+    pub code: u16,
+    pub min: i32,
+    pub max: i32,
+    /// Units per millimetre (or per degree for tilt axes); 0 if unknown.
+    pub resolution: i32,
+}
+
+/// What a virtual device advertises to the kernel.
+#[derive(Debug, Default)]
+pub struct DeviceSpec<'a> {
+    pub name: &'a str,
+    pub props: &'a [u16],
+    pub keys: &'a [u16],
+    /// Relative axes (`REL_*` codes).
+    pub rels: &'a [u16],
+    pub axes: &'a [AbsAxis],
+}
+
+/// A virtual input device; destroyed when dropped.
+pub struct UinputDevice {
+    file: File,
+    // Events are buffered until `sync` so a frame reaches the kernel in one write.
+    buf: Vec<u8>,
+}
+
+impl UinputDevice {
+    pub fn create(spec: &DeviceSpec) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open("/dev/uinput")?;
+        let fd = file.as_raw_fd();
+
+        if !spec.keys.is_empty() {
+            ioctl_int(fd, UI_SET_EVBIT, EV_KEY)?;
+            for &key in spec.keys {
+                ioctl_int(fd, UI_SET_KEYBIT, key)?;
+            }
+        }
+        if !spec.rels.is_empty() {
+            ioctl_int(fd, UI_SET_EVBIT, EV_REL)?;
+            for &rel in spec.rels {
+                ioctl_int(fd, UI_SET_RELBIT, rel)?;
+            }
+        }
+        if !spec.axes.is_empty() {
+            ioctl_int(fd, UI_SET_EVBIT, EV_ABS)?;
+        }
+        for axis in spec.axes {
+            ioctl_int(fd, UI_SET_ABSBIT, axis.code)?;
+            // SAFETY: plain-old-data struct, zero is a valid bit pattern.
+            let mut abs: libc::uinput_abs_setup = unsafe { std::mem::zeroed() };
+            abs.code = axis.code;
+            abs.absinfo.minimum = axis.min;
+            abs.absinfo.maximum = axis.max;
+            abs.absinfo.resolution = axis.resolution;
+            ioctl_ptr(fd, UI_ABS_SETUP, &abs)?;
+        }
+        for &prop in spec.props {
+            ioctl_int(fd, UI_SET_PROPBIT, prop)?;
+        }
+
+        // SAFETY: plain-old-data struct, zero is a valid bit pattern.
+        let mut setup: libc::uinput_setup = unsafe { std::mem::zeroed() };
+        setup.id.bustype = BUS_VIRTUAL;
+        setup.id.vendor = 0x1209; // pid.codes open-source vendor id
+        setup.id.product = 0x0001;
+        setup.id.version = 1;
+        for (dst, src) in setup
+            .name
+            .iter_mut()
+            .zip(spec.name.bytes().take(libc::UINPUT_MAX_NAME_SIZE - 1))
+        {
+            *dst = src as libc::c_char;
+        }
+        ioctl_ptr(fd, UI_DEV_SETUP, &setup)?;
+        ioctl_none(fd, UI_DEV_CREATE)?;
+
+        Ok(Self {
+            file,
+            buf: Vec::with_capacity(16 * size_of::<libc::input_event>()),
+        })
+    }
+
+    /// Queue one event; nothing is sent until [`UinputDevice::sync`].
+    pub fn emit(&mut self, kind: u16, code: u16, value: i32) {
+        // SAFETY: plain-old-data struct, zero is a valid bit pattern.
+        let mut ev: libc::input_event = unsafe { std::mem::zeroed() };
+        ev.type_ = kind;
+        ev.code = code;
+        ev.value = value;
+        // SAFETY: `input_event` is `repr(C)` with no padding-sensitive invariants.
+        let bytes = unsafe {
+            std::slice::from_raw_parts(
+                (&ev as *const libc::input_event).cast::<u8>(),
+                size_of::<libc::input_event>(),
+            )
+        };
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// Terminate the frame with `SYN_REPORT` and flush it to the kernel.
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.emit(EV_SYN, SYN_REPORT, 0);
+        let res = self.file.write_all(&self.buf);
+        self.buf.clear();
+        res
+    }
+}
+
+impl Drop for UinputDevice {
+    fn drop(&mut self) {
+        let _ = ioctl_none(self.file.as_raw_fd(), UI_DEV_DESTROY);
+    }
+}
+
+fn ioctl_none(fd: libc::c_int, req: u64) -> io::Result<()> {
+    // SAFETY: request takes no argument.
+    check(unsafe { libc::ioctl(fd, req as _) })
+}
+
+fn ioctl_int(fd: libc::c_int, req: u64, value: u16) -> io::Result<()> {
+    // SAFETY: UI_SET_*BIT requests take an int by value.
+    check(unsafe { libc::ioctl(fd, req as _, libc::c_int::from(value)) })
+}
+
+fn ioctl_ptr<T>(fd: libc::c_int, req: u64, arg: &T) -> io::Result<()> {
+    // SAFETY: `req` encodes `size_of::<T>()` and the kernel only reads from `arg`.
+    check(unsafe { libc::ioctl(fd, req as _, arg as *const T) })
+}
+
+fn check(ret: libc::c_int) -> io::Result<()> {
+    if ret < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
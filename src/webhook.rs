@@ -0,0 +1,81 @@
+use reqwest::{Client, Url};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+/// How long a webhook may take to answer before the event is given up on.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Events waiting for one webhook. Past this, new ones are dropped, so a hook that is down
+/// can't hold on to memory without bound.
+const QUEUE: usize = 64;
+
+/// URLs every session event is posted to as JSON (`--webhook`), for home automation or
+/// alerting. Each hook gets the events in order, from its own queue, so a slow one
+/// doesn't hold up the others.
+#[derive(Debug)]
+pub struct Webhooks {
+    /// Each hook's queue, with its origin to name it by; the rest of a hook's URL often
+    /// holds a secret.
+    queues: Vec<(String, mpsc::Sender<Arc<str>>)>,
+}
+
+impl Webhooks {
+    /// Start posting to `urls`. Must be called from within the runtime.
+    pub fn start(urls: &[Url]) -> anyhow::Result<Self> {
+        let client = Client::builder().timeout(TIMEOUT).build()?;
+        let queues = urls
+            .iter()
+            .map(|url| {
+                let (tx, rx) = mpsc::channel(QUEUE);
+                let origin = url.origin().ascii_serialization();
+                tokio::spawn(post_events(client.clone(), url.clone(), origin.clone(), rx));
+                (origin, tx)
+            })
+            .collect();
+        Ok(Self { queues })
+    }
+
+    /// Queue `payload`, a JSON object, for every hook.
+    pub fn send(&self, payload: &str) {
+        let payload: Arc<str> = payload.into();
+        for (origin, queue) in &self.queues {
+            if queue.try_send(payload.clone()).is_err() {
+                warn!("Dropping an event for webhook {origin}: too many waiting");
+            }
+        }
+    }
+}
+
+/// Post the events queued for `url` one by one. Failures are reported once until the
+/// hook answers again.
+async fn post_events(
+    client: Client,
+    url: Url,
+    origin: String,
+    mut events: mpsc::Receiver<Arc<str>>,
+) {
+    let mut failing = false;
+    while let Some(payload) = events.recv().await {
+        let res = client
+            .post(url.clone())
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(payload.to_string())
+            .send()
+            .await
+            .and_then(|res| res.error_for_status());
+        match res {
+            Ok(_) if failing => {
+                info!("Webhook {origin} answers again");
+                failing = false;
+            }
+            Ok(_) => {}
+            Err(err) if !failing => {
+                warn!("Failed to post to webhook {origin}: {}", err.without_url());
+                failing = true;
+            }
+            Err(_) => {}
+        }
+    }
+}
@@ -0,0 +1,295 @@
+pub use platform::{Listener, Peer, Peers};
+
+/// Whether this build can serve WebRTC data channels (the `webrtc` feature).
+pub const AVAILABLE: bool = cfg!(feature = "webrtc");
+
+#[cfg(feature = "webrtc")]
+mod platform {
+    use crate::net;
+    use anyhow::{Context, Result};
+    use std::net::{IpAddr, SocketAddr, UdpSocket};
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::time::{Duration, Instant};
+    use str0m::change::SdpOffer;
+    use str0m::net::{Protocol, Receive};
+    use str0m::{Candidate, Event, IceConnectionState, Input, Output, Rtc};
+    use tokio::sync::mpsc;
+    use tracing::{debug, info};
+
+    /// Data channel messages held for a session that is busy; later ones are dropped, as
+    /// the channel itself would drop them.
+    const QUEUE: usize = 64;
+
+    /// Longest the run loop sleeps when no connection has a timer due.
+    const MAX_WAIT: Duration = Duration::from_millis(100);
+
+    enum Command {
+        Open {
+            id: u64,
+            rtc: Box<Rtc>,
+            data: mpsc::Sender<Vec<u8>>,
+        },
+        Candidate {
+            id: u64,
+            candidate: Candidate,
+        },
+    }
+
+    /// The UDP socket every WebRTC connection shares, and the run loop driving them.
+    pub struct Listener {
+        socket: tokio::net::UdpSocket,
+        peers: Peers,
+        commands: mpsc::UnboundedReceiver<Command>,
+    }
+
+    impl Listener {
+        /// Listen on `socket`, offering browsers `hosts` (with its port) as candidates.
+        pub fn bind(socket: UdpSocket, hosts: &[IpAddr]) -> Result<Self> {
+            let port = socket.local_addr()?.port();
+            let socket = tokio::net::UdpSocket::from_std(socket)
+                .context("Failed to start the WebRTC socket")?;
+            let (tx, commands) = mpsc::unbounded_channel();
+            let peers = Peers {
+                hosts: hosts.iter().map(|ip| SocketAddr::new(*ip, port)).collect(),
+                commands: tx,
+            };
+            Ok(Self {
+                socket,
+                peers,
+                commands,
+            })
+        }
+
+        pub fn port(&self) -> Result<u16> {
+            Ok(self.socket.local_addr()?.port())
+        }
+
+        /// Handle for the WebSocket sessions to answer offers with.
+        pub fn peers(&self) -> Peers {
+            self.peers.clone()
+        }
+
+        /// Pass packets between the socket and the connections until the server stops.
+        pub async fn serve(mut self) -> Result<()> {
+            let local = self.socket.local_addr()?;
+            info!("WebRTC listening on {local}");
+            let mut conns: Vec<Conn> = Vec::new();
+            let mut buf = vec![0; 2000];
+            loop {
+                let mut deadline = Instant::now() + MAX_WAIT;
+                for conn in &mut conns {
+                    deadline = deadline.min(conn.poll(&self.socket, local));
+                }
+                // A connection whose session is gone is dropped with it.
+                conns.retain(|conn| conn.rtc.is_alive() && !conn.data.is_closed());
+
+                tokio::select! {
+                    res = self.socket.recv_from(&mut buf) => {
+                        let Ok((n, source)) = res else { continue };
+                        let source = net::canonical(source);
+                        let Some(&destination) = self.peers.host_for(source) else { continue };
+                        let Ok(receive) =
+                            Receive::new(Protocol::Udp, source, destination, &buf[..n])
+                        else {
+                            continue;
+                        };
+                        let input = Input::Receive(Instant::now(), receive);
+                        match conns.iter_mut().find(|conn| conn.rtc.accepts(&input)) {
+                            Some(conn) => {
+                                if let Err(err) = conn.rtc.handle_input(input) {
+                                    debug!("WebRTC input from {source} failed: {err}");
+                                }
+                            }
+                            // Often a check that beat its connection to the loop.
+                            None => debug!("No WebRTC connection takes a packet from {source}"),
+                        }
+                    }
+                    Some(command) = self.commands.recv() => match command {
+                        Command::Open { id, rtc, data } => conns.push(Conn { id, rtc, data }),
+                        Command::Candidate { id, candidate } => {
+                            if let Some(conn) = conns.iter_mut().find(|conn| conn.id == id) {
+                                conn.rtc.add_remote_candidate(candidate);
+                            }
+                        }
+                    },
+                    _ = tokio::time::sleep_until(deadline.into()) => {}
+                }
+
+                let now = Instant::now();
+                for conn in &mut conns {
+                    let _ = conn.rtc.handle_input(Input::Timeout(now));
+                }
+            }
+        }
+    }
+
+    /// One browser's connection, owned by the run loop.
+    struct Conn {
+        id: u64,
+        rtc: Box<Rtc>,
+        /// Where its binary messages go: the session that answered its offer.
+        data: mpsc::Sender<Vec<u8>>,
+    }
+
+    impl Conn {
+        /// Send what the connection has to send and hand on what it received, until it
+        /// waits for a timer; returns when that is due.
+        fn poll(&mut self, socket: &tokio::net::UdpSocket, local: SocketAddr) -> Instant {
+            loop {
+                if !self.rtc.is_alive() {
+                    return Instant::now();
+                }
+                match self.rtc.poll_output() {
+                    Ok(Output::Timeout(at)) => return at,
+                    Ok(Output::Transmit(t)) => {
+                        // A full socket loses the packet, as the network could have.
+                        let _ = socket.try_send_to(&t.contents, outgoing(t.destination, local));
+                    }
+                    Ok(Output::Event(Event::ChannelData(msg))) if msg.binary => {
+                        let _ = self.data.try_send(msg.data);
+                    }
+                    Ok(Output::Event(Event::ChannelOpen(_, label))) => {
+                        debug!("WebRTC data channel {label:?} opened");
+                    }
+                    Ok(Output::Event(Event::IceConnectionStateChange(
+                        IceConnectionState::Disconnected,
+                    ))) => self.rtc.disconnect(),
+                    Ok(Output::Event(_)) => {}
+                    Err(err) => {
+                        debug!("WebRTC connection failed: {err}");
+                        self.rtc.disconnect();
+                    }
+                }
+            }
+        }
+    }
+
+    /// `addr` as the socket sends to it: IPv4-mapped on a dual-stack socket.
+    fn outgoing(addr: SocketAddr, local: SocketAddr) -> SocketAddr {
+        match addr {
+            SocketAddr::V4(v4) if local.is_ipv6() => {
+                SocketAddr::new(v4.ip().to_ipv6_mapped().into(), v4.port())
+            }
+            addr => addr,
+        }
+    }
+
+    /// Opens connections on the [`Listener`]; cheap to clone.
+    #[derive(Clone)]
+    pub struct Peers {
+        /// The socket's addresses, offered to browsers as host candidates.
+        hosts: Arc<[SocketAddr]>,
+        commands: mpsc::UnboundedSender<Command>,
+    }
+
+    impl Peers {
+        /// Answer a browser's SDP offer. Binary messages on the data channels it opens
+        /// come out of the returned [`Peer`], and the connection closes when it is dropped.
+        pub fn accept(&self, offer: &str) -> Result<(String, Peer)> {
+            static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+            let offer = SdpOffer::from_sdp_string(offer).context("Bad offer")?;
+            let mut rtc = Rtc::builder().build(Instant::now());
+            for host in self.hosts.iter() {
+                rtc.add_local_candidate(Candidate::host(*host, "udp")?);
+            }
+            let answer = rtc.sdp_api().accept_offer(offer)?;
+            let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+            let (tx, data) = mpsc::channel(QUEUE);
+            self.commands
+                .send(Command::Open {
+                    id,
+                    rtc: Box::new(rtc),
+                    data: tx,
+                })
+                .ok()
+                .context("The WebRTC server has stopped")?;
+            let peer = Peer {
+                id,
+                data,
+                commands: self.commands.clone(),
+            };
+            Ok((answer.to_sdp_string(), peer))
+        }
+
+        /// The host candidate a packet from `source` reached: the socket can't tell which
+        /// address it came in on, so the first of the same family.
+        fn host_for(&self, source: SocketAddr) -> Option<&SocketAddr> {
+            self.hosts
+                .iter()
+                .find(|host| host.is_ipv4() == source.is_ipv4())
+        }
+    }
+
+    /// A WebSocket session's end of its WebRTC connection.
+    pub struct Peer {
+        id: u64,
+        data: mpsc::Receiver<Vec<u8>>,
+        commands: mpsc::UnboundedSender<Command>,
+    }
+
+    impl Peer {
+        /// The next binary message from the browser; `None` once the connection is gone.
+        pub async fn recv(&mut self) -> Option<Vec<u8>> {
+            self.data.recv().await
+        }
+
+        /// Add a candidate the browser gathered, as in its `a=candidate:` line.
+        pub fn add_candidate(&self, candidate: &str) -> Result<()> {
+            let candidate = Candidate::from_sdp_string(candidate)?;
+            let _ = self.commands.send(Command::Candidate {
+                id: self.id,
+                candidate,
+            });
+            Ok(())
+        }
+    }
+}
+
+#[cfg(not(feature = "webrtc"))]
+mod platform {
+    use anyhow::{Result, bail};
+    use std::net::{IpAddr, UdpSocket};
+
+    pub struct Listener;
+
+    impl Listener {
+        pub fn bind(_socket: UdpSocket, _hosts: &[IpAddr]) -> Result<Self> {
+            bail!("built without the webrtc feature")
+        }
+
+        pub fn port(&self) -> Result<u16> {
+            bail!("built without the webrtc feature")
+        }
+
+        pub fn peers(&self) -> Peers {
+            Peers
+        }
+
+        pub async fn serve(self) -> Result<()> {
+            bail!("built without the webrtc feature")
+        }
+    }
+
+    #[derive(Clone)]
+    pub struct Peers;
+
+    impl Peers {
+        pub fn accept(&self, _offer: &str) -> Result<(String, Peer)> {
+            bail!("built without the webrtc feature")
+        }
+    }
+
+    pub struct Peer;
+
+    impl Peer {
+        pub async fn recv(&mut self) -> Option<Vec<u8>> {
+            None
+        }
+
+        pub fn add_candidate(&self, _candidate: &str) -> Result<()> {
+            bail!("built without the webrtc feature")
+        }
+    }
+}
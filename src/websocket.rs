@@ -1,57 +1,147 @@
-use crate::connection::{ApprovalBroker, ConnectionSlot};
-use crate::mouse::MouseController;
+use crate::actions::{ActionQueue, ActionRegistry};
+use crate::air::AirMouse;
+use crate::browser;
+use crate::clipboard::{Clipboard, MAX_CLIPBOARD_BYTES};
+use crate::connection::{
+    same_secret, ApprovalBroker, Decision, Device, Role, SessionEvent, SessionManager, Transport,
+};
+use crate::cursor::{CursorPos, CursorStream};
+use crate::display::Display;
+use crate::gesture::{Feedback, GestureRecognizer};
+use crate::ipfilter::IpFilter;
+use crate::keyboard::{HeldKeys, StickyModifiers};
+use crate::mapping::Homography;
+use crate::mouse::{HeldButtons, MouseController, SessionMapping};
+use crate::net;
+use crate::pointer::PointerProfile;
+use crate::proto::{self, ClientMessage, ControlAction, Encoding, Nack};
+use crate::quality::Quality;
+use crate::share::FileShares;
+use crate::tls::Tls;
+use crate::webrtc::{self, Peer};
 use axum::{
-    extract::ws::{Message, WebSocket, WebSocketUpgrade},
-    extract::{ConnectInfo, State},
-    response::IntoResponse,
+    extract::{ConnectInfo, Query, State},
+    http::{header::AUTHORIZATION, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
     routing::get,
     Router,
 };
 use futures::{SinkExt, StreamExt};
 use serde::Deserialize;
-use std::net::SocketAddr;
+use std::net::{SocketAddr, TcpListener};
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::Instant;
 use tracing::{info, warn};
+use yawc::close::CloseCode;
+use yawc::{Frame, HttpWebSocket, IncomingUpgrade, OpCode, Options};
+
+/// Drop the session if the client goes silent (the web client pings every second).
+const IDLE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often the client is sent a `quality` report.
+const QUALITY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Longest `text` message accepted, in characters; longer pastes are rejected outright.
+const MAX_TEXT_CHARS: usize = 4096;
 
 #[derive(Clone)]
 pub struct AppState {
-    pub slot: Arc<ConnectionSlot>,
+    pub sessions: Arc<SessionManager>,
     pub broker: ApprovalBroker,
+    /// Key the approval page must present (`--admin-key`, random by default).
+    pub admin_key: Arc<str>,
+    /// Token every `/ws` client must present (`--access-token`), unless turned off.
+    pub access_token: Option<Arc<str>>,
+    /// Addresses that may connect at all (`--allow` / `--deny`).
+    pub ip_filter: Arc<IpFilter>,
+    /// A controller sending no input for this long must be approved again
+    /// (`--reapprove-after`).
+    pub reapprove_after: Option<Duration>,
     pub mouse: Arc<MouseController>,
+    pub actions: Arc<ActionRegistry>,
+    pub clipboard: Clipboard,
+    pub shares: Arc<FileShares>,
+    /// Answers WebRTC offers (`--webrtc-port`); `None` when it is off.
+    pub webrtc: Option<webrtc::Peers>,
 }
 
 #[derive(Default)]
 struct ClientCtx {
     width: u16,
     height: u16,
+    held: HeldButtons,
+    held_keys: HeldKeys,
+    sticky: StickyModifiers,
+    gestures: GestureRecognizer,
+    air: AirMouse,
+    pointer: PointerProfile,
+    /// Display, region, transform and the like, put back when control passes here.
+    mapping: SessionMapping,
+    /// Periodic `cursor_pos` reports, while asked for.
+    cursor_stream: Option<CursorStream>,
+    /// Last `control` state sent, when control is shared.
+    in_control: Option<bool>,
+    /// How messages other than moves arrive, as agreed at `init`.
+    encoding: Encoding,
+    /// Whether moves may also arrive batched, as agreed at `init`.
+    batch: bool,
+    quality: Quality,
 }
 
+/// Query parameters of the `/ws` upgrade request.
 #[derive(Deserialize)]
-struct InitMsg {
-    #[serde(rename = "type")]
-    msg_type: String,
-    width: u16,
-    height: u16,
+struct WsParams {
+    /// `viewer` asks for a view-only session.
+    #[serde(default)]
+    role: Role,
+    /// When there is no room, ask to replace the client in control instead of giving up.
+    #[serde(default)]
+    takeover: bool,
+    /// Session token from an earlier `session` message, to resume without approval.
+    token: Option<String>,
+    /// ID the device generated for itself; once approved, it connects without asking.
+    device: Option<String>,
+    /// What the user calls the device, e.g. `Dana's phone`, shown in the approval prompt.
+    name: Option<String>,
+    /// What the device is, e.g. `iPhone 15`.
+    platform: Option<String>,
+    /// The pairing code shown in the server terminal, for `--pairing pin`.
+    pin: Option<String>,
+    /// The one-time token from the pairing QR code, to connect without approval.
+    pair: Option<String>,
+    /// The server's access token, for clients that can't send an `Authorization` header.
+    access_token: Option<String>,
 }
 
+/// Query parameters of the `/admin` upgrade request.
 #[derive(Deserialize)]
-struct PingMsg {
-    #[serde(rename = "type")]
-    msg_type: String,
-    t: u64,
+struct AdminParams {
+    key: Option<String>,
 }
 
-/// Build router exposing /ws endpoint.
+/// Messages from the approval page.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum AdminMsg {
+    Answer { number: u32, approve: bool },
+}
+
+/// Build router exposing the /ws and /admin endpoints.
 pub fn build_ws_router(state: AppState) -> anyhow::Result<Router> {
     let router = Router::new()
         .route("/ws", get(ws_handler))
+        .route("/admin", get(admin_handler))
         .with_state(state);
     Ok(router)
 }
 
-/// Start websocket server on given port.
-pub async fn serve_ws(app: Router, port: u16) -> anyhow::Result<()> {
-    let listener = tokio::net::TcpListener::bind(("0.0.0.0", port)).await?;
+/// Run the websocket server on `listener`, over WSS with `tls`.
+pub async fn serve_ws(app: Router, listener: TcpListener, tls: Option<Tls>) -> anyhow::Result<()> {
+    if let Some(tls) = tls {
+        return tls.serve_with_addr(app, listener).await;
+    }
+    let listener = tokio::net::TcpListener::from_std(listener)?;
     let svc = app.into_make_service_with_connect_info::<SocketAddr>();
     axum::serve(listener, svc).await?;
     Ok(())
@@ -60,89 +150,993 @@ pub async fn serve_ws(app: Router, port: u16) -> anyhow::Result<()> {
 async fn ws_handler(
     State(state): State<AppState>,
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
-    ws: WebSocketUpgrade,
-) -> impl IntoResponse {
-    ws.on_upgrade(move |socket| handle_socket(socket, addr, state))
+    Query(params): Query<WsParams>,
+    headers: HeaderMap,
+    ws: IncomingUpgrade,
+) -> Response {
+    let addr = net::canonical(addr);
+    // Checked before anything else, so scanners never get as far as a prompt.
+    if !state.ip_filter.permits(addr.ip()) {
+        warn!("Refusing {}: not allowed by --allow/--deny", addr);
+        return StatusCode::FORBIDDEN.into_response();
+    }
+    if let Some(expected) = &state.access_token {
+        let bearer = headers
+            .get(AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "));
+        let presented = params.access_token.as_deref().or(bearer);
+        if !presented.is_some_and(|token| same_secret(token, expected)) {
+            warn!("Refusing {}: missing or wrong access token", addr);
+            return StatusCode::UNAUTHORIZED.into_response();
+        }
+    }
+    let device = Device::new(
+        params.device.as_deref(),
+        params.name.as_deref(),
+        params.platform.as_deref(),
+    )
+    .with_pin(params.pin.as_deref())
+    .with_pair(params.pair.as_deref());
+    upgrade(ws, move |socket| async move {
+        match params.role {
+            Role::Controller => {
+                let token = params.token.and_then(|t| u128::from_str_radix(&t, 16).ok());
+                handle_socket(socket, addr, state, params.takeover, token, device).await
+            }
+            Role::Viewer => handle_viewer(socket, addr, state, device).await,
+        }
+    })
 }
 
-async fn handle_socket(stream: WebSocket, addr: SocketAddr, state: AppState) {
-    if !state.slot.try_claim(addr).await {
-        warn!("Rejecting {}: already connected client present", addr);
-        let _ = send_one(stream, Message::Text("Already connected".into())).await;
-        return;
+/// Answer a WebSocket upgrade, agreeing to `permessage-deflate` when the client offers
+/// it, and run `handle` on the socket once it is open.
+fn upgrade<F, Fut>(ws: IncomingUpgrade, handle: F) -> Response
+where
+    F: FnOnce(HttpWebSocket) -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let options = Options::default()
+        .with_low_latency_compression()
+        .with_utf8();
+    let (response, socket) = match ws.upgrade(options) {
+        Ok(upgrade) => upgrade,
+        Err(err) => {
+            warn!("WebSocket upgrade failed: {err}");
+            return StatusCode::BAD_REQUEST.into_response();
+        }
+    };
+    tokio::spawn(async move {
+        if let Ok(socket) = socket.await {
+            handle(socket).await;
+        }
+    });
+    response.into_response()
+}
+
+async fn admin_handler(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Query(params): Query<AdminParams>,
+    ws: IncomingUpgrade,
+) -> Response {
+    let addr = net::canonical(addr);
+    // Only a browser on this machine that holds the key printed at startup may answer.
+    let key_ok = params.key.as_deref().is_some_and(|key| same_secret(key, &state.admin_key));
+    if !addr.ip().is_loopback() || !key_ok {
+        warn!("Refusing approval page connection from {}", addr);
+        return StatusCode::FORBIDDEN.into_response();
+    }
+    upgrade(ws, move |socket| handle_admin(socket, addr, state))
+}
+
+/// The approval page: the requests waiting for approval whenever they change, and the
+/// answers given to them.
+async fn handle_admin(stream: HttpWebSocket, addr: SocketAddr, state: AppState) {
+    let (mut sender, mut receiver) = stream.split();
+    let mut waiting = state.broker.subscribe_waiting();
+    waiting.mark_changed();
+    info!("🔑 Approval page connected: {}", addr);
+
+    loop {
+        tokio::select! {
+            Ok(()) = waiting.changed() => {
+                let msg = serde_json::json!({"type":"pending","requests":&*waiting.borrow_and_update()});
+                if sender.send(Frame::text(msg.to_string())).await.is_err() {
+                    break;
+                }
+            }
+            frame = receiver.next() => match frame {
+                Some(frame) if frame.opcode() == OpCode::Text => {
+                    if let Ok(AdminMsg::Answer { number, approve }) = serde_json::from_slice(frame.payload()) {
+                        let decision = if approve {
+                            Decision::Approved
+                        } else {
+                            Decision::Rejected
+                        };
+                        state.broker.answer(number, decision);
+                    }
+                }
+                Some(frame) if frame.opcode() == OpCode::Close => break,
+                None => break,
+                Some(_) => {}
+            },
+        }
     }
+    info!("✗ Approval page disconnected: {}", addr);
+}
 
-    let approved = state.broker.request_approval(addr).await;
-    if !approved {
-        let _ = send_one(stream, Message::Text("rejected".into())).await;
-        state.slot.release().await;
+async fn handle_socket(
+    mut stream: HttpWebSocket,
+    addr: SocketAddr,
+    state: AppState,
+    takeover: bool,
+    token: Option<u128>,
+    device: Device,
+) {
+    if state.sessions.is_halted() {
+        warn!("Rejecting {}: remote input is stopped", addr);
+        let _ = send_one(stream, Frame::text("rejected")).await;
         return;
     }
+    let resumed =
+        token.and_then(|t| Some((state.sessions.resume_session(t, addr)?.claim, t)));
+    let (claim, token) = match resumed {
+        Some(resumed) => {
+            info!("↩ Session resumed by {}", device.describe(addr));
+            resumed
+        }
+        None => {
+            let claim = if let Some(claim) = state.sessions.claim(addr) {
+                let approval = state.broker.request_approval(addr, Role::Controller, &device);
+                let Some(decision) = unless_hung_up(&mut stream, approval).await else {
+                    info!("✗ {} hung up while waiting for approval", addr);
+                    return;
+                };
+                match decision {
+                    // The emergency stop may have been hit while the prompt was open.
+                    Decision::Approved if state.sessions.is_halted() => Err(Decision::Rejected),
+                    Decision::Approved => Ok(claim),
+                    decision => Err(decision),
+                }
+            } else if takeover {
+                let approval = state.broker.request_takeover(&state.sessions, addr, &device);
+                let Some(claim) = unless_hung_up(&mut stream, approval).await else {
+                    info!("✗ {} hung up while waiting for approval", addr);
+                    return;
+                };
+                claim
+            } else {
+                warn!("Rejecting {}: no room for another controller", addr);
+                let _ = send_one(stream, Frame::text("Already connected")).await;
+                return;
+            };
+            let claim = match claim {
+                Ok(claim) => claim,
+                Err(decision) => {
+                    let _ = send_one(stream, refusal(decision)).await;
+                    return;
+                }
+            };
+            (claim, state.sessions.issue_token(addr))
+        }
+    };
+    state.sessions.activate(addr);
 
     let (mut sender, mut receiver) = stream.split();
-    if sender.send(Message::Text("connected".into())).await.is_err() {
-        state.slot.release().await;
+    if sender.send(Frame::text("connected")).await.is_err() {
         return;
     }
 
     // This handler runs on a single async task, so no locking is needed.
     let mut ctx = ClientCtx::default();
     let mouse = state.mouse.clone();
-    let slot = state.slot.clone();
+    let sessions = state.sessions.clone();
+    let actions = ActionQueue::new(state.actions.clone(), mouse.clone());
+    let mut listing = sessions.list(addr, Transport::WebSocket, Role::Controller, &device);
+    let mut control = sessions.subscribe_controller();
+    control.mark_changed();
+    let mut session_events = sessions.subscribe_events();
+    let mut clipboard_changes = state.clipboard.subscribe();
+    let mut file_offers = state.shares.subscribe();
+    let mut display_changes = mouse.subscribe_displays();
+    // The WebRTC connection moves may also come in on, once the client offers one.
+    let mut rtc: Option<Peer> = None;
+    let mut last_input = Instant::now();
+    // Counted from the last message, not restarted by everything else the loop waits on.
+    let mut last_heard = Instant::now();
+    let mut quality_tick = tokio::time::interval_at(Instant::now() + QUALITY_INTERVAL, QUALITY_INTERVAL);
 
     {
+        let msg = serde_json::json!({"type":"session","token":format!("{token:032x}")}).to_string();
+        if sender.send(Frame::text(msg)).await.is_err() {
+            return;
+        }
+
         let (w, h) = mouse.screen_size();
         let msg = serde_json::json!({"type":"remote_screen","width":w,"height":h}).to_string();
-        if sender.send(Message::Text(msg.into())).await.is_err() {
-            slot.release().await;
+        if sender.send(Frame::text(msg)).await.is_err() {
+            return;
+        }
+
+        if sender
+            .send(displays_message(&mouse.displays(), mouse.default_display()))
+            .await
+            .is_err()
+        {
+            return;
+        }
+
+        let names: Vec<&str> = state.actions.names().collect();
+        let msg = serde_json::json!({"type":"actions","names":names}).to_string();
+        if sender.send(Frame::text(msg)).await.is_err() {
             return;
         }
     }
 
-    while let Some(msg) = receiver.next().await {
-        match msg {
-            Ok(Message::Text(text)) => {
-                if let Ok(init) = serde_json::from_str::<InitMsg>(&text) {
-                    if init.msg_type == "init" {
-                        ctx.width = init.width;
-                        ctx.height = init.height;
-                        info!("📡 Screen size: {}x{} from {}", init.width, init.height, addr);
-                        continue;
+    loop {
+        let gesture_deadline = ctx.gestures.deadline();
+        let cursor_deadline = ctx.cursor_stream.as_ref().map(CursorStream::deadline);
+        let reapprove_deadline = state.reapprove_after.map(|after| last_input + after);
+        let next = tokio::select! {
+            next = tokio::time::timeout_at(last_heard + IDLE_TIMEOUT, receiver.next()) => next,
+            // Handled like a binary frame on the WebSocket.
+            data = recv_rtc(&mut rtc) => match data {
+                Some(data) => Ok(Some(Frame::binary(data))),
+                None => {
+                    info!("✗ WebRTC connection of {} closed", addr);
+                    rtc = None;
+                    continue;
+                }
+            },
+            _ = sessions.halted() => {
+                info!("✗ Emergency stop: disconnecting {}", addr);
+                break;
+            }
+            _ = sessions.shutting_down() => {
+                info!("✗ Shutting down: disconnecting {}", addr);
+                let _ = sender.send(going_away()).await;
+                break;
+            }
+            _ = quality_tick.tick() => {
+                // TCP loses nothing, so there is no loss to report.
+                let report = ctx.quality.report(0);
+                let msg = serde_json::json!({
+                    "type": "quality",
+                    "rtt_ms": report.rtt_ms,
+                    "jitter_ms": report.jitter_ms,
+                    "loss": report.loss,
+                    "dropped": report.dropped,
+                });
+                if sender.send(Frame::text(msg.to_string())).await.is_err() {
+                    break;
+                }
+                if let Some(max_hz) = ctx.quality.rate_hint(mouse.move_stats()) {
+                    let msg = serde_json::json!({"type":"rate_hint","max_hz":max_hz});
+                    if sender.send(Frame::text(msg.to_string())).await.is_err() {
+                        break;
                     }
                 }
-
-                // App-level ping/pong for RTT measurement.
-                if let Ok(ping) = serde_json::from_str::<PingMsg>(&text) {
-                    if ping.msg_type == "ping" {
-                        let pong = serde_json::json!({"type":"pong","t":ping.t}).to_string();
-                        if sender.send(Message::Text(pong.into())).await.is_err() {
+                continue;
+            }
+            _ = tokio::time::sleep_until(reapprove_deadline.unwrap_or_else(Instant::now)),
+                if reapprove_deadline.is_some() =>
+            {
+                info!("⏲ No input for a while: disconnecting {}, to be approved again", device.describe(addr));
+                sessions.revoke(addr);
+                state.broker.require_approval(&device);
+                let _ = sender.send(Frame::text("idle")).await;
+                break;
+            }
+            Ok(event) = session_events.recv() => {
+                match event {
+                    SessionEvent::TakeoverRequested { target, by } if target == addr => {
+                        let msg = serde_json::json!({"type":"takeover_request","addr":by.to_string()});
+                        if sender.send(Frame::text(msg.to_string())).await.is_err() {
                             break;
                         }
                     }
+                    SessionEvent::Evicted { target } if target == addr => {
+                        info!("✗ Taken over: disconnecting {}", addr);
+                        let _ = sender.send(Frame::text("taken_over")).await;
+                        break;
+                    }
+                    SessionEvent::Resumed { from, to } if from == addr => {
+                        info!("✗ Session resumed by {}: dropping {}", to, addr);
+                        break;
+                    }
+                    SessionEvent::Kicked { target } if target == addr => {
+                        info!("✗ Kicked: disconnecting {}", addr);
+                        let _ = sender.send(Frame::text("kicked")).await;
+                        break;
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+            Ok(()) = control.changed(), if sessions.is_shared() => {
+                let in_control = control.borrow_and_update().is_some_and(|a| a == addr);
+                if ctx.in_control.replace(in_control) != Some(in_control) {
+                    let msg = serde_json::json!({"type":"control","in_control":in_control});
+                    if sender.send(Frame::text(msg.to_string())).await.is_err() {
+                        break;
+                    }
                 }
+                continue;
             }
-            Ok(Message::Binary(bin)) => {
-                if bin.len() >= 4 {
-                    let x = u16::from_be_bytes([bin[0], bin[1]]);
-                    let y = u16::from_be_bytes([bin[2], bin[3]]);
-                    if ctx.width > 0 && ctx.height > 0 {
-                        let _ = mouse.move_absolute(ctx.width, ctx.height, x, y);
+            _ = tokio::time::sleep_until(gesture_deadline.unwrap_or_else(Instant::now)),
+                if gesture_deadline.is_some() =>
+            {
+                if let Some(action) = ctx.gestures.poll(Instant::now()) {
+                    action.apply(&mouse, &mut ctx.held, &mut ctx.pointer);
+                    if let Some(feedback) = action.feedback()
+                        && sender.send(feedback_message(feedback)).await.is_err()
+                    {
+                        break;
                     }
                 }
+                continue;
+            }
+            _ = tokio::time::sleep_until(cursor_deadline.unwrap_or_else(Instant::now)),
+                if cursor_deadline.is_some() =>
+            {
+                let pos = mouse.cursor_position().await;
+                if let Some(stream) = ctx.cursor_stream.as_mut()
+                    && let Some(pos) = stream.update(pos)
+                    && sender.send(cursor_message(Some(pos))).await.is_err()
+                {
+                    break;
+                }
+                continue;
+            }
+            Ok(text) = clipboard_changes.recv() => {
+                let msg = serde_json::json!({"type":"clipboard","text":text}).to_string();
+                if sender.send(Frame::text(msg)).await.is_err() {
+                    break;
+                }
+                continue;
+            }
+            Ok(()) = display_changes.recv() => {
+                let displays = mouse.displays();
+                if ctx.mapping.display >= Some(displays.len()) {
+                    ctx.mapping.display = None;
+                }
+                let current = ctx.mapping.display.unwrap_or_else(|| mouse.default_display());
+                if let Some(display) = displays.get(current) {
+                    let (w, h) = display.size();
+                    let msg = serde_json::json!({"type":"remote_screen","width":w,"height":h});
+                    if sender.send(Frame::text(msg.to_string())).await.is_err() {
+                        break;
+                    }
+                }
+                if sender.send(displays_message(&displays, current)).await.is_err() {
+                    break;
+                }
+                continue;
             }
-            Ok(Message::Close(_)) => break,
-            Ok(_) => {}
-            Err(err) => {
-                warn!("WebSocket error from {}: {}", addr, err);
+            Ok(offer) = file_offers.recv() => {
+                let msg = serde_json::json!({"type":"file_offer","name":offer.name,"url":offer.url})
+                    .to_string();
+                if sender.send(Frame::text(msg)).await.is_err() {
+                    break;
+                }
+                continue;
+            }
+        };
+        let msg = match next {
+            Ok(Some(msg)) => {
+                last_heard = Instant::now();
+                msg
+            }
+            Ok(None) => break,
+            Err(_) => {
+                info!("✗ Client timed out: {}", addr);
                 break;
             }
+        };
+        let msg = match (msg.opcode(), msg.payload()) {
+            (OpCode::Text, text) => {
+                serde_json::from_slice::<ClientMessage>(text).map_err(|err| parse_error_message(&err))
+            }
+            (OpCode::Binary, bin)
+                if ctx.batch && bin.len() != 4 && bin.first() == Some(&proto::WS_MOVE_BATCH) =>
+            {
+                let reply = match proto::decode_ws_batch(bin) {
+                    Err(code) => error_message(
+                        code,
+                        "a batch is [2][count:u8] then [age:u16][x:u16][y:u16] per move",
+                    ),
+                    Ok(_) if !sessions.take_turn(addr, &mouse, &ctx.mapping) => {
+                        error_message(Nack::NotInControl, "another client is in control")
+                    }
+                    Ok(samples) => {
+                        ctx.quality.moved(samples.len() as u64);
+                        let now = Instant::now().into_std();
+                        let moves = samples.into_iter().map(|s| {
+                            let age = Duration::from_millis(s.age_ms.into());
+                            (now.checked_sub(age).unwrap_or(now), s.x, s.y)
+                        });
+                        let _ = mouse.move_absolute_batch(ctx.width, ctx.height, moves);
+                        continue;
+                    }
+                };
+                ctx.quality.dropped();
+                if sender.send(reply).await.is_err() {
+                    break;
+                }
+                continue;
+            }
+            // Once MessagePack is on, anything but a 4-byte move is an encoded message.
+            (OpCode::Binary, bin) if ctx.encoding == Encoding::Msgpack && bin.len() != 4 => {
+                rmp_serde::from_slice::<ClientMessage>(bin).map_err(|err| parse_error_message(&err))
+            }
+            (OpCode::Binary, bin) => {
+                let reply = match proto::decode_ws_move(bin) {
+                    Err(code) => error_message(code, "a move is [x:u16][y:u16]"),
+                    Ok(_) if !sessions.take_turn(addr, &mouse, &ctx.mapping) => {
+                        error_message(Nack::NotInControl, "another client is in control")
+                    }
+                    Ok((x, y)) => {
+                        ctx.quality.moved(1);
+                        if ctx.width > 0 && ctx.height > 0 {
+                            let _ = mouse.move_absolute(ctx.width, ctx.height, x, y);
+                        }
+                        continue;
+                    }
+                };
+                ctx.quality.dropped();
+                if sender.send(reply).await.is_err() {
+                    break;
+                }
+                continue;
+            }
+            (OpCode::Close, _) => break,
+            _ => continue,
+        };
+        let msg = match msg {
+            Ok(msg) => msg,
+            Err(reply) => {
+                ctx.quality.dropped();
+                if sender.send(reply).await.is_err() {
+                    break;
+                }
+                continue;
+            }
+        };
+        if let Some(kind) = msg.input_kind() {
+            last_input = Instant::now();
+            if !sessions.take_turn(addr, &mouse, &ctx.mapping) {
+                let reply = error_message(Nack::NotInControl, "another client is in control");
+                ctx.quality.dropped();
+                if sender.send(reply).await.is_err() {
+                    break;
+                }
+                continue;
+            }
+            listing.count(kind);
         }
+        match msg {
+            ClientMessage::Init {
+                width,
+                height,
+                mode,
+                version,
+                encoding,
+                batch,
+            } => {
+                let Some(version) = proto::negotiate(version) else {
+                    info!("✗ Protocol version too old: disconnecting {}", addr);
+                    let msg = serde_json::json!({
+                        "type": "unsupported_version",
+                        "min": proto::MIN_VERSION,
+                        "max": proto::VERSION,
+                    });
+                    let _ = sender.send(Frame::text(msg.to_string())).await;
+                    break;
+                };
+                ctx.encoding = encoding;
+                ctx.batch = batch;
+                let msg = serde_json::json!({
+                    "type": "version",
+                    "version": version,
+                    "encoding": encoding,
+                    "batch": batch,
+                    "webrtc": state.webrtc.is_some(),
+                });
+                let msg = msg.to_string();
+                if sender.send(Frame::text(msg)).await.is_err() {
+                    break;
+                }
+                ctx.width = width;
+                ctx.height = height;
+                info!("📡 Screen size: {}x{} from {}", width, height, addr);
+                ctx.mapping.aspect = mode;
+                if sessions.has_mapping(addr) {
+                    let _ = mouse.set_aspect_mode(mode);
+                }
+                let msg = serde_json::json!({"type":"mapping","mode":mode}).to_string();
+                if sender.send(Frame::text(msg)).await.is_err() {
+                    break;
+                }
+            }
+            // App-level ping/pong for RTT measurement.
+            ClientMessage::Ping { t, rtt_ms } => {
+                if let Some(rtt) = rtt_ms {
+                    ctx.quality.rtt(Duration::from_millis(rtt));
+                    sessions.report_rtt(addr, Duration::from_millis(rtt));
+                }
+                let pong = serde_json::json!({"type":"pong","t":t}).to_string();
+                if sender.send(Frame::text(pong)).await.is_err() {
+                    break;
+                }
+            }
+            ClientMessage::Click { button, count, .. } => {
+                let _ = mouse.click(button, count);
+            }
+            ClientMessage::ButtonDown { button } => {
+                let _ = mouse.press_held(&mut ctx.held, button);
+            }
+            ClientMessage::ButtonUp { button } => {
+                let _ = mouse.release_held(&mut ctx.held, button);
+            }
+            ClientMessage::Scroll { dx, dy } => {
+                let (dx, dy) = ctx.pointer.scroll(dx, dy);
+                let _ = mouse.scroll(dx, dy);
+            }
+            ClientMessage::Fling { vx, vy } => {
+                let (vx, vy) = ctx.pointer.fling(vx, vy);
+                let _ = mouse.fling(vx, vy);
+            }
+            ClientMessage::MoveRel { dx, dy } => {
+                let _ = mouse.move_relative(&mut ctx.pointer, dx, dy);
+            }
+            ClientMessage::Key { code, down } => {
+                let _ = match down {
+                    Some(true) => mouse.press_key(&mut ctx.held_keys, code),
+                    Some(false) => mouse.release_key(&mut ctx.held_keys, code),
+                    None => mouse.tap_key_sticky(&mut ctx.sticky, code),
+                };
+            }
+            ClientMessage::KeyHold { code } => {
+                let _ = mouse.hold_key(&mut ctx.held_keys, code);
+            }
+            ClientMessage::KeyRelease { code } => {
+                let _ = mouse.release_key(&mut ctx.held_keys, code);
+            }
+            ClientMessage::StickyKeys { enabled } => {
+                ctx.sticky.set_enabled(enabled);
+            }
+            ClientMessage::Text { value } => {
+                if value.chars().count() > MAX_TEXT_CHARS {
+                    ctx.quality.dropped();
+                    if sender.send(error_message(Nack::Malformed, "text too long")).await.is_err() {
+                        break;
+                    }
+                    continue;
+                }
+                let _ = mouse.type_text(&value);
+            }
+            ClientMessage::Shortcut { modifiers, key } => {
+                let _ = mouse.shortcut(&modifiers, key);
+            }
+            ClientMessage::Media { action } => {
+                let _ = mouse.tap_key(action.key());
+            }
+            ClientMessage::ClipboardSet { text } => {
+                if text.len() > MAX_CLIPBOARD_BYTES {
+                    ctx.quality.dropped();
+                    let reply = error_message(Nack::Malformed, "clipboard too large");
+                    if sender.send(reply).await.is_err() {
+                        break;
+                    }
+                    continue;
+                }
+                state.clipboard.set_text(text);
+            }
+            ClientMessage::OpenUrl { url } => {
+                browser::open_url(&url);
+            }
+            ClientMessage::Action { name } => {
+                if !actions.push(name) {
+                    ctx.quality.dropped();
+                    let reply = error_message(Nack::Busy, "too many actions queued");
+                    if sender.send(reply).await.is_err() {
+                        break;
+                    }
+                }
+            }
+            ClientMessage::Stylus(sample) => {
+                let _ = mouse.stylus(ctx.width, ctx.height, sample);
+            }
+            ClientMessage::Touch { contacts } => {
+                let _ = mouse.touch(ctx.width, ctx.height, &contacts);
+            }
+            ClientMessage::Motion(sample) => {
+                if let Some((dx, dy)) = ctx.air.sample(sample) {
+                    let _ = mouse.move_relative(&mut ctx.pointer, dx, dy);
+                }
+            }
+            ClientMessage::Touchpad { contacts } => {
+                for action in ctx.gestures.frame(&contacts, Instant::now()) {
+                    action.apply(&mouse, &mut ctx.held, &mut ctx.pointer);
+                    if let Some(feedback) = action.feedback() {
+                        // A dead socket is noticed by the next receive.
+                        let _ = sender.send(feedback_message(feedback)).await;
+                    }
+                }
+            }
+            ClientMessage::Region { region } => {
+                ctx.mapping.region = region;
+                let _ = mouse.confine(region);
+            }
+            ClientMessage::MapTarget { target } => {
+                ctx.mapping.target = target;
+                let _ = mouse.set_map_target(target);
+            }
+            ClientMessage::Transform(transform) => {
+                ctx.mapping.transform = transform;
+                let _ = mouse.set_transform(transform);
+            }
+            ClientMessage::Calibrate { points } => {
+                // An empty list clears; anything else must fit or nothing changes.
+                let fit = points.as_slice().try_into().ok().and_then(|points| {
+                    Homography::from_client_points(points, ctx.width, ctx.height)
+                });
+                let ok = points.is_empty() || fit.is_some();
+                if ok {
+                    ctx.mapping.calibration = fit;
+                    let _ = mouse.calibrate(fit);
+                }
+                let msg = serde_json::json!({"type":"calibration","ok":ok}).to_string();
+                if sender.send(Frame::text(msg)).await.is_err() {
+                    break;
+                }
+            }
+            ClientMessage::Gamepad(pad) => {
+                let _ = mouse.gamepad(pad);
+            }
+            ClientMessage::Displays => {
+                let current = ctx.mapping.display.unwrap_or_else(|| mouse.default_display());
+                let msg = displays_message(&mouse.displays(), current);
+                if sender.send(msg).await.is_err() {
+                    break;
+                }
+            }
+            ClientMessage::Display { index } => {
+                // An unknown index leaves the current display in place.
+                if mouse.displays().get(index).is_some() {
+                    ctx.mapping.display = Some(index);
+                }
+                if let Some(display) = mouse.select_display(ctx.mapping.display)
+                    && sender.send(display_message(&display)).await.is_err()
+                {
+                    break;
+                }
+            }
+            ClientMessage::NextDisplay => {
+                let current = ctx.mapping.display.unwrap_or_else(|| mouse.default_display());
+                ctx.mapping.display = Some((current + 1) % mouse.displays().len());
+                if let Some(display) = mouse.select_display(ctx.mapping.display)
+                    && sender.send(display_message(&display)).await.is_err()
+                {
+                    break;
+                }
+            }
+            ClientMessage::CursorPos => {
+                let pos = mouse.cursor_position().await;
+                if sender.send(cursor_message(pos)).await.is_err() {
+                    break;
+                }
+            }
+            ClientMessage::CursorStream { interval_ms } => {
+                ctx.cursor_stream = CursorStream::new(Duration::from_millis(interval_ms));
+            }
+            ClientMessage::Control { action } => match action {
+                ControlAction::Request => sessions.request_control(addr),
+                ControlAction::Release => sessions.release_control(addr),
+            },
+            ClientMessage::Settings(settings) => {
+                if let Some(smoothing) = settings.smoothing {
+                    ctx.mapping.smoothing = Some(smoothing);
+                    let _ = mouse.set_smoothing(smoothing);
+                }
+                ctx.pointer.update(settings);
+            }
+            ClientMessage::RtcOffer { sdp } => {
+                let Some(peers) = &state.webrtc else {
+                    let reply = error_message(Nack::UnknownType, "WebRTC is off on this server");
+                    if sender.send(reply).await.is_err() {
+                        break;
+                    }
+                    continue;
+                };
+                let reply = match peers.accept(&sdp) {
+                    Ok((answer, peer)) => {
+                        info!("🌐 WebRTC offered by {}", addr);
+                        rtc = Some(peer);
+                        let msg = serde_json::json!({"type":"rtc_answer","sdp":answer});
+                        Frame::text(msg.to_string())
+                    }
+                    Err(err) => error_message(Nack::Malformed, &format!("{err:#}")),
+                };
+                if sender.send(reply).await.is_err() {
+                    break;
+                }
+            }
+            // Candidates the server can't use, such as mDNS names, are left out.
+            ClientMessage::RtcCandidate { candidate } => {
+                if let Some(peer) = &rtc {
+                    let _ = peer.add_candidate(&candidate);
+                }
+            }
+            // Never deserialized from a WebSocket message.
+            ClientMessage::Hello(_) | ClientMessage::Move { .. } | ClientMessage::Encrypted => {}
+        }
+    }
+
+    mouse.release_all(&mut ctx.held);
+    mouse.release_keys(&mut ctx.held_keys);
+    mouse.stylus_leave();
+    mouse.touch_release();
+    mouse.stop_fling();
+    mouse.gamepad_release();
+    // Another controller puts its own mapping back when it next takes its turn.
+    if claim.release() {
+        mouse.reset_session_mapping();
+    }
+    info!("✗ Client disconnected: {}", device.describe(addr));
+}
+
+/// A view-only session: it follows the cursor and the controlling client, but nothing it
+/// sends reaches the desktop. Any number may watch alongside the controller.
+async fn handle_viewer(
+    mut stream: HttpWebSocket,
+    addr: SocketAddr,
+    state: AppState,
+    device: Device,
+) {
+    if state.sessions.is_halted() {
+        warn!("Rejecting viewer {}: remote input is stopped", addr);
+        let _ = send_one(stream, Frame::text("rejected")).await;
+        return;
+    }
+    let approval = state.broker.request_approval(addr, Role::Viewer, &device);
+    let Some(decision) = unless_hung_up(&mut stream, approval).await else {
+        info!("✗ Viewer {} hung up while waiting for approval", addr);
+        return;
+    };
+    match decision {
+        Decision::Approved if !state.sessions.is_halted() => {}
+        Decision::Approved => {
+            let _ = send_one(stream, refusal(Decision::Rejected)).await;
+            return;
+        }
+        decision => {
+            let _ = send_one(stream, refusal(decision)).await;
+            return;
+        }
+    }
+
+    let (mut sender, mut receiver) = stream.split();
+    if sender.send(Frame::text("connected")).await.is_err() {
+        return;
     }
+    info!("👀 Viewer connected: {}", device.describe(addr));
 
-    slot.release().await;
-    info!("✗ Client disconnected: {}", addr);
+    let mouse = state.mouse.clone();
+    let sessions = state.sessions.clone();
+    let _listing = sessions.list(addr, Transport::WebSocket, Role::Viewer, &device);
+    let mut cursor_stream: Option<CursorStream> = None;
+    let mut controller = sessions.subscribe_controller();
+    let mut session_events = sessions.subscribe_events();
+    let mut display_changes = mouse.subscribe_displays();
+    // Counted from the last message, not restarted by everything else the loop waits on.
+    let mut last_heard = Instant::now();
+
+    // The current controller is sent first, then again whenever it changes.
+    controller.mark_changed();
+    let (w, h) = mouse.screen_size();
+    let msg = serde_json::json!({"type":"remote_screen","width":w,"height":h}).to_string();
+    if sender.send(Frame::text(msg)).await.is_err()
+        || sender
+            .send(displays_message(&mouse.displays(), mouse.default_display()))
+            .await
+            .is_err()
+    {
+        return;
+    }
+
+    loop {
+        let cursor_deadline = cursor_stream.as_ref().map(CursorStream::deadline);
+        let next = tokio::select! {
+            next = tokio::time::timeout_at(last_heard + IDLE_TIMEOUT, receiver.next()) => next,
+            _ = sessions.halted() => {
+                info!("✗ Emergency stop: disconnecting viewer {}", addr);
+                break;
+            }
+            _ = sessions.shutting_down() => {
+                info!("✗ Shutting down: disconnecting viewer {}", addr);
+                let _ = sender.send(going_away()).await;
+                break;
+            }
+            Ok(event) = session_events.recv() => {
+                if let SessionEvent::Kicked { target } = event
+                    && target == addr
+                {
+                    info!("✗ Kicked: disconnecting viewer {}", addr);
+                    let _ = sender.send(Frame::text("kicked")).await;
+                    break;
+                }
+                continue;
+            }
+            _ = tokio::time::sleep_until(cursor_deadline.unwrap_or_else(Instant::now)),
+                if cursor_deadline.is_some() =>
+            {
+                let pos = mouse.cursor_position().await;
+                if let Some(stream) = cursor_stream.as_mut()
+                    && let Some(pos) = stream.update(pos)
+                    && sender.send(cursor_message(Some(pos))).await.is_err()
+                {
+                    break;
+                }
+                continue;
+            }
+            Ok(()) = controller.changed() => {
+                let addr = controller.borrow_and_update().map(|a| a.to_string());
+                let msg = serde_json::json!({"type":"controller","addr":addr}).to_string();
+                if sender.send(Frame::text(msg)).await.is_err() {
+                    break;
+                }
+                continue;
+            }
+            Ok(()) = display_changes.recv() => {
+                let displays = mouse.displays();
+                let current = mouse.default_display();
+                if let Some(display) = displays.get(current) {
+                    let (w, h) = display.size();
+                    let msg = serde_json::json!({"type":"remote_screen","width":w,"height":h});
+                    if sender.send(Frame::text(msg.to_string())).await.is_err() {
+                        break;
+                    }
+                }
+                if sender.send(displays_message(&displays, current)).await.is_err() {
+                    break;
+                }
+                continue;
+            }
+        };
+        let msg = match next {
+            Ok(Some(msg)) => {
+                last_heard = Instant::now();
+                msg
+            }
+            Ok(None) => break,
+            Err(_) => {
+                info!("✗ Viewer timed out: {}", addr);
+                break;
+            }
+        };
+        let text = match msg.opcode() {
+            OpCode::Text => msg.into_payload(),
+            OpCode::Close => break,
+            // Moves and everything else that would drive the desktop are refused.
+            OpCode::Binary => {
+                let reply = error_message(Nack::Unauthorized, "viewers can't move the pointer");
+                if sender.send(reply).await.is_err() {
+                    break;
+                }
+                continue;
+            }
+            _ => continue,
+        };
+        let reply = match serde_json::from_slice::<ClientMessage>(&text) {
+            Ok(ClientMessage::Ping { t, rtt_ms }) => {
+                if let Some(rtt) = rtt_ms {
+                    sessions.report_rtt(addr, Duration::from_millis(rtt));
+                }
+                let pong = serde_json::json!({"type":"pong","t":t}).to_string();
+                Frame::text(pong)
+            }
+            Ok(ClientMessage::Displays) => displays_message(&mouse.displays(), mouse.default_display()),
+            Ok(ClientMessage::CursorPos) => cursor_message(mouse.cursor_position().await),
+            Ok(ClientMessage::CursorStream { interval_ms }) => {
+                cursor_stream = CursorStream::new(Duration::from_millis(interval_ms));
+                continue;
+            }
+            Ok(_) => error_message(Nack::Unauthorized, "viewers can only watch"),
+            Err(err) => parse_error_message(&err),
+        };
+        if sender.send(reply).await.is_err() {
+            break;
+        }
+    }
+
+    info!("✗ Viewer disconnected: {}", device.describe(addr));
+}
+
+fn displays_message(displays: &[Display], current: usize) -> Frame {
+    let msg = serde_json::json!({"type":"displays","displays":displays,"current":current});
+    Frame::text(msg.to_string())
+}
+
+fn display_message(display: &Display) -> Frame {
+    let msg = serde_json::json!({"type":"display","display":display}).to_string();
+    Frame::text(msg)
+}
+
+/// `null` coordinates when the platform can't read the cursor back.
+fn cursor_message(pos: Option<CursorPos>) -> Frame {
+    let msg = serde_json::json!({
+        "type": "cursor_pos",
+        "x": pos.map(|p| p.x),
+        "y": pos.map(|p| p.y),
+        "display": pos.and_then(|p| p.display),
+    });
+    Frame::text(msg.to_string())
+}
+
+fn error_message(code: Nack, message: &str) -> Frame {
+    let msg = serde_json::json!({"type":"error","code":code,"message":message}).to_string();
+    Frame::text(msg)
+}
+
+/// The `error` reply to a message that didn't parse.
+fn parse_error_message(err: &impl std::fmt::Display) -> Frame {
+    let message = err.to_string();
+    let code = if message.starts_with("unknown variant") {
+        Nack::UnknownType
+    } else {
+        Nack::Malformed
+    };
+    error_message(code, &message)
+}
+
+fn feedback_message(feedback: Feedback) -> Frame {
+    let msg = serde_json::json!({"type":"feedback","event":feedback}).to_string();
+    Frame::text(msg)
+}
+
+/// The next message on `peer`'s data channel; never, without one.
+async fn recv_rtc(peer: &mut Option<Peer>) -> Option<Vec<u8>> {
+    match peer {
+        Some(peer) => peer.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Wait for an approval unless the client hangs up first, which withdraws the request
+/// from the console's queue. Anything the client sends meanwhile is dropped.
+async fn unless_hung_up<T>(
+    stream: &mut HttpWebSocket,
+    approval: impl Future<Output = T>,
+) -> Option<T> {
+    let hung_up = async {
+        while let Some(frame) = stream.next().await {
+            if frame.opcode() == OpCode::Close {
+                break;
+            }
+        }
+    };
+    tokio::select! {
+        decision = approval => Some(decision),
+        _ = hung_up => None,
+    }
+}
+
+/// The close frame every client gets when the server shuts down.
+fn going_away() -> Frame {
+    Frame::close(CloseCode::Away, "server shutting down")
+}
+
+/// What a client is told when its approval request doesn't go through.
+fn refusal(decision: Decision) -> Frame {
+    match decision {
+        Decision::TimedOut => Frame::text("timed_out"),
+        Decision::Unpaired => Frame::text("pairing_required"),
+        _ => Frame::text("rejected"),
+    }
 }
 
-async fn send_one(mut stream: WebSocket, msg: Message) -> Result<(), axum::Error> {
-    stream.send(msg).await
+async fn send_one(mut stream: HttpWebSocket, frame: Frame) -> yawc::Result<()> {
+    stream.send(frame).await
 }
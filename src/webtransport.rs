@@ -0,0 +1,209 @@
+pub use platform::Listener;
+
+/// Whether this build can serve WebTransport (the `webtransport` feature).
+pub const AVAILABLE: bool = cfg!(feature = "webtransport");
+
+#[cfg(feature = "webtransport")]
+mod platform {
+    use crate::connection::Transport;
+    use crate::ipfilter::IpFilter;
+    use crate::net;
+    use crate::quic::Sessions;
+    use anyhow::{Context, Result};
+    use axum::body::Bytes;
+    use axum::http::{Method, Response, StatusCode};
+    use chrono::{Datelike, Days, NaiveDate, Utc};
+    use rustls::ServerConfig;
+    use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+    use sha2::{Digest, Sha256};
+    use std::net::{SocketAddr, UdpSocket};
+    use std::sync::Arc;
+    use tracing::info;
+
+    /// The WebTransport (HTTP/3) endpoint, with a certificate made for this run.
+    pub struct Listener {
+        endpoint: quinn::Endpoint,
+        cert_hash: [u8; 32],
+    }
+
+    impl Listener {
+        /// Listen on `socket` with a fresh certificate for `names`. Browsers only accept a
+        /// self-signed certificate by its hash, and only one valid for two weeks at most, so
+        /// it isn't kept like the HTTPS one.
+        pub fn bind(socket: UdpSocket, names: Vec<String>) -> Result<Self> {
+            let key = rcgen::KeyPair::generate()?;
+            let mut params = rcgen::CertificateParams::new(names)?;
+            let today = Utc::now().date_naive();
+            let date =
+                |d: NaiveDate| rcgen::date_time_ymd(d.year(), d.month() as u8, d.day() as u8);
+            params.not_before = date(today - Days::new(1));
+            params.not_after = date(today + Days::new(12));
+            let cert = params.self_signed(&key)?;
+            let cert_hash = Sha256::digest(cert.der()).into();
+
+            let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key.serialize_der()));
+            let mut config = ServerConfig::builder_with_provider(Arc::new(
+                rustls::crypto::ring::default_provider(),
+            ))
+            .with_protocol_versions(&[&rustls::version::TLS13])?
+            .with_no_client_auth()
+            .with_single_cert(vec![CertificateDer::from(cert.der().to_vec())], key)?;
+            config.alpn_protocols = vec![b"h3".to_vec()];
+            let crypto = quinn::crypto::rustls::QuicServerConfig::try_from(config)?;
+            let endpoint = quinn::Endpoint::new(
+                quinn::EndpointConfig::default(),
+                Some(quinn::ServerConfig::with_crypto(Arc::new(crypto))),
+                socket,
+                Arc::new(quinn::TokioRuntime),
+            )
+            .context("Failed to start the WebTransport endpoint")?;
+            Ok(Self {
+                endpoint,
+                cert_hash,
+            })
+        }
+
+        pub fn port(&self) -> Result<u16> {
+            Ok(self.endpoint.local_addr()?.port())
+        }
+
+        /// SHA-256 of the certificate, for the browser's `serverCertificateHashes`.
+        pub fn cert_hash(&self) -> [u8; 32] {
+            self.cert_hash
+        }
+
+        /// Accept sessions and hand their datagrams to the UDP server through `sessions`.
+        pub async fn serve(self, sessions: Sessions, ip_filter: Arc<IpFilter>) -> Result<()> {
+            info!(
+                "WebTransport server listening on {}",
+                self.endpoint.local_addr()?
+            );
+            while let Some(incoming) = self.endpoint.accept().await {
+                let addr = net::canonical(incoming.remote_address());
+                if !ip_filter.permits(addr.ip()) {
+                    incoming.refuse();
+                    continue;
+                }
+                let sessions = sessions.clone();
+                tokio::spawn(async move {
+                    if let Err(err) = session(incoming, addr, sessions).await {
+                        info!("✗ WebTransport session from {addr} failed: {err}");
+                    }
+                });
+            }
+            Ok(())
+        }
+    }
+
+    /// Run one connection: wait for the request opening a WebTransport session, then pass
+    /// datagrams both ways until either side goes away.
+    async fn session(
+        incoming: quinn::Incoming,
+        addr: SocketAddr,
+        sessions: Sessions,
+    ) -> Result<()> {
+        let conn = incoming.await?;
+        let mut h3 = h3::server::builder()
+            .enable_webtransport(true)
+            .enable_extended_connect(true)
+            .enable_datagram(true)
+            .max_webtransport_sessions(1)
+            .build::<_, Bytes>(h3_quinn::Connection::new(conn.clone()))
+            .await?;
+
+        // Anything but the session request is turned away.
+        let mut stream = loop {
+            let Some(resolver) = h3.accept().await? else {
+                return Ok(());
+            };
+            let (req, mut stream) = resolver.resolve_request().await?;
+            let protocol = req.extensions().get::<h3::ext::Protocol>();
+            if req.method() == Method::CONNECT
+                && protocol == Some(&h3::ext::Protocol::WEB_TRANSPORT)
+            {
+                break stream;
+            }
+            stream
+                .send_response(Response::builder().status(StatusCode::NOT_FOUND).body(())?)
+                .await?;
+            stream.finish().await?;
+        };
+        let response = Response::builder()
+            .status(StatusCode::OK)
+            .header("sec-webtransport-http3-draft", "draft02")
+            .body(())?;
+        stream.send_response(response).await?;
+
+        // Datagrams of this session start with its quarter stream ID.
+        let prefix = varint(stream.id().into_inner() / 4);
+        let mut rx = sessions.open(addr, Transport::Http3);
+        info!("🌐 WebTransport session opened by {addr}");
+        loop {
+            tokio::select! {
+                res = conn.read_datagram() => {
+                    let Ok(datagram) = res else { break };
+                    let Some(pkt) = datagram.strip_prefix(prefix.as_slice()) else { continue };
+                    if !sessions.deliver(addr, pkt.to_vec()).await {
+                        break;
+                    }
+                }
+                Some(pkt) = rx.recv() => {
+                    let _ = conn.send_datagram([prefix.as_slice(), &pkt].concat().into());
+                }
+                // The browser closed the session, or sent something else on its stream.
+                res = stream.recv_data() => {
+                    if !matches!(res, Ok(Some(_))) {
+                        break;
+                    }
+                }
+                res = h3.accept() => {
+                    if !matches!(res, Ok(Some(_))) {
+                        break;
+                    }
+                }
+            }
+        }
+        sessions.close(addr);
+        info!("✗ WebTransport session closed by {addr}");
+        Ok(())
+    }
+
+    /// `n` as a QUIC variable-length integer.
+    fn varint(n: u64) -> Vec<u8> {
+        match n {
+            0..0x40 => vec![n as u8],
+            0x40..0x4000 => ((n as u16) | 0x4000).to_be_bytes().to_vec(),
+            0x4000..0x4000_0000 => ((n as u32) | 0x8000_0000).to_be_bytes().to_vec(),
+            _ => (n | 0xC000_0000_0000_0000).to_be_bytes().to_vec(),
+        }
+    }
+}
+
+#[cfg(not(feature = "webtransport"))]
+mod platform {
+    use crate::ipfilter::IpFilter;
+    use crate::quic::Sessions;
+    use anyhow::{Result, bail};
+    use std::net::UdpSocket;
+    use std::sync::Arc;
+
+    pub struct Listener;
+
+    impl Listener {
+        pub fn bind(_socket: UdpSocket, _names: Vec<String>) -> Result<Self> {
+            bail!("built without the webtransport feature")
+        }
+
+        pub fn port(&self) -> Result<u16> {
+            bail!("built without the webtransport feature")
+        }
+
+        pub fn cert_hash(&self) -> [u8; 32] {
+            [0; 32]
+        }
+
+        pub async fn serve(self, _sessions: Sessions, _ip_filter: Arc<IpFilter>) -> Result<()> {
+            bail!("built without the webtransport feature")
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use crate::region::Region;
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// How often the focused window's bounds are re-queried while mapping to it.
+const REFRESH: Duration = Duration::from_millis(250);
+
+/// What the client's coordinate space is stretched over.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MapTarget {
+    /// The whole of the display in use.
+    #[default]
+    Screen,
+    /// The content area of the focused window, so the phone covers just that app.
+    Window,
+    /// The bounding box of every display, so the phone reaches all of them.
+    Desktop,
+}
+
+/// Bounds of the focused window, cached between queries.
+#[derive(Default)]
+pub(crate) struct FocusedWindow {
+    probe: Option<platform::Probe>,
+    tried: bool,
+    bounds: Option<Region>,
+    checked: Option<Instant>,
+}
+
+impl FocusedWindow {
+    /// The focused window's content area in desktop pixels, or `None` if it can't be found
+    /// (nothing focused, minimized, or no way to ask on this platform).
+    pub fn bounds(&mut self) -> Option<Region> {
+        let now = Instant::now();
+        if self
+            .checked
+            .is_some_and(|t| now.duration_since(t) < REFRESH)
+        {
+            return self.bounds;
+        }
+        self.checked = Some(now);
+
+        if !self.tried {
+            self.tried = true;
+            self.probe = platform::Probe::open();
+            if self.probe.is_none() {
+                warn!("Cannot query the focused window here; window mapping uses the whole screen");
+            }
+        }
+        self.bounds = self.probe.as_mut().and_then(platform::Probe::bounds);
+        self.bounds
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use crate::region::Region;
+    use x11rb::connection::Connection;
+    use x11rb::protocol::xproto::{AtomEnum, ConnectionExt, Window};
+    use x11rb::rust_connection::RustConnection;
+
+    /// Asks the X server (or XWayland) for the window named by `_NET_ACTIVE_WINDOW`.
+    pub struct Probe {
+        conn: RustConnection,
+        root: Window,
+        active_window: u32,
+    }
+
+    impl Probe {
+        pub fn open() -> Option<Self> {
+            let (conn, screen) = x11rb::connect(None).ok()?;
+            let root = conn.setup().roots.get(screen)?.root;
+            let active_window = conn
+                .intern_atom(false, b"_NET_ACTIVE_WINDOW")
+                .ok()?
+                .reply()
+                .ok()?
+                .atom;
+            Some(Self {
+                conn,
+                root,
+                active_window,
+            })
+        }
+
+        pub fn bounds(&mut self) -> Option<Region> {
+            let reply = self
+                .conn
+                .get_property(false, self.root, self.active_window, AtomEnum::WINDOW, 0, 1)
+                .ok()?
+                .reply()
+                .ok()?;
+            let window = reply.value32()?.next().filter(|w| *w != 0)?;
+            let geometry = self.conn.get_geometry(window).ok()?.reply().ok()?;
+            let origin = self
+                .conn
+                .translate_coordinates(window, self.root, 0, 0)
+                .ok()?
+                .reply()
+                .ok()?;
+            Region::new(
+                origin.dst_x.into(),
+                origin.dst_y.into(),
+                geometry.width.into(),
+                geometry.height.into(),
+            )
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use crate::region::Region;
+    use windows_sys::Win32::Foundation::{POINT, RECT};
+    use windows_sys::Win32::Graphics::Gdi::ClientToScreen;
+    use windows_sys::Win32::UI::WindowsAndMessaging::{
+        GetClientRect, GetForegroundWindow, IsIconic,
+    };
+
+    /// Client area of the foreground window.
+    pub struct Probe;
+
+    impl Probe {
+        pub fn open() -> Option<Self> {
+            Some(Self)
+        }
+
+        pub fn bounds(&mut self) -> Option<Region> {
+            // SAFETY: FFI calls with valid out-pointers; a stale handle only makes them fail.
+            unsafe {
+                let hwnd = GetForegroundWindow();
+                if hwnd.is_null() || IsIconic(hwnd) != 0 {
+                    return None;
+                }
+                let mut rect: RECT = std::mem::zeroed();
+                if GetClientRect(hwnd, &mut rect) == 0 {
+                    return None;
+                }
+                let mut origin = POINT { x: 0, y: 0 };
+                if ClientToScreen(hwnd, &mut origin) == 0 {
+                    return None;
+                }
+                Region::new(
+                    origin.x,
+                    origin.y,
+                    u32::try_from(rect.right).ok()?,
+                    u32::try_from(rect.bottom).ok()?,
+                )
+            }
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+mod platform {
+    use crate::region::Region;
+
+    pub struct Probe;
+
+    impl Probe {
+        pub fn open() -> Option<Self> {
+            None
+        }
+
+        pub fn bounds(&mut self) -> Option<Region> {
+            None
+        }
+    }
+}
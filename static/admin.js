@@ -0,0 +1,106 @@
+const statusText = document.getElementById("status");
+const emptyText = document.getElementById("empty");
+const list = document.getElementById("requests");
+
+const params = new URLSearchParams(window.location.search);
+const key = params.get("key") || "";
+// The page's own port unless the WebSocket server has one of its own.
+const wsHost = params.get("ws") ? `${window.location.hostname}:${params.get("ws")}` : window.location.host;
+
+const RECONNECT_MS = 2000;
+
+let ws = null;
+let requests = [];
+
+// `iPhone 15 'Dana's phone' (192.168.1.5:51234)`, or just the address.
+function from(req) {
+  const label = [req.platform, req.name && `'${req.name}'`].filter(Boolean).join(" ");
+  return label ? `${label} (${req.addr})` : req.addr;
+}
+
+function describe(req) {
+  if (req.replaces) return `Takeover from ${from(req)} (disconnects ${req.replaces})`;
+  if (req.role === "viewer") return `View-only request from ${from(req)}`;
+  return `Connection request from ${from(req)}`;
+}
+
+function seconds(ms) {
+  return `${Math.max(0, Math.round(ms / 1000))}s`;
+}
+
+function detail(req) {
+  const now = Date.now();
+  let text = `#${req.number} · waiting ${seconds(now - req.since)}`;
+  if (typeof req.expires === "number") text += ` · times out in ${seconds(req.expires - now)}`;
+  return text;
+}
+
+function answer(number, approve) {
+  ws?.send(JSON.stringify({ type: "answer", number, approve }));
+}
+
+function render() {
+  list.replaceChildren(
+    ...requests.map((req) => {
+      const item = document.createElement("li");
+      item.className = "request";
+
+      const info = document.createElement("div");
+      info.className = "info";
+      const title = document.createElement("div");
+      title.textContent = describe(req);
+      const more = document.createElement("div");
+      more.className = "detail";
+      more.textContent = detail(req);
+      info.append(title, more);
+
+      const approve = document.createElement("button");
+      approve.className = "approve";
+      approve.textContent = "Approve";
+      approve.addEventListener("click", () => answer(req.number, true));
+      const deny = document.createElement("button");
+      deny.className = "deny";
+      deny.textContent = "Deny";
+      deny.addEventListener("click", () => answer(req.number, false));
+
+      item.append(info, approve, deny);
+      return item;
+    }),
+  );
+  emptyText.classList.toggle("hidden", requests.length > 0 || ws?.readyState !== WebSocket.OPEN);
+}
+
+function connect() {
+  const scheme = window.location.protocol === "https:" ? "wss" : "ws";
+  const url = `${scheme}://${wsHost}/admin?key=${encodeURIComponent(key)}`;
+  ws = new WebSocket(url);
+
+  ws.addEventListener("open", () => {
+    statusText.textContent = "Connected";
+    render();
+  });
+  ws.addEventListener("message", (event) => {
+    try {
+      const msg = JSON.parse(event.data);
+      if (msg && msg.type === "pending" && Array.isArray(msg.requests)) {
+        requests = msg.requests;
+        render();
+      }
+    } catch (_) {
+      // ignore
+    }
+  });
+  ws.addEventListener("close", () => {
+    statusText.textContent = "Disconnected (check the key in the address); retrying...";
+    requests = [];
+    render();
+    window.setTimeout(connect, RECONNECT_MS);
+  });
+}
+
+// Keep the waiting and time-out counters current.
+window.setInterval(() => {
+  if (requests.length > 0) render();
+}, 1000);
+
+connect();
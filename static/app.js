@@ -5,6 +5,7 @@ import {
   absoluteToScreen,
   RelativeTracker,
 } from "./touchpad.js";
+import { encode as encodeMsgpack } from "./msgpack.js";
 
 const statusText = document.getElementById("status-text");
 const indicator = document.getElementById("touch-indicator");
@@ -29,7 +30,39 @@ function setUiConnected(isConnected) {
   }
 }
 
+// Newest protocol version this client speaks; the server answers with the one agreed on.
+const PROTOCOL_VERSION = 1;
+
 let ws;
+// Whether the server agreed to MessagePack for messages other than moves; until it
+// answers `init`, everything goes as JSON.
+let useMsgpack = false;
+// Whether the server agreed to batched moves: every touch sample of a frame goes in one
+// binary message with its age, instead of only the newest.
+let useBatch = false;
+// WebRTC connection offered when the server has it on, and its data channel once open:
+// unordered and never resent, so a lost move doesn't hold up the ones behind it the way
+// it does on the WebSocket. Moves go there while it is open; everything else stays on the
+// WebSocket, where nothing gets lost.
+let rtc = null;
+let moveChannel = null;
+// Pairing code for `--pairing pin`, from `?pin=` or asked for when the server wants it.
+let pairingPin = new URLSearchParams(window.location.search).get("pin") || "";
+// One-time token from the pairing QR code; used up by the first connection.
+let pairingToken = new URLSearchParams(window.location.search).get("pair") || "";
+// The server's access token, part of the address it prints and of the QR code.
+const accessToken = new URLSearchParams(window.location.search).get("access_token") || "";
+// The WebSocket port the server actually took, from `/discovery`, in case it had to pick
+// another one than it was asked for.
+let discoveredWsPort = "";
+fetch("/discovery")
+  .then((res) => (res.ok ? res.json() : null))
+  .then((ports) => {
+    if (ports?.ws) discoveredWsPort = String(ports.ws);
+  })
+  .catch(() => {
+    // ignore: an older server, or the page came from elsewhere
+  });
 let touchPoint = { x: 0, y: 0 };
 let frameRequested = false;
 let connected = false;
@@ -39,6 +72,14 @@ let connecting = false;
 const moveBuf = new ArrayBuffer(4);
 const moveView = new DataView(moveBuf);
 
+// Batched moves: [2][count:u8] then count x [age:u16 ms][x:u16][y:u16], big-endian.
+const MOVE_BATCH = 2;
+const MAX_BATCH = 64;
+const batchBuf = new ArrayBuffer(2 + 6 * MAX_BATCH);
+const batchView = new DataView(batchBuf);
+// Samples since the last send, oldest first.
+const pendingMoves = [];
+
 // Cache layout values for the hot path.
 let padRect = null;
 let clientW = Math.max(1, Math.min(65535, Math.round(window.innerWidth)));
@@ -60,6 +101,11 @@ let padH = 0;
 // Metrics (shown on-screen so mobile can debug without console)
 let wsUrlInUse = "";
 let lastRttMs = null;
+// Latest `quality` report from the server.
+let quality = null;
+// Most moves a second the server asked for when it can't keep up; null for no limit.
+let maxSendHz = null;
+let lastSendAt = 0;
 let lastPongAt = 0;
 let pingTimer = null;
 let metricsTimer = null;
@@ -117,7 +163,8 @@ function startMetricsLoop() {
       `Mode: ${inputMode}\n` +
       `Touch: ${touchPoint.x},${touchPoint.y}\n` +
       `Send: ${sendRate.toFixed(1)}/s\n` +
-      `RTT: ${rtt} (pong ${pongAge})`;
+      `RTT: ${rtt} (pong ${pongAge})\n` +
+      `Jitter: ${quality?.jitter_ms == null ? "-" : `${quality.jitter_ms}ms`}, dropped ${quality?.dropped ?? "-"}`;
   }, 250);
 }
 
@@ -138,13 +185,34 @@ function updateSendRate() {
   }
 }
 
+// Random ID this browser keeps, so the server can remember it once approved.
+function deviceId() {
+  let id = null;
+  try {
+    id = window.localStorage.getItem("penput-device");
+  } catch (_) {
+    // ignore
+  }
+  if (id) return id;
+  const bytes = new Uint8Array(16);
+  window.crypto.getRandomValues(bytes);
+  id = Array.from(bytes, (b) => b.toString(16).padStart(2, "0")).join("");
+  try {
+    window.localStorage.setItem("penput-device", id);
+  } catch (_) {
+    // ignore
+  }
+  return id;
+}
+
 function connect() {
   if (connecting || connected) return;
   const loc = window.location;
   const params = new URLSearchParams(loc.search);
   const explicitPort = params.get("ws");
-  const httpPort = loc.port || "";
-  const tryPorts = [explicitPort, "9001", httpPort].filter(Boolean);
+  // The server answers WebSockets on the page's own port unless it was given --ws-port.
+  const httpPort = loc.port || (loc.protocol === "https:" ? "443" : "80");
+  const tryPorts = [...new Set([discoveredWsPort, explicitPort, httpPort, "9001"].filter(Boolean))];
   const wsScheme = loc.protocol === "https:" ? "wss" : "ws";
   let attempt = 0;
 
@@ -155,7 +223,8 @@ function connect() {
 
   const tryConnect = () => {
     if (attempt >= tryPorts.length) {
-      statusText.textContent = "Connection failed";
+      // A missing token looks like any other failure from here.
+      statusText.textContent = accessToken ? "Connection failed" : "Connection failed (open the address the server printed)";
       connectBtn.disabled = false;
       connecting = false;
       return;
@@ -165,7 +234,17 @@ function connect() {
     statusText.textContent = `Connecting ${wsUrl}`;
     wsUrlInUse = wsUrl;
 
-    ws = new WebSocket(wsUrl);
+    // The device ID stays out of the status text, since it lets this browser in.
+    const platform = navigator.userAgentData?.platform || navigator.platform || "";
+    const query = new URLSearchParams({ device: deviceId(), platform });
+    if (pairingPin) query.set("pin", pairingPin);
+    if (pairingToken) query.set("pair", pairingToken);
+    if (accessToken) query.set("access_token", accessToken);
+    useMsgpack = false;
+    useBatch = false;
+    maxSendHz = null;
+    stopRtc();
+    ws = new WebSocket(`${wsUrl}?${query}`);
     ws.binaryType = "arraybuffer";
 
     const cleanup = () => {
@@ -184,7 +263,7 @@ function connect() {
         if (!connected) return;
         const t = Math.floor(performance.now());
         try {
-          ws?.send(JSON.stringify({ type: "ping", t }));
+          sendMessage({ type: "ping", t });
         } catch (_) {
           // ignore
         }
@@ -194,6 +273,13 @@ function connect() {
     const onMsg = (event) => {
       const msg = event.data;
       if (msg === "connected") {
+        if (pairingToken) {
+          // Don't offer a used-up token again, also not after a reload.
+          pairingToken = "";
+          const url = new URL(window.location.href);
+          url.searchParams.delete("pair");
+          window.history.replaceState(null, "", url);
+        }
         connected = true;
         connecting = false;
         statusText.textContent = "Connected";
@@ -205,6 +291,17 @@ function connect() {
         statusText.textContent = msg;
         statusText.classList.remove("ready");
         disconnect();
+      } else if (msg === "pairing_required") {
+        // Ask on this port rather than moving on to the next one.
+        cleanup();
+        disconnect();
+        const pin = window.prompt("Enter the pairing code shown on the computer");
+        if (pin) {
+          pairingPin = pin.trim();
+          connect();
+        } else {
+          statusText.textContent = "Pairing code needed";
+        }
       } else if (typeof msg === "string" && msg.startsWith("{")) {
         // App-level pong for RTT measurement.
         try {
@@ -216,6 +313,30 @@ function connect() {
             if (connected) sendInit();
             return;
           }
+          if (obj && obj.type === "rate_hint") {
+            maxSendHz = typeof obj.max_hz === "number" ? obj.max_hz : null;
+            return;
+          }
+          if (obj && obj.type === "quality") {
+            quality = obj;
+            return;
+          }
+          if (obj && obj.type === "version") {
+            useMsgpack = obj.encoding === "msgpack";
+            useBatch = obj.batch === true;
+            if (obj.webrtc === true) startRtc();
+            return;
+          }
+          if (obj && obj.type === "rtc_answer" && rtc) {
+            rtc.setRemoteDescription({ type: "answer", sdp: obj.sdp }).catch(stopRtc);
+            return;
+          }
+          if (obj && obj.type === "unsupported_version") {
+            cleanup();
+            disconnect();
+            statusText.textContent = `Update this page: the server needs protocol ${obj.min} or newer`;
+            return;
+          }
           if (obj && obj.type === "pong" && typeof obj.t === "number") {
             lastRttMs = performance.now() - obj.t;
             lastPongAt = performance.now();
@@ -226,15 +347,17 @@ function connect() {
       }
     };
 
-    const onClose = () => {
+    const onClose = (event) => {
       cleanup();
+      stopRtc();
       if (pingTimer) {
         window.clearInterval(pingTimer);
         pingTimer = null;
       }
       if (connected) {
         connected = false;
-        statusText.textContent = "Disconnected";
+        // 1001: the server is shutting down.
+        statusText.textContent = event.code === 1001 ? "Server stopped" : "Disconnected";
         statusText.classList.remove("ready");
         indicator.classList.remove("active");
         connectBtn.classList.remove("hidden");
@@ -265,14 +388,59 @@ function connect() {
 function sendInit() {
   refreshPadRect();
   refreshClientSize();
+  // Always JSON: the server may not know MessagePack, and says in its answer.
   const payload = JSON.stringify({
     type: "init",
     width: clientW,
     height: clientH,
+    version: PROTOCOL_VERSION,
+    encoding: "msgpack",
+    batch: true,
   });
   ws?.send(payload);
 }
 
+function sendMessage(msg) {
+  ws?.send(useMsgpack ? encodeMsgpack(msg) : JSON.stringify(msg));
+}
+
+// Offer the server a WebRTC connection; signalling goes over the WebSocket.
+async function startRtc() {
+  if (rtc || typeof RTCPeerConnection === "undefined") return;
+  const pc = new RTCPeerConnection();
+  rtc = pc;
+  const channel = pc.createDataChannel("moves", { ordered: false, maxRetransmits: 0 });
+  channel.binaryType = "arraybuffer";
+  channel.addEventListener("open", () => {
+    if (rtc === pc) moveChannel = channel;
+  });
+  channel.addEventListener("close", () => {
+    if (moveChannel === channel) moveChannel = null;
+  });
+  pc.addEventListener("icecandidate", (event) => {
+    if (rtc === pc && event.candidate?.candidate) {
+      sendMessage({ type: "rtc_candidate", candidate: event.candidate.candidate });
+    }
+  });
+  try {
+    await pc.setLocalDescription(await pc.createOffer());
+    if (rtc === pc) sendMessage({ type: "rtc_offer", sdp: pc.localDescription.sdp });
+  } catch (_) {
+    if (rtc === pc) stopRtc();
+  }
+}
+
+function stopRtc() {
+  rtc?.close();
+  rtc = null;
+  moveChannel = null;
+}
+
+// Where moves go: the data channel while it is open, the WebSocket otherwise.
+function sendMove(data) {
+  (moveChannel ?? ws)?.send(data);
+}
+
 function requestFullscreen() {
   if (document.fullscreenElement) return Promise.resolve();
   return pad.requestFullscreen?.() ?? Promise.resolve();
@@ -283,14 +451,39 @@ function scheduleSend() {
   frameRequested = true;
   requestAnimationFrame(() => {
     frameRequested = false;
-    moveView.setUint16(0, touchPoint.x, false); // big-endian
-    moveView.setUint16(2, touchPoint.y, false);
-    ws?.send(moveBuf);
+    // Slowed down on request: wait for a later frame, batching the samples meanwhile.
+    const now = performance.now();
+    if (maxSendHz && now - lastSendAt < 1000 / maxSendHz) {
+      scheduleSend();
+      return;
+    }
+    lastSendAt = now;
+    if (useBatch && pendingMoves.length > 1) {
+      sendBatch();
+    } else {
+      moveView.setUint16(0, touchPoint.x, false); // big-endian
+      moveView.setUint16(2, touchPoint.y, false);
+      sendMove(moveBuf);
+    }
+    pendingMoves.length = 0;
     sendCount += 1;
     updateSendRate();
   });
 }
 
+function sendBatch() {
+  const now = performance.now();
+  batchView.setUint8(0, MOVE_BATCH);
+  batchView.setUint8(1, pendingMoves.length);
+  pendingMoves.forEach((move, i) => {
+    const at = 2 + 6 * i;
+    batchView.setUint16(at, Math.min(65535, Math.round(now - move.t)), false);
+    batchView.setUint16(at + 2, move.x, false);
+    batchView.setUint16(at + 4, move.y, false);
+  });
+  sendMove(new Uint8Array(batchBuf, 0, 2 + 6 * pendingMoves.length));
+}
+
 function onTouchStart(e) {
   if (e.target === connectBtn || e.target === exitBtn) return;
   e.preventDefault();
@@ -339,10 +532,15 @@ function updatePoint(e) {
 
   touchPoint.x = screenPos.x;
   touchPoint.y = screenPos.y;
+  if (useBatch) {
+    if (pendingMoves.length === MAX_BATCH) pendingMoves.shift();
+    pendingMoves.push({ t: e.timeStamp, x: screenPos.x, y: screenPos.y });
+  }
   scheduleSend();
 }
 
 function disconnect() {
+  stopRtc();
   ws?.close();
   ws = undefined;
   connected = false;
@@ -0,0 +1,68 @@
+// Minimal MessagePack encoder for client messages: maps, arrays, strings, numbers,
+// booleans and null. The server answers in JSON, so there is no decoder.
+
+const textEncoder = new TextEncoder();
+
+export function encode(value) {
+  const out = [];
+  write(out, value);
+  return new Uint8Array(out);
+}
+
+function write(out, value) {
+  if (value === null || value === undefined) {
+    out.push(0xc0);
+  } else if (value === false) {
+    out.push(0xc2);
+  } else if (value === true) {
+    out.push(0xc3);
+  } else if (typeof value === "number") {
+    writeNumber(out, value);
+  } else if (typeof value === "string") {
+    const bytes = textEncoder.encode(value);
+    const len = bytes.length;
+    if (len < 32) out.push(0xa0 | len);
+    else if (len < 0x100) out.push(0xd9, len);
+    else if (len < 0x10000) out.push(0xda, len >> 8, len & 0xff);
+    else out.push(0xdb, ...uint32(len));
+    for (const b of bytes) out.push(b);
+  } else if (Array.isArray(value)) {
+    writeHeader(out, value.length, 0x90, 0xdc);
+    for (const item of value) write(out, item);
+  } else {
+    const entries = Object.entries(value).filter(([, v]) => v !== undefined);
+    writeHeader(out, entries.length, 0x80, 0xde);
+    for (const [k, v] of entries) {
+      write(out, k);
+      write(out, v);
+    }
+  }
+}
+
+// fixarray/fixmap up to 15 entries, then the 16- or 32-bit form.
+function writeHeader(out, len, fix, wide) {
+  if (len < 16) out.push(fix | len);
+  else if (len < 0x10000) out.push(wide, len >> 8, len & 0xff);
+  else out.push(wide + 1, ...uint32(len));
+}
+
+function writeNumber(out, n) {
+  if (Number.isInteger(n) && n >= -32 && n < 128) {
+    out.push(n & 0xff);
+  } else if (Number.isInteger(n) && n >= 0 && n < 0x100000000) {
+    if (n < 0x100) out.push(0xcc, n);
+    else if (n < 0x10000) out.push(0xcd, n >> 8, n & 0xff);
+    else out.push(0xce, ...uint32(n));
+  } else if (Number.isInteger(n) && n < 0 && n >= -0x80000000) {
+    out.push(0xd2, ...uint32(n >>> 0));
+  } else {
+    const view = new DataView(new ArrayBuffer(8));
+    view.setFloat64(0, n, false);
+    out.push(0xcb);
+    for (let i = 0; i < 8; i += 1) out.push(view.getUint8(i));
+  }
+}
+
+function uint32(n) {
+  return [(n >>> 24) & 0xff, (n >>> 16) & 0xff, (n >>> 8) & 0xff, n & 0xff];
+}